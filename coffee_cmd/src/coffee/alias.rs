@@ -0,0 +1,70 @@
+//! Alternative names of the plugins, an alias can be used in
+//! place of the plugin name in all the coffee commands.
+use coffee_lib::errors::CoffeeError;
+
+use super::audit::{record, Change};
+use super::reserved;
+use super::CoffeeManager;
+
+/// true when a plugin with the `name` is available or installed.
+fn is_plugin(coffee: &CoffeeManager, name: &str) -> bool {
+    coffee.search_plugin(name).is_some()
+        || coffee
+            .config
+            .plugins
+            .iter()
+            .any(|installed| installed.name() == name)
+}
+
+/// Make `alias` an alternative name of the `plugin`.
+pub async fn set(coffee: &mut CoffeeManager, alias: &str, plugin: &str) -> Result<(), CoffeeError> {
+    // an alias of an alias points to the plugin.
+    let plugin = coffee.resolve_alias(plugin);
+    if alias == plugin {
+        return Err(CoffeeError::new(1, "an alias can not be the plugin name"));
+    }
+    reserved::check(coffee, alias)?;
+    if is_plugin(coffee, alias) {
+        return Err(CoffeeError::new(
+            1,
+            &format!("`{alias}` is already the name of a plugin"),
+        ));
+    }
+    if !is_plugin(coffee, &plugin) {
+        return Err(coffee.not_found(&plugin));
+    }
+    let previous = coffee
+        .config
+        .aliases
+        .insert(alias.to_owned(), plugin.clone());
+    coffee.storage.store(&coffee.storage_info()).await?;
+    let change = Change::SetAlias {
+        alias: alias.to_owned(),
+        plugin: plugin.clone(),
+        previous,
+    };
+    record(coffee, change).await;
+    println!("`{alias}` is an alias of `{plugin}`");
+    Ok(())
+}
+
+/// Remove the `alias`.
+pub async fn remove(coffee: &mut CoffeeManager, alias: &str) -> Result<(), CoffeeError> {
+    let Some(plugin) = coffee.config.aliases.remove(alias) else {
+        return Err(CoffeeError::new(1, &format!("alias `{alias}` not found")));
+    };
+    coffee.storage.store(&coffee.storage_info()).await?;
+    let change = Change::RemoveAlias {
+        alias: alias.to_owned(),
+        plugin,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Print all the aliases.
+pub fn list(coffee: &CoffeeManager) {
+    for (alias, plugin) in &coffee.config.aliases {
+        println!("{alias} -> {plugin}");
+    }
+}
@@ -0,0 +1,306 @@
+//! Declarative state of coffee, the machine is converged to the
+//! repositories, the plugins and the aliases declared inside a
+//! state file, so coffee can be driven by a configuration
+//! management tool (e.g. ansible) and run again without changes.
+use std::collections::BTreeMap;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::repository::Layout;
+use coffee_lib::url::URL;
+use coffee_lib::utils::is_inside;
+use log::warn;
+use serde::Deserialize;
+use tokio::fs::read_to_string;
+
+use super::{options, worktree, CoffeeManager};
+
+/// Repository declared inside the state file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DeclaredRepository {
+    pub name: String,
+    pub url: String,
+    /// organization of the plugins inside the repository.
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+/// Plugin declared inside the state file, by name or with
+/// the version that must be installed and its options.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum DeclaredPlugin {
+    Name(String),
+    Detailed {
+        name: String,
+        /// tag, branch or commit of the repository where the plugin
+        /// is pinned, as with `install --at`.
+        version: Option<String>,
+        /// options of the plugin, from the key to the value.
+        #[serde(default)]
+        options: BTreeMap<String, String>,
+    },
+}
+
+impl DeclaredPlugin {
+    pub fn name(&self) -> &str {
+        match self {
+            DeclaredPlugin::Name(name) | DeclaredPlugin::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            DeclaredPlugin::Name(_) => None,
+            DeclaredPlugin::Detailed { version, .. } => version.as_deref(),
+        }
+    }
+
+    pub fn options(&self) -> BTreeMap<String, String> {
+        match self {
+            DeclaredPlugin::Name(_) => BTreeMap::new(),
+            DeclaredPlugin::Detailed { options, .. } => options.clone(),
+        }
+    }
+}
+
+/// Content of the state file.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct State {
+    pub repositories: Vec<DeclaredRepository>,
+    pub plugins: Vec<DeclaredPlugin>,
+    /// alternative names of the plugins, from the alias to the plugin.
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl State {
+    /// parse the content of a state file.
+    pub fn parse(content: &str) -> Result<Self, CoffeeError> {
+        serde_yaml::from_str(content)
+            .map_err(|err| CoffeeError::new(1, &format!("state file malformed: {err}")))
+    }
+}
+
+/// Converge coffee to the state declared inside the file at `path`, the
+/// plugins and the repositories that are not declared are removed only
+/// when `prune` is true.
+pub async fn apply(coffee: &mut CoffeeManager, path: &str, prune: bool) -> Result<(), CoffeeError> {
+    let content = read_to_string(path)
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("state file {path}: {err}")))?;
+    let state = State::parse(&content)?;
+    let mut changes = 0;
+
+    for declared in &state.repositories {
+        let url = URL::parse(&coffee.install_root(), &declared.url, &declared.name)?;
+        match coffee
+            .repos
+            .iter()
+            .find(|repo| repo.name() == declared.name)
+        {
+            Some(repo) if repo.url().url_string != url.url_string => {
+                let err = CoffeeError::new(
+                    1,
+                    &format!(
+                        "repository `{}` points to {}, remove it to use {}",
+                        declared.name,
+                        repo.url().url_string,
+                        declared.url
+                    ),
+                );
+                return Err(err);
+            }
+            Some(_) => {}
+            None => {
+                if coffee.config.settings.offline {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("coffee is offline, `{}` can not be cloned", declared.name),
+                    );
+                    return Err(err);
+                }
+                coffee
+                    .add_remote_with_layout(&declared.name, &declared.url, declared.layout)
+                    .await?;
+                println!("[{}] added {}", declared.name, declared.url);
+                changes += 1;
+            }
+        }
+    }
+
+    // the upstreams are fetched first, otherwise the outdated
+    // plugins are compared with the last fetch.
+    if !coffee.config.settings.offline {
+        for repo in &coffee.repos {
+            let path = repo.url().path_string;
+            let used = coffee
+                .config
+                .plugins
+                .iter()
+                .any(|plugin| is_inside(&plugin.root_path(), &path));
+            if used {
+                if let Err(err) = repo.fetch().await {
+                    warn!("repository {} not fetched: {err}", repo.name());
+                }
+            }
+        }
+    }
+
+    // the plugins to install, grouped by the reference where they
+    // are pinned.
+    let mut to_install: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+    let mut to_repin = vec![];
+    let mut to_upgrade = vec![];
+    for declared in &state.plugins {
+        let name = coffee.resolve_alias(declared.name());
+        let version = declared.version().map(str::to_owned);
+        let Some(installed) = coffee
+            .config
+            .plugins
+            .iter()
+            .find(|installed| installed.name() == name)
+        else {
+            to_install.entry(version).or_default().push(name);
+            continue;
+        };
+        if coffee.config.pins.get(&name) != version.as_ref() {
+            to_repin.push((name, version));
+        } else if coffee.is_outdated(installed) {
+            to_upgrade.push(name);
+        }
+    }
+    for (version, names) in &to_install {
+        let plugins: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        worktree::install(coffee, &plugins, version.as_deref(), false, false).await?;
+        changes += names.len();
+    }
+    for (name, version) in &to_repin {
+        worktree::repin(coffee, name, version.as_deref()).await?;
+        match version {
+            Some(version) => println!("[{name}] pinned at {version}"),
+            None => println!("[{name}] follows its repository"),
+        }
+        changes += 1;
+    }
+    if !to_upgrade.is_empty() {
+        let plugins: Vec<&str> = to_upgrade.iter().map(|name| name.as_str()).collect();
+        coffee.upgrade(&plugins).await?;
+        changes += to_upgrade.len();
+    }
+    for declared in &state.plugins {
+        let name = coffee.resolve_alias(declared.name());
+        for (key, value) in declared.options() {
+            let current = coffee
+                .config
+                .options
+                .get(&name)
+                .and_then(|options| options.get(&key));
+            if current != Some(&value) {
+                options::set(coffee, &name, &key, &value, false).await?;
+                changes += 1;
+            }
+        }
+    }
+
+    for (alias, plugin) in &state.aliases {
+        if coffee.config.aliases.get(alias) != Some(plugin) {
+            coffee.config.aliases.insert(alias.clone(), plugin.clone());
+            println!("`{alias}` is an alias of `{plugin}`");
+            changes += 1;
+        }
+    }
+
+    if prune {
+        let declared: Vec<String> = state
+            .plugins
+            .iter()
+            .map(|plugin| coffee.resolve_alias(plugin.name()))
+            .collect();
+        let undeclared: Vec<String> = coffee
+            .config
+            .plugins
+            .iter()
+            .map(|plugin| plugin.name())
+            .filter(|name| !declared.contains(name))
+            .collect();
+        for name in undeclared {
+            coffee.remove(&name).await?;
+            println!("[{name}] removed");
+            changes += 1;
+        }
+        let undeclared: Vec<String> = coffee
+            .repos
+            .iter()
+            .map(|repo| repo.name())
+            .filter(|name| {
+                !state.repositories.iter().any(|repo| repo.name == *name)
+                    && !coffee
+                        .config
+                        .overrides
+                        .values()
+                        .any(|plugin_override| plugin_override.repository == *name)
+            })
+            .collect();
+        for name in undeclared {
+            coffee.rm_remote(&name).await?;
+            println!("[{name}] removed");
+            changes += 1;
+        }
+        let undeclared: Vec<String> = coffee
+            .config
+            .aliases
+            .keys()
+            .filter(|alias| !state.aliases.contains_key(*alias))
+            .cloned()
+            .collect();
+        for alias in undeclared {
+            coffee.config.aliases.remove(&alias);
+            println!("alias `{alias}` removed");
+            changes += 1;
+        }
+        for declared in &state.plugins {
+            let name = coffee.resolve_alias(declared.name());
+            let options = declared.options();
+            let undeclared: Vec<String> = coffee
+                .config
+                .options
+                .get(&name)
+                .into_iter()
+                .flat_map(|set| set.keys())
+                .filter(|key| !options.contains_key(*key))
+                .cloned()
+                .collect();
+            for key in undeclared {
+                options::remove(coffee, &name, &key).await?;
+                println!("[{name}] option `{key}` removed");
+                changes += 1;
+            }
+        }
+    }
+
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("changed: {changes}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeclaredPlugin, State};
+
+    #[test]
+    fn test_state_parse() {
+        let state = State::parse(
+            "repositories:\n  - name: lightningd\n    url: https://github.com/lightningd/plugins\nplugins:\n  - summary\n  - name: clearnet\n    version: v0.1.0\n    options:\n      clearnet-enable: \"true\"\n",
+        )
+        .unwrap();
+        assert_eq!(state.repositories[0].name, "lightningd");
+        assert_eq!(state.plugins[0], DeclaredPlugin::Name("summary".to_owned()));
+        assert_eq!(state.plugins[1].name(), "clearnet");
+        assert_eq!(state.plugins[1].version(), Some("v0.1.0"));
+        assert_eq!(state.plugins[1].options()["clearnet-enable"], "true");
+        assert!(state.plugins[0].options().is_empty());
+        assert!(state.aliases.is_empty());
+        assert!(State::parse("plugins: 1").is_err());
+    }
+}
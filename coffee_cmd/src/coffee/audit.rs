@@ -0,0 +1,493 @@
+//! Audit log of the changes of the coffee state, every install,
+//! upgrade, removal and configuration change is appended to the
+//! `audit.log` file of the coffee root, one JSON object per line.
+//!
+//! A change can be reverted when coffee still has what is needed
+//! to undo it, e.g. the commit of a repository before an upgrade.
+use std::env;
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::secrets;
+use coffee_lib::utils::unix_time;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{read_to_string, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use super::status::format_age;
+use super::{disable, options, patch, plugin_env, plugin_override, CoffeeManager};
+
+/// Change of the coffee state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Change {
+    Install {
+        plugin: String,
+        repository: Option<String>,
+        commit: Option<String>,
+    },
+    Remove {
+        plugin: String,
+        repository: Option<String>,
+        commit: Option<String>,
+    },
+    Upgrade {
+        repository: String,
+        from: String,
+        to: String,
+    },
+    AddRemote {
+        name: String,
+        url: String,
+    },
+    RemoveRemote {
+        name: String,
+        url: String,
+    },
+    SetAlias {
+        alias: String,
+        plugin: String,
+        previous: Option<String>,
+    },
+    RemoveAlias {
+        alias: String,
+        plugin: String,
+    },
+    /// the value of a `secret` option is redacted.
+    SetOption {
+        plugin: String,
+        key: String,
+        value: String,
+        previous: Option<String>,
+        secret: bool,
+    },
+    RemoveOption {
+        plugin: String,
+        key: String,
+        value: String,
+        secret: bool,
+    },
+    /// the values of the environment are never recorded.
+    SetEnv {
+        plugin: String,
+        key: String,
+        created: bool,
+    },
+    UnsetEnv {
+        plugin: String,
+        key: String,
+    },
+    Disable {
+        plugin: String,
+        reason: String,
+    },
+    Enable {
+        plugin: String,
+        reason: String,
+    },
+    AddPatch {
+        plugin: String,
+        patch: String,
+    },
+    RemovePatch {
+        plugin: String,
+        patch: String,
+    },
+    SetOverride {
+        plugin: String,
+        url: String,
+        branch: Option<String>,
+        previous: Option<(String, Option<String>)>,
+    },
+    ResetOverride {
+        plugin: String,
+        url: String,
+        branch: Option<String>,
+    },
+    /// undo of the change `id`, made with the `changes`.
+    Revert {
+        id: u64,
+        changes: Vec<u64>,
+    },
+}
+
+impl Change {
+    /// one line description of the change.
+    pub fn describe(&self) -> String {
+        let at = |commit: &Option<String>| {
+            commit
+                .as_ref()
+                .map(|commit| format!(" at {}", short(commit)))
+                .unwrap_or_default()
+        };
+        match self {
+            Change::Install {
+                plugin,
+                repository,
+                commit,
+            } => format!(
+                "install `{plugin}` from `{}`{}",
+                repository.as_deref().unwrap_or("unknown"),
+                at(commit)
+            ),
+            Change::Remove { plugin, commit, .. } => format!("remove `{plugin}`{}", at(commit)),
+            Change::Upgrade {
+                repository,
+                from,
+                to,
+            } => format!("upgrade `{repository}` {}..{}", short(from), short(to)),
+            Change::AddRemote { name, url } => format!("add repository `{name}` {url}"),
+            Change::RemoveRemote { name, url } => format!("remove repository `{name}` {url}"),
+            Change::SetAlias { alias, plugin, .. } => format!("alias `{alias}` -> `{plugin}`"),
+            Change::RemoveAlias { alias, plugin } => {
+                format!("remove alias `{alias}` -> `{plugin}`")
+            }
+            Change::SetOption {
+                plugin, key, value, ..
+            } => format!("set option `{key}={value}` of `{plugin}`"),
+            Change::RemoveOption { plugin, key, .. } => {
+                format!("remove option `{key}` of `{plugin}`")
+            }
+            Change::SetEnv { plugin, key, .. } => format!("set variable `{key}` of `{plugin}`"),
+            Change::UnsetEnv { plugin, key } => format!("unset variable `{key}` of `{plugin}`"),
+            Change::Disable { plugin, reason } => format!("disable `{plugin}`: {reason}"),
+            Change::Enable { plugin, .. } => format!("enable `{plugin}`"),
+            Change::AddPatch { plugin, patch } => format!("add patch `{patch}` to `{plugin}`"),
+            Change::RemovePatch { plugin, patch } => {
+                format!("remove patch `{patch}` of `{plugin}`")
+            }
+            Change::SetOverride { plugin, url, .. } => format!("override `{plugin}` with {url}"),
+            Change::ResetOverride { plugin, .. } => format!("reset the override of `{plugin}`"),
+            Change::Revert { id, .. } => format!("revert #{id}"),
+        }
+    }
+}
+
+/// abbreviated commit.
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(8)]
+}
+
+/// Entry of the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    /// unix time of the change.
+    pub time: u64,
+    /// user that run coffee.
+    pub user: String,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// path of the audit log of the coffee root.
+fn log_path(coffee: &CoffeeManager) -> String {
+    format!("{}/audit.log", coffee.config.root_path)
+}
+
+/// Read all the entries of the audit log, the lines that can
+/// not be parsed are skipped.
+pub async fn entries(coffee: &CoffeeManager) -> Result<Vec<AuditEntry>, CoffeeError> {
+    let path = log_path(coffee);
+    if !Path::new(&path).exists() {
+        return Ok(vec![]);
+    }
+    let content = read_to_string(&path).await?;
+    let entries = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                warn!("audit entry `{line}` malformed: {err}");
+                None
+            }
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Append the `change` to the audit log, a change that can not be
+/// recorded does not fail the operation that made it.
+pub async fn record(coffee: &CoffeeManager, change: Change) {
+    if let Err(err) = append(coffee, change).await {
+        warn!("change not recorded inside the audit log: {err}");
+    }
+}
+
+async fn append(coffee: &CoffeeManager, change: Change) -> Result<(), CoffeeError> {
+    let id = entries(coffee)
+        .await?
+        .last()
+        .map(|entry| entry.id + 1)
+        .unwrap_or(1);
+    let entry = AuditEntry {
+        id,
+        time: unix_time(),
+        user: env::var("USER").unwrap_or_else(|_| "unknown".to_owned()),
+        change,
+    };
+    let line = serde_json::to_string(&entry)
+        .map_err(|err| CoffeeError::new(1, &format!("audit entry not serialized: {err}")))?;
+    // the audit log is stored, so the secrets never reach it.
+    let mut line = secrets::conceal(&line);
+    line.push('\n');
+    debug!("audit entry {line}");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path(coffee))
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Print the audit log, the most recent change last.
+pub async fn history(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let entries = entries(coffee).await?;
+    if entries.is_empty() {
+        println!("no changes recorded");
+    }
+    for entry in entries {
+        println!(
+            "#{:<4} {:>8}  {:<10} {}",
+            entry.id,
+            format_age(entry.time),
+            entry.user,
+            entry.change.describe()
+        );
+    }
+    Ok(())
+}
+
+/// Undo the `change`, the undo is recorded as a new change.
+pub async fn undo(coffee: &mut CoffeeManager, change: &Change) -> Result<(), CoffeeError> {
+    match change {
+        Change::Install { plugin, .. } => coffee.remove(plugin).await,
+        Change::Remove { plugin, commit, .. } => {
+            let plugin_name = plugin.clone();
+            let current = coffee
+                .search_plugin(plugin)
+                .and_then(|plugin| coffee.repo_of(&plugin).map(|repo| repo.commit()))
+                .transpose()?;
+            if let (Some(commit), Some(current)) = (commit, &current) {
+                if commit != current {
+                    println!(
+                        "[{plugin_name}] installed at {}, it was removed at {}",
+                        short(current),
+                        short(commit)
+                    );
+                }
+            }
+            coffee.install(&[plugin.as_str()], false, false).await
+        }
+        Change::Upgrade {
+            repository, from, ..
+        } => {
+            let index = coffee
+                .repos
+                .iter()
+                .position(|repo| repo.name() == *repository)
+                .ok_or_else(|| {
+                    CoffeeError::new(1, &format!("repository `{repository}` not found"))
+                })?;
+            let to = coffee.repos[index].commit()?;
+            coffee.repos[index].reset(from).await?;
+            println!("[{repository}] back to {}", short(from));
+            let failures = coffee.rebuild_plugins(index).await?;
+            coffee.storage.store(&coffee.storage_info()).await?;
+            coffee.update_conf().await?;
+            let change = Change::Upgrade {
+                repository: repository.clone(),
+                from: to,
+                to: from.clone(),
+            };
+            record(coffee, change).await;
+            if failures > 0 {
+                let err = CoffeeError::new(1, &format!("{failures} plugins not rebuilt"));
+                return Err(err);
+            }
+            Ok(())
+        }
+        Change::AddRemote { name, .. } => coffee.rm_remote(name).await,
+        Change::RemoveRemote { name, url } => coffee.add_remote(name, url).await,
+        Change::SetAlias {
+            alias,
+            previous: Some(previous),
+            ..
+        } => super::alias::set(coffee, alias, previous).await,
+        Change::SetAlias { alias, .. } => super::alias::remove(coffee, alias).await,
+        Change::RemoveAlias { alias, plugin } => super::alias::set(coffee, alias, plugin).await,
+        Change::SetOption {
+            plugin,
+            key,
+            previous: None,
+            ..
+        } => options::remove(coffee, plugin, key).await,
+        Change::SetOption {
+            secret: true, key, ..
+        }
+        | Change::RemoveOption {
+            secret: true, key, ..
+        } => Err(CoffeeError::new(
+            1,
+            &format!("the value of the secret option `{key}` is not recorded"),
+        )),
+        Change::SetOption {
+            plugin,
+            key,
+            previous: Some(value),
+            ..
+        }
+        | Change::RemoveOption {
+            plugin, key, value, ..
+        } => options::set(coffee, plugin, key, value, false).await,
+        Change::SetEnv {
+            plugin,
+            key,
+            created: true,
+        } => plugin_env::unset(coffee, plugin, key).await,
+        Change::SetEnv { key, .. } | Change::UnsetEnv { key, .. } => Err(CoffeeError::new(
+            1,
+            &format!("the previous value of the variable `{key}` is not recorded"),
+        )),
+        Change::Disable { plugin, .. } => disable::enable(coffee, plugin).await,
+        Change::Enable { plugin, reason } => disable::disable(coffee, plugin, reason).await,
+        Change::AddPatch { plugin, patch } => patch::remove(coffee, plugin, patch).await,
+        Change::RemovePatch { patch, .. } => Err(CoffeeError::new(
+            1,
+            &format!("the removed patch `{patch}` is not kept"),
+        )),
+        Change::SetOverride {
+            plugin,
+            previous: Some((url, branch)),
+            ..
+        }
+        | Change::ResetOverride {
+            plugin,
+            url,
+            branch,
+        } => plugin_override::set(coffee, plugin, url, branch.clone()).await,
+        Change::SetOverride { plugin, .. } => plugin_override::reset(coffee, plugin).await,
+        Change::Revert { id, .. } => Err(CoffeeError::new(
+            1,
+            &format!("the revert of #{id} can not be reverted"),
+        )),
+    }
+}
+
+/// Undo the change with the `id`.
+pub async fn revert(coffee: &mut CoffeeManager, id: u64) -> Result<(), CoffeeError> {
+    let entry = entries(coffee)
+        .await?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| CoffeeError::new(1, &format!("change #{id} not found")))?;
+    println!("reverting #{id}: {}", entry.change.describe());
+    let last = last_id(coffee).await?;
+    undo(coffee, &entry.change).await?;
+    // the changes made by the undo are part of the revert.
+    let changes = entries(coffee)
+        .await?
+        .iter()
+        .map(|entry| entry.id)
+        .filter(|change| *change > last)
+        .collect();
+    record(coffee, Change::Revert { id, changes }).await;
+    Ok(())
+}
+
+/// id of the last entry of the audit log, zero when it is empty.
+async fn last_id(coffee: &CoffeeManager) -> Result<u64, CoffeeError> {
+    Ok(entries(coffee)
+        .await?
+        .last()
+        .map(|entry| entry.id)
+        .unwrap_or_default())
+}
+
+/// The most recent change that is not reverted, the reverts
+/// and the changes made by them are skipped.
+pub fn last_change(entries: &[AuditEntry]) -> Option<&AuditEntry> {
+    let mut skipped = vec![];
+    for entry in entries {
+        if let Change::Revert { id, changes } = &entry.change {
+            skipped.push(entry.id);
+            skipped.push(*id);
+            skipped.extend(changes);
+        }
+    }
+    entries
+        .iter()
+        .rev()
+        .find(|entry| !skipped.contains(&entry.id))
+}
+
+/// Undo the most recent change that is not reverted yet, more
+/// undo go back in the history.
+pub async fn undo_last(coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+    let entries = entries(coffee).await?;
+    let id = last_change(&entries)
+        .map(|entry| entry.id)
+        .ok_or_else(|| CoffeeError::new(1, "no changes to undo"))?;
+    revert(coffee, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{last_change, AuditEntry, Change};
+
+    #[test]
+    fn test_audit_entry() {
+        let line = r#"{"id":3,"time":1700000000,"user":"bitcoin","action":"upgrade","repository":"lightningd","from":"0a1b2c3d4e5f","to":"f5e4d3c2b1a0"}"#;
+        let entry: AuditEntry = serde_json::from_str(line).unwrap();
+        assert_eq!(entry.id, 3);
+        assert_eq!(
+            entry.change.describe(),
+            "upgrade `lightningd` 0a1b2c3d..f5e4d3c2"
+        );
+        assert_eq!(serde_json::to_string(&entry).unwrap(), line);
+        let change = Change::Install {
+            plugin: "summary".to_owned(),
+            repository: None,
+            commit: None,
+        };
+        assert_eq!(change.describe(), "install `summary` from `unknown`");
+        let line = r#"{"action":"set_option","plugin":"summary","key":"summary-currency","value":"EUR","previous":null,"secret":false}"#;
+        let change: Change = serde_json::from_str(line).unwrap();
+        assert_eq!(
+            change.describe(),
+            "set option `summary-currency=EUR` of `summary`"
+        );
+        assert_eq!(serde_json::to_string(&change).unwrap(), line);
+    }
+
+    #[test]
+    fn test_audit_last_change() {
+        let entry = |id, change| AuditEntry {
+            id,
+            time: 0,
+            user: "bitcoin".to_owned(),
+            change,
+        };
+        let alias = |alias: &str| Change::RemoveAlias {
+            alias: alias.to_owned(),
+            plugin: "summary".to_owned(),
+        };
+        let mut entries = vec![entry(1, alias("a")), entry(2, alias("b"))];
+        assert_eq!(last_change(&entries).unwrap().id, 2);
+        // the revert of #2 made the change #3.
+        entries.push(entry(3, alias("c")));
+        entries.push(entry(
+            4,
+            Change::Revert {
+                id: 2,
+                changes: vec![3],
+            },
+        ));
+        assert_eq!(last_change(&entries).unwrap().id, 1);
+        assert!(last_change(&entries[..0]).is_none());
+    }
+}
@@ -0,0 +1,69 @@
+//! Orphan plugins, installed only as dependencies of other plugins
+//! that are not installed anymore, so nothing keeps them installed.
+use std::mem;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::PluginManager;
+
+use super::config::ConfirmPolicy;
+use super::graph;
+use super::why::explain;
+use super::CoffeeManager;
+
+/// Installed plugins that no request of the user keeps installed.
+pub fn orphans(coffee: &CoffeeManager) -> Vec<String> {
+    let graph = graph::build(coffee);
+    coffee
+        .config
+        .auto_installed
+        .iter()
+        .filter(|plugin| graph.contains_key(*plugin))
+        .filter(|plugin| {
+            explain(
+                &graph,
+                &coffee.config.auto_installed,
+                &coffee.config.bundles,
+                plugin,
+            )
+            .kept_by
+            .is_empty()
+        })
+        .cloned()
+        .collect()
+}
+
+/// Print the orphan plugins, if any, e.g. after a removal.
+pub fn hint(coffee: &CoffeeManager) {
+    let orphans = orphans(coffee);
+    if !orphans.is_empty() {
+        println!(
+            "{} not needed anymore, remove them with `coffee autoremove`",
+            orphans.join(", ")
+        );
+    }
+}
+
+/// Remove the orphan plugins, after the confirmation of the user.
+pub async fn autoremove(coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+    let orphans = orphans(coffee);
+    if orphans.is_empty() {
+        println!("no orphan plugins");
+        return Ok(());
+    }
+    let question = format!("remove the orphan plugins {}?", orphans.join(", "));
+    if !coffee.config.settings.confirm.confirm(&question)? {
+        return Err(CoffeeError::new(1, "autoremove aborted"));
+    }
+    // the user already confirmed the removal of all of them.
+    let confirm = mem::replace(&mut coffee.config.settings.confirm, ConfirmPolicy::Yes);
+    let mut result = Ok(());
+    for plugin in &orphans {
+        result = coffee.remove(plugin).await;
+        if result.is_err() {
+            break;
+        }
+        println!("[{plugin}] removed");
+    }
+    coffee.config.settings.confirm = confirm;
+    result
+}
@@ -0,0 +1,159 @@
+//! Backup and restore of the coffee state, the backup contains
+//! only the information that can not be rebuilt (storage,
+//! configurations), the repositories and the plugins artifacts
+//! are rebuilt from the sources during the restore.
+use std::fs::File;
+use std::path::Path;
+
+use coffee_github::repository::Github;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::repository::Repository;
+use coffee_lib::utils::is_inside;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::debug;
+use tar::{Archive, Builder};
+use walkdir::WalkDir;
+
+use super::{build_plugin, worktree, CoffeeManager};
+
+/// Directory of the coffee root that contains the checkouts,
+/// they are cloned again during the restore.
+const REPOSITORIES_DIR: &str = "repositories";
+
+/// Directory of the coffee root that contains the downloads cache.
+const CACHE_DIR: &str = "cache";
+
+/// true when the file inside the coffee root must not
+/// be part of the backup.
+fn is_rebuildable(relative: &Path) -> bool {
+    relative.starts_with(REPOSITORIES_DIR)
+        || relative.starts_with(CACHE_DIR)
+        || relative
+            .extension()
+            .map(|extension| extension == "tmp")
+            .unwrap_or_default()
+}
+
+/// Write inside the `tarball` the coffee state.
+pub async fn backup(coffee: &CoffeeManager, tarball: &str) -> Result<(), CoffeeError> {
+    let root = Path::new(&coffee.config.root_path);
+    let encoder = GzEncoder::new(File::create(tarball)?, Compression::default());
+    let mut builder = Builder::new(encoder);
+    let walk = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry
+                .path()
+                .strip_prefix(root)
+                .map(|relative| !is_rebuildable(relative))
+                .unwrap_or_default()
+        });
+    for entry in walk {
+        let entry = entry.map_err(|err| CoffeeError::new(1, &format!("{err}")))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        debug!("backup of {}", relative.display());
+        builder.append_path_with_name(entry.path(), relative)?;
+    }
+    builder.into_inner()?.finish()?;
+    println!("coffee state stored in {tarball}");
+    Ok(())
+}
+
+/// Restore the coffee state from the `tarball`, and then rebuild
+/// the repositories and the plugins that are missing.
+pub async fn restore(coffee: &mut CoffeeManager, tarball: &str) -> Result<(), CoffeeError> {
+    if !Path::new(tarball).exists() {
+        return Err(CoffeeError::new(1, &format!("backup {tarball} not found")));
+    }
+    let storage_path = format!("{}/storage.json", coffee.config.root_path);
+    if Path::new(&storage_path).exists() {
+        let question = format!(
+            "the coffee state inside {} will be overridden, continue?",
+            coffee.config.root_path
+        );
+        if !coffee.config.settings.confirm.confirm(&question)? {
+            return Err(CoffeeError::new(1, "restore aborted by the user"));
+        }
+    }
+    let mut archive = Archive::new(GzDecoder::new(File::open(tarball)?));
+    archive.unpack(&coffee.config.root_path)?;
+    println!("coffee state restored from {tarball}");
+
+    coffee.repos.clear();
+    coffee.inventory().await?;
+
+    let mut failures = 0;
+    let mut cloned = vec![];
+    for repo in coffee.repos.iter_mut() {
+        let url = repo.url();
+        if Path::new(&url.path_string).exists() {
+            continue;
+        }
+        if coffee.config.settings.offline {
+            println!("[{}] not cloned, coffee is offline", repo.name());
+            failures += 1;
+            continue;
+        }
+        let mut github = Github::new(&repo.name(), &url).with_layout(repo.layout());
+        match github.init().await {
+            Ok(()) => {
+                println!("[{}] cloned {}", repo.name(), url.url_string);
+                cloned.push(url.path_string);
+                *repo = Box::new(github);
+            }
+            Err(err) => {
+                println!("[{}] clone failed: {err}", repo.name());
+                failures += 1;
+            }
+        }
+    }
+
+    // the worktrees of the plugins are not part of the clones.
+    for plugin in coffee.config.plugins.iter().map(|plugin| plugin.name()) {
+        if let Err(err) = worktree::restore(coffee, &plugin).await {
+            println!("[{plugin}] worktree not restored: {err}");
+            failures += 1;
+        }
+    }
+
+    let sandbox = coffee.sandbox();
+    let run_as = coffee.run_as();
+    let env = coffee.plugin_env();
+    for plugin in coffee.config.plugins.iter_mut() {
+        // the build artifacts of the cloned repositories are
+        // missing even when the executable is there.
+        let built = plugin
+            .exec_path()
+            .map(|exec_path| Path::new(&exec_path).exists())
+            .unwrap_or_default()
+            && !cloned
+                .iter()
+                .any(|path| is_inside(&plugin.root_path(), path));
+        if built || !Path::new(&plugin.root_path()).exists() {
+            continue;
+        }
+        match build_plugin(plugin, false, &sandbox, &run_as, &env).await {
+            Ok(_) => println!("[{}] rebuilt", plugin.name()),
+            Err(err) => {
+                println!("[{}] rebuild failed: {err}", plugin.name());
+                failures += 1;
+            }
+        }
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+
+    if failures > 0 {
+        let err = CoffeeError::new(
+            1,
+            &format!("{failures} repositories or plugins not rebuilt after the restore"),
+        );
+        return Err(err);
+    }
+    Ok(())
+}
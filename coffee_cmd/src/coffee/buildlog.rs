@@ -0,0 +1,146 @@
+//! Logs of the builds, the output of the build commands of every
+//! install attempt is kept inside the `buildlogs` directory of the
+//! coffee root, so the error of a failed build can be shared.
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::sandbox::Sandbox;
+use coffee_lib::utils::unix_time;
+use log::warn;
+use tokio::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use super::CoffeeManager;
+
+/// Logs kept for each plugin, the oldest are removed.
+const KEPT_LOGS: usize = 5;
+
+/// Suffix of the logs of the failed builds.
+const FAILED_SUFFIX: &str = "-failed.log";
+
+/// logs of the plugin inside `dir`, the oldest first.
+async fn logs(dir: &str) -> Result<Vec<String>, CoffeeError> {
+    if !Path::new(dir).exists() {
+        return Ok(vec![]);
+    }
+    let mut logs = vec![];
+    let mut entries = read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.ends_with(".log") {
+            logs.push(file_name);
+        }
+    }
+    // the name starts with the unix time of the build.
+    logs.sort_by_key(|log| {
+        log.split(['-', '.'])
+            .next()
+            .and_then(|time| time.parse::<u64>().ok())
+            .unwrap_or_default()
+    });
+    Ok(logs)
+}
+
+/// Start the build log of the `plugin`, the oldest logs are rotated
+/// away, return the sandbox that writes inside the new log.
+pub async fn start(sandbox: &Sandbox, plugin: &str) -> Result<Sandbox, CoffeeError> {
+    let Some(log_dir) = &sandbox.build_log_dir else {
+        return Ok(sandbox.clone());
+    };
+    let dir = format!("{log_dir}/{plugin}");
+    create_dir_all(&dir).await?;
+    let logs = logs(&dir).await?;
+    for old in logs.iter().take((logs.len() + 1).saturating_sub(KEPT_LOGS)) {
+        remove_file(format!("{dir}/{old}")).await?;
+    }
+    let mut time = unix_time();
+    // two builds of the same plugin within the same second.
+    while logs
+        .iter()
+        .any(|log| log.starts_with(&format!("{time}.")) || log.starts_with(&format!("{time}-")))
+    {
+        time += 1;
+    }
+    Ok(sandbox.clone().with_build_log(&format!("{dir}/{time}.log")))
+}
+
+/// Close the build log of the `sandbox` with the `result` of the
+/// build, the log of a failed build is marked as such.
+pub async fn finish<T>(sandbox: &Sandbox, result: &Result<T, CoffeeError>) {
+    let Some(log) = &sandbox.build_log else {
+        return;
+    };
+    let end = match result {
+        Ok(_) => "build succeeded\n".to_owned(),
+        Err(err) => format!("build failed: {err}\n"),
+    };
+    let written = async {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .await?;
+        file.write_all(end.as_bytes()).await?;
+        if result.is_err() {
+            let failed = format!("{}{FAILED_SUFFIX}", log.trim_end_matches(".log"));
+            rename(log, failed).await?;
+        }
+        Ok::<(), CoffeeError>(())
+    };
+    if let Err(err) = written.await {
+        warn!("build log {log} not written: {err}");
+    }
+}
+
+/// Print the log of the last failed build of the `plugin`.
+pub async fn show(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let dir = format!("{}/buildlogs/{plugin}", coffee.config.root_path);
+    let Some(log) = logs(&dir)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|log| log.ends_with(FAILED_SUFFIX))
+    else {
+        println!("no failed build of `{plugin}`");
+        return Ok(());
+    };
+    let path = format!("{dir}/{log}");
+    println!("# {path}");
+    print!("{}", read_to_string(&path).await?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{create_dir_all, read_dir, remove_dir_all, write};
+
+    use coffee_lib::errors::CoffeeError;
+    use coffee_lib::sandbox::Sandbox;
+
+    use super::{finish, start, KEPT_LOGS};
+
+    #[tokio::test]
+    async fn test_buildlog_rotation() {
+        let dir = format!("{}/coffee-buildlogs", env::temp_dir().to_string_lossy());
+        create_dir_all(format!("{dir}/summary")).unwrap();
+        for time in 1..=KEPT_LOGS {
+            write(format!("{dir}/summary/{time}.log"), "").unwrap();
+        }
+        let sandbox = Sandbox::default().with_build_log_dir(&dir);
+        let sandbox = start(&sandbox, "summary").await.unwrap();
+        let result: Result<(), CoffeeError> = Err(CoffeeError::new(1, "pip failed"));
+        finish(&sandbox, &result).await;
+
+        let mut logs: Vec<String> = read_dir(format!("{dir}/summary"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        logs.sort();
+        assert_eq!(logs.len(), KEPT_LOGS);
+        assert!(!logs.contains(&"1.log".to_owned()));
+        assert!(logs.iter().any(|log| log.ends_with("-failed.log")));
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Bundles of plugins, e.g. `routing-ops` made of rebalance, summary
+//! and feeadjuster, installed, upgraded and removed as a unit. The
+//! bundles are defined inside the config file, or published by the
+//! repositories inside a `bundles.yml` at their root.
+use std::collections::BTreeMap;
+use std::fs;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::PluginManager;
+use log::warn;
+use serde::Deserialize;
+
+use super::CoffeeManager;
+
+/// File at the root of a repository with the bundles it publishes.
+pub const BUNDLES_FILE: &str = "bundles.yml";
+
+/// Content of the bundles file of a repository.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct BundlesFile {
+    bundles: BTreeMap<String, Vec<String>>,
+}
+
+/// A named group of plugins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bundle {
+    pub name: String,
+    pub plugins: Vec<String>,
+    /// where the bundle is defined, `config` or the repository name.
+    pub source: String,
+}
+
+/// bundles published inside the `content` of a bundles file.
+fn parse(content: &str) -> Result<BTreeMap<String, Vec<String>>, String> {
+    serde_yaml::from_str::<BundlesFile>(content)
+        .map(|file| file.bundles)
+        .map_err(|err| err.to_string())
+}
+
+/// All the bundles known by coffee, the ones of the config file win
+/// over the ones of the repositories.
+pub fn bundles(coffee: &CoffeeManager) -> BTreeMap<String, Bundle> {
+    let mut bundles = BTreeMap::new();
+    for (name, plugins) in &coffee.config.settings.bundles {
+        let bundle = Bundle {
+            name: name.clone(),
+            plugins: plugins.clone(),
+            source: "config".to_owned(),
+        };
+        bundles.insert(name.clone(), bundle);
+    }
+    for repo in &coffee.repos {
+        let path = format!("{}/{BUNDLES_FILE}", repo.url().path_string);
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let published = match parse(&content) {
+            Ok(published) => published,
+            Err(err) => {
+                warn!("bundles of {} not loaded: {err}", repo.name());
+                continue;
+            }
+        };
+        for (name, plugins) in published {
+            let bundle = Bundle {
+                name: name.clone(),
+                plugins,
+                source: repo.name(),
+            };
+            bundles.entry(name).or_insert(bundle);
+        }
+    }
+    bundles
+}
+
+/// The bundle with the `name`.
+fn find(coffee: &CoffeeManager, name: &str) -> Result<Bundle, CoffeeError> {
+    bundles(coffee)
+        .remove(name)
+        .ok_or_else(|| CoffeeError::new(1, &format!("bundle `{name}` not found")))
+}
+
+/// true when the `plugin` is installed.
+fn is_installed(coffee: &CoffeeManager, plugin: &str) -> bool {
+    coffee
+        .config
+        .plugins
+        .iter()
+        .any(|installed| installed.name() == plugin)
+}
+
+/// Print all the bundles with their plugins.
+pub fn list(coffee: &CoffeeManager) {
+    for bundle in bundles(coffee).values() {
+        let installed = if coffee.config.bundles.contains_key(&bundle.name) {
+            ", installed"
+        } else {
+            ""
+        };
+        println!(
+            "{} ({}{installed}): {}",
+            bundle.name,
+            bundle.source,
+            bundle.plugins.join(", ")
+        );
+    }
+}
+
+/// Install the plugins of the bundle that are not installed yet.
+pub async fn install(
+    coffee: &mut CoffeeManager,
+    name: &str,
+    verbose: bool,
+    dynamic: bool,
+) -> Result<(), CoffeeError> {
+    let bundle = find(coffee, name)?;
+    let plugins: Vec<String> = bundle
+        .plugins
+        .iter()
+        .map(|plugin| coffee.resolve_alias(plugin))
+        .collect();
+    let missing: Vec<&str> = plugins
+        .iter()
+        .filter(|plugin| !is_installed(coffee, plugin))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        println!("[{name}] all the plugins are already installed");
+    } else {
+        coffee.install(&missing, verbose, dynamic).await?;
+    }
+    coffee.config.bundles.insert(name.to_owned(), plugins);
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("[{name}] installed");
+    Ok(())
+}
+
+/// plugins of the installed bundle, or of the bundle definition when
+/// it is not installed.
+fn plugins_of(coffee: &CoffeeManager, name: &str) -> Result<Vec<String>, CoffeeError> {
+    match coffee.config.bundles.get(name) {
+        Some(plugins) => Ok(plugins.clone()),
+        None => Ok(find(coffee, name)?.plugins),
+    }
+}
+
+/// Upgrade the installed plugins of the bundle.
+pub async fn upgrade(coffee: &mut CoffeeManager, name: &str) -> Result<(), CoffeeError> {
+    let plugins = plugins_of(coffee, name)?;
+    let installed: Vec<&str> = plugins
+        .iter()
+        .filter(|plugin| is_installed(coffee, plugin))
+        .map(String::as_str)
+        .collect();
+    if installed.is_empty() {
+        let err = CoffeeError::new(1, &format!("bundle `{name}` has no installed plugins"));
+        return Err(err);
+    }
+    coffee.upgrade(&installed).await
+}
+
+/// Remove the plugins of the bundle, the plugins that are part of
+/// another installed bundle are kept.
+pub async fn remove(coffee: &mut CoffeeManager, name: &str) -> Result<(), CoffeeError> {
+    let plugins = plugins_of(coffee, name)?;
+    for plugin in &plugins {
+        let needed_by = coffee
+            .config
+            .bundles
+            .iter()
+            .find(|(other, members)| other.as_str() != name && members.contains(plugin));
+        if let Some((other, _)) = needed_by {
+            println!("[{plugin}] kept, it is part of the bundle `{other}`");
+            continue;
+        }
+        if is_installed(coffee, plugin) {
+            coffee.remove(plugin).await?;
+        }
+    }
+    coffee.config.bundles.remove(name);
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("[{name}] removed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn test_bundle_parse() {
+        let bundles =
+            parse("bundles:\n  routing-ops: [rebalance, summary, feeadjuster]\n").unwrap();
+        assert_eq!(
+            bundles.get("routing-ops").unwrap(),
+            &vec!["rebalance", "summary", "feeadjuster"]
+        );
+        assert!(parse("bundles: [summary]").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Canary of an upgrade, after the upgrade the plugins are watched for
+//! a while, and when one of them stops or the node logs an error the
+//! upgrade is rolled back to the previous version.
+use std::time::{Duration, Instant};
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt;
+use log::debug;
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use super::health::{check_health, PluginHealth};
+use super::CoffeeManager;
+
+/// Interval between two checks of the canary.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Parse a duration like `90`, `90s`, `15m`, `2h` or `1d`.
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let (value, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("duration `{duration}` must start with a number"))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("unit `{unit}` unknown, use s, m, h or d")),
+    };
+    let seconds = value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration `{duration}` too large"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// lines of the node log at the `broken` level.
+async fn broken_lines(coffee: &CoffeeManager) -> Vec<String> {
+    let Ok(logs) = coffee
+        .cln::<Value, Value>("getlog", json!({"level": "broken"}))
+        .await
+    else {
+        return vec![];
+    };
+    logs["log"]
+        .as_array()
+        .map(|lines| {
+            lines
+                .iter()
+                .filter(|line| line["type"] == "BROKEN")
+                .filter_map(|line| {
+                    Some(format!(
+                        "{}: {}",
+                        line["source"].as_str()?,
+                        line["log"].as_str()?
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Watch the upgraded `plugins` (all the installed ones when empty)
+/// for the `duration`, fail when one of them is not active anymore or
+/// when the node logs a new error.
+pub async fn watch(
+    coffee: &CoffeeManager,
+    plugins: &[String],
+    duration: Duration,
+) -> Result<(), CoffeeError> {
+    if !coffee.is_node_running() {
+        let err = CoffeeError::new(1, "the canary needs the node running");
+        return Err(err);
+    }
+    let names: Vec<String> = plugins
+        .iter()
+        .map(|plugin| coffee.resolve_alias(plugin))
+        .collect();
+    let watched: Vec<(String, String)> = coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| names.is_empty() || names.contains(&plugin.name()))
+        .filter(|plugin| !coffee.config.disabled.contains_key(&plugin.name()))
+        .filter(|plugin| !coffee.config.systemd_units.contains_key(&plugin.name()))
+        .filter_map(|plugin| Some((plugin.name(), plugin.exec_path()?)))
+        .collect();
+    let known = broken_lines(coffee).await.len();
+    println!("canary: watching the upgrade for {}s", duration.as_secs());
+    let start = Instant::now();
+    loop {
+        for (plugin, exec_path) in &watched {
+            let health = check_health(coffee, exec_path, false).await;
+            debug!("canary: {plugin} is {health}");
+            if health != PluginHealth::Active {
+                let err =
+                    CoffeeError::new(1, &format!("canary failed, plugin `{plugin}` is {health}"));
+                return Err(err);
+            }
+        }
+        if let Some(line) = broken_lines(coffee).await.get(known) {
+            let err = CoffeeError::new(1, &format!("canary failed, the node logged `{line}`"));
+            return Err(err);
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            break;
+        }
+        tokio::select! {
+            _ = sleep(CHECK_INTERVAL.min(duration - elapsed)) => {}
+            _ = interrupt::interrupted() => return Err(interrupt::interrupted_error()),
+        }
+    }
+    println!("canary: the upgrade is healthy");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_duration;
+
+    #[test]
+    fn test_canary_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("3w").is_err());
+        assert!(parse_duration("999999999999999d").is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+    }
+}
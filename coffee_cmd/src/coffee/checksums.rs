@@ -0,0 +1,183 @@
+//! Checksums of the plugins installed, every version of a plugin
+//! that coffee installs is appended with the hash of its content to
+//! the `checksums.log` file of the coffee root, one JSON object per line.
+//!
+//! The same version of a plugin must always come with the same
+//! content, a different hash means that the upstream rewrote the
+//! version (e.g. a tag moved), so coffee warns loudly.
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::utils::unix_time;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{read_to_string, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use super::status::format_age;
+use super::CoffeeManager;
+
+/// Entry of the checksums log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChecksumEntry {
+    /// unix time of the first install of the content.
+    pub time: u64,
+    pub plugin: String,
+    /// url of the repository where the plugin comes from.
+    pub repository: Option<String>,
+    /// version declared inside the manifest, if any.
+    pub version: Option<String>,
+    /// hash of the content of the plugin.
+    pub hash: String,
+}
+
+/// path of the checksums log of the coffee root.
+fn log_path(coffee: &CoffeeManager) -> String {
+    format!("{}/checksums.log", coffee.config.root_path)
+}
+
+/// Read all the entries of the checksums log, the lines that can
+/// not be parsed are skipped.
+pub async fn entries(coffee: &CoffeeManager) -> Result<Vec<ChecksumEntry>, CoffeeError> {
+    let path = log_path(coffee);
+    if !Path::new(&path).exists() {
+        return Ok(vec![]);
+    }
+    let content = read_to_string(&path).await?;
+    let entries = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                warn!("checksum entry `{line}` malformed: {err}");
+                None
+            }
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// The entry recorded for the same version of the plugin of `entry`
+/// with a different content, the plugins without a version are
+/// never checked.
+pub fn mismatch<'a>(
+    entries: &'a [ChecksumEntry],
+    entry: &ChecksumEntry,
+) -> Option<&'a ChecksumEntry> {
+    entry.version.as_ref()?;
+    entries.iter().find(|recorded| {
+        recorded.plugin == entry.plugin
+            && recorded.repository == entry.repository
+            && recorded.version == entry.version
+            && recorded.hash != entry.hash
+    })
+}
+
+/// Record the content of the `plugin` just installed, and warn when
+/// the same version was installed before with a different content.
+/// A checksum that can not be recorded does not fail the install.
+pub async fn record(coffee: &CoffeeManager, plugin: &Plugin) {
+    if let Err(err) = check_and_append(coffee, plugin).await {
+        warn!("checksum of `{}` not recorded: {err}", plugin.name());
+    }
+}
+
+async fn check_and_append(coffee: &CoffeeManager, plugin: &Plugin) -> Result<(), CoffeeError> {
+    // e.g. a plugin linked from a working directory.
+    let Some(hash) = plugin.content_hash() else {
+        return Ok(());
+    };
+    let entry = ChecksumEntry {
+        time: unix_time(),
+        plugin: plugin.name(),
+        repository: coffee.repo_of(plugin).map(|repo| repo.url().url_string),
+        version: plugin.conf().map(|conf| conf.plugin.version),
+        hash,
+    };
+    let entries = entries(coffee).await?;
+    if let Some(recorded) = mismatch(&entries, &entry) {
+        let message = format!(
+            "[{}] WARNING: version {} was installed {} with the content {}, now it has the content {}, the upstream may have rewritten the version",
+            entry.plugin,
+            entry.version.as_deref().unwrap_or_default(),
+            format_age(recorded.time),
+            recorded.hash,
+            entry.hash
+        );
+        eprintln!("{message}");
+        warn!("{message}");
+    }
+    let known = entries.iter().any(|recorded| {
+        recorded.plugin == entry.plugin
+            && recorded.repository == entry.repository
+            && recorded.version == entry.version
+            && recorded.hash == entry.hash
+    });
+    if known {
+        return Ok(());
+    }
+    let mut line = serde_json::to_string(&entry)
+        .map_err(|err| CoffeeError::new(1, &format!("checksum entry not serialized: {err}")))?;
+    line.push('\n');
+    debug!("checksum entry {line}");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path(coffee))
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Print the checksums recorded for the `plugin`, or for all the
+/// plugins, the oldest first.
+pub async fn list(coffee: &CoffeeManager, plugin: Option<String>) -> Result<(), CoffeeError> {
+    let plugin = plugin.map(|plugin| coffee.resolve_alias(&plugin));
+    let entries: Vec<ChecksumEntry> = entries(coffee)
+        .await?
+        .into_iter()
+        .filter(|entry| plugin.is_none() || plugin.as_deref() == Some(entry.plugin.as_str()))
+        .collect();
+    if entries.is_empty() {
+        println!("no checksums recorded");
+    }
+    for entry in &entries {
+        let warning = if mismatch(&entries, entry).is_some() {
+            "  (content changed for the same version)"
+        } else {
+            ""
+        };
+        println!(
+            "{:>8}  {:<20} {:<10} {}{warning}",
+            format_age(entry.time),
+            entry.plugin,
+            entry.version.as_deref().unwrap_or("-"),
+            entry.hash
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mismatch, ChecksumEntry};
+
+    #[test]
+    fn test_checksums_mismatch() {
+        let entry = |version: Option<&str>, hash: &str| ChecksumEntry {
+            time: 0,
+            plugin: "summary".to_owned(),
+            repository: Some("https://github.com/lightningd/plugins".to_owned()),
+            version: version.map(str::to_owned),
+            hash: hash.to_owned(),
+        };
+        let entries = vec![entry(Some("0.1.0"), "aaaa"), entry(None, "bbbb")];
+        assert!(mismatch(&entries, &entry(Some("0.1.0"), "aaaa")).is_none());
+        assert!(mismatch(&entries, &entry(Some("0.2.0"), "cccc")).is_none());
+        assert!(mismatch(&entries, &entry(None, "cccc")).is_none());
+        let changed = mismatch(&entries, &entry(Some("0.1.0"), "cccc"));
+        assert_eq!(changed.unwrap().hash, "aaaa");
+    }
+}
@@ -1,5 +1,10 @@
 //! Coffee command line arguments definition.
-use clap::{Parser, Subcommand};
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use coffee_lib::repository::Layout;
+
+use super::selector::Selector;
 
 /// Coffee main command line definition for the command line tools.
 #[derive(Debug, Parser)]
@@ -14,46 +19,621 @@ pub struct CoffeeArgs {
     pub network: Option<String>,
     #[clap(short, long, value_parser, name = "data-dir")]
     pub data_dir: Option<String>,
+    /// Answer yes to all the confirmation prompts.
+    #[clap(short, long, action = clap::ArgAction::SetTrue, conflicts_with = "assume_no")]
+    pub yes: bool,
+    /// Answer no to all the confirmation prompts.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub assume_no: bool,
+    /// Never reach the network.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub offline: bool,
+    /// Download again the content also when it is cached.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub no_cache: bool,
+    /// Plain ASCII output, one line per message, without colors
+    /// and progress bars.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub plain: bool,
+    /// Allow the plugins and the aliases with the name of a
+    /// plugin of core lightning.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub allow_reserved: bool,
+    /// Print the values marked as secret in clear.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub reveal_secrets: bool,
+    /// Stop and restart the payment critical plugins also when the
+    /// node has HTLCs in flight.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub force_restart: bool,
+    /// Stop the operations on more items at the first failure.
+    #[clap(long, action = clap::ArgAction::SetTrue, conflicts_with = "keep_going")]
+    pub fail_fast: bool,
+    /// Continue the operations on more items after a failure, and
+    /// report all the failures at the end.
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    pub keep_going: bool,
+    /// Profile of the node managed by the command, each profile
+    /// has its own plugins and core lightning configuration.
+    #[clap(long, value_parser)]
+    pub profile: Option<String>,
 }
 
 /// Coffee subcommand of the command line daemon.
 #[derive(Debug, Subcommand)]
 pub enum CoffeeCommand {
-    /// Install a single by name.
+    /// Install one or more plugins by name.
     #[clap(arg_required_else_help = true)]
     Install {
-        plugin: String,
+        #[arg(required = true)]
+        plugins: Vec<String>,
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         verbose: bool,
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         dynamic: bool,
+        /// Undo the install when the plugin is not healthy.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        rollback_on_failure: bool,
+        /// Undo the install of all the plugins when one fails.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        atomic: bool,
+        /// Tag, branch or commit of the repository where the plugins
+        /// are pinned, they are not upgraded with the repository.
+        #[arg(long)]
+        at: Option<String>,
+    },
+    /// upgrade a single or a list of plugins (also glob patterns,
+    /// e.g. `cln-*`), all the installed plugins when no one is given.
+    Upgrade {
+        plugins: Vec<String>,
+        #[command(flatten)]
+        selector: Selector,
+        /// Save the local modifications of the sources as a
+        /// patch instead of refusing the upgrade.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        stash_local: bool,
+        /// Apply again the saved local modifications after the upgrade.
+        #[arg(long, action = clap::ArgAction::SetTrue, requires = "stash_local")]
+        reapply: bool,
+        /// Undo the upgrade of all the plugins when one fails.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        atomic: bool,
+        /// Watch the upgraded plugins for the duration (e.g. `15m`),
+        /// and roll back the upgrade when they are not healthy.
+        #[arg(long, value_parser = crate::coffee::canary::parse_duration)]
+        canary: Option<Duration>,
     },
-    /// upgrade a single or a list of plugins.
-    #[clap(arg_required_else_help = true)]
-    Upgrade,
     /// Print the list of plugins installed in cln.
     #[clap(arg_required_else_help = false)]
     List {
         #[arg(short, long, action = clap::ArgAction::SetTrue)]
         remotes: bool,
+        #[command(flatten)]
+        selector: Selector,
     },
-    /// Remove a plugin installed in cln.
+    /// Print what coffee knows about a plugin, from the local index.
     #[clap(arg_required_else_help = true)]
-    Remove,
+    Show { plugin: String },
+    /// Remove one or more plugins installed in cln, by name,
+    /// glob pattern or selector.
+    #[clap(arg_required_else_help = true)]
+    Remove {
+        #[arg(required_unless_present_any = ["lang", "repo", "outdated"])]
+        plugins: Vec<String>,
+        #[command(flatten)]
+        selector: Selector,
+    },
+    /// Install, upgrade and remove a named group of plugins as a unit.
+    #[clap(arg_required_else_help = true)]
+    Bundle {
+        #[clap(subcommand)]
+        action: BundleAction,
+    },
+    /// Remove the plugins installed as dependencies that no other
+    /// plugin needs anymore.
+    Autoremove,
+    /// Keep the plugins installed, but do not start them with the node.
+    #[clap(arg_required_else_help = true)]
+    Disable {
+        #[arg(required_unless_present_any = ["lang", "repo", "outdated"])]
+        plugins: Vec<String>,
+        #[command(flatten)]
+        selector: Selector,
+    },
+    /// Start again the disabled plugins with the node.
+    #[clap(arg_required_else_help = true)]
+    Enable {
+        #[arg(required_unless_present_any = ["lang", "repo", "outdated"])]
+        plugins: Vec<String>,
+        #[command(flatten)]
+        selector: Selector,
+    },
     /// Manage Repository subcommand
     #[clap(arg_required_else_help = true)]
     Remote {
         #[clap(subcommand)]
         action: RemoteAction,
     },
-    /// Configur coffee with the core lightning
-    /// configuration
+    /// Configure coffee with the core lightning
+    /// configuration, the core lightning directory
+    /// is detected when missing.
+    Setup { cln_conf: Option<String> },
+    /// Print the core lightning log lines emitted by a plugin.
+    #[clap(arg_required_else_help = true)]
+    Logs {
+        plugin: String,
+        /// Keep printing the new log lines.
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        follow: bool,
+    },
+    /// Adopt the plugins and the sources installed with the
+    /// python reckless plugin manager.
+    MigrateFromPython {
+        /// Path of the reckless directory, by default
+        /// `<lightning-dir>/reckless`.
+        #[arg(long)]
+        reckless_dir: Option<String>,
+    },
+    /// Print an overview of coffee and of the node.
+    Status,
+    /// Fetch the upstreams of the repositories and check the
+    /// installed plugins, `status` reports the last run.
+    Nurse,
+    /// Print the cpu and the memory used by the plugins running
+    /// on the node.
+    Top,
+    /// Explain why a plugin is installed, and what keeps it installed.
+    #[clap(arg_required_else_help = true)]
+    Why { plugin: String },
+    /// Print the dependency graph of the installed plugins, with
+    /// their toolchain and the node features they need.
+    Graph {
+        /// Print the graph in the Graphviz format.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        dot: bool,
+    },
+    /// Check if a new version of coffee is available
+    /// and install it.
+    SelfUpdate {
+        /// Only check if a new version is available.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        check: bool,
+    },
+    /// Install a plugin from a fork of its repository, while
+    /// the other plugins keep coming from the upstream.
+    #[clap(arg_required_else_help = true)]
+    Override {
+        plugin: String,
+        /// Url of the fork.
+        #[arg(long, required_unless_present = "reset")]
+        url: Option<String>,
+        /// Branch of the fork.
+        #[arg(long, conflicts_with = "reset")]
+        branch: Option<String>,
+        /// Install the plugin from the upstream again.
+        #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "url")]
+        reset: bool,
+    },
+    /// Search the plugins available inside the repositories.
+    Search {
+        /// Part of the plugin name.
+        query: Option<String>,
+        /// Only the plugins with the tag.
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only the plugins that provide the RPC method, the hook
+        /// or the notification, e.g. `htlc_accepted`.
+        #[arg(long)]
+        provides: Option<String>,
+        /// Order of the plugins, the forge signals are asked
+        /// to the GitHub API.
+        #[arg(long, value_enum, default_value_t = SortBy::Name)]
+        sort: SortBy,
+        /// Print the tags of the plugins instead.
+        #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["query", "tag", "provides"])]
+        tags: bool,
+        /// Search only inside the local index, coffee is opened
+        /// read-only.
+        #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "sort")]
+        cached: bool,
+    },
+    /// Print the README of a plugin.
+    #[clap(arg_required_else_help = true)]
+    Readme { plugin: String },
+    /// Print and open the homepage of a plugin.
+    #[clap(arg_required_else_help = true)]
+    Open {
+        plugin: String,
+        /// Open the issue tracker instead.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        issues: bool,
+    },
+    /// Manage the alternative names of the plugins.
+    #[clap(arg_required_else_help = true)]
+    Alias {
+        #[clap(subcommand)]
+        action: AliasAction,
+    },
+    /// Manage the local patches applied on top of the
+    /// sources of a plugin.
+    #[clap(arg_required_else_help = true)]
+    Patch {
+        #[clap(subcommand)]
+        action: PatchAction,
+    },
+    /// Store the coffee state inside a tarball, the repositories
+    /// and the plugins builds are not part of it.
+    #[clap(arg_required_else_help = true)]
+    Backup { tarball: String },
+    /// Restore the coffee state from a tarball, and rebuild
+    /// the repositories and the plugins.
+    #[clap(arg_required_else_help = true)]
+    Restore { tarball: String },
+    /// Print the systemd unit of a plugin that runs as a daemon
+    /// companion of the node, or install and enable it.
+    #[clap(arg_required_else_help = true)]
+    Systemd {
+        plugin: String,
+        /// Install and enable the unit, the daemon is started by
+        /// systemd and not by core lightning.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        install: bool,
+        /// Use the systemd instance of the user.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        user: bool,
+        /// Disable and remove the unit.
+        #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["install", "user"])]
+        remove: bool,
+    },
+    /// Browse the repositories and manage the plugins
+    /// with an interactive terminal interface.
+    Tui,
+    /// Print a deployment descriptor that builds the installed
+    /// plugins somewhere else.
+    #[clap(group(clap::ArgGroup::new("format").required(true)))]
+    Export {
+        /// Compose service of core lightning with the plugins
+        /// baked inside the image.
+        #[arg(long, group = "format", action = clap::ArgAction::SetTrue)]
+        docker_compose: bool,
+        /// Dockerfile that bakes the plugins inside the image
+        /// of core lightning.
+        #[arg(long, group = "format", action = clap::ArgAction::SetTrue)]
+        dockerfile: bool,
+        /// Nix expression with a derivation for each plugin, pinned
+        /// to the commit of its repository.
+        #[arg(long, group = "format", action = clap::ArgAction::SetTrue)]
+        nix: bool,
+        /// Image of core lightning used as base.
+        #[arg(long, conflicts_with = "nix", default_value = crate::coffee::export::DEFAULT_IMAGE)]
+        image: String,
+    },
+    /// Converge coffee to the repositories, the plugins and the
+    /// aliases declared inside a state file.
+    #[clap(arg_required_else_help = true)]
+    Apply {
+        state: String,
+        /// Remove the plugins, the repositories and the aliases
+        /// that are not declared.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        prune: bool,
+        /// Undo all the changes when one of them fails.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        atomic: bool,
+    },
+    /// Run the operations of a script (YAML or JSON) one after the
+    /// other, all of them are rolled back when one fails.
+    #[clap(arg_required_else_help = true)]
+    Run { script: String },
+    /// Copy the plugins and the configuration include on the
+    /// host of the node configured with `ssh`.
+    Sync,
+    /// Serve the operations of coffee with a local HTTP API.
+    Serve {
+        /// Address where the API listens.
+        #[arg(long, default_value = crate::coffee::serve::DEFAULT_BIND)]
+        bind: String,
+    },
+    /// Watch the upstream repositories and the running plugins,
+    /// and send the notifications configured inside `config.yml`.
+    Daemon {
+        /// Seconds between two checks.
+        #[arg(long, default_value = crate::coffee::daemon::DEFAULT_INTERVAL)]
+        interval: u64,
+        /// Address where the Prometheus metrics are served, e.g.
+        /// `127.0.0.1:9475`.
+        #[arg(long)]
+        metrics: Option<String>,
+    },
+    /// Print the changes made to the coffee state.
+    History {
+        #[clap(subcommand)]
+        action: Option<HistoryAction>,
+    },
+    /// Undo the most recent change of the coffee state that
+    /// is not undone yet.
+    Undo,
+    /// Print the content hashes of the plugin versions installed.
+    Checksums { plugin: Option<String> },
+    /// Print the log of the last failed build of a plugin.
+    Buildlog { plugin: String },
+    /// Manage the runtime options of a plugin, the options are
+    /// checked against the ones declared by the plugin.
+    #[clap(arg_required_else_help = true)]
+    Option {
+        #[clap(subcommand)]
+        action: OptionAction,
+    },
+    /// Manage the environment variables of a plugin (e.g. API keys),
+    /// stored encrypted and given to the plugin when it starts.
+    #[clap(arg_required_else_help = true)]
+    Env {
+        plugin: String,
+        #[clap(subcommand)]
+        action: EnvAction,
+    },
+    /// Try a plugin on a throwaway regtest node before the install,
+    /// the node of the user is never touched.
+    #[clap(arg_required_else_help = true)]
+    Try {
+        plugin: String,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        verbose: bool,
+    },
+    /// Run the indexer of coffee against a local checkout of a
+    /// repository and print what it detects as JSON.
+    #[clap(arg_required_else_help = true, args_conflicts_with_subcommands = true)]
+    Index {
+        /// Path of the repository checkout to check.
+        #[arg(long)]
+        check: Option<String>,
+        #[clap(subcommand)]
+        action: Option<IndexAction>,
+    },
+    /// Develop a plugin from a local working directory.
+    #[clap(arg_required_else_help = true)]
+    Dev {
+        #[clap(subcommand)]
+        action: DevAction,
+    },
+    /// Manage the profiles of the nodes.
     #[clap(arg_required_else_help = true)]
-    Setup { cln_conf: String },
+    Profile {
+        #[clap(subcommand)]
+        action: ProfileAction,
+    },
+}
+
+impl CoffeeCommand {
+    /// true for the inspection commands, they work from the local
+    /// index alone, so coffee is opened read-only for them.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            CoffeeCommand::List { .. }
+                | CoffeeCommand::Show { .. }
+                | CoffeeCommand::Status
+                | CoffeeCommand::Top
+                | CoffeeCommand::Graph { .. }
+                | CoffeeCommand::Remote {
+                    action: RemoteAction::Browse { .. }
+                }
+                | CoffeeCommand::Why { .. }
+                | CoffeeCommand::Bundle {
+                    action: BundleAction::List
+                }
+                | CoffeeCommand::Checksums { .. }
+                | CoffeeCommand::Buildlog { .. }
+                | CoffeeCommand::Search { cached: true, .. }
+        )
+    }
+
+    /// true for the commands that run until they are stopped, they
+    /// do not hold the lock of the state.
+    pub fn is_long_running(&self) -> bool {
+        matches!(
+            self,
+            CoffeeCommand::Serve { .. } | CoffeeCommand::Daemon { .. }
+        )
+    }
 }
 
 #[derive(Debug, Subcommand)]
 pub enum RemoteAction {
-    Add { name: String, url: String },
+    /// Add a repository, with only the url its name is derived
+    /// from the owner and the name of the repository.
+    Add {
+        #[arg(required_unless_present = "defaults")]
+        name: Option<String>,
+        url: Option<String>,
+        /// Add the well-known repositories shipped with coffee.
+        #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["name", "url"])]
+        defaults: bool,
+        /// Organization of the plugins inside the repository:
+        /// flat, by-language or archived-excluded.
+        #[arg(long, conflicts_with = "defaults")]
+        layout: Option<Layout>,
+    },
+    Remove {
+        name: String,
+    },
+    /// Index again the repository with another organization
+    /// of the plugins.
+    Layout {
+        name: String,
+        layout: Layout,
+    },
+    /// Check that the checkout of the repository matches
+    /// the upstream and that it has no local modifications.
+    Verify {
+        name: String,
+    },
+    /// Print the plugins of the repository at a tag, a branch or a
+    /// commit, without touching its checkout.
+    Browse {
+        name: String,
+        /// Tag, branch or commit to index.
+        #[arg(long)]
+        at: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PatchAction {
+    /// Register a patch applied after every install and upgrade
+    /// of the plugin, the paths are relative to the repository root.
+    Add { plugin: String, patch: String },
+    /// Remove a patch registered for the plugin.
+    Remove { plugin: String, patch: String },
+    /// Print the patches registered for a plugin or for all plugins.
+    List { plugin: Option<String> },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryAction {
+    /// Undo the change with the id, when it is possible.
+    Revert { id: u64 },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OptionAction {
+    /// Set the option of the plugin.
+    Set {
+        plugin: String,
+        key: String,
+        value: String,
+        /// Redact the value from the output and the logs.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        secret: bool,
+    },
+    /// Remove the option of the plugin.
+    Remove { plugin: String, key: String },
+    /// Print the options set for the plugin.
+    List {
+        plugin: String,
+        /// Print the options declared by the plugin instead.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        declared: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BundleAction {
+    /// Print the bundles of the config file and of the repositories.
+    List,
+    /// Install the plugins of the bundle.
+    Install {
+        name: String,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        verbose: bool,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        dynamic: bool,
+        /// Undo the install of all the plugins when one fails.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        atomic: bool,
+    },
+    /// Upgrade the installed plugins of the bundle.
+    Upgrade {
+        name: String,
+        /// Undo the upgrade of all the plugins when one fails.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        atomic: bool,
+    },
+    /// Remove the plugins of the bundle, except the ones of
+    /// another installed bundle.
     Remove { name: String },
 }
+
+#[derive(Debug, Subcommand)]
+pub enum EnvAction {
+    /// Set the variable of the plugin, as `KEY=VALUE`.
+    Set {
+        assignment: String,
+        /// Redact the value from the output and the logs.
+        #[arg(long, action = clap::ArgAction::SetTrue)]
+        secret: bool,
+    },
+    /// Remove the variable of the plugin.
+    Unset { key: String },
+    /// Print the variables of the plugin.
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileAction {
+    /// Print the profiles with their network and core lightning directory.
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IndexAction {
+    /// Export the versioned JSON index of a repository checkout,
+    /// to publish it as a static index.
+    Export {
+        path: String,
+        /// Url where the repository is published.
+        #[arg(long)]
+        url: Option<String>,
+        /// File where the index is written, by default it is printed.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Ssh key that signs the index, the signature is written
+        /// next to the index.
+        #[arg(long, requires = "output")]
+        key: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DevAction {
+    /// Build the plugin of the working directory and restart it
+    /// on the node.
+    Reload {
+        path: String,
+        /// Build and restart the plugin again on every change of
+        /// the sources.
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        watch: bool,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        verbose: bool,
+    },
+    /// Install the plugin of the working directory in editable mode,
+    /// the node runs it straight from the working directory.
+    Link {
+        path: String,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        verbose: bool,
+    },
+    /// Run the test command declared inside the manifest of the plugin
+    /// against a regtest node, the plugin is an installed one or the
+    /// path of a working directory.
+    Test {
+        plugin: String,
+        /// Lightning directory of a regtest node already running,
+        /// by default coffee starts a new one.
+        #[arg(long)]
+        attach: Option<String>,
+        #[arg(short, long, action = clap::ArgAction::SetTrue)]
+        verbose: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AliasAction {
+    /// Make the alias an alternative name of the plugin.
+    Set { alias: String, plugin: String },
+    /// Remove an alias.
+    Remove { alias: String },
+    /// Print all the aliases.
+    List,
+}
+
+/// Order of the plugins found by a search.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SortBy {
+    Name,
+    /// the most starred repositories first.
+    Stars,
+    /// the plugins with the most recent commit first.
+    Maintained,
+}
@@ -0,0 +1,163 @@
+//! Aliases of the commands, defined by the user inside the `config.yml`
+//! (e.g. `up: upgrade --yes`) and expanded like the aliases of git,
+//! before the command line is parsed.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use clap::CommandFactory;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::home_dir;
+use log::warn;
+use tokio::fs::read_to_string;
+
+use super::cmd::CoffeeArgs;
+use super::config::{Settings, PROFILES_DIR};
+
+/// true when the global option `arg` is followed by its value.
+fn takes_value(arg: &str) -> bool {
+    CoffeeArgs::command().get_arguments().any(|global| {
+        let long = global.get_long().map(|long| format!("--{long}"));
+        let short = global.get_short().map(|short| format!("-{short}"));
+        (long.as_deref() == Some(arg) || short.as_deref() == Some(arg))
+            && global.get_action().takes_values()
+    })
+}
+
+/// the position of the subcommand inside the `args`, after the
+/// global options.
+fn subcommand_position(args: &[String]) -> Option<usize> {
+    let mut index = 1;
+    while index < args.len() {
+        let arg = &args[index];
+        if arg == "--" {
+            return None;
+        }
+        if !arg.starts_with('-') {
+            return Some(index);
+        }
+        index += if !arg.contains('=') && takes_value(arg) {
+            2
+        } else {
+            1
+        };
+    }
+    None
+}
+
+/// the value of the global option `id` (e.g. `profile`), if any.
+fn global_value(args: &[String], id: &str) -> Option<String> {
+    let command = CoffeeArgs::command();
+    let global = command
+        .get_arguments()
+        .find(|global| global.get_id() == id)?;
+    let long = global.get_long().map(|long| format!("--{long}"));
+    let short = global.get_short().map(|short| format!("-{short}"));
+    let end = subcommand_position(args).unwrap_or(args.len());
+    let mut args = args[1..end].iter();
+    while let Some(arg) = args.next() {
+        if let Some((flag, value)) = arg.split_once('=') {
+            if long.as_deref() == Some(flag) {
+                return Some(value.to_owned());
+            }
+        } else if long.as_deref() == Some(arg) || short.as_deref() == Some(arg) {
+            return args.next().cloned();
+        }
+    }
+    None
+}
+
+/// the aliases inside the `config.yml` of the coffee home, or
+/// of the profile of the `args`.
+async fn load(args: &[String]) -> BTreeMap<String, String> {
+    let home = global_value(args, "data-dir")
+        .or_else(home_dir)
+        .unwrap_or_default();
+    let home = format!("{home}/.coffee");
+    let mut path = format!("{home}/config.yml");
+    if let Some(profile) = global_value(args, "profile") {
+        let profile_path = format!("{home}/{PROFILES_DIR}/{profile}/config.yml");
+        if Path::new(&profile_path).exists() {
+            path = profile_path;
+        }
+    }
+    let Ok(content) = read_to_string(&path).await else {
+        return BTreeMap::new();
+    };
+    // a malformed config is reported when coffee loads it.
+    match serde_yaml::from_str::<Settings>(&content) {
+        Ok(settings) => settings.command_aliases,
+        Err(err) => {
+            warn!("command aliases of {path} not loaded: {err}");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Expand the alias used as subcommand of the `args`, the commands
+/// of coffee win over the aliases with the same name.
+fn expand_with(
+    mut args: Vec<String>,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<String>, CoffeeError> {
+    let command = CoffeeArgs::command();
+    let mut expanded: Vec<String> = vec![];
+    while let Some(position) = subcommand_position(&args) {
+        let name = args[position].clone();
+        if command.find_subcommand(&name).is_some() {
+            break;
+        }
+        let Some(alias) = aliases.get(&name) else {
+            break;
+        };
+        if expanded.contains(&name) {
+            let err = CoffeeError::new(
+                1,
+                &format!(
+                    "alias `{name}` expands to itself ({})",
+                    expanded.join(" -> ")
+                ),
+            );
+            return Err(err);
+        }
+        expanded.push(name);
+        args.splice(
+            position..=position,
+            alias.split_whitespace().map(str::to_owned),
+        );
+    }
+    Ok(args)
+}
+
+/// Expand the command aliases of the user inside the `args`.
+pub async fn expand(args: Vec<String>) -> Result<Vec<String>, CoffeeError> {
+    let aliases = load(&args).await;
+    expand_with(args, &aliases)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::expand_with;
+
+    #[test]
+    fn test_command_alias_expand() {
+        let aliases = BTreeMap::from([
+            ("up".to_owned(), "upgrade --stash-local".to_owned()),
+            ("u".to_owned(), "up".to_owned()),
+            ("install".to_owned(), "remove".to_owned()),
+            ("loop".to_owned(), "loop".to_owned()),
+        ]);
+        let args = |args: &str| args.split(' ').map(str::to_owned).collect::<Vec<_>>();
+        assert_eq!(
+            expand_with(args("coffee --network testnet u summary"), &aliases).unwrap(),
+            args("coffee --network testnet upgrade --stash-local summary")
+        );
+        // the commands of coffee can not be overridden.
+        assert_eq!(
+            expand_with(args("coffee install summary"), &aliases).unwrap(),
+            args("coffee install summary")
+        );
+        assert!(expand_with(args("coffee loop"), &aliases).is_err());
+    }
+}
@@ -1,12 +1,221 @@
 //! Coffee configuration utils.
 
+use coffee_lib::cargo::CargoConf;
+use coffee_lib::container::ContainerConf;
+use coffee_lib::download::DownloadLimits;
+use coffee_lib::go::GoConf;
+use coffee_lib::i18n::{locale, tr};
+use coffee_lib::node::NodeConf;
+use coffee_lib::python::PythonConf;
+use coffee_lib::repository::Layout;
+use coffee_lib::run_as::RunAs;
+use coffee_lib::sandbox::{Limits, SandboxPolicy};
 use coffee_lib::{errors::CoffeeError, plugin::Plugin};
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, IsTerminal, Write};
 use std::{env, path::Path};
-use tokio::fs::create_dir;
+use tokio::fs::{create_dir, create_dir_all, read_to_string};
 
 use super::cmd::CoffeeArgs;
+use super::crash_loop::CrashLoopPolicy;
+use super::maintenance::MaintenanceConf;
+use super::notify::NotificationTarget;
+use super::ssh::SshHost;
+use super::worktree::Worktree;
+
+/// Well-known plugin repositories shipped with coffee.
+const DEFAULT_REPOSITORIES: &str = include_str!("repositories.yml");
+
+/// Repository that is part of the coffee defaults.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DefaultRepository {
+    pub name: String,
+    pub url: String,
+    /// organization of the plugins inside the repository.
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+/// Return the list of well-known repositories shipped with coffee.
+pub fn default_repositories() -> Result<Vec<DefaultRepository>, CoffeeError> {
+    serde_yaml::from_str(DEFAULT_REPOSITORIES)
+        .map_err(|err| CoffeeError::new(1, &format!("default repositories malformed: {err}")))
+}
+
+/// Policy used by coffee when an operation needs the
+/// confirmation of the user.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmPolicy {
+    /// ask to the user with an interactive prompt.
+    #[default]
+    Ask,
+    /// assume yes to all the questions.
+    Yes,
+    /// assume no to all the questions.
+    No,
+}
+
+impl ConfirmPolicy {
+    /// ask the confirmation to the user, following the policy.
+    ///
+    /// When coffee is not attached to a terminal the answer
+    /// is always no, to avoid to hang an automation script.
+    pub fn confirm(&self, question: &str) -> Result<bool, CoffeeError> {
+        match self {
+            ConfirmPolicy::Yes => Ok(true),
+            ConfirmPolicy::No => Ok(false),
+            ConfirmPolicy::Ask => {
+                let args = [("question", question)];
+                if !io::stdin().is_terminal() {
+                    info!("{}", tr("confirm-no-terminal", &args));
+                    return Ok(false);
+                }
+                print!("{}", tr("confirm-prompt", &args));
+                io::stdout().flush()?;
+                let mut answer = String::new();
+                io::stdin().read_line(&mut answer)?;
+                let answer = answer.trim().to_lowercase();
+                Ok(locale().yes().contains(&answer.as_str()))
+            }
+        }
+    }
+}
+
+/// What the operations on more items (e.g. the install of more
+/// plugins) do when one of the items fails.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailurePolicy {
+    /// stop at the first failure.
+    FailFast,
+    /// continue with the other items, and report all the failures.
+    KeepGoing,
+}
+
+/// Coffee settings that the user can write inside the
+/// configuration file `config.yml` in the coffee root path.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// policy used before destructive operations and
+    /// before running the install script of a manifest.
+    pub confirm: ConfirmPolicy,
+    /// policy used before running an install script not trusted
+    /// yet, `confirm` when it is not set. It is chosen by each
+    /// request of `coffee serve`, and never stored.
+    #[serde(skip)]
+    pub scripts: Option<ConfirmPolicy>,
+    /// coffee never reach the network when this is true.
+    pub offline: bool,
+    /// undo the install of a plugin that is not healthy.
+    pub rollback_on_failure: bool,
+    /// roll back all the steps of an operation when one
+    /// of them fails.
+    pub atomic: bool,
+    /// save the local modifications of a repository as a
+    /// patch before the upgrade, instead of refusing it.
+    pub stash_local: bool,
+    /// apply again the saved local modifications after the upgrade.
+    pub reapply_local: bool,
+    /// personal access token used with the GitHub API.
+    pub github_token: Option<String>,
+    /// download again the content also when it is cached.
+    pub no_cache: bool,
+    /// isolation of the commands that build the plugins.
+    pub sandbox: SandboxPolicy,
+    /// environment variables that the build commands receive
+    /// in addition to the defaults.
+    pub sandbox_env: Vec<String>,
+    /// resources that the build commands can use.
+    pub build_limits: Limits,
+    /// unix user that runs the plugins, when missing the plugins
+    /// run as the node user.
+    pub run_as: Option<RunAs>,
+    /// build of the plugins inside a container.
+    pub container_build: ContainerConf,
+    /// host of the node, when it does not run on this machine.
+    pub ssh: Option<SshHost>,
+    /// token of the HTTP API, when missing it is generated
+    /// inside the coffee root.
+    pub api_token: Option<String>,
+    /// targets of the notifications of the plugins lifecycle.
+    pub notifications: Vec<NotificationTarget>,
+    /// directory where the repositories are cloned and the plugins
+    /// are built, when it is shared by more profiles (e.g. mainnet
+    /// and testnet) the plugins are built once for all of them.
+    pub install_root: Option<String>,
+    /// concurrency and bandwidth of the clones and of the downloads.
+    pub downloads: DownloadLimits,
+    /// install options of the python plugins.
+    pub python: PythonConf,
+    /// node runtime of the javascript plugins.
+    pub node: NodeConf,
+    /// module proxy and flags of the go plugins.
+    pub go: GoConf,
+    /// cargo options of the rust plugins.
+    pub cargo: CargoConf,
+    /// locale of the messages (e.g. `it`), when missing it comes
+    /// from the environment.
+    pub locale: Option<String>,
+    /// line-oriented ASCII output, without colors, progress bars
+    /// and terminal interfaces, for the screen readers and the logs.
+    pub plain: bool,
+    /// policy of the operations on more items when one fails, when
+    /// missing each operation uses its default.
+    pub on_failure: Option<FailurePolicy>,
+    /// allow the plugins and the aliases with the name of a plugin
+    /// of core lightning, e.g. `pay`.
+    pub allow_reserved: bool,
+    /// print the values marked as secret in clear, they are
+    /// redacted otherwise.
+    pub reveal_secrets: bool,
+    /// stop and restart the payment critical plugins also when the
+    /// node has HTLCs in flight.
+    pub force_restart: bool,
+    /// when the daemon considers a plugin in a crash loop, and
+    /// what it does with it.
+    pub crash_loop: CrashLoopPolicy,
+    /// automatic upgrades of the daemon, and when they can run.
+    pub maintenance: MaintenanceConf,
+    /// bundles of plugins, e.g. `routing-ops: [rebalance, summary]`,
+    /// they win over the bundles of the repositories.
+    pub bundles: BTreeMap<String, Vec<String>>,
+    /// aliases of the commands, e.g. `up: upgrade --yes`.
+    pub command_aliases: BTreeMap<String, String>,
+}
+
+/// Source of a plugin chosen by the user, e.g. a fork.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PluginOverride {
+    /// name of the repository that contains the fork.
+    pub repository: String,
+    pub url: String,
+    pub branch: Option<String>,
+}
+
+/// Systemd unit installed for a plugin.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SystemdUnit {
+    pub path: String,
+    /// the unit belongs to the systemd instance of the user.
+    pub user: bool,
+}
+
+/// Trust decision of the user about a repository.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrustedRepository {
+    /// commit checked out when the repository was trusted, if any.
+    pub commit: Option<String>,
+    /// unix time of the decision.
+    pub time: u64,
+    /// fingerprints of the install scripts trusted, indexed
+    /// by plugin name.
+    #[serde(default)]
+    pub scripts: BTreeMap<String, String>,
+}
 
 /// Custom coffee configuration, given by a command line list of arguments
 /// or a coffee configuration file.
@@ -29,6 +238,89 @@ pub struct CoffeeConf {
     /// all plugins that are installed
     /// with the plugin manager.
     pub plugins: Vec<Plugin>,
+    /// local patches applied on top of the plugin sources,
+    /// indexed by plugin name.
+    #[serde(default)]
+    pub patches: BTreeMap<String, Vec<String>>,
+    /// plugins installed from a repository that is not the
+    /// one where they are published, indexed by plugin name.
+    #[serde(default)]
+    pub overrides: BTreeMap<String, PluginOverride>,
+    /// alternative names of the plugins, from the alias
+    /// to the name of the plugin.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// systemd units installed for the plugins that run as
+    /// daemons, indexed by plugin name.
+    #[serde(default)]
+    pub systemd_units: BTreeMap<String, SystemdUnit>,
+    /// plugins that failed to install since coffee was set up.
+    #[serde(default)]
+    pub install_failures: u64,
+    /// runtime options of the plugins, indexed by plugin name.
+    #[serde(default)]
+    pub options: BTreeMap<String, BTreeMap<String, String>>,
+    /// options and environment variables marked as secret, indexed
+    /// by plugin name, their values are redacted from the output.
+    #[serde(default)]
+    pub secrets: BTreeMap<String, BTreeSet<String>>,
+    /// plugins that core lightning does not start, with the
+    /// reason, indexed by plugin name.
+    #[serde(default)]
+    pub disabled: BTreeMap<String, String>,
+    /// plugins installed only because another plugin needs them,
+    /// and not requested by the user.
+    #[serde(default)]
+    pub auto_installed: BTreeSet<String>,
+    /// tag, branch or commit of the repository where a plugin is
+    /// pinned, indexed by plugin name.
+    #[serde(default)]
+    pub pins: BTreeMap<String, String>,
+    /// worktrees with the sources of the installed plugins,
+    /// indexed by plugin name.
+    #[serde(default)]
+    pub worktrees: BTreeMap<String, Worktree>,
+    /// bundles installed as a unit, with their plugins at the
+    /// time of the install, indexed by bundle name.
+    #[serde(default)]
+    pub bundles: BTreeMap<String, Vec<String>>,
+    /// trust decisions of the user, indexed by repository url.
+    #[serde(default)]
+    pub trusted: BTreeMap<String, TrustedRepository>,
+    /// settings from the config file and the command line,
+    /// they are not part of the coffee storage.
+    #[serde(skip)]
+    pub settings: Settings,
+    /// home of coffee, that contains the directories of the
+    /// profiles, it is the root path without a profile.
+    #[serde(skip)]
+    pub home_path: String,
+    /// profile chosen from the command line, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// Directory of the coffee home that contains the profiles.
+pub const PROFILES_DIR: &str = "profiles";
+
+/// Name of the coffee home used without a profile, it can not be the
+/// name of another profile.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The profile chosen with `profile`, the default profile is the
+/// coffee home and not a profile of its own.
+fn selected_profile(profile: Option<&str>) -> Option<String> {
+    profile
+        .filter(|profile| *profile != DEFAULT_PROFILE)
+        .map(str::to_owned)
+}
+
+/// true when the profile name can be used as a directory name.
+pub fn is_valid_profile(profile: &str) -> bool {
+    !profile.is_empty()
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 async fn check_dir_or_make_if_missing(path: String) -> Result<(), CoffeeError> {
@@ -40,8 +332,10 @@ async fn check_dir_or_make_if_missing(path: String) -> Result<(), CoffeeError> {
 }
 
 impl CoffeeConf {
-    /// Create a new instance of the coffee configuration from the args.
-    pub async fn new(conf: &CoffeeArgs) -> Result<Self, CoffeeError> {
+    /// Create a new instance of the coffee configuration from the args,
+    /// when `read_only` is true the directories of coffee are never
+    /// created and coffee is offline.
+    pub async fn new(conf: &CoffeeArgs, read_only: bool) -> Result<Self, CoffeeError> {
         #[allow(deprecated)]
         let mut def_path = env::home_dir().unwrap().to_str().unwrap().to_string();
         if let Some(data_dir) = &conf.data_dir {
@@ -50,32 +344,83 @@ impl CoffeeConf {
 
         // FIXME: check for double slash
         def_path += "/.coffee";
-        check_dir_or_make_if_missing(def_path.to_string()).await?;
+        if !read_only {
+            check_dir_or_make_if_missing(def_path.to_string()).await?;
+        }
         info!("creating coffee home at {def_path}");
-        check_dir_or_make_if_missing(format!("{def_path}/bitcoin")).await?;
-        check_dir_or_make_if_missing(format!("{def_path}/testnet")).await?;
+        let home_path = def_path.clone();
+        let profile = selected_profile(conf.profile.as_deref());
+        if let Some(profile) = &profile {
+            if !is_valid_profile(profile) {
+                let err = CoffeeError::new(
+                    1,
+                    &format!("profile `{profile}` must contain only letters, digits, `-` and `_`"),
+                );
+                return Err(err);
+            }
+            def_path = format!("{home_path}/{PROFILES_DIR}/{profile}");
+            if !read_only {
+                create_dir_all(&def_path).await?;
+            }
+        }
+        if !read_only {
+            check_dir_or_make_if_missing(format!("{def_path}/bitcoin")).await?;
+            check_dir_or_make_if_missing(format!("{def_path}/testnet")).await?;
+        }
         let mut coffee = CoffeeConf {
             network: "bitcoin".to_owned(),
             root_path: format!("{def_path}"),
             config_path: format!("{def_path}/bitcoin/coffee.conf"),
             plugins: vec![],
+            patches: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            systemd_units: BTreeMap::new(),
+            install_failures: 0,
+            options: BTreeMap::new(),
+            secrets: BTreeMap::new(),
+            disabled: BTreeMap::new(),
+            auto_installed: BTreeSet::new(),
+            pins: BTreeMap::new(),
+            worktrees: BTreeMap::new(),
+            bundles: BTreeMap::new(),
+            trusted: BTreeMap::new(),
             cln_config_path: None,
             cln_root: None,
+            settings: Settings::default(),
+            home_path,
+            profile,
         };
 
+        // we try to see if there is a configuration file
+        // stored already to the disk.
+        coffee.load_from_file().await?;
+
         // check the command line arguments and bind them
-        // inside the coffee conf
+        // inside the coffee conf, they win over the file.
         coffee.bind_cmd_line_params(&conf)?;
-
-        // after we know all the information regarding
-        // the configuration we try to see if there is
-        // something stored already to the disk.
-        coffee.load_from_file().await?;
+        if read_only {
+            coffee.settings.offline = true;
+        }
 
         Ok(coffee)
     }
 
     async fn load_from_file(&mut self) -> Result<(), CoffeeError> {
+        // a profile without its own config file uses the one
+        // of the coffee home.
+        let mut path = format!("{}/config.yml", self.root_path);
+        if !Path::new(&path).exists() {
+            path = format!("{}/config.yml", self.home_path);
+        }
+        if !Path::new(&path).exists() {
+            debug!("config file {path} not found");
+            return Ok(());
+        }
+        let content = read_to_string(&path).await?;
+        self.settings = serde_yaml::from_str(&content)
+            .map_err(|err| CoffeeError::new(1, &format!("config file {path} malformed: {err}")))?;
+        debug!("settings loaded from {path}: {:?}", self.settings);
         Ok(())
     }
 
@@ -89,9 +434,60 @@ impl CoffeeConf {
             self.config_path = config.to_owned();
         }
 
+        if conf.offline {
+            self.settings.offline = true;
+        }
+        if conf.no_cache {
+            self.settings.no_cache = true;
+        }
+        if conf.plain {
+            self.settings.plain = true;
+        }
+        if conf.allow_reserved {
+            self.settings.allow_reserved = true;
+        }
+        if conf.reveal_secrets {
+            self.settings.reveal_secrets = true;
+        }
+        if conf.force_restart {
+            self.settings.force_restart = true;
+        }
+        if conf.fail_fast {
+            self.settings.on_failure = Some(FailurePolicy::FailFast);
+        } else if conf.keep_going {
+            self.settings.on_failure = Some(FailurePolicy::KeepGoing);
+        }
+
+        if conf.yes {
+            self.settings.confirm = ConfirmPolicy::Yes;
+        } else if conf.assume_no {
+            self.settings.confirm = ConfirmPolicy::No;
+        }
+
         // FIXME: be able to put the directory also in another place!
         // for now it is fixed in the Home/.coffee but another good place
         // will be, the .lightning dir
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{default_repositories, is_valid_profile, selected_profile};
+
+    #[test]
+    fn test_default_repositories() {
+        let repos = default_repositories().unwrap();
+        assert!(!repos.is_empty());
+        assert_eq!(repos[0].name, "lightningd");
+    }
+
+    #[test]
+    fn test_profile_name() {
+        assert!(is_valid_profile("node-a_1"));
+        assert!(!is_valid_profile(""));
+        assert!(!is_valid_profile("../node"));
+        assert_eq!(selected_profile(Some("default")), None);
+        assert_eq!(selected_profile(Some("nodeA")).as_deref(), Some("nodeA"));
+    }
+}
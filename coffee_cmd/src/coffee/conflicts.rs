@@ -0,0 +1,144 @@
+//! Conflicts between the plugins, core lightning does not start when
+//! two plugins register the same RPC method or the same exclusive hook,
+//! so the user is warned before the install.
+//!
+//! A plugin registers what its manifest declares (`provides`), and for
+//! the installed plugins without it, what the `getmanifest` handshake
+//! answers.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_conf::Provides;
+use log::debug;
+use serde_json::Value;
+
+use super::{options, CoffeeManager};
+
+/// Hooks that core lightning gives to a single plugin.
+pub const EXCLUSIVE_HOOKS: &[&str] = &["db_write"];
+
+/// what the answer of the `getmanifest` handshake registers.
+fn from_manifest(manifest: &Value) -> Provides {
+    // the hooks are objects on the recent nodes, strings on the old ones.
+    let names = |key: &str| -> Vec<String> {
+        manifest[key]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item["name"].as_str().or(item.as_str()))
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    Provides {
+        rpc_methods: names("rpcmethods"),
+        hooks: names("hooks"),
+        notifications: names("subscriptions"),
+    }
+}
+
+/// what the installed `plugin` registers, the declared one or the
+/// one of the handshake.
+async fn registered(plugin: &Plugin) -> Provides {
+    let provides = plugin.provides();
+    if !provides.is_empty() {
+        return provides;
+    }
+    let Some(exec_path) = plugin.exec_path() else {
+        return provides;
+    };
+    match options::manifest(&exec_path).await {
+        Ok(manifest) => from_manifest(&manifest),
+        Err(err) => {
+            debug!("manifest of {} not available: {err}", plugin.name());
+            provides
+        }
+    }
+}
+
+/// The RPC methods and the exclusive hooks registered by both.
+pub fn conflicts(first: &Provides, second: &Provides) -> Vec<String> {
+    let mut conflicts: Vec<String> = first
+        .rpc_methods
+        .iter()
+        .filter(|method| second.rpc_methods.contains(method))
+        .map(|method| format!("the RPC method `{method}`"))
+        .collect();
+    conflicts.extend(
+        first
+            .hooks
+            .iter()
+            .filter(|hook| EXCLUSIVE_HOOKS.contains(&hook.as_str()) && second.hooks.contains(hook))
+            .map(|hook| format!("the hook `{hook}`")),
+    );
+    conflicts
+}
+
+/// Check that the `plugins` do not conflict with the installed plugins
+/// and between them, when they do the user is asked to continue anyway.
+pub async fn check(coffee: &CoffeeManager, plugins: &[Plugin]) -> Result<(), CoffeeError> {
+    if plugins.iter().all(|plugin| plugin.provides().is_empty()) {
+        return Ok(());
+    }
+    let names: Vec<String> = plugins.iter().map(|plugin| plugin.name()).collect();
+    let mut others = vec![];
+    for installed in &coffee.config.plugins {
+        // a plugin installed again replaces itself.
+        if !names.contains(&installed.name()) {
+            others.push((installed.name(), registered(installed).await));
+        }
+    }
+    for plugin in plugins {
+        let provides = plugin.provides();
+        for (other, other_provides) in &others {
+            let conflicts = conflicts(&provides, other_provides);
+            if conflicts.is_empty() {
+                continue;
+            }
+            let question = format!(
+                "plugins `{}` and `{other}` both register {}, core lightning does not start with both, continue?",
+                plugin.name(),
+                conflicts.join(" and ")
+            );
+            if !coffee.config.settings.confirm.confirm(&question)? {
+                let err = CoffeeError::new(
+                    1,
+                    &format!("plugin `{}` conflicts with `{other}`", plugin.name()),
+                );
+                return Err(err);
+            }
+        }
+        others.push((plugin.name(), provides));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use coffee_lib::plugin_conf::Provides;
+    use serde_json::json;
+
+    use super::{conflicts, from_manifest};
+
+    #[test]
+    fn test_conflicts() {
+        let manifest = json!({
+            "rpcmethods": [{"name": "summary", "usage": ""}],
+            "hooks": [{"name": "db_write"}, "htlc_accepted"],
+            "subscriptions": ["forward_event"],
+        });
+        let installed = from_manifest(&manifest);
+        assert_eq!(installed.hooks, vec!["db_write", "htlc_accepted"]);
+        let plugin = Provides {
+            rpc_methods: vec!["summary".to_owned()],
+            hooks: vec!["htlc_accepted".to_owned(), "db_write".to_owned()],
+            notifications: vec!["forward_event".to_owned()],
+        };
+        assert_eq!(
+            conflicts(&plugin, &installed),
+            vec!["the RPC method `summary`", "the hook `db_write`"]
+        );
+        assert!(conflicts(&plugin, &Provides::default()).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! Crash loop detection of the daemon, a plugin that crashes too
+//! many times is disabled (or only reported, following the policy)
+//! instead of flapping forever on the node.
+//!
+//! A crash is a `Killing plugin` line of the plugin inside the node
+//! log, or a plugin that was active at the previous check and it is
+//! not anymore.
+use std::collections::BTreeMap;
+
+use coffee_lib::utils::unix_time;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::disable::disable;
+use super::health::plugin_logs;
+use super::notify::{notify, Event};
+use super::CoffeeManager;
+
+/// What the daemon does with a plugin in a crash loop.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrashLoopAction {
+    /// disable the plugin and notify it.
+    #[default]
+    Disable,
+    /// only notify it.
+    Notify,
+}
+
+/// When a plugin is in a crash loop.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashLoopPolicy {
+    /// crashes inside the window that make a crash loop.
+    pub max_crashes: usize,
+    /// window of the crashes, in seconds.
+    pub window: u64,
+    pub action: CrashLoopAction,
+}
+
+impl Default for CrashLoopPolicy {
+    fn default() -> Self {
+        CrashLoopPolicy {
+            max_crashes: 3,
+            window: 3600,
+            action: CrashLoopAction::Disable,
+        }
+    }
+}
+
+/// Crashes of the plugins seen by the daemon.
+#[derive(Debug, Default)]
+pub struct Crashes {
+    /// unix times of the crashes, indexed by plugin name.
+    times: BTreeMap<String, Vec<u64>>,
+    /// crash lines of the plugins already inside the node log.
+    logged: BTreeMap<String, usize>,
+    /// plugins active at the previous check.
+    active: BTreeMap<String, bool>,
+    /// plugins in a crash loop already notified.
+    notified: BTreeMap<String, usize>,
+}
+
+/// true when the node log `line` of a plugin reports its death.
+fn is_crash(line: &str) -> bool {
+    line.contains("Killing plugin")
+}
+
+impl Crashes {
+    /// record the `logged` crash lines of the `plugin` and its
+    /// `active` state at the unix `time`, return the crashes of the
+    /// plugin inside the `window`.
+    fn observe(
+        &mut self,
+        plugin: &str,
+        logged: usize,
+        active: bool,
+        time: u64,
+        window: u64,
+    ) -> usize {
+        let seen = self.logged.insert(plugin.to_owned(), logged);
+        // the log starts again when the node restarts.
+        let mut new = match seen {
+            Some(seen) if logged >= seen => logged - seen,
+            Some(_) => logged,
+            // the crashes before the daemon started are not counted.
+            None => 0,
+        };
+        let was_active = self.active.insert(plugin.to_owned(), active);
+        if new == 0 && was_active == Some(true) && !active {
+            new = 1;
+        }
+        let times = self.times.entry(plugin.to_owned()).or_default();
+        times.extend(std::iter::repeat_n(time, new));
+        times.retain(|crash| time.saturating_sub(*crash) < window);
+        times.len()
+    }
+}
+
+/// Check the plugins started by the node, and disable (or notify) the
+/// ones in a crash loop.
+pub async fn check(coffee: &mut CoffeeManager, crashes: &mut Crashes) {
+    let policy = coffee.config.settings.crash_loop.clone();
+    let Ok(plugins) = coffee
+        .cln::<Value, Value>("plugin", json!({"subcommand": "list"}))
+        .await
+    else {
+        return;
+    };
+    let running = plugins["plugins"].as_array().cloned().unwrap_or_default();
+    let watched: Vec<(String, String)> = coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| !coffee.config.disabled.contains_key(&plugin.name()))
+        .filter(|plugin| !coffee.config.systemd_units.contains_key(&plugin.name()))
+        .filter_map(|plugin| Some((plugin.name(), plugin.exec_path()?)))
+        .collect();
+    let now = unix_time();
+    for (plugin, exec_path) in watched {
+        let logged = plugin_logs(coffee, &exec_path)
+            .await
+            .iter()
+            .filter(|line| is_crash(line))
+            .count();
+        let active = running.iter().any(|running| {
+            running["name"].as_str() == Some(exec_path.as_str())
+                && running["active"].as_bool().unwrap_or_default()
+        });
+        let count = crashes.observe(&plugin, logged, active, now, policy.window);
+        debug!("plugin {plugin} crashed {count} times inside the window");
+        if count < policy.max_crashes {
+            crashes.notified.remove(&plugin);
+            continue;
+        }
+        let disabled = policy.action == CrashLoopAction::Disable;
+        if disabled {
+            let reason = format!("crashed {count} times in {}s", policy.window);
+            if let Err(err) = disable(coffee, &plugin, &reason).await {
+                warn!("plugin {plugin} in a crash loop not disabled: {err}");
+                continue;
+            }
+            crashes.times.remove(&plugin);
+        } else if crashes.notified.get(&plugin) == Some(&count) {
+            continue;
+        }
+        crashes.notified.insert(plugin.clone(), count);
+        let event = Event::CrashLoop {
+            plugin,
+            crashes: count,
+            disabled,
+        };
+        notify(&coffee.config.settings, &coffee.config.network, event).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_crash, Crashes};
+
+    #[test]
+    fn test_crash_loop_observe() {
+        assert!(is_crash("Killing plugin: exited during normal operation"));
+        let mut crashes = Crashes::default();
+        assert_eq!(crashes.observe("summary", 0, true, 100, 3600), 0);
+        // the plugin died without a log line.
+        assert_eq!(crashes.observe("summary", 0, false, 200, 3600), 1);
+        assert_eq!(crashes.observe("summary", 2, true, 300, 3600), 3);
+        // the old crashes leave the window.
+        assert_eq!(crashes.observe("summary", 2, true, 3800, 3600), 2);
+        // the node restarted, with a fresh log.
+        assert_eq!(crashes.observe("summary", 1, true, 3850, 3600), 3);
+    }
+}
@@ -0,0 +1,201 @@
+//! Daemon mode of coffee, it watches the upstream repositories and
+//! the plugins running inside the node, and notifies what changed.
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt;
+use coffee_lib::utils::{is_inside, unix_time};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use super::crash_loop::{self, Crashes};
+use super::maintenance;
+use super::metrics::{self, Metrics};
+use super::notify::{notify, Event};
+use super::top;
+use super::CoffeeManager;
+
+/// Interval between two checks when none is given, in seconds.
+pub const DEFAULT_INTERVAL: &str = "3600";
+
+/// What the daemon already notified, so an event is sent
+/// only when the state changes.
+#[derive(Debug, Default)]
+struct Watched {
+    outdated: BTreeSet<String>,
+    unhealthy: BTreeSet<String>,
+    crashes: Crashes,
+}
+
+/// installed plugins whose repository has new commits upstream, the
+/// repositories are fetched first when coffee is online.
+async fn outdated_plugins(coffee: &CoffeeManager) -> BTreeSet<String> {
+    if !coffee.config.settings.offline {
+        for repo in &coffee.repos {
+            let path = repo.url().path_string;
+            let used = coffee
+                .config
+                .plugins
+                .iter()
+                .any(|plugin| is_inside(&plugin.root_path(), &path));
+            if used {
+                if let Err(err) = repo.fetch().await {
+                    warn!("repository {} not fetched: {err}", repo.name());
+                }
+            }
+        }
+    }
+    coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| coffee.is_outdated(plugin))
+        .map(|plugin| plugin.name())
+        .collect()
+}
+
+/// installed plugins that are registered inside the node but are not
+/// active, with their state, `None` when the node is not reachable.
+async fn unhealthy_plugins(coffee: &CoffeeManager) -> Option<Vec<(String, String)>> {
+    if !coffee.is_node_running() {
+        return None;
+    }
+    let plugins: Value = coffee
+        .cln("plugin", json!({"subcommand": "list"}))
+        .await
+        .ok()?;
+    let running = plugins["plugins"].as_array()?;
+    let unhealthy = coffee
+        .config
+        .plugins
+        .iter()
+        // the daemons of systemd are not started by the node.
+        .filter(|plugin| !coffee.config.systemd_units.contains_key(&plugin.name()))
+        .filter(|plugin| !coffee.config.disabled.contains_key(&plugin.name()))
+        .filter_map(|plugin| {
+            let exec_path = plugin.exec_path()?;
+            let active = running
+                .iter()
+                .find(|running| running["name"].as_str() == Some(exec_path.as_str()))
+                .map(|running| running["active"].as_bool().unwrap_or_default());
+            match active {
+                Some(true) => None,
+                Some(false) => Some((plugin.name(), "not active".to_owned())),
+                None => Some((plugin.name(), "not running".to_owned())),
+            }
+        })
+        .collect();
+    Some(unhealthy)
+}
+
+/// run a check and notify what changed since the last one.
+async fn check(coffee: &mut CoffeeManager, watched: &mut Watched, metrics: &Mutex<Metrics>) {
+    // the plugins can be changed by other coffee commands, the check
+    // holds the lock until its changes are done.
+    if let Err(err) = coffee.lock_state().await {
+        coffee.release_state_lock();
+        warn!("check skipped, coffee state not reloaded: {err}");
+        return;
+    }
+    if coffee.is_node_running() {
+        crash_loop::check(coffee, &mut watched.crashes).await;
+    }
+
+    let mut outdated = outdated_plugins(coffee).await;
+    debug!("outdated plugins: {:?}", outdated);
+    if !outdated.is_empty() && outdated != watched.outdated {
+        let plugins = outdated.iter().cloned().collect();
+        let event = Event::UpgradesAvailable { plugins };
+        notify(&coffee.config.settings, &coffee.config.network, event).await;
+    }
+    if maintenance::auto_upgrade(coffee, &outdated).await {
+        outdated.clear();
+    }
+    watched.outdated = outdated;
+    coffee.release_state_lock();
+
+    let coffee = &*coffee;
+    let settings = &coffee.config.settings;
+    let network = &coffee.config.network;
+
+    let snapshot = Metrics {
+        installed: coffee.config.plugins.len(),
+        outdated: watched.outdated.len(),
+        unhealthy: watched.unhealthy.len(),
+        repositories: coffee.repos.len(),
+        last_update: coffee
+            .repos
+            .iter()
+            .filter_map(|repo| repo.last_update())
+            .max(),
+        install_failures: coffee.config.install_failures,
+        last_check: unix_time(),
+        usage: top::sample(coffee, top::SAMPLE_INTERVAL)
+            .await
+            .unwrap_or_else(|err| {
+                warn!("resource usage of the plugins not sampled: {err}");
+                vec![]
+            }),
+    };
+
+    let Some(unhealthy) = unhealthy_plugins(coffee).await else {
+        debug!("node not reachable, health check skipped");
+        update_metrics(metrics, snapshot);
+        return;
+    };
+    debug!("unhealthy plugins: {:?}", unhealthy);
+    for (plugin, health) in &unhealthy {
+        if !watched.unhealthy.contains(plugin) {
+            let event = Event::Unhealthy {
+                plugin: plugin.clone(),
+                health: health.clone(),
+            };
+            notify(settings, network, event).await;
+        }
+    }
+    watched.unhealthy = unhealthy.into_iter().map(|(plugin, _)| plugin).collect();
+    let snapshot = Metrics {
+        unhealthy: watched.unhealthy.len(),
+        ..snapshot
+    };
+    update_metrics(metrics, snapshot);
+}
+
+/// replace the metrics served with the `snapshot`.
+fn update_metrics(metrics: &Mutex<Metrics>, snapshot: Metrics) {
+    match metrics.lock() {
+        Ok(mut metrics) => *metrics = snapshot,
+        Err(err) => warn!("metrics not updated: {err}"),
+    }
+}
+
+/// Check the plugins every `interval` seconds, until coffee
+/// is interrupted, the metrics are served on the `metrics_bind`
+/// address when it is given.
+pub async fn daemon(
+    coffee: &mut CoffeeManager,
+    interval: u64,
+    metrics_bind: Option<String>,
+) -> Result<(), CoffeeError> {
+    let mut watched = Watched::default();
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    if let Some(bind) = metrics_bind {
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(&bind, metrics).await {
+                eprintln!("metrics not served: {err}");
+            }
+        });
+    }
+    println!("coffee daemon checking the plugins every {interval}s");
+    loop {
+        check(coffee, &mut watched, &metrics).await;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(interval)) => {}
+            _ = interrupt::interrupted() => return Err(interrupt::interrupted_error()),
+        }
+    }
+}
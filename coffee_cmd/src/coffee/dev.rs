@@ -0,0 +1,292 @@
+//! Development of the plugins, a plugin in a local working directory
+//! is built and restarted on the node without going through a
+//! repository.
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use coffee_github::repository::{plugin_from_dir, plugin_from_script};
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt;
+use coffee_lib::plugin::Plugin;
+use log::debug;
+use tokio::process::Command;
+use tokio::time::sleep;
+use walkdir::WalkDir;
+
+use super::audit::{record, Change};
+use super::regtest::Regtest;
+use super::{build_plugin, health, maintenance, CoffeeManager};
+
+/// How often the working directory is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// directories that change during the build, or that are not
+/// part of the sources.
+const IGNORED_DIRS: [&str; 5] = ["target", "node_modules", "__pycache__", "build", "venv"];
+
+/// plugin of the working directory, or of the single script, at `path`.
+async fn local_plugin(path: &Path) -> Result<Plugin, CoffeeError> {
+    if !path.exists() {
+        return Err(CoffeeError::new(
+            1,
+            &format!("path {} not found", path.display()),
+        ));
+    }
+    if path.is_file() {
+        return plugin_from_script(path).ok_or_else(|| {
+            CoffeeError::new(
+                1,
+                &format!("script {} has no supported interpreter", path.display()),
+            )
+        });
+    }
+    plugin_from_dir(path).await
+}
+
+/// Build the plugin at `path` and restart it on the node.
+async fn rebuild(coffee: &CoffeeManager, path: &Path, verbose: bool) -> Result<(), CoffeeError> {
+    let mut plugin = local_plugin(path).await?;
+    let name = plugin.name();
+    println!("[{name}] building");
+    let exec_path = build_plugin(
+        &mut plugin,
+        verbose,
+        &coffee.sandbox(),
+        &coffee.run_as(),
+        &coffee.plugin_env(),
+    )
+    .await?;
+    if !coffee.is_node_running() {
+        println!("[{name}] built {exec_path}, the node is not running");
+        return Ok(());
+    }
+    if let Some(reason) = maintenance::restart_blocked(coffee, &plugin).await {
+        println!("[{name}] built {exec_path}, restart deferred, {reason}");
+        return Ok(());
+    }
+    // the plugin is not running the first time.
+    if let Err(err) = coffee.stop_plugin(&exec_path).await {
+        debug!("plugin {exec_path} not stopped: {err}");
+    }
+    coffee.start_pluing(&exec_path).await?;
+    let health = health::check_health(coffee, &exec_path, true).await;
+    println!("[{name}] {health}");
+    if !health.is_healthy() {
+        let err = CoffeeError::new(1, &format!("plugin `{name}` is {health}"));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Install the plugin of the working directory at `path` in editable
+/// mode, the node runs the plugin straight from the working directory,
+/// so the plugin is never upgraded by coffee.
+pub async fn link(
+    coffee: &mut CoffeeManager,
+    path: &str,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    let path = Path::new(path)
+        .canonicalize()
+        .map_err(|err| CoffeeError::new(1, &format!("path {path} not found: {err}")))?;
+    let mut plugin = local_plugin(&path).await?;
+    let name = plugin.name();
+    if let Some(installed) = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == name)
+    {
+        let err = if installed.is_dev_linked() {
+            CoffeeError::new(
+                1,
+                &format!(
+                    "plugin `{name}` is already linked to {}",
+                    installed.root_path()
+                ),
+            )
+        } else {
+            CoffeeError::new(
+                1,
+                &format!("plugin `{name}` is already installed, remove it before linking"),
+            )
+        };
+        return Err(err);
+    }
+    println!("[{name}] building");
+    let exec_path = build_plugin(
+        &mut plugin,
+        verbose,
+        &coffee.sandbox(),
+        &coffee.run_as(),
+        &coffee.plugin_env(),
+    )
+    .await?;
+    plugin.set_dev_linked();
+    coffee.config.plugins.push(plugin);
+    coffee
+        .coffe_cln_config
+        .add_conf("plugin", &exec_path)
+        .map_err(|err| CoffeeError::new(1, &err.cause))?;
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    let change = Change::Install {
+        plugin: name.clone(),
+        repository: None,
+        commit: None,
+    };
+    record(coffee, change).await;
+    let health = health::check_health(coffee, &exec_path, false).await;
+    println!("[{name}] linked to {}, {health}", path.display());
+    Ok(())
+}
+
+/// plugin to test, the `plugin` is the path of a working directory
+/// or the name of an installed plugin.
+async fn plugin_to_test(
+    coffee: &CoffeeManager,
+    plugin: &str,
+    verbose: bool,
+) -> Result<(Plugin, String), CoffeeError> {
+    if Path::new(plugin).exists() {
+        let path = Path::new(plugin).canonicalize()?;
+        let mut plugin = local_plugin(&path).await?;
+        let exec_path = build_plugin(
+            &mut plugin,
+            verbose,
+            &coffee.sandbox(),
+            &coffee.run_as(),
+            &coffee.plugin_env(),
+        )
+        .await?;
+        return Ok((plugin, exec_path));
+    }
+    let name = coffee.resolve_alias(plugin);
+    let installed = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == name)
+        .ok_or_else(|| coffee.not_installed(&name))?;
+    let exec_path = installed.exec_path().ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("plugin `{name}` has no executable, install it again"),
+        )
+    })?;
+    Ok((installed.clone(), exec_path))
+}
+
+/// Run the test command declared inside the coffee manifest of the
+/// `plugin` against a regtest node, the node is started by coffee
+/// unless the lightning directory of a running one is given with
+/// `attach`.
+pub async fn test(
+    coffee: &CoffeeManager,
+    plugin: &str,
+    attach: Option<String>,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    let (plugin, exec_path) = plugin_to_test(coffee, plugin, verbose).await?;
+    let name = plugin.name();
+    let Some(command) = plugin.conf().and_then(|conf| conf.plugin.test) else {
+        let err = CoffeeError::new(
+            1,
+            &format!("plugin `{name}` do not declare a test command inside its manifest"),
+        );
+        return Err(err);
+    };
+    let node = match attach {
+        Some(lightning_dir) => Regtest::attach(&lightning_dir).await?,
+        None => Regtest::start().await?,
+    };
+    let result = run_test(&node, &plugin, &exec_path, command.trim()).await;
+    node.stop().await;
+    match &result {
+        Ok(()) => println!("[{name}] test passed"),
+        Err(err) => println!("[{name}] test failed: {err}"),
+    }
+    result
+}
+
+/// start the plugin on the `node` and run the test `command` inside
+/// the root directory of the plugin.
+async fn run_test(
+    node: &Regtest,
+    plugin: &Plugin,
+    exec_path: &str,
+    command: &str,
+) -> Result<(), CoffeeError> {
+    node.start_plugin(exec_path).await?;
+    println!("[{}] running `{command}`", plugin.name());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(plugin.root_path())
+        .env("LIGHTNING_DIR", &node.lightning_dir)
+        .env("LIGHTNING_RPC", node.rpc_path())
+        .env("COFFEE_PLUGIN", exec_path)
+        .status()
+        .await?;
+    if !status.success() {
+        let err = CoffeeError::new(1, &format!("`{command}` exited with {status}"));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// fingerprint of the sources at `path`, it changes when a file
+/// is added, removed or modified.
+fn fingerprint(path: &Path) -> (usize, SystemTime) {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0 || !(name.starts_with('.') || IGNORED_DIRS.contains(&name.as_ref()))
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .fold((0, SystemTime::UNIX_EPOCH), |(count, latest), modified| {
+            (count + 1, latest.max(modified))
+        })
+}
+
+/// Rebuild the plugin of the working directory at `path` and restart
+/// it on the node, with `watch` it is done again on every change of
+/// the sources, until coffee is interrupted.
+pub async fn reload(
+    coffee: &CoffeeManager,
+    path: &str,
+    watch: bool,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    let path = Path::new(path)
+        .canonicalize()
+        .map_err(|err| CoffeeError::new(1, &format!("path {path} not found: {err}")))?;
+    let result = rebuild(coffee, &path, verbose).await;
+    if !watch {
+        return result;
+    }
+    if let Err(err) = result {
+        println!("{err}");
+    }
+    println!("watching {}", path.display());
+    let mut last = fingerprint(&path);
+    loop {
+        tokio::select! {
+            _ = sleep(WATCH_INTERVAL) => {}
+            _ = interrupt::interrupted() => return Err(interrupt::interrupted_error()),
+        }
+        let current = fingerprint(&path);
+        if current == last {
+            continue;
+        }
+        // a failing build waits for the next change.
+        if let Err(err) = rebuild(coffee, &path, verbose).await {
+            println!("{err}");
+        }
+        // the build can write inside the working directory.
+        last = fingerprint(&path);
+    }
+}
@@ -0,0 +1,128 @@
+//! Diagnostics of the failed builds, the output of the build and the
+//! error are matched against the signatures of the common failures,
+//! and coffee prints how to fix them.
+//!
+//! A new failure is supported by adding a rule to `RULES`.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::sandbox::Sandbox;
+use log::debug;
+use tokio::fs::read_to_string;
+
+/// Rule of the diagnostics, a failed build with one of the
+/// `signatures` inside its output gets the `hint`.
+pub struct Rule {
+    pub name: &'static str,
+    /// text inside the output of the build, compared ignoring the case.
+    pub signatures: &'static [&'static str],
+    pub hint: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "python-headers",
+        signatures: &["Python.h: No such file or directory"],
+        hint: "the python headers are missing, install them (e.g. `apt install python3-dev`)",
+    },
+    Rule {
+        name: "externally-managed-python",
+        signatures: &["externally-managed-environment"],
+        hint: "the python of the system can not be changed by pip, set `python.environment: isolated` in the config.yml",
+    },
+    Rule {
+        name: "missing-compiler",
+        signatures: &[
+            "linker `cc` not found",
+            "gcc: not found",
+            "gcc: command not found",
+            "cc: command not found",
+            "command 'gcc' failed",
+            "no acceptable C compiler found",
+        ],
+        hint: "a C compiler is missing, install one (e.g. `apt install build-essential`) or enable `container_build`",
+    },
+    Rule {
+        name: "missing-toolchain",
+        signatures: &["not found (os error 2)", "No such file or directory (os error 2)"],
+        hint: "the toolchain of the plugin is not installed, install it or enable `container_build`",
+    },
+    Rule {
+        name: "network",
+        signatures: &[
+            "Read timed out",
+            "Connection timed out",
+            "Temporary failure in name resolution",
+            "Could not resolve host",
+            "i/o timeout",
+            "Network is unreachable",
+            "Failed to establish a new connection",
+        ],
+        hint: "the build can not reach the network, check the connection of the node or the proxy (keep `HTTPS_PROXY` with `sandbox_env`)",
+    },
+    Rule {
+        name: "glibc",
+        signatures: &["version `GLIBC_"],
+        hint: "a prebuilt binary needs a newer glibc than the one of the system, build it from the sources or inside a container (`container_build`)",
+    },
+    Rule {
+        name: "old-rust",
+        signatures: &["requires rustc", "is not supported by the following package"],
+        hint: "the plugin needs a newer rust, update it with `rustup update`",
+    },
+    Rule {
+        name: "disk-full",
+        signatures: &["No space left on device"],
+        hint: "the disk is full, free some space (e.g. `coffee` caches and old build logs)",
+    },
+    Rule {
+        name: "build-limits",
+        signatures: &[
+            "exceeded the time limit",
+            "exceeded the CPU time limit",
+            "exceeded the memory limit",
+        ],
+        hint: "the build exceeded the limits, raise them with `build_limits` in the config.yml",
+    },
+];
+
+/// The rules that match the `output` of a failed build.
+pub fn diagnose(output: &str) -> Vec<&'static Rule> {
+    let output = output.to_lowercase();
+    RULES
+        .iter()
+        .filter(|rule| {
+            rule.signatures
+                .iter()
+                .any(|signature| output.contains(&signature.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Print the hints for the build of the `plugin` that failed with
+/// the `err`, the output of the build is read from its build log.
+pub async fn report(plugin: &str, sandbox: &Sandbox, err: &CoffeeError) {
+    let mut output = match &sandbox.build_log {
+        Some(log) => read_to_string(log).await.unwrap_or_default(),
+        None => String::new(),
+    };
+    output += &err.to_string();
+    for rule in diagnose(&output) {
+        debug!("build of {plugin} matches the diagnostic {}", rule.name);
+        println!("[{plugin}] hint: {}", rule.hint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diagnose;
+
+    #[test]
+    fn test_diagnostics_rules() {
+        let output = "$ pip install -r requirements.txt\n\
+                      fatal error: Python.h: No such file or directory\n";
+        let rules: Vec<&str> = diagnose(output).iter().map(|rule| rule.name).collect();
+        assert_eq!(rules, vec!["python-headers"]);
+        let output = "pip._vendor.urllib3.exceptions.ReadTimeoutError: read timed out.";
+        assert_eq!(diagnose(output)[0].name, "network");
+        assert!(diagnose("build failed: exit status 1").is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Disabled plugins, they stay installed (with their options and
+//! their build) but core lightning does not start them, until they
+//! are enabled again.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use log::warn;
+
+use super::audit::{record, Change};
+use super::{maintenance, ssh, systemd, CoffeeManager};
+
+/// the installed `plugin` and its executable.
+fn installed(coffee: &CoffeeManager, plugin: &str) -> Result<(Plugin, String), CoffeeError> {
+    let installed = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .ok_or_else(|| coffee.not_installed(plugin))?;
+    let exec_path = installed.exec_path().ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` has no executable, install it again"),
+        )
+    })?;
+    Ok((installed.clone(), exec_path))
+}
+
+/// Disable the `plugin` for the `reason`, it is stopped when the
+/// node is running, or with its systemd unit when it has one.
+pub async fn disable(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    reason: &str,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let (installed, exec_path) = installed(coffee, &plugin)?;
+    if coffee.config.disabled.contains_key(&plugin) {
+        let err = CoffeeError::new(1, &format!("plugin `{plugin}` is already disabled"));
+        return Err(err);
+    }
+    if let Some(reason) = maintenance::restart_blocked(coffee, &installed).await {
+        let err = CoffeeError::new(1, &format!("plugin `{plugin}` not stopped, {reason}"));
+        return Err(err);
+    }
+    if coffee.config.systemd_units.contains_key(&plugin) {
+        systemd::stop(coffee, &plugin).await?;
+    } else if coffee.is_node_running() {
+        if let Err(err) = coffee.stop_plugin(&exec_path).await {
+            warn!("plugin {exec_path} not stopped: {err}");
+        }
+    }
+    if let Some(paths) = coffee.coffe_cln_config.fields.get_mut("plugin") {
+        paths.retain(|path| *path != exec_path);
+    }
+    coffee
+        .config
+        .disabled
+        .insert(plugin.clone(), reason.to_owned());
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    ssh::sync(coffee).await?;
+    println!("[{plugin}] disabled: {reason}");
+    let change = Change::Disable {
+        plugin,
+        reason: reason.to_owned(),
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Enable again the disabled `plugin`, it is started when the
+/// node is running, or with its systemd unit when it has one.
+pub async fn enable(coffee: &mut CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let (_, exec_path) = installed(coffee, &plugin)?;
+    let Some(reason) = coffee.config.disabled.get(&plugin).cloned() else {
+        let err = CoffeeError::new(1, &format!("plugin `{plugin}` is not disabled"));
+        return Err(err);
+    };
+    // the daemon is started by systemd, and not by core lightning.
+    if coffee.config.systemd_units.contains_key(&plugin) {
+        systemd::start(coffee, &plugin).await?;
+        coffee.config.disabled.remove(&plugin);
+        coffee.storage.store(&coffee.storage_info()).await?;
+        println!("[{plugin}] enabled and started by its systemd unit");
+        record(coffee, Change::Enable { plugin, reason }).await;
+        return Ok(());
+    }
+    coffee
+        .coffe_cln_config
+        .add_conf("plugin", &exec_path)
+        .map_err(|err| CoffeeError::new(1, &err.cause))?;
+    coffee.config.disabled.remove(&plugin);
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    ssh::sync(coffee).await?;
+    let change = Change::Enable {
+        plugin: plugin.clone(),
+        reason,
+    };
+    record(coffee, change).await;
+    if coffee.is_node_running() {
+        coffee.start_pluing(&exec_path).await?;
+        println!("[{plugin}] enabled and started");
+    } else {
+        println!("[{plugin}] enabled, active after the node restart");
+    }
+    Ok(())
+}
@@ -0,0 +1,420 @@
+//! Export of the installed plugins as a deployment descriptor, so
+//! the same plugins can be built somewhere else (e.g. inside the
+//! container image of core lightning).
+use std::path::Path;
+
+use coffee_lib::entry_point::resolve_entry_point;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::{Plugin, PluginLang};
+
+use super::CoffeeManager;
+
+/// Image of core lightning used when none is given.
+pub const DEFAULT_IMAGE: &str = "elementsproject/lightningd:latest";
+
+/// Directory of the repositories inside the image.
+const SOURCES_DIR: &str = "/opt/coffee";
+
+/// Directory that core lightning scans for plugins at startup.
+const PLUGINS_DIR: &str = "/usr/local/libexec/c-lightning/plugins";
+
+/// Formats of the export.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// a compose service with the image built inline.
+    DockerCompose,
+    /// a Dockerfile.
+    Dockerfile,
+    /// a Nix expression with a derivation for each plugin.
+    Nix,
+}
+
+/// Installed plugin with what is needed to build it again from
+/// its upstream repository.
+#[derive(Debug)]
+struct ExportedPlugin {
+    name: String,
+    repo: String,
+    url: String,
+    commit: String,
+    lang: PluginLang,
+    /// directory of the plugin, relative to the repository.
+    dir: String,
+    /// build commands, they run inside the directory of the plugin.
+    build: Vec<String>,
+    /// python packages of the `requirements.txt` of the plugin, when
+    /// there is no install script.
+    requirements: Option<Vec<String>>,
+    /// executable of the plugin, relative to the repository.
+    exec: String,
+}
+
+/// `path` relative to the directory `root`.
+fn relative(root: &str, path: &str) -> String {
+    match path.strip_prefix(root) {
+        Some(path) if !path.trim_start_matches('/').is_empty() => {
+            path.trim_start_matches('/').to_owned()
+        }
+        _ => ".".to_owned(),
+    }
+}
+
+/// Collect the installed plugins, with the commit of their repository.
+async fn exported_plugins(coffee: &CoffeeManager) -> Result<Vec<ExportedPlugin>, CoffeeError> {
+    let mut exported = vec![];
+    for plugin in &coffee.config.plugins {
+        let repo = coffee.repo_of(plugin).ok_or_else(|| {
+            CoffeeError::new(
+                1,
+                &format!("repository of the plugin `{}` not found", plugin.name()),
+            )
+        })?;
+        // the plugins inside a worktree are exported at its commit.
+        let worktree = coffee.config.worktrees.get(&plugin.name());
+        let (root, commit) = match worktree {
+            Some(worktree) => (worktree.path.clone(), worktree.commit.clone()),
+            None => (repo.url().path_string, repo.commit()?),
+        };
+        if !repo.local_changes()?.is_empty() || coffee.config.patches.contains_key(&repo.name()) {
+            eprintln!(
+                "[{}] the local changes of `{}` are not part of the export",
+                plugin.name(),
+                repo.name()
+            );
+        }
+        let (build, exec) = build_steps(plugin)?;
+        let requirements =
+            if build.is_empty() && !plugin.is_standalone() && plugin.lang() == PluginLang::Python {
+                let path = format!("{}/requirements.txt", plugin.path);
+                let requirements = match Path::new(&path).exists() {
+                    true => tokio::fs::read_to_string(&path).await?,
+                    false => String::new(),
+                };
+                Some(python_packages(&requirements))
+            } else {
+                None
+            };
+        exported.push(ExportedPlugin {
+            name: plugin.name(),
+            repo: repo.name(),
+            url: repo.url().url_string,
+            commit,
+            lang: plugin.lang(),
+            dir: relative(&root, &plugin.root_path()),
+            build: build
+                .into_iter()
+                .map(|cmd| cmd.replace(&format!("{root}/"), ""))
+                .collect(),
+            requirements,
+            exec: relative(&root, &exec),
+        });
+    }
+    Ok(exported)
+}
+
+/// Build commands and executable of the `plugin`, the same that
+/// coffee uses on the host, without the wrappers that it generates.
+fn build_steps(plugin: &Plugin) -> Result<(Vec<String>, String), CoffeeError> {
+    if plugin.is_standalone() {
+        return Ok((vec![], plugin.path.clone()));
+    }
+    if let Some(conf) = plugin.conf() {
+        if let Some(script) = conf.plugin.install {
+            let build = script.trim().lines().map(str::to_owned).collect();
+            return Ok((build, format!("{}/{}", plugin.path, conf.plugin.main)));
+        }
+    }
+    match plugin.lang() {
+        PluginLang::Python | PluginLang::Shell => {
+            let exec = resolve_entry_point(&plugin.lang(), &plugin.path, &plugin.name())?;
+            Ok((vec![], exec))
+        }
+        PluginLang::C => {
+            let exec = resolve_entry_point(&plugin.lang(), &plugin.path, &plugin.name())?;
+            let mut build = vec![];
+            if !Path::new(&format!("{}/Makefile", plugin.path)).exists() {
+                build.push("sh configure".to_owned());
+            }
+            let flags = plugin
+                .conf()
+                .and_then(|conf| conf.plugin.build_flags)
+                .unwrap_or_default();
+            build.push(format!("make {}", flags.join(" ")).trim_end().to_owned());
+            Ok((build, exec))
+        }
+        lang => Err(CoffeeError::new(
+            1,
+            &format!(
+                "plugin `{}` has no install script, the build for {lang:?} is unknown",
+                plugin.name()
+            ),
+        )),
+    }
+}
+
+/// Dockerfile that builds the `plugins` on top of the `image`, the
+/// plugins are started by core lightning from its plugins directory.
+fn dockerfile(plugins: &[ExportedPlugin], image: &str, network: &str) -> String {
+    let mut packages = vec!["git", "ca-certificates"];
+    if plugins
+        .iter()
+        .any(|plugin| plugin.lang == PluginLang::Python)
+    {
+        packages.push("python3-pip");
+    }
+    let mut dockerfile = format!(
+        "# generated by coffee with the plugins installed on {network}.\n\
+         FROM {image}\n\
+         ENV PIP_BREAK_SYSTEM_PACKAGES=1\n\
+         RUN apt-get update && apt-get install -y --no-install-recommends {} && rm -rf /var/lib/apt/lists/*\n",
+        packages.join(" ")
+    );
+    let mut cloned: Vec<&str> = vec![];
+    for plugin in plugins {
+        let repo = format!("{SOURCES_DIR}/{}", plugin.repo);
+        if !cloned.contains(&plugin.repo.as_str()) {
+            cloned.push(&plugin.repo);
+            dockerfile += &format!(
+                "RUN git clone --recurse-submodules {} {repo} && git -C {repo} checkout {}\n",
+                plugin.url, plugin.commit
+            );
+        }
+        dockerfile += &format!("# plugin `{}`\n", plugin.name);
+        let dir = match plugin.dir.as_str() {
+            "." => repo.clone(),
+            dir => format!("{repo}/{dir}"),
+        };
+        let mut run = vec![format!("cd {dir}")];
+        if plugin.requirements.is_some() {
+            run.push("pip install -r requirements.txt".to_owned());
+        }
+        run.extend(plugin.build.iter().cloned());
+        run.push(format!("chmod +x {repo}/{}", plugin.exec));
+        run.push(format!("mkdir -p {PLUGINS_DIR}"));
+        run.push(format!(
+            "ln -s {repo}/{} {PLUGINS_DIR}/{}",
+            plugin.exec, plugin.name
+        ));
+        dockerfile += &format!("RUN {}\n", run.join(" && "));
+    }
+    dockerfile
+}
+
+/// Compose service of core lightning, with the image built from
+/// the Dockerfile inline.
+fn docker_compose(plugins: &[ExportedPlugin], image: &str, network: &str) -> String {
+    let mut compose = "# generated by coffee, merge the service with the one of core lightning.\n\
+                       services:\n  \
+                         lightningd:\n    \
+                           build:\n      \
+                             context: .\n      \
+                             dockerfile_inline: |\n"
+        .to_owned();
+    for line in dockerfile(plugins, image, network).lines() {
+        compose += &format!("        {line}\n");
+    }
+    compose
+}
+
+/// Name of the packages inside a `requirements.txt`, as the
+/// attributes of the python packages of nixpkgs.
+fn python_packages(requirements: &str) -> Vec<String> {
+    requirements
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('-'))
+        .map(|line| {
+            let end = line
+                .find(|c: char| "=<>!~;[@ ".contains(c))
+                .unwrap_or(line.len());
+            line[..end].to_lowercase().replace(['_', '.'], "-")
+        })
+        .collect()
+}
+
+/// `script` inside an indented string of nix.
+fn nix_string(script: &str) -> String {
+    script.replace("''", "'''").replace("${", "''${")
+}
+
+/// Packages of nixpkgs that build a plugin written in `lang`.
+fn nix_inputs(lang: &PluginLang) -> &'static [&'static str] {
+    match lang {
+        PluginLang::Go => &["go"],
+        PluginLang::Rust => &["cargo", "rustc"],
+        PluginLang::Dart => &["dart"],
+        PluginLang::JVM => &["jdk"],
+        PluginLang::JavaScript => &["nodejs"],
+        PluginLang::TypeScript => &["deno"],
+        PluginLang::C => &["gnumake", "gcc"],
+        PluginLang::Shell => &["bash"],
+        PluginLang::Python | PluginLang::Other(_) | PluginLang::Unknown => &[],
+    }
+}
+
+/// Nix expression with a derivation for each plugin, the source is
+/// pinned to the commit of the repository, and `all` joins the plugins
+/// directories for core lightning.
+fn nix(plugins: &[ExportedPlugin], network: &str) -> String {
+    let mut nix = format!(
+        "# generated by coffee with the plugins installed on {network}.\n\
+         # core lightning loads them with `plugin-dir=${{all}}/libexec/c-lightning/plugins`.\n\
+         {{ pkgs ? import <nixpkgs> {{ }} }}:\n\
+         let\n  \
+           sources = {{\n"
+    );
+    let mut pinned: Vec<&str> = vec![];
+    for plugin in plugins {
+        if pinned.contains(&plugin.repo.as_str()) {
+            continue;
+        }
+        pinned.push(&plugin.repo);
+        nix += &format!(
+            "    \"{}\" = builtins.fetchGit {{\n      \
+               url = \"{}\";\n      \
+               rev = \"{}\";\n      \
+               allRefs = true;\n      \
+               submodules = true;\n    \
+             }};\n",
+            plugin.repo, plugin.url, plugin.commit
+        );
+    }
+    nix += "  };\n  plugins = {\n";
+    for plugin in plugins {
+        let native_inputs: String = nix_inputs(&plugin.lang)
+            .iter()
+            .map(|input| format!("pkgs.{input} "))
+            .collect();
+        // the interpreter of the scripts is patched by the fixup of
+        // nix with the python of the build inputs.
+        let inputs = match &plugin.requirements {
+            Some(requirements) => format!(
+                "(pkgs.python3.withPackages (ps: with ps; [ {} ])) ",
+                requirements.join(" ")
+            ),
+            None if plugin.lang == PluginLang::Python => "pkgs.python3 ".to_owned(),
+            None => String::new(),
+        };
+        let build: String = plugin
+            .build
+            .iter()
+            .map(|cmd| format!("        {}\n", nix_string(cmd)))
+            .collect();
+        let short = &plugin.commit[..plugin.commit.len().min(7)];
+        nix += &format!(
+            "    \"{name}\" = pkgs.stdenv.mkDerivation {{\n      \
+               pname = \"{name}\";\n      \
+               version = \"unstable-{short}\";\n      \
+               src = sources.\"{repo}\";\n      \
+               nativeBuildInputs = [ {native_inputs}];\n      \
+               buildInputs = [ {inputs}];\n      \
+               buildPhase = ''\n        \
+                 runHook preBuild\n        \
+                 pushd {dir}\n{build}        \
+                 popd\n        \
+                 runHook postBuild\n      \
+               '';\n      \
+               installPhase = ''\n        \
+                 runHook preInstall\n        \
+                 mkdir -p $out/share/coffee $out/libexec/c-lightning/plugins\n        \
+                 cp -r . $out/share/coffee\n        \
+                 chmod +x $out/share/coffee/{exec}\n        \
+                 ln -s $out/share/coffee/{exec} $out/libexec/c-lightning/plugins/{name}\n        \
+                 runHook postInstall\n      \
+               '';\n    \
+             }};\n",
+            name = plugin.name,
+            repo = plugin.repo,
+            dir = plugin.dir,
+            exec = plugin.exec,
+        );
+    }
+    nix += "  };\n\
+            in\n\
+            plugins // {\n  \
+              all = pkgs.symlinkJoin {\n    \
+                name = \"coffee-plugins\";\n    \
+                paths = builtins.attrValues plugins;\n  \
+              };\n\
+            }\n";
+    nix
+}
+
+/// Print the descriptor that builds the installed plugins in the
+/// `format`, on top of the `image` of core lightning.
+pub async fn export(
+    coffee: &CoffeeManager,
+    format: ExportFormat,
+    image: &str,
+) -> Result<(), CoffeeError> {
+    let plugins = exported_plugins(coffee).await?;
+    if plugins.is_empty() {
+        return Err(CoffeeError::new(
+            1,
+            "no plugins installed, nothing to export",
+        ));
+    }
+    let network = &coffee.config.network;
+    let descriptor = match format {
+        ExportFormat::DockerCompose => docker_compose(&plugins, image, network),
+        ExportFormat::Dockerfile => dockerfile(&plugins, image, network),
+        ExportFormat::Nix => nix(&plugins, network),
+    };
+    print!("{descriptor}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use coffee_lib::plugin::PluginLang;
+
+    use super::{dockerfile, nix, python_packages, relative, ExportedPlugin};
+
+    fn plugins() -> Vec<ExportedPlugin> {
+        vec![ExportedPlugin {
+            name: "summary".to_owned(),
+            repo: "lightningd".to_owned(),
+            url: "https://github.com/lightningd/plugins".to_owned(),
+            commit: "0a1b2c".to_owned(),
+            lang: PluginLang::Python,
+            dir: "summary".to_owned(),
+            build: vec![],
+            requirements: Some(vec!["pyln-client".to_owned()]),
+            exec: "summary/summary.py".to_owned(),
+        }]
+    }
+
+    #[test]
+    fn test_export_dockerfile() {
+        assert_eq!(
+            relative("/repos/plugins", "/repos/plugins/summary"),
+            "summary"
+        );
+        assert_eq!(relative("/repos/plugins", "/repos/plugins"), ".");
+        let dockerfile = dockerfile(&plugins(), "lightningd:v24", "bitcoin");
+        assert!(dockerfile.contains("FROM lightningd:v24\n"));
+        assert!(dockerfile.contains("git -C /opt/coffee/lightningd checkout 0a1b2c\n"));
+        assert!(dockerfile.contains(
+            "RUN cd /opt/coffee/lightningd/summary && pip install -r requirements.txt && "
+        ));
+        assert!(dockerfile.contains(
+            "ln -s /opt/coffee/lightningd/summary/summary.py /usr/local/libexec/c-lightning/plugins/summary\n"
+        ));
+    }
+
+    #[test]
+    fn test_export_nix() {
+        assert_eq!(
+            python_packages("pyln-client>=23.0 # rpc\n-r dev.txt\nPyYAML==6.0\n"),
+            ["pyln-client", "pyyaml"]
+        );
+        let nix = nix(&plugins(), "bitcoin");
+        assert!(nix.contains("rev = \"0a1b2c\";\n"));
+        assert!(nix.contains(
+            "buildInputs = [ (pkgs.python3.withPackages (ps: with ps; [ pyln-client ])) ];\n"
+        ));
+        assert!(nix.contains(
+            "ln -s $out/share/coffee/summary/summary.py $out/libexec/c-lightning/plugins/summary\n"
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! Node features required by the plugins, e.g. a plugin that opens
+//! dual funded channels needs a node with `experimental-dual-fund`.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use serde_json::{json, Value};
+
+use super::CoffeeManager;
+
+/// true when the node configuration `configs` (the answer of
+/// `listconfigs`) enables the `feature`.
+pub fn is_enabled(configs: &Value, feature: &str) -> bool {
+    let feature = feature.trim_start_matches("experimental-");
+    [format!("experimental-{feature}"), feature.to_owned()]
+        .iter()
+        .any(|name| {
+            // the recent nodes answer with an object for each
+            // option, the old ones with the value only.
+            let option = configs
+                .get("configs")
+                .and_then(|configs| configs.get(name))
+                .or_else(|| configs.get(name));
+            match option {
+                Some(Value::Bool(enabled)) => *enabled,
+                Some(Value::Object(option)) => {
+                    option.get("set").and_then(Value::as_bool).unwrap_or(true)
+                        && option
+                            .get("value_bool")
+                            .and_then(Value::as_bool)
+                            .unwrap_or(true)
+                }
+                Some(_) => true,
+                None => false,
+            }
+        })
+}
+
+/// Check that the node enables the features required by the
+/// `plugins`, when a feature is missing the user is asked to
+/// continue anyway.
+pub async fn check(coffee: &CoffeeManager, plugins: &[Plugin]) -> Result<(), CoffeeError> {
+    let required: Vec<(String, Vec<String>)> = plugins
+        .iter()
+        .map(|plugin| (plugin.name(), plugin.node_features()))
+        .filter(|(_, features)| !features.is_empty())
+        .collect();
+    if required.is_empty() {
+        return Ok(());
+    }
+    let configs = if coffee.is_node_running() {
+        coffee
+            .cln::<Value, Value>("listconfigs", json!({}))
+            .await
+            .ok()
+    } else {
+        None
+    };
+    let Some(configs) = configs else {
+        for (plugin, features) in &required {
+            println!(
+                "[{plugin}] the node is not reachable, check that it enables: {}",
+                features.join(", ")
+            );
+        }
+        return Ok(());
+    };
+    for (plugin, features) in required {
+        let missing: Vec<String> = features
+            .into_iter()
+            .filter(|feature| !is_enabled(&configs, feature))
+            .collect();
+        if missing.is_empty() {
+            continue;
+        }
+        let question = format!(
+            "plugin `{plugin}` needs the node features {}, that the node does not enable, continue?",
+            missing.join(", ")
+        );
+        if !coffee.config.settings.confirm.confirm(&question)? {
+            let err = CoffeeError::new(
+                1,
+                &format!(
+                    "plugin `{plugin}` needs the node features {}",
+                    missing.join(", ")
+                ),
+            );
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::is_enabled;
+
+    #[test]
+    fn test_node_features() {
+        let configs = json!({"configs": {
+            "experimental-dual-fund": {"set": true, "value_bool": true},
+            "experimental-offers": {"set": false, "value_bool": false},
+        }});
+        assert!(is_enabled(&configs, "dual-fund"));
+        assert!(is_enabled(&configs, "experimental-dual-fund"));
+        assert!(!is_enabled(&configs, "offers"));
+        assert!(!is_enabled(&configs, "splicing"));
+        let old = json!({"experimental-offers": true});
+        assert!(is_enabled(&old, "offers"));
+    }
+}
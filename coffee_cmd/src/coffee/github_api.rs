@@ -0,0 +1,235 @@
+//! Client of the GitHub API, used to enrich the plugins
+//! with the maintenance signals of the forge.
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::unix_time;
+use log::debug;
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::time::sleep;
+
+use super::config::Settings;
+use super::http_cache::HttpCache;
+
+/// Entry point of the GitHub API.
+const API_URL: &str = "https://api.github.com";
+
+/// Maintenance signals of a plugin.
+#[derive(Clone, Debug, Default)]
+pub struct PluginStats {
+    /// stars of the repository that contains the plugin.
+    pub stars: u64,
+    /// date of the last commit that touched the plugin directory.
+    pub last_commit: Option<String>,
+    /// open issues that mention the plugin in the title.
+    pub open_issues: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    stargazers_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssuesSearch {
+    total_count: u64,
+}
+
+/// Return the owner and the name of the repository when
+/// the `url` points to GitHub.
+pub fn github_slug(url: &str) -> Option<(String, String)> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))
+        .or_else(|| url.strip_prefix("git@github.com:"))?;
+    let mut parts = path.trim_end_matches('/').split('/');
+    let owner = parts.next()?;
+    let name = parts.next()?.trim_end_matches(".git");
+    if owner.is_empty() || name.is_empty() {
+        return None;
+    }
+    Some((owner.to_owned(), name.to_owned()))
+}
+
+fn http_err(err: reqwest::Error) -> CoffeeError {
+    CoffeeError::new(1, &format!("GitHub API not reachable: {err}"))
+}
+
+/// The API `path` with the `query`, every pair is url encoded so the
+/// names typed by the user can not change the request.
+fn with_query(path: &str, query: &[(&str, &str)]) -> String {
+    let mut url = Url::parse(&format!("{API_URL}{path}")).expect("the API url is valid");
+    url.query_pairs_mut().extend_pairs(query);
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+/// Longest time that coffee waits for the reset of the rate limit,
+/// after that the request fails.
+const MAX_RATE_LIMIT_WAIT: u64 = 60;
+
+pub struct GithubApi {
+    client: reqwest::Client,
+    /// personal access token, to raise the rate limit.
+    token: Option<String>,
+    /// cache of the responses, revalidated with their ETag.
+    cache: HttpCache,
+    /// stars of the repositories already requested.
+    stars: HashMap<(String, String), u64>,
+}
+
+impl GithubApi {
+    /// Create a new client, the token is taken from the settings
+    /// or from the `GITHUB_TOKEN` environment variable.
+    pub fn new(settings: &Settings, root_path: &str) -> Result<Self, CoffeeError> {
+        let client = reqwest::Client::builder()
+            .user_agent(format!("coffee/{}", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(http_err)?;
+        let token = settings
+            .github_token
+            .clone()
+            .or_else(|| env::var("GITHUB_TOKEN").ok())
+            .filter(|token| !token.is_empty());
+        Ok(GithubApi {
+            client,
+            token,
+            cache: HttpCache::new(settings, root_path),
+            stars: HashMap::new(),
+        })
+    }
+
+    /// Seconds to wait before retrying a request that hit the
+    /// rate limit, if the response is a rate limit error.
+    fn rate_limit_wait(response: &reqwest::Response) -> Option<u64> {
+        let status = response.status();
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+        let header = |name: &str| -> Option<u64> {
+            response.headers().get(name)?.to_str().ok()?.parse().ok()
+        };
+        if let Some(retry_after) = header("retry-after") {
+            return Some(retry_after);
+        }
+        if header("x-ratelimit-remaining") == Some(0) {
+            let reset = header("x-ratelimit-reset").unwrap_or_default();
+            return Some(reset.saturating_sub(unix_time()).max(1));
+        }
+        None
+    }
+
+    /// Request the json at `path`, the cached response is revalidated
+    /// with its ETag and the rate limit of the API is respected.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, CoffeeError> {
+        let url = format!("{API_URL}{path}");
+        let mut retried = false;
+        loop {
+            debug!("GitHub API request {url}");
+            let mut request = self
+                .client
+                .get(&url)
+                .header("Accept", "application/vnd.github+json");
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let request = self.cache.conditional(&url, request).await;
+            let response = request.send().await.map_err(http_err)?;
+            if let Some(wait) = Self::rate_limit_wait(&response) {
+                if retried || wait > MAX_RATE_LIMIT_WAIT {
+                    let mut msg = format!("GitHub API rate limit exceeded, retry in {wait}s");
+                    if self.token.is_none() {
+                        msg += ", set `github_token` in the config.yml or GITHUB_TOKEN to raise the limit";
+                    }
+                    return Err(CoffeeError::new(1, &msg));
+                }
+                println!("GitHub API rate limit reached, waiting {wait}s");
+                sleep(Duration::from_secs(wait)).await;
+                retried = true;
+                continue;
+            }
+            let Some(body) = self.cache.resolve(&url, response).await? else {
+                // the validators are removed, the request is sent again without them.
+                continue;
+            };
+            return serde_json::from_slice(&body).map_err(|err| {
+                CoffeeError::new(1, &format!("GitHub API answer malformed: {err}"))
+            });
+        }
+    }
+
+    /// Maintenance signals of the plugin `name` that lives in the
+    /// directory `dir` of the repository at `url`.
+    pub async fn plugin_stats(
+        &mut self,
+        url: &str,
+        dir: &str,
+        name: &str,
+    ) -> Result<PluginStats, CoffeeError> {
+        let slug = github_slug(url)
+            .ok_or_else(|| CoffeeError::new(1, &format!("{url} is not a GitHub repository")))?;
+        let (owner, repo) = &slug;
+        let stars = match self.stars.get(&slug) {
+            Some(stars) => *stars,
+            None => {
+                let info: RepoInfo = self.get(&format!("/repos/{owner}/{repo}")).await?;
+                self.stars.insert(slug.clone(), info.stargazers_count);
+                info.stargazers_count
+            }
+        };
+        let commits_path = format!("/repos/{owner}/{repo}/commits");
+        let mut query = vec![("per_page", "1")];
+        if !dir.is_empty() {
+            query.push(("path", dir));
+        }
+        let commits: Value = self.get(&with_query(&commits_path, &query)).await?;
+        let last_commit = commits[0]["commit"]["committer"]["date"]
+            .as_str()
+            .map(str::to_owned);
+        let search = format!("repo:{owner}/{repo} is:issue is:open in:title {name}");
+        let issues: IssuesSearch = self
+            .get(&with_query("/search/issues", &[("q", &search)]))
+            .await?;
+        Ok(PluginStats {
+            stars,
+            last_commit,
+            open_issues: issues.total_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{github_slug, with_query};
+
+    #[test]
+    fn test_github_slug() {
+        let slug = Some(("lightningd".to_owned(), "plugins".to_owned()));
+        assert_eq!(github_slug("https://github.com/lightningd/plugins"), slug);
+        assert_eq!(
+            github_slug("https://github.com/lightningd/plugins.git"),
+            slug
+        );
+        assert_eq!(github_slug("git@github.com:lightningd/plugins.git"), slug);
+        assert_eq!(github_slug("https://gitlab.com/lightningd/plugins"), None);
+    }
+
+    #[test]
+    fn test_github_query() {
+        assert_eq!(
+            with_query("/search/issues", &[("q", "repo:a/b in:title sum&mary#1")]),
+            "/search/issues?q=repo%3Aa%2Fb+in%3Atitle+sum%26mary%231"
+        );
+        assert_eq!(
+            with_query(
+                "/repos/a/b/commits",
+                &[("per_page", "1"), ("path", "cln rest")]
+            ),
+            "/repos/a/b/commits?per_page=1&path=cln+rest"
+        );
+    }
+}
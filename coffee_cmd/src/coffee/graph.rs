@@ -0,0 +1,163 @@
+//! Dependency graph of the installed plugins, with the other plugins
+//! they need (the `dependencies` of the manifest), the toolchain of
+//! their language and the node features, in ASCII or in Graphviz.
+use std::collections::BTreeMap;
+
+use coffee_lib::plugin::PluginLang;
+
+use super::CoffeeManager;
+
+/// Installed plugin inside the graph.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Node {
+    /// other plugins needed by the plugin.
+    pub dependencies: Vec<String>,
+    /// programs of the system that build or run the plugin.
+    pub toolchain: Vec<String>,
+    /// features of the node needed by the plugin.
+    pub features: Vec<String>,
+}
+
+/// Programs of the system that build or run a plugin written in `lang`.
+fn toolchain(lang: &PluginLang) -> Vec<String> {
+    let programs: &[&str] = match lang {
+        PluginLang::Python => &["python3", "pip"],
+        PluginLang::Go => &["go"],
+        PluginLang::Rust => &["cargo"],
+        PluginLang::Dart => &["dart"],
+        PluginLang::JVM => &["java"],
+        PluginLang::JavaScript => &["node", "npm"],
+        PluginLang::TypeScript => &["deno"],
+        PluginLang::C => &["make", "cc"],
+        PluginLang::Shell => &["sh"],
+        PluginLang::Other(lang) => return vec![lang.clone()],
+        PluginLang::Unknown => &[],
+    };
+    programs.iter().map(|program| program.to_string()).collect()
+}
+
+/// Graph of the installed plugins, indexed by plugin name.
+pub fn build(coffee: &CoffeeManager) -> BTreeMap<String, Node> {
+    coffee
+        .config
+        .plugins
+        .iter()
+        .map(|plugin| {
+            let node = Node {
+                dependencies: plugin.dependencies(),
+                toolchain: toolchain(&plugin.lang()),
+                features: plugin.node_features(),
+            };
+            (plugin.name(), node)
+        })
+        .collect()
+}
+
+/// plugins that need the `plugin`.
+pub fn dependents(graph: &BTreeMap<String, Node>, plugin: &str) -> Vec<String> {
+    graph
+        .iter()
+        .filter(|(_, node)| node.dependencies.iter().any(|dep| dep == plugin))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Graph as an ASCII tree for each plugin.
+fn ascii(graph: &BTreeMap<String, Node>) -> String {
+    let mut out = String::new();
+    for (name, node) in graph {
+        let mut lines = vec![];
+        for dep in &node.dependencies {
+            let missing = if graph.contains_key(dep) {
+                ""
+            } else {
+                " (missing)"
+            };
+            lines.push(format!("depends on {dep}{missing}"));
+        }
+        let dependents = dependents(graph, name);
+        if !dependents.is_empty() {
+            lines.push(format!("needed by {}", dependents.join(", ")));
+        }
+        if !node.toolchain.is_empty() {
+            lines.push(format!("requires {}", node.toolchain.join(", ")));
+        }
+        if !node.features.is_empty() {
+            lines.push(format!("node features {}", node.features.join(", ")));
+        }
+        out.push_str(&format!("{name}\n"));
+        for (index, line) in lines.iter().enumerate() {
+            let branch = if index + 1 == lines.len() {
+                "`--"
+            } else {
+                "|--"
+            };
+            out.push_str(&format!("{branch} {line}\n"));
+        }
+    }
+    out
+}
+
+/// Graph in the Graphviz format, the plugins are ellipses and the
+/// requirements of the system are boxes.
+fn dot(graph: &BTreeMap<String, Node>) -> String {
+    let mut out = String::from("digraph coffee {\n");
+    for (name, node) in graph {
+        out.push_str(&format!("  \"{name}\";\n"));
+        for dep in &node.dependencies {
+            if !graph.contains_key(dep) {
+                out.push_str(&format!("  \"{dep}\" [color=red];\n"));
+            }
+            out.push_str(&format!("  \"{name}\" -> \"{dep}\";\n"));
+        }
+        for program in &node.toolchain {
+            out.push_str(&format!("  \"{program}\" [shape=box];\n"));
+            out.push_str(&format!("  \"{name}\" -> \"{program}\" [style=dashed];\n"));
+        }
+        for feature in &node.features {
+            out.push_str(&format!("  \"{feature}\" [shape=hexagon];\n"));
+            out.push_str(&format!("  \"{name}\" -> \"{feature}\" [style=dotted];\n"));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Print the dependency graph of the installed plugins.
+pub fn graph(coffee: &CoffeeManager, graphviz: bool) {
+    let graph = build(coffee);
+    if graphviz {
+        print!("{}", dot(&graph));
+    } else {
+        print!("{}", ascii(&graph));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{ascii, dependents, dot, Node};
+
+    #[test]
+    fn test_graph_output() {
+        let mut graph = BTreeMap::new();
+        let summary = Node {
+            dependencies: vec!["rebalance".to_owned(), "sauron".to_owned()],
+            toolchain: vec!["python3".to_owned()],
+            features: vec![],
+        };
+        graph.insert("summary".to_owned(), summary);
+        graph.insert("rebalance".to_owned(), Node::default());
+        assert_eq!(dependents(&graph, "rebalance"), vec!["summary"]);
+        assert_eq!(
+            ascii(&graph),
+            "rebalance\n`-- needed by summary\nsummary\n|-- depends on rebalance\n\
+             |-- depends on sauron (missing)\n`-- requires python3\n"
+        );
+        let dot = dot(&graph);
+        assert!(dot.contains("\"summary\" -> \"rebalance\";"));
+        assert!(dot.contains("\"sauron\" [color=red];"));
+        assert!(dot.contains("\"summary\" -> \"python3\" [style=dashed];"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Health check of the plugins managed by coffee, it asks
+//! core lightning if the plugin is running after an install.
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use log::debug;
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use super::CoffeeManager;
+
+/// How many times core lightning is asked about the plugin
+/// before considering it not responding.
+const HEALTH_POLLS: u64 = 5;
+
+/// Health of a plugin from the core lightning point of view.
+#[derive(Debug, PartialEq)]
+pub enum PluginHealth {
+    /// the plugin is running.
+    Active,
+    /// the plugin died during the startup.
+    Crashed,
+    /// the plugin is registered but never became active.
+    NotResponding,
+    /// the plugin is going to run after the node restart.
+    PendingRestart,
+}
+
+impl PluginHealth {
+    /// true when the plugin can be considered installed with success.
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, PluginHealth::Active | PluginHealth::PendingRestart)
+    }
+}
+
+impl fmt::Display for PluginHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            PluginHealth::Active => "active",
+            PluginHealth::Crashed => "crashed at startup",
+            PluginHealth::NotResponding => "not responding",
+            PluginHealth::PendingRestart => "installed, active after the node restart",
+        };
+        write!(f, "{status}")
+    }
+}
+
+/// Ask core lightning the state of the plugin registered with `exec_path`.
+///
+/// When the plugin was started dynamically, core lightning is asked
+/// more times to give the time to the plugin to complete the init.
+pub async fn check_health(coffee: &CoffeeManager, exec_path: &str, dynamic: bool) -> PluginHealth {
+    if !dynamic && !coffee.is_node_running() {
+        return PluginHealth::PendingRestart;
+    }
+    let polls = if dynamic { HEALTH_POLLS } else { 1 };
+    for poll in 1..=polls {
+        let plugins: Result<Value, _> = coffee.cln("plugin", json!({"subcommand": "list"})).await;
+        let found = plugins.ok().and_then(|plugins| {
+            plugins["plugins"]
+                .as_array()?
+                .iter()
+                .find(|plugin| plugin["name"].as_str() == Some(exec_path))
+                .map(|plugin| plugin["active"].as_bool().unwrap_or_default())
+        });
+        debug!("health poll {poll} of {exec_path}: {:?}", found);
+        match found {
+            Some(true) => return PluginHealth::Active,
+            None if dynamic => return PluginHealth::Crashed,
+            _ if poll < polls => sleep(Duration::from_secs(1)).await,
+            _ => {}
+        }
+    }
+    if dynamic {
+        PluginHealth::NotResponding
+    } else {
+        PluginHealth::PendingRestart
+    }
+}
+
+/// Return the core lightning log lines emitted by the plugin
+/// registered with `exec_path`.
+pub async fn plugin_logs(coffee: &CoffeeManager, exec_path: &str) -> Vec<String> {
+    let file_name = Path::new(exec_path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let source = format!("plugin-{file_name}");
+    let logs: Value = match coffee.cln("getlog", json!({"level": "debug"})).await {
+        Ok(logs) => logs,
+        Err(_) => return vec![],
+    };
+    logs["log"]
+        .as_array()
+        .map(|lines| {
+            lines
+                .iter()
+                .filter(|line| {
+                    line["source"]
+                        .as_str()
+                        .map(|line_source| line_source.contains(&source))
+                        .unwrap_or_default()
+                })
+                .filter_map(|line| line["log"].as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+//! On-disk cache of the HTTP responses, keyed by url.
+//!
+//! A cached response is revalidated with a conditional request
+//! (`If-None-Match` and `If-Modified-Since`), so an unchanged
+//! content is never downloaded again. The large downloads used once
+//! (e.g. the binary of a self update) do not go inside the cache.
+use std::path::Path;
+
+use coffee_lib::download;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::atomic_write;
+use log::debug;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs::{create_dir_all, read, read_to_string, remove_file};
+
+use super::config::Settings;
+
+/// Validators of a cached response.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+pub struct HttpCache {
+    /// directory where the responses are stored.
+    dir: String,
+    /// false when the user asked to bypass the cache.
+    enabled: bool,
+}
+
+fn http_err(err: reqwest::Error) -> CoffeeError {
+    CoffeeError::new(1, &format!("http request failed: {err}"))
+}
+
+/// read the body of the `response`, at the download bandwidth.
+async fn body(mut response: Response) -> Result<Vec<u8>, CoffeeError> {
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(http_err)? {
+        download::throttle(chunk.len() as u64).await;
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Download the content at `url` with the `client`, without the cache.
+pub async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, CoffeeError> {
+    let _slot = download::slot().await?;
+    let response = client.get(url).send().await.map_err(http_err)?;
+    body(response.error_for_status().map_err(http_err)?).await
+}
+
+impl HttpCache {
+    pub fn new(settings: &Settings, root_path: &str) -> Self {
+        HttpCache {
+            dir: format!("{root_path}/cache/http"),
+            enabled: !settings.no_cache,
+        }
+    }
+
+    fn path(&self, url: &str, extension: &str) -> String {
+        let digest = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        format!("{}/{digest}.{extension}", self.dir)
+    }
+
+    /// validators of the cached response of `url`, only when its
+    /// content is cached too.
+    async fn entry(&self, url: &str) -> Option<CacheEntry> {
+        if !self.enabled || !Path::new(&self.path(url, "body")).exists() {
+            return None;
+        }
+        let content = read_to_string(self.path(url, "meta")).await.ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Add to the `request` of the `url` the validators of the
+    /// cached response, if any.
+    pub async fn conditional(&self, url: &str, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(entry) = self.entry(url).await {
+            if let Some(etag) = entry.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = entry.last_modified {
+                request = request.header("If-Modified-Since", last_modified);
+            }
+        }
+        request
+    }
+
+    /// Return the content of the `response` to the request of `url`,
+    /// from the cache when the content is not modified.
+    ///
+    /// `None` when the content is not modified but it is missing from
+    /// the cache, the validators are removed and the request must be
+    /// sent again.
+    pub async fn resolve(
+        &self,
+        url: &str,
+        response: Response,
+    ) -> Result<Option<Vec<u8>>, CoffeeError> {
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Ok(body) = read(self.path(url, "body")).await {
+                debug!("{url} not modified, using the cache");
+                return Ok(Some(body));
+            }
+            // the request was not conditional, a retry gets the same answer.
+            if remove_file(self.path(url, "meta")).await.is_err() {
+                let err =
+                    CoffeeError::new(1, &format!("{url} not modified, but nothing is cached"));
+                return Err(err);
+            }
+            debug!("{url} not modified, but its content is not cached");
+            return Ok(None);
+        }
+        let response = response.error_for_status().map_err(http_err)?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let entry = CacheEntry {
+            url: url.to_owned(),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+        };
+        let body = body(response).await?;
+        if self.enabled && (entry.etag.is_some() || entry.last_modified.is_some()) {
+            if let Err(err) = self.store(&entry, &body).await {
+                debug!("response of {url} not cached: {err}");
+            }
+        }
+        Ok(Some(body))
+    }
+
+    async fn store(&self, entry: &CacheEntry, body: &[u8]) -> Result<(), CoffeeError> {
+        create_dir_all(&self.dir).await?;
+        let meta =
+            serde_json::to_string(entry).map_err(|err| CoffeeError::new(1, &format!("{err}")))?;
+        // the body is written first, so a validator always
+        // comes with its content.
+        atomic_write(&self.path(&entry.url, "body"), body).await?;
+        atomic_write(&self.path(&entry.url, "meta"), meta.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Download the content at `url` with the `client`.
+    pub async fn get(&self, client: &reqwest::Client, url: &str) -> Result<Vec<u8>, CoffeeError> {
+        let _slot = download::slot().await?;
+        loop {
+            let request = self.conditional(url, client.get(url)).await;
+            let response = request.send().await.map_err(http_err)?;
+            if let Some(body) = self.resolve(url, response).await? {
+                return Ok(body);
+            }
+        }
+    }
+}
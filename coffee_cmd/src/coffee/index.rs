@@ -0,0 +1,348 @@
+//! Check of a repository of plugins, the indexer of coffee runs
+//! against a local checkout and reports what it would detect, so
+//! the maintainers of a collection can run it in the CI.
+//!
+//! The same report can be exported as a static index, versioned
+//! and signed, that the maintainers can publish.
+use std::path::Path;
+use std::process::Stdio;
+
+use coffee_github::git;
+use coffee_github::repository::{is_hidden, plugin_from_dir, plugin_from_script};
+use coffee_lib::entry_point::resolve_entry_point;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::installer::InstallerRegistry;
+use coffee_lib::plugin::{Plugin, PluginLang};
+use coffee_lib::plugin_conf::Provides;
+use coffee_lib::utils::unix_time;
+use serde::{Deserialize, Serialize};
+use tokio::fs::write;
+use tokio::process::Command;
+use walkdir::WalkDir;
+
+/// Version of the format of the exported index, it changes only
+/// when the format is not compatible anymore.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Namespace of the signatures of the exported index.
+const SIGNATURE_NAMESPACE: &str = "coffee-index";
+
+/// Plugin detected by the indexer.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedPlugin {
+    pub name: String,
+    /// directory of the plugin, relative to the repository.
+    pub directory: String,
+    pub language: Option<PluginLang>,
+    /// entry point, relative to the repository.
+    pub entry_point: Option<String>,
+    /// coffee manifest of the plugin, if any.
+    pub manifest: Option<String>,
+    /// version declared inside the manifest.
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// RPC methods, hooks and notifications declared inside the manifest.
+    #[serde(default, skip_serializing_if = "Provides::is_empty")]
+    pub provides: Provides,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+/// Report of the indexer for a repository.
+#[derive(Debug, Serialize)]
+pub struct IndexReport {
+    pub repository: String,
+    pub plugins: Vec<IndexedPlugin>,
+    /// directories that do not look like a plugin.
+    pub skipped: Vec<String>,
+    /// problems of the repository, e.g. two plugins with the same name.
+    pub problems: Vec<String>,
+}
+
+impl IndexReport {
+    /// true when the repository and all its plugins have no problems.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty() && self.plugins.iter().all(|plugin| plugin.problems.is_empty())
+    }
+}
+
+/// manifest file inside the `dir`, if any.
+fn manifest_file(dir: &Path) -> Option<String> {
+    ["coffee.yaml", "coffee.yml"]
+        .into_iter()
+        .find(|file| dir.join(file).is_file())
+        .map(|file| file.to_owned())
+}
+
+/// check the `plugin` found inside the `directory` of the repository at `root`.
+fn check_plugin(
+    root: &Path,
+    plugin: &Plugin,
+    directory: &str,
+    manifest: Option<String>,
+) -> IndexedPlugin {
+    let mut problems = vec![];
+    if let PluginLang::Other(lang) = plugin.lang() {
+        if InstallerRegistry::get(&lang).is_none() {
+            problems.push(format!("language {lang} not supported"));
+        }
+    }
+    let entry_point = match (plugin.is_standalone(), plugin.conf()) {
+        (true, _) => Some(plugin.path.clone()),
+        (false, Some(conf)) => {
+            let main = format!("{}/{}", plugin.path, conf.plugin.main);
+            if !Path::new(&main).is_file() {
+                problems.push(format!("main file `{}` not found", conf.plugin.main));
+            }
+            if conf.plugin.version.trim().is_empty() {
+                problems.push("empty version".to_owned());
+            }
+            Some(main)
+        }
+        (false, None) => match resolve_entry_point(&plugin.lang(), &plugin.path, &plugin.name()) {
+            Ok(main) => Some(main),
+            Err(err) => {
+                problems.push(format!("entry point not found: {err}"));
+                None
+            }
+        },
+    };
+    let entry_point = entry_point.map(|main| {
+        Path::new(&main)
+            .strip_prefix(root)
+            .map(|main| main.to_string_lossy().to_string())
+            .unwrap_or(main)
+    });
+    IndexedPlugin {
+        name: plugin.name(),
+        directory: directory.to_owned(),
+        language: Some(plugin.lang()),
+        entry_point,
+        manifest,
+        version: plugin.conf().map(|conf| conf.plugin.version),
+        tags: plugin.tags(),
+        provides: plugin.provides(),
+        problems,
+    }
+}
+
+/// Run the indexer against the repository checkout at `path`.
+pub async fn index_report(path: &Path) -> Result<IndexReport, CoffeeError> {
+    let mut report = IndexReport {
+        repository: path.to_string_lossy().to_string(),
+        plugins: vec![],
+        skipped: vec![],
+        problems: vec![],
+    };
+    let entries = WalkDir::new(path)
+        .max_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !is_hidden(entry));
+    for entry in entries {
+        let entry = entry.map_err(|err| CoffeeError::new(1, &err.to_string()))?;
+        let dir = entry.path();
+        let directory = dir
+            .strip_prefix(path)
+            .unwrap_or(dir)
+            .to_string_lossy()
+            .to_string();
+        let directory = if directory.is_empty() {
+            ".".to_owned()
+        } else {
+            directory
+        };
+        if entry.file_type().is_file() {
+            // only the scripts with a supported shebang are plugins.
+            if let Some(plugin) = plugin_from_script(dir) {
+                report
+                    .plugins
+                    .push(check_plugin(path, &plugin, &directory, None));
+            }
+            continue;
+        }
+        let manifest = manifest_file(dir);
+        match plugin_from_dir(dir).await {
+            Ok(plugin) if plugin.lang() == PluginLang::Unknown && manifest.is_none() => {
+                // the root of a collection is not a plugin.
+                if entry.depth() > 0 {
+                    report.skipped.push(directory);
+                }
+            }
+            Ok(plugin) => report
+                .plugins
+                .push(check_plugin(path, &plugin, &directory, manifest)),
+            Err(err) => report.plugins.push(IndexedPlugin {
+                name: entry.file_name().to_string_lossy().to_string(),
+                directory,
+                language: None,
+                entry_point: None,
+                manifest,
+                version: None,
+                tags: vec![],
+                provides: Provides::default(),
+                problems: vec![err.to_string()],
+            }),
+        }
+    }
+    let mut names: Vec<&str> = report
+        .plugins
+        .iter()
+        .map(|plugin| plugin.name.as_str())
+        .collect();
+    names.sort();
+    let mut duplicates: Vec<String> = names
+        .windows(2)
+        .filter(|pair| pair[0] == pair[1])
+        .map(|pair| format!("more plugins with the name `{}`", pair[0]))
+        .collect();
+    duplicates.dedup();
+    report.problems = duplicates;
+    Ok(report)
+}
+
+/// Print the report of the indexer for the repository checkout at
+/// `path` as JSON, it fails when the report has problems.
+pub async fn check(path: &str) -> Result<(), CoffeeError> {
+    let path = Path::new(path)
+        .canonicalize()
+        .map_err(|err| CoffeeError::new(1, &format!("path {path} not found: {err}")))?;
+    let report = index_report(&path).await?;
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|err| CoffeeError::new(1, &format!("report not serialized: {err}")))?;
+    println!("{json}");
+    if !report.is_ok() {
+        return Err(CoffeeError::new(1, "the repository has problems"));
+    }
+    Ok(())
+}
+
+/// Index of a repository exported by coffee.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedIndex {
+    /// version of the format, see `INDEX_VERSION`.
+    pub version: u32,
+    /// unix time of the export.
+    pub generated_at: u64,
+    /// url where the repository is published, if known.
+    pub url: Option<String>,
+    /// commit of the repository that was indexed.
+    pub commit: Option<String>,
+    pub plugins: Vec<IndexedPlugin>,
+}
+
+/// Export the index of the repository checkout at `path`, the index
+/// is printed or written to `output`, and signed with the ssh `key`
+/// when it is given (the signature is written next to the index).
+pub async fn export(
+    path: &str,
+    url: Option<String>,
+    output: Option<String>,
+    key: Option<String>,
+) -> Result<(), CoffeeError> {
+    let path = Path::new(path)
+        .canonicalize()
+        .map_err(|err| CoffeeError::new(1, &format!("path {path} not found: {err}")))?;
+    let report = index_report(&path).await?;
+    if !report.is_ok() {
+        let err = CoffeeError::new(
+            1,
+            "the repository has problems, run `coffee index --check` to see them",
+        );
+        return Err(err);
+    }
+    let commit = git::backend().head(&path.to_string_lossy()).ok();
+    let index = ExportedIndex {
+        version: INDEX_VERSION,
+        generated_at: unix_time(),
+        url,
+        commit,
+        plugins: report.plugins,
+    };
+    let json = serde_json::to_string_pretty(&index)
+        .map_err(|err| CoffeeError::new(1, &format!("index not serialized: {err}")))?;
+    let Some(output) = output else {
+        if key.is_some() {
+            return Err(CoffeeError::new(
+                1,
+                "the index can be signed only with `--output`",
+            ));
+        }
+        println!("{json}");
+        return Ok(());
+    };
+    write(&output, format!("{json}\n")).await?;
+    println!(
+        "index with {} plugins written to {output}",
+        index.plugins.len()
+    );
+    if let Some(key) = key {
+        sign(&output, &key).await?;
+        println!("signature written to {output}.sig");
+    }
+    Ok(())
+}
+
+/// sign the `file` with the ssh `key`, the signature goes to `<file>.sig`.
+async fn sign(file: &str, key: &str) -> Result<(), CoffeeError> {
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "sign", "-f", key, "-n", SIGNATURE_NAMESPACE, file])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("ssh-keygen not started: {err}")))?;
+    if !output.status.success() {
+        let err = CoffeeError::new(
+            1,
+            &format!(
+                "index not signed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        );
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::Path;
+
+    use super::index_report;
+
+    #[tokio::test]
+    async fn test_index_report() {
+        let path = format!("{}/coffee-index-check", env::temp_dir().to_string_lossy());
+        create_dir_all(format!("{path}/summary")).unwrap();
+        create_dir_all(format!("{path}/broken")).unwrap();
+        create_dir_all(format!("{path}/docs")).unwrap();
+        write(format!("{path}/summary/requirements.txt"), "").unwrap();
+        write(format!("{path}/summary/summary.py"), "").unwrap();
+        write(
+            format!("{path}/broken/coffee.yml"),
+            "plugin:\n  name: broken\n  version: 0.1.0\n  lang: py\n  main: broken.py\n",
+        )
+        .unwrap();
+        write(format!("{path}/docs/index.md"), "").unwrap();
+
+        let report = index_report(Path::new(&path)).await.unwrap();
+        assert_eq!(report.skipped, vec!["docs".to_owned()]);
+        let summary = report
+            .plugins
+            .iter()
+            .find(|plugin| plugin.name == "summary");
+        let summary = summary.unwrap();
+        assert!(summary.problems.is_empty());
+        assert_eq!(summary.entry_point.as_deref(), Some("summary/summary.py"));
+        let broken = report.plugins.iter().find(|plugin| plugin.name == "broken");
+        assert_eq!(
+            broken.unwrap().problems,
+            vec!["main file `broken.py` not found"]
+        );
+        assert!(!report.is_ok());
+        remove_dir_all(path).unwrap();
+    }
+}
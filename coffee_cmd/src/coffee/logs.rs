@@ -0,0 +1,103 @@
+//! Logs of a plugin managed by coffee, filtered out
+//! from the core lightning log.
+use std::path::Path;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use log::debug;
+use serde_json::{json, Value};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use tokio::time::sleep;
+
+use super::health::plugin_logs;
+use super::CoffeeManager;
+
+/// Find the path of the core lightning log file, looking first
+/// inside the core lightning configuration and then asking to the
+/// node with `listconfigs`.
+async fn log_file_path(coffee: &CoffeeManager) -> Option<String> {
+    let from_conf = coffee
+        .cln_config
+        .as_ref()
+        .and_then(|conf| conf.fields.get("log-file"))
+        .and_then(|values| values.last().cloned());
+    let log_file = match from_conf {
+        Some(log_file) => log_file,
+        None => {
+            let configs: Value = coffee.cln("listconfigs", json!({})).await.ok()?;
+            // core lightning changed the format of `listconfigs` over the time.
+            let log_file = &configs["configs"]["log-file"];
+            let log_file = log_file["values_str"]
+                .as_array()
+                .and_then(|values| values.last())
+                .or(log_file.get("value_str"))
+                .or(configs.get("log-file"))?;
+            log_file.as_str()?.to_owned()
+        }
+    };
+    if Path::new(&log_file).is_absolute() {
+        return Some(log_file);
+    }
+    // a relative path is relative to the network directory.
+    let root = coffee.config.cln_root.as_ref()?;
+    Some(format!("{root}/{}/{log_file}", coffee.config.network))
+}
+
+/// Print the core lightning log lines emitted by the plugin, and
+/// with `follow` keep printing the new lines.
+pub async fn logs(coffee: &CoffeeManager, plugin: &str, follow: bool) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let plugin = plugin.as_str();
+    let exec_path = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .and_then(|installed| installed.exec_path())
+        .ok_or_else(|| coffee.not_installed(plugin))?;
+    let file_name = Path::new(&exec_path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let source = format!("plugin-{file_name}");
+
+    let Some(log_path) = log_file_path(coffee).await else {
+        // without a log file the only logs available are the
+        // ones that the node keeps in memory.
+        debug!("log file not found, using the node in memory logs");
+        if follow {
+            return Err(CoffeeError::new(
+                1,
+                "core lightning do not write a log file, `--follow` is not available",
+            ));
+        }
+        for line in plugin_logs(coffee, &exec_path).await {
+            println!("{line}");
+        }
+        return Ok(());
+    };
+    debug!("reading the logs of {plugin} from {log_path}");
+
+    let mut file = File::open(&log_path).await?;
+    let mut content = String::new();
+    loop {
+        content.clear();
+        file.read_to_string(&mut content).await?;
+        content
+            .lines()
+            .filter(|line| line.contains(&source))
+            .for_each(|line| println!("{line}"));
+        if !follow {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(500)).await;
+        // the log file can be rotated by the node.
+        let position = file.stream_position().await?;
+        if std::fs::metadata(&log_path)?.len() < position {
+            file = File::open(&log_path).await?;
+            file.seek(SeekFrom::Start(0)).await?;
+        }
+    }
+}
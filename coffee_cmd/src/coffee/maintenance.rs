@@ -0,0 +1,218 @@
+//! Maintenance windows of the daemon, the automatic upgrades (and so
+//! the restarts of the plugins) run only inside the time windows of
+//! the user, and optionally only when the node has no pending HTLCs.
+//!
+//! The payment critical plugins are never stopped while the node has
+//! HTLCs in flight, unless the user forces the restart.
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::utils::local_minute_of_day;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::transaction::{self, Transaction};
+use super::CoffeeManager;
+
+/// Time window of the day in the local time zone, e.g. `03:00-05:00`,
+/// it can cross the midnight (e.g. `23:00-01:00`), and a window that
+/// ends when it starts is the whole day.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Window {
+    /// first minute of the day inside the window.
+    start: u32,
+    /// first minute of the day after the window.
+    end: u32,
+}
+
+impl Window {
+    /// true when the `minute` of the day is inside the window.
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start == self.end {
+            true
+        } else if self.start < self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// minute of the day of the `time`, e.g. `03:30`.
+fn parse_time(time: &str) -> Result<u32, String> {
+    let (hours, minutes) = time
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("time `{time}` must be HH:MM"))?;
+    let hours: u32 = hours
+        .parse()
+        .map_err(|_| format!("hours of `{time}` are not a number"))?;
+    let minutes: u32 = minutes
+        .parse()
+        .map_err(|_| format!("minutes of `{time}` are not a number"))?;
+    if hours > 24 || minutes > 59 || (hours == 24 && minutes > 0) {
+        return Err(format!("time `{time}` is not inside a day"));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(window: &str) -> Result<Self, Self::Err> {
+        let (start, end) = window
+            .split_once(['-', '–'])
+            .ok_or_else(|| format!("window `{window}` must be HH:MM-HH:MM"))?;
+        Ok(Window {
+            start: parse_time(start)? % 1440,
+            end: parse_time(end)? % 1440,
+        })
+    }
+}
+
+impl TryFrom<String> for Window {
+    type Error = String;
+
+    fn try_from(window: String) -> Result<Self, Self::Error> {
+        window.parse()
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+impl From<Window> for String {
+    fn from(window: Window) -> Self {
+        window.to_string()
+    }
+}
+
+/// Automatic upgrades of the daemon.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConf {
+    /// the daemon upgrades the outdated plugins, instead of only
+    /// notifying them.
+    pub auto_upgrade: bool,
+    /// windows of the upgrades, any time when empty.
+    pub windows: Vec<Window>,
+    /// upgrade only when the node has no HTLCs in flight.
+    pub no_pending_htlcs: bool,
+}
+
+/// Number of the HTLCs in flight on the channels of the node.
+pub async fn pending_htlcs(coffee: &CoffeeManager) -> Result<usize, CoffeeError> {
+    let channels: Value = coffee.cln("listpeerchannels", json!({})).await?;
+    let htlcs = channels["channels"]
+        .as_array()
+        .map(|channels| {
+            channels
+                .iter()
+                .filter_map(|channel| channel["htlcs"].as_array())
+                .map(|htlcs| htlcs.len())
+                .sum()
+        })
+        .unwrap_or_default();
+    Ok(htlcs)
+}
+
+/// The reason why the payment critical `plugin` can not be stopped
+/// now, if any.
+pub async fn restart_blocked(coffee: &CoffeeManager, plugin: &Plugin) -> Option<String> {
+    if !plugin.is_payment_critical()
+        || coffee.config.settings.force_restart
+        || !coffee.is_node_running()
+    {
+        return None;
+    }
+    match pending_htlcs(coffee).await {
+        Ok(0) => None,
+        Ok(htlcs) => Some(format!(
+            "the node has {htlcs} HTLCs in flight, retry later or use --force-restart"
+        )),
+        Err(err) => Some(format!(
+            "HTLCs in flight not checked ({err}), use --force-restart"
+        )),
+    }
+}
+
+/// The reason why the daemon can not upgrade now, if any.
+async fn deferred(coffee: &CoffeeManager, conf: &MaintenanceConf) -> Option<String> {
+    let minute = local_minute_of_day();
+    if !conf.windows.is_empty() && !conf.windows.iter().any(|window| window.contains(minute)) {
+        let windows: Vec<String> = conf.windows.iter().map(Window::to_string).collect();
+        return Some(format!("outside the windows {}", windows.join(", ")));
+    }
+    if conf.no_pending_htlcs && coffee.is_node_running() {
+        match pending_htlcs(coffee).await {
+            Ok(0) => {}
+            Ok(htlcs) => return Some(format!("{htlcs} HTLCs in flight")),
+            Err(err) => return Some(format!("HTLCs not checked: {err}")),
+        }
+    }
+    None
+}
+
+/// Upgrade the `outdated` plugins when the maintenance settings allow
+/// it, return true when they are upgraded.
+pub async fn auto_upgrade(coffee: &mut CoffeeManager, outdated: &BTreeSet<String>) -> bool {
+    let conf = coffee.config.settings.maintenance.clone();
+    if !conf.auto_upgrade || outdated.is_empty() {
+        return false;
+    }
+    if let Some(reason) = deferred(coffee, &conf).await {
+        println!("automatic upgrade deferred, {reason}");
+        return false;
+    }
+    println!(
+        "automatic upgrade of {}",
+        outdated.iter().cloned().collect::<Vec<_>>().join(", ")
+    );
+    // a failed upgrade does not leave the node with half of the plugins.
+    let transaction = Some(Transaction::begin(coffee));
+    let plugins: Vec<&str> = outdated.iter().map(String::as_str).collect();
+    let result = coffee.upgrade(&plugins).await;
+    match transaction::finish(coffee, transaction, result).await {
+        Ok(()) => true,
+        Err(err) => {
+            println!("automatic upgrade failed: {err}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Window;
+
+    #[test]
+    fn test_maintenance_window() {
+        let window: Window = "03:00-05:00".parse().unwrap();
+        assert!(window.contains(3 * 60));
+        assert!(window.contains(4 * 60 + 59));
+        assert!(!window.contains(5 * 60));
+        let night: Window = "23:30–01:00".parse().unwrap();
+        assert!(night.contains(23 * 60 + 45));
+        assert!(night.contains(30));
+        assert!(!night.contains(12 * 60));
+        assert_eq!(night.to_string(), "23:30-01:00");
+        assert!("00:00-24:00".parse::<Window>().unwrap().contains(12 * 60));
+        assert!("25:00-01:00".parse::<Window>().is_err());
+        assert!("03:00".parse::<Window>().is_err());
+    }
+}
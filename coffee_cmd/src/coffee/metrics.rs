@@ -0,0 +1,166 @@
+//! Metrics of the daemon mode in the Prometheus text format, so
+//! the monitoring of the node can alert on stale or broken plugins.
+use std::sync::{Arc, Mutex};
+
+use coffee_lib::errors::CoffeeError;
+use log::{debug, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::top::Usage;
+
+/// Snapshot of the state of the plugins, updated by the daemon
+/// after every check.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub installed: usize,
+    pub outdated: usize,
+    pub unhealthy: usize,
+    pub repositories: usize,
+    /// unix time of the most recent update of a repository.
+    pub last_update: Option<u64>,
+    /// plugins that failed to install since coffee was set up.
+    pub install_failures: u64,
+    /// unix time of the last check of the daemon.
+    pub last_check: u64,
+    /// resource usage of the plugins running.
+    pub usage: Vec<Usage>,
+}
+
+impl Metrics {
+    /// render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut metrics = vec![
+            (
+                "coffee_plugins_installed",
+                "gauge",
+                "plugins installed by coffee.",
+                self.installed as u64,
+            ),
+            (
+                "coffee_plugins_outdated",
+                "gauge",
+                "installed plugins with a new version upstream.",
+                self.outdated as u64,
+            ),
+            (
+                "coffee_plugins_unhealthy",
+                "gauge",
+                "installed plugins that are not running inside the node.",
+                self.unhealthy as u64,
+            ),
+            (
+                "coffee_repositories",
+                "gauge",
+                "repositories known by coffee.",
+                self.repositories as u64,
+            ),
+            (
+                "coffee_install_failures_total",
+                "counter",
+                "plugins that failed to install.",
+                self.install_failures,
+            ),
+            (
+                "coffee_last_check_timestamp_seconds",
+                "gauge",
+                "unix time of the last check of the daemon.",
+                self.last_check,
+            ),
+        ];
+        if let Some(last_update) = self.last_update {
+            metrics.push((
+                "coffee_last_update_timestamp_seconds",
+                "gauge",
+                "unix time of the last update of a repository.",
+                last_update,
+            ));
+        }
+        let mut text: String = metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n")
+            })
+            .collect();
+        if !self.usage.is_empty() {
+            text.push_str("# HELP coffee_plugin_cpu_percent cpu used by the plugin, 100 is one core.\n# TYPE coffee_plugin_cpu_percent gauge\n");
+            for usage in &self.usage {
+                text.push_str(&format!(
+                    "coffee_plugin_cpu_percent{{plugin=\"{}\"}} {:.1}\n",
+                    usage.plugin, usage.cpu_percent
+                ));
+            }
+            text.push_str("# HELP coffee_plugin_memory_bytes resident memory of the plugin.\n# TYPE coffee_plugin_memory_bytes gauge\n");
+            for usage in &self.usage {
+                text.push_str(&format!(
+                    "coffee_plugin_memory_bytes{{plugin=\"{}\"}} {}\n",
+                    usage.plugin, usage.memory
+                ));
+            }
+        }
+        text
+    }
+}
+
+/// Serve the `metrics` on the `bind` address, to every request.
+pub async fn serve(bind: &str, metrics: Arc<Mutex<Metrics>>) -> Result<(), CoffeeError> {
+    let listener = TcpListener::bind(bind)
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("{bind} not available: {err}")))?;
+    println!("coffee metrics on http://{bind}/metrics");
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+        debug!("{peer} asked the metrics");
+        // the request is not interesting, every path has the metrics.
+        let mut request = [0u8; 1024];
+        let _ = stream.read(&mut request).await;
+        let body = metrics
+            .lock()
+            .map(|metrics| metrics.render())
+            .unwrap_or_default();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        if let Err(err) = stream.write_all(response.as_bytes()).await {
+            warn!("metrics not sent: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::coffee::top::Usage;
+
+    #[test]
+    fn test_metrics_render() {
+        let metrics = Metrics {
+            installed: 3,
+            outdated: 1,
+            last_update: Some(1700000000),
+            ..Metrics::default()
+        };
+        let text = metrics.render();
+        assert!(
+            text.contains("# TYPE coffee_plugins_installed gauge\ncoffee_plugins_installed 3\n")
+        );
+        assert!(text.contains("coffee_plugins_outdated 1\n"));
+        assert!(text.contains("coffee_last_update_timestamp_seconds 1700000000\n"));
+        let metrics = Metrics {
+            usage: vec![Usage {
+                plugin: "summary".to_owned(),
+                pids: vec![42],
+                cpu_percent: 12.5,
+                memory: 4096,
+            }],
+            ..Metrics::default()
+        };
+        let text = metrics.render();
+        assert!(text.contains("coffee_plugin_cpu_percent{plugin=\"summary\"} 12.5\n"));
+        assert!(text.contains("coffee_plugin_memory_bytes{plugin=\"summary\"} 4096\n"));
+        assert!(!Metrics::default()
+            .render()
+            .contains("coffee_last_update_timestamp_seconds"));
+    }
+}
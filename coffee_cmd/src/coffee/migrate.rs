@@ -0,0 +1,132 @@
+//! Migration from the python `reckless` plugin manager
+//! shipped with core lightning.
+//!
+//! reckless keeps the list of the sources inside the file
+//! `<lightning-dir>/reckless/.sources`, and it enables the
+//! plugins inside the file `<network>-reckless.conf` that is
+//! included inside the core lightning configuration.
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::{Plugin, PluginLang};
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::url::URL;
+use coffee_lib::utils::{atomic_write, get_shebang_interpreter};
+use log::{debug, warn};
+use tokio::fs::read_to_string;
+
+use super::CoffeeManager;
+
+/// Adopt the plugins and the sources managed by the python
+/// reckless inside coffee, and remove the reckless include
+/// from the core lightning configuration.
+pub async fn migrate_from_python(
+    coffee: &mut CoffeeManager,
+    reckless_dir: Option<String>,
+) -> Result<(), CoffeeError> {
+    let (Some(cln_root), Some(cln_config_path)) = (
+        coffee.config.cln_root.clone(),
+        coffee.config.cln_config_path.clone(),
+    ) else {
+        return Err(CoffeeError::new(
+            1,
+            "coffee is not configured with core lightning, run `coffee setup` first",
+        ));
+    };
+    let reckless_dir = reckless_dir.unwrap_or(format!("{cln_root}/reckless"));
+    if !Path::new(&reckless_dir).exists() {
+        let err = CoffeeError::new(1, &format!("reckless directory {reckless_dir} not found"));
+        return Err(err);
+    }
+    println!("migrating reckless from {reckless_dir}");
+
+    // 1. the sources become coffee repositories.
+    let sources_path = format!("{reckless_dir}/.sources");
+    let sources = read_to_string(&sources_path).await.unwrap_or_default();
+    for source in sources
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        let url = match URL::parse(&coffee.install_root(), source, "") {
+            Ok(url) => url,
+            Err(err) => {
+                println!("[{source}] source not adopted: {err}");
+                continue;
+            }
+        };
+        if coffee
+            .repos
+            .iter()
+            .any(|repo| repo.url().url_string == url.url_string)
+        {
+            println!("[{}] source already present", url.repo_name);
+            continue;
+        }
+        match coffee.add_remote(&url.repo_name, source).await {
+            Ok(()) => println!("[{}] source adopted", url.repo_name),
+            Err(err) => println!("[{}] source not adopted: {err}", url.repo_name),
+        }
+    }
+
+    // 2. the plugins enabled by reckless become coffee plugins.
+    let network = coffee.config.network.clone();
+    let reckless_conf = format!("{reckless_dir}/{network}-reckless.conf");
+    let content = read_to_string(&reckless_conf).await.unwrap_or_default();
+    for line in content.lines().map(str::trim) {
+        if let Some(disabled) = line.strip_prefix("disable-plugin=") {
+            warn!("plugin {disabled} is disabled inside reckless, it is not migrated");
+            continue;
+        }
+        let Some(exec_path) = line.strip_prefix("plugin=") else {
+            continue;
+        };
+        let Some(plugin_dir) = Path::new(exec_path).parent() else {
+            continue;
+        };
+        let name = plugin_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if coffee
+            .config
+            .plugins
+            .iter()
+            .any(|plugin| plugin.name() == name)
+        {
+            println!("[{name}] plugin already present");
+            continue;
+        }
+        let lang = get_shebang_interpreter(Path::new(exec_path))
+            .map(|interpreter| PluginLang::from_shebang(&interpreter))
+            .unwrap_or(PluginLang::Unknown);
+        let plugin_dir = plugin_dir.to_string_lossy().to_string();
+        let mut plugin = Plugin::new(&name, &plugin_dir, &plugin_dir, lang, None);
+        plugin.set_exec_path(exec_path);
+        coffee
+            .coffe_cln_config
+            .add_conf("plugin", exec_path)
+            .map_err(|err| CoffeeError::new(1, &err.cause))?;
+        coffee.config.plugins.push(plugin);
+        println!("[{name}] plugin adopted");
+    }
+
+    // 3. core lightning should load the plugins only from coffee.
+    let cln_conf = read_to_string(&cln_config_path).await?;
+    let mut rewritten = String::new();
+    for line in cln_conf.lines() {
+        if line.trim().starts_with("include") && line.contains("reckless.conf") {
+            debug!("disabling reckless include: {line}");
+            rewritten += &format!("# {line} (migrated to coffee)\n");
+        } else {
+            rewritten += &format!("{line}\n");
+        }
+    }
+    atomic_write(&cln_config_path, rewritten.as_bytes()).await?;
+
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    println!("reckless migration completed, the reckless include is disabled in {cln_config_path}");
+    Ok(())
+}
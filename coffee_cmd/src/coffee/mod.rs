@@ -1,17 +1,33 @@
 //! Coffee mod implementation
+use self::audit::{record, Change};
 use self::cmd::CoffeeArgs;
-use self::config::CoffeeConf;
+use self::config::{default_repositories, CoffeeConf, FailurePolicy, Settings};
+use self::health::PluginHealth;
+use self::notify::{notify, Event};
+use self::plugin_env::PluginEnv;
+use self::state_lock::StateLock;
 use async_trait::async_trait;
 use clightningrpc_common::client::Client;
 use clightningrpc_common::json_utils;
 use clightningrpc_conf::{CLNConf, SyncCLNConf};
+use coffee_github::git;
 use coffee_github::repository::Github;
+use coffee_lib::download;
 use coffee_lib::errors::CoffeeError;
-use coffee_lib::plugin::Plugin;
-use coffee_lib::plugin_manager::PluginManager;
-use coffee_lib::repository::Repository;
+use coffee_lib::i18n::{self, tr, Locale};
+use coffee_lib::interrupt;
+use coffee_lib::names;
+use coffee_lib::plugin::{Plugin, PluginLang};
+use coffee_lib::plugin_manager::{PluginInspector, PluginManager};
+use coffee_lib::python;
+use coffee_lib::repository::{Layout, Repository};
+use coffee_lib::run_as::RunAs;
+use coffee_lib::sandbox::Sandbox;
+use coffee_lib::secrets;
 use coffee_lib::url::URL;
+use coffee_lib::utils::{atomic_write, home_dir, is_inside, unix_time};
 use coffee_storage::file::FileStorage;
+use coffee_storage::migration::Migration;
 use coffee_storage::model::repository::{Kind, Repository as RepositoryInfo};
 use coffee_storage::storage::StorageManager;
 use log::{debug, error, info, trace, warn};
@@ -19,11 +35,92 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
+use std::collections::BTreeSet;
+use std::env;
 use std::fmt::Debug;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::vec::Vec;
+use tokio::fs::{create_dir_all, remove_dir_all};
+use tokio::task::{spawn_blocking, JoinSet};
 
+pub mod alias;
+pub mod apply;
+pub mod audit;
+pub mod autoremove;
+pub mod backup;
+pub mod buildlog;
+pub mod bundle;
+pub mod canary;
+pub mod checksums;
 pub mod cmd;
+pub mod command_alias;
 mod config;
+pub mod conflicts;
+pub mod crash_loop;
+pub mod daemon;
+pub mod dev;
+pub mod diagnostics;
+pub mod disable;
+pub mod export;
+pub mod features;
+pub mod github_api;
+pub mod graph;
+pub mod health;
+pub mod http_cache;
+pub mod index;
+pub mod logs;
+pub mod maintenance;
+pub mod metrics;
+pub mod migrate;
+pub mod notify;
+pub mod nurse;
+pub mod open;
+pub mod options;
+pub mod patch;
+pub mod plugin_env;
+pub mod plugin_override;
+pub mod profile;
+pub mod readme;
+pub mod regtest;
+pub mod reserved;
+pub mod run;
+pub mod search;
+pub mod selector;
+pub mod self_update;
+pub mod serve;
+pub mod ssh;
+pub mod state_lock;
+pub mod status;
+pub mod systemd;
+pub mod top;
+pub mod transaction;
+pub mod trial;
+pub mod trust;
+pub mod tui;
+pub mod why;
+pub mod worktree;
+
+/// Networks where core lightning can run.
+const NETWORKS: [&str; 4] = ["bitcoin", "testnet", "signet", "regtest"];
+
+/// Migrations of the coffee storage schema, the position
+/// inside the list is the version where the migration starts.
+const STORAGE_MIGRATIONS: [Migration; 1] = [
+    // version 0 -> 1: the first versioned schema, the repositories
+    // know the time of the last update.
+    |storage| {
+        if let Some(repos) = storage["repositories"].as_array_mut() {
+            for repo in repos {
+                if repo.get("last_update").is_none() {
+                    repo["last_update"] = Value::Null;
+                }
+            }
+        }
+        Ok(())
+    },
+];
 
 #[derive(Serialize, Deserialize)]
 /// FIXME: move the list of plugin
@@ -66,35 +163,129 @@ pub struct CoffeeManager {
     storage: Box<dyn StorageManager<CoffeStorageInfo, Err = CoffeeError> + Send + Sync>,
     /// core lightning rpc connection
     rpc: Option<Client>,
+    /// the state of coffee is never written, see `read_only`.
+    read_only: bool,
+    /// lock of the state, held by the commands that change it.
+    state_lock: Option<StateLock>,
 }
 
 impl CoffeeManager {
     pub async fn new(conf: &CoffeeArgs) -> Result<Self, CoffeeError> {
-        let conf = CoffeeConf::new(conf).await?;
+        Self::open(conf, false).await
+    }
+
+    /// Open coffee for the read-only commands, the local state is
+    /// never written and the network is never reached, so the
+    /// inspection commands are safe to run e.g. from a cron job.
+    pub async fn read_only(conf: &CoffeeArgs) -> Result<Self, CoffeeError> {
+        Self::open(conf, true).await
+    }
+
+    async fn open(conf: &CoffeeArgs, read_only: bool) -> Result<Self, CoffeeError> {
+        let long_running = conf.command.is_long_running();
+        let conf = CoffeeConf::new(conf, read_only).await?;
+        // the long running commands would keep the other commands out,
+        // they take the lock for each operation, see `lock_state`.
+        let state_lock = if read_only || long_running {
+            None
+        } else {
+            Some(StateLock::acquire(&conf.root_path)?)
+        };
+        download::configure(&conf.settings.downloads);
+        i18n::configure(Locale::detect(conf.settings.locale.as_deref()));
+        secrets::configure(conf.settings.reveal_secrets);
+        let mut storage = FileStorage::new(&conf.root_path).with_migrations(&STORAGE_MIGRATIONS);
+        if read_only {
+            storage = storage.read_only();
+        }
         let mut coffee = CoffeeManager {
             config: conf.clone(),
             coffe_cln_config: CLNConf::new(conf.config_path, true),
             repos: vec![],
-            storage: Box::new(FileStorage::new(&conf.root_path)),
+            storage: Box::new(storage),
             cln_config: None,
             rpc: None,
+            read_only,
+            state_lock,
         };
         coffee.inventory().await?;
         Ok(coffee)
     }
 
+    /// Undo the install of the `plugin` that runs from `path`: its
+    /// entries of the configuration are removed, the worktree and the
+    /// pin it had before the install are restored with the other
+    /// failed installs.
+    async fn rollback_install(&mut self, plugin: &Plugin, path: &str) {
+        if let Some(position) = self
+            .config
+            .plugins
+            .iter()
+            .rposition(|installed| installed.name() == plugin.name())
+        {
+            self.config.plugins.remove(position);
+        }
+        if let Some(paths) = self.coffe_cln_config.fields.get_mut("plugin") {
+            paths.retain(|installed| installed != path);
+        }
+        let repo = self.repo_of(plugin);
+        let change = Change::Remove {
+            plugin: plugin.name(),
+            repository: repo.map(|repo| repo.name()),
+            commit: repo.and_then(|repo| repo.commit().ok()),
+        };
+        record(self, change).await;
+    }
+
+    /// Take the lock of the state for an operation of a long running
+    /// command, the state is loaded again because the other commands
+    /// can change it while the lock is not held.
+    pub async fn lock_state(&mut self) -> Result<(), CoffeeError> {
+        if self.state_lock.is_none() {
+            self.state_lock = Some(StateLock::acquire(&self.config.root_path)?);
+        }
+        self.repos.clear();
+        self.coffe_cln_config = CLNConf::new(self.config.config_path.clone(), true);
+        self.inventory().await
+    }
+
+    /// Release the lock of the state at the end of an operation of a
+    /// long running command, or before the process exits, the exit
+    /// does not drop coffee.
+    pub fn release_state_lock(&mut self) {
+        if self.state_lock.take().is_some() {
+            debug!("state lock released");
+        }
+    }
+
     /// when coffee is configure run an inventory to collect all the necessary information
     /// about the coffee ecosystem.
     async fn inventory(&mut self) -> Result<(), CoffeeError> {
-        let store = if let Ok(store) = self.storage.load().await {
-            store
-        } else {
-            info!("storage file do not exist");
-            return Ok(());
+        let store = match self.storage.load().await {
+            Ok(store) => store,
+            // a storage that can not be loaded must not be overridden.
+            Err(err) if Path::new(&format!("{}/storage.json", self.config.root_path)).exists() => {
+                return Err(err)
+            }
+            Err(_) => {
+                info!("storage file do not exist");
+                return Ok(());
+            }
         };
         // this is really needed? I think no, because coffee at this point
         // have a new conf loading
+        let settings = self.config.settings.clone();
+        let (home_path, profile) = (self.config.home_path.clone(), self.config.profile.clone());
         self.config = store.config;
+        self.config.settings = settings;
+        self.config.home_path = home_path;
+        self.config.profile = profile;
+        for (plugin, keys) in &self.config.secrets {
+            let options = self.config.options.get(plugin);
+            keys.iter()
+                .filter_map(|key| options.and_then(|options| options.get(key)))
+                .for_each(|value| secrets::register(value));
+        }
         store.repositories.iter().for_each(|repo| match repo.kind {
             Kind::Git => {
                 let repo = Github::from(repo);
@@ -144,22 +335,321 @@ impl CoffeeManager {
         Ok(())
     }
 
+    /// add all the well-known repositories shipped with coffee,
+    /// the repositories already present are skipped.
+    pub async fn add_default_remotes(&mut self) -> Result<(), CoffeeError> {
+        let mut failures = 0;
+        for default in default_repositories()? {
+            let present = self.repos.iter().any(|repo| {
+                repo.name() == default.name
+                    || repo.url().url_string == URL::new("", &default.url, "").url_string
+            });
+            if present {
+                println!("[{}] already present", default.name);
+                continue;
+            }
+            match self
+                .add_remote_with_layout(&default.name, &default.url, default.layout)
+                .await
+            {
+                Ok(()) => println!("[{}] added {}", default.name, default.url),
+                Err(err) => {
+                    println!("[{}] failed: {err}", default.name);
+                    failures += 1;
+                }
+            }
+        }
+        if failures > 0 {
+            let err = CoffeeError::new(1, &format!("{failures} default repositories not added"));
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// check and install a new version of coffee.
+    pub async fn self_update(&self, check: bool) -> Result<(), CoffeeError> {
+        self_update::self_update(&self.config.settings, &self.config.root_path, check).await
+    }
+
+    /// search the plugin by name inside all the repositories.
+    pub fn search_plugin(&self, name: &str) -> Option<Plugin> {
+        // a repository of an override provides only the
+        // overridden plugin.
+        if let Some(plugin_override) = self.config.overrides.get(name) {
+            return self
+                .repos
+                .iter()
+                .find(|repo| repo.name() == plugin_override.repository)
+                .and_then(|repo| repo.get_plugin_by_name(name));
+        }
+        self.repos
+            .iter()
+            .filter(|repo| {
+                !self
+                    .config
+                    .overrides
+                    .values()
+                    .any(|plugin_override| plugin_override.repository == repo.name())
+            })
+            .find_map(|repo| repo.get_plugin_by_name(name))
+    }
+
+    /// name of the plugin that the `name` is an alias of, or the
+    /// name of the installed or indexed plugin that the `name` matches
+    /// (without the case, with `-` as `_`), or the `name` itself.
+    pub fn resolve_alias(&self, name: &str) -> String {
+        let name = self
+            .config
+            .aliases
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_owned());
+        if let Some(installed) = self
+            .config
+            .plugins
+            .iter()
+            .find(|installed| names::matches(&installed.name(), &name))
+        {
+            return installed.name();
+        }
+        self.search_plugin(&name)
+            .map(|plugin| plugin.name())
+            .unwrap_or(name)
+    }
+
+    /// Error for the `name` of a plugin that is not installed, with
+    /// the installed plugins that have a similar name.
+    pub fn not_installed(&self, name: &str) -> CoffeeError {
+        let message = tr("plugin-not-installed", &[("plugin", name)]);
+        let installed: Vec<String> = self
+            .config
+            .plugins
+            .iter()
+            .map(|plugin| plugin.name())
+            .collect();
+        let message = did_you_mean(message, name, installed.iter().map(String::as_str));
+        CoffeeError::new(1, &message)
+    }
+
+    /// Error for the `name` of a plugin that is not inside the
+    /// repositories, with the plugins that have a similar name.
+    pub fn not_found(&self, name: &str) -> CoffeeError {
+        let message = format!("plugin `{name}` are not present inside the repositories");
+        let indexed: Vec<String> = self
+            .repos
+            .iter()
+            .flat_map(|repo| repo.plugin_names())
+            .collect();
+        let message = did_you_mean(message, name, indexed.iter().map(String::as_str));
+        CoffeeError::new(1, &message)
+    }
+
+    /// directory where the repositories are cloned, it is the
+    /// coffee root unless an install root is configured.
+    pub fn install_root(&self) -> String {
+        self.config
+            .settings
+            .install_root
+            .clone()
+            .unwrap_or_else(|| self.config.root_path.clone())
+    }
+
+    /// true when the checkouts of the repositories can be shared
+    /// with other profiles.
+    pub fn is_install_root_shared(&self) -> bool {
+        self.config.settings.install_root.is_some()
+    }
+
+    /// true when the upstream of the repository changed the `plugin`,
+    /// a pinned plugin is never outdated.
+    pub fn is_outdated(&self, plugin: &Plugin) -> bool {
+        if self.config.pins.contains_key(&plugin.name()) {
+            return false;
+        }
+        self.repo_of(plugin)
+            .map(|repo| repo.is_plugin_outdated(plugin))
+            .unwrap_or_default()
+    }
+
+    /// repository where the plugin comes from.
+    pub fn repo_of(&self, plugin: &Plugin) -> Option<&(dyn Repository + Send + Sync)> {
+        self.repos
+            .iter()
+            .find(|repo| is_inside(&plugin.root_path(), &repo.url().path_string))
+            .map(|repo| repo.as_ref())
+    }
+
+    pub async fn stop_plugin(&self, path: &str) -> Result<(), CoffeeError> {
+        let mut payload = json_utils::init_payload();
+        json_utils::add_str(&mut payload, "subcommand", "stop");
+        json_utils::add_str(&mut payload, "plugin", path);
+        let response = self
+            .cln::<serde_json::Value, serde_json::Value>("plugin", payload)
+            .await?;
+        debug!("plugin stopped: {response}");
+        Ok(())
+    }
+
+    /// verify the integrity of the repository against its upstream,
+    /// the upstream is not fetched when coffee is offline.
+    pub async fn verify_remote(&self, name: &str) -> Result<(), CoffeeError> {
+        let repo = self
+            .repos
+            .iter()
+            .find(|repo| repo.name() == name)
+            .ok_or_else(|| {
+                CoffeeError::new(1, &tr("repository-not-found", &[("repository", name)]))
+            })?;
+        let fetch = !self.config.settings.offline;
+        if !fetch {
+            println!("coffee is offline, using the last fetched upstream");
+        }
+        repo.verify(fetch).await?;
+        println!("[{name}] checkout is part of the upstream history");
+        let changes = repo.local_changes()?;
+        if changes.is_empty() {
+            println!("[{name}] working tree is clean");
+        } else {
+            println!("[{name}] warning: local modifications are overridden by an update:");
+            changes.iter().for_each(|change| println!("  - {change}"));
+        }
+        Ok(())
+    }
+
+    /// index the repository at the `reference` (a tag, a branch or a
+    /// commit) without touching its checkout, the files of the commit
+    /// are written inside a temporary directory and indexed there.
+    pub async fn browse_remote(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<index::IndexReport, CoffeeError> {
+        let repo = self
+            .repos
+            .iter()
+            .find(|repo| repo.name() == name)
+            .ok_or_else(|| {
+                CoffeeError::new(1, &tr("repository-not-found", &[("repository", name)]))
+            })?;
+        let path = repo.url().path_string;
+        let git = git::backend();
+        let commit = git.resolve(&path, reference)?;
+        let dir = format!(
+            "{}/coffee-browse-{name}-{commit}",
+            env::temp_dir().to_string_lossy()
+        );
+        if Path::new(&dir).exists() {
+            remove_dir_all(&dir).await?;
+        }
+        let report = match git.export_tree(&path, &commit, &dir) {
+            Ok(()) => index::index_report(Path::new(&dir)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = remove_dir_all(&dir).await {
+            debug!("directory {dir} not removed: {err}");
+        }
+        let mut report = report?;
+        report.repository = format!("{name}@{reference} ({commit})");
+        Ok(report)
+    }
+
+    /// start a transaction when the operations are atomic.
+    pub fn begin(&self) -> Option<transaction::Transaction> {
+        self.config
+            .settings
+            .atomic
+            .then(|| transaction::Transaction::begin(self))
+    }
+
+    /// mutable access to the settings of coffee, used to
+    /// bind the options of a single command.
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.config.settings
+    }
+
+    /// sandbox where the build commands of the plugins run.
+    pub fn sandbox(&self) -> Sandbox {
+        let settings = &self.config.settings;
+        Sandbox::new(
+            settings.sandbox.clone(),
+            settings.sandbox_env.clone(),
+            settings.build_limits.clone(),
+        )
+        .with_container(settings.container_build.clone())
+        .with_python(
+            settings
+                .python
+                .clone()
+                .with_lock_dir(&format!("{}/locks", self.config.root_path))
+                .with_shared_dir(&format!("{}/python", self.config.root_path)),
+        )
+        .with_node(
+            settings
+                .node
+                .clone()
+                .with_runtime_dir(&format!("{}/node", self.config.root_path)),
+        )
+        .with_go(settings.go.clone().with_offline(settings.offline))
+        .with_cargo(
+            settings
+                .cargo
+                .clone()
+                .with_home_dir(&format!("{}/cargo", self.config.root_path))
+                .with_offline(settings.offline),
+        )
+        .with_build_log_dir(&format!("{}/buildlogs", self.config.root_path))
+        .with_wrapper_dir(&format!("{}/scripts", self.config.root_path))
+        .with_plain(settings.plain)
+    }
+
+    /// dedicated user of the plugins, if any, with the directory
+    /// of the wrappers that run the plugins as this user.
+    pub fn run_as(&self) -> Option<(RunAs, String)> {
+        let run_as = self.config.settings.run_as.clone()?;
+        Some((run_as, format!("{}/wrappers", self.config.root_path)))
+    }
+
+    /// encrypted environment variables of the plugins.
+    pub fn plugin_env(&self) -> PluginEnv {
+        PluginEnv::new(&format!("{}/env", self.config.root_path))
+    }
+
     pub fn storage_info(&self) -> CoffeStorageInfo {
         CoffeStorageInfo::from(self)
     }
 
     pub async fn update_conf(&self) -> Result<(), CoffeeError> {
+        if self.read_only {
+            return Err(CoffeeError::new(
+                1,
+                "coffee is opened read-only, the configuration is not written",
+            ));
+        }
         self.coffe_cln_config.flush()?;
         debug!("stored all the cln info in {}", self.coffe_cln_config);
         Ok(())
     }
 
+    /// path of the core lightning rpc socket, if coffee
+    /// is configured with core lightning.
+    pub fn rpc_path(&self) -> Option<String> {
+        let root = self.config.cln_root.as_ref()?;
+        Some(format!("{root}/{}/lightning-rpc", self.config.network))
+    }
+
+    /// true when the core lightning rpc socket exists, this do not
+    /// guarantee that the node is answering.
+    pub fn is_node_running(&self) -> bool {
+        self.rpc_path()
+            .map(|path| Path::new(&path).exists())
+            .unwrap_or_default()
+    }
+
     pub async fn load_cln_conf(&mut self) -> Result<(), CoffeeError> {
         if self.config.cln_config_path.is_none() {
             return Ok(());
         }
-        let root = self.config.cln_root.clone().unwrap();
-        let rpc = Client::new(format!("{root}/{}/lightning-rpc", self.config.network));
+        let rpc = Client::new(self.rpc_path().unwrap_or_default());
         self.rpc = Some(rpc);
         let path = self.config.cln_config_path.clone().unwrap();
         let mut file = CLNConf::new(path.clone(), true);
@@ -171,6 +661,368 @@ impl CoffeeManager {
         Ok(())
     }
 
+    /// guided configuration of coffee for a new user, it detects
+    /// the core lightning directory and network when they are not
+    /// provided, and checks that the node can be reached.
+    pub async fn setup_wizard(&mut self, cln_dir: Option<String>) -> Result<(), CoffeeError> {
+        let cln_dir = match cln_dir {
+            Some(cln_dir) => cln_dir,
+            None => {
+                let home = home_dir().unwrap_or_default();
+                let cln_dir = format!("{home}/.lightning");
+                if !Path::new(&cln_dir).exists() {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!(
+                            "core lightning directory not found in {cln_dir}, please provide it"
+                        ),
+                    );
+                    return Err(err);
+                }
+                println!("found core lightning directory {cln_dir}");
+                cln_dir
+            }
+        };
+
+        // when the node is not running on the network chosen,
+        // look for the network where the node is running.
+        if !Path::new(&format!("{cln_dir}/{}", self.config.network)).exists() {
+            if let Some(network) = NETWORKS
+                .iter()
+                .find(|network| Path::new(&format!("{cln_dir}/{network}")).exists())
+            {
+                println!("found core lightning running on {network}");
+                self.config.network = network.to_string();
+                self.config.config_path =
+                    format!("{}/{network}/coffee.conf", self.config.root_path);
+                self.coffe_cln_config = CLNConf::new(self.config.config_path.clone(), true);
+            }
+        }
+        let network_dir = format!("{}/{}", self.config.root_path, self.config.network);
+        if !Path::new(&network_dir).exists() {
+            create_dir_all(&network_dir).await?;
+        }
+
+        let settings_path = format!("{}/config.yml", self.config.root_path);
+        if !Path::new(&settings_path).exists() {
+            // the command line arguments are not persisted.
+            let settings = serde_yaml::to_string(&Settings::default())
+                .map_err(|err| CoffeeError::new(1, &format!("{err}")))?;
+            atomic_write(&settings_path, settings.as_bytes()).await?;
+            println!("created coffee configuration {settings_path}");
+        }
+
+        self.setup(&cln_dir).await?;
+        println!("coffee configured with core lightning in {cln_dir}");
+
+        if self.repos.is_empty() {
+            // the community repository is the first of the defaults.
+            if let Some(community) = default_repositories()?.first() {
+                let question = format!("add the community repository {}?", community.url);
+                if self.config.settings.confirm.confirm(&question)? {
+                    self.add_remote(&community.name, &community.url).await?;
+                }
+            }
+        }
+
+        match self.cln::<Value, Value>("getinfo", json!({})).await {
+            Ok(info) => println!("connected to the node {}", info["id"]),
+            Err(err) => println!("node not reachable, is core lightning running? {err}"),
+        }
+        Ok(())
+    }
+
+    /// Add the repository `name` at `url`, the plugins are indexed
+    /// following the `layout` of the repository.
+    pub async fn add_remote_with_layout(
+        &mut self,
+        name: &str,
+        url: &str,
+        layout: Layout,
+    ) -> Result<(), CoffeeError> {
+        if self.repos.iter().any(|repo| repo.name() == name) {
+            let err = CoffeeError::new(1, &format!("repository `{name}` already exists"));
+            return Err(err);
+        }
+        let url = URL::parse(&self.install_root(), url, name)?;
+        debug!("remote adding: {} {}", name, &url.url_string);
+        let mut repo = Github::new(name, &url).with_layout(layout);
+        let shared = self.is_install_root_shared() && Path::new(&url.path_string).exists();
+        if shared {
+            println!("[{name}] using the checkout shared with the other profiles");
+            repo.index_repository().await?;
+        } else {
+            repo.init().await?;
+        }
+        // nothing inside the checkout ran yet.
+        if let Err(err) = trust::trust_repository(self, &repo).await {
+            if !shared {
+                remove_dir_all(&url.path_string).await?;
+            }
+            return Err(err);
+        }
+        self.repos.push(Box::new(repo));
+        debug!("remote added: {} {}", name, &url.url_string);
+        self.storage.store(&self.storage_info()).await?;
+        let change = Change::AddRemote {
+            name: name.to_owned(),
+            url: url.url_string.clone(),
+        };
+        record(self, change).await;
+        Ok(())
+    }
+
+    /// Name of the repository at `url` when the user does not give
+    /// one, derived from the url and made unique among the repositories.
+    pub fn remote_name(&self, url: &str) -> Result<String, CoffeeError> {
+        let url = URL::parse(&self.install_root(), url, "")?;
+        if let Some(repo) = self
+            .repos
+            .iter()
+            .find(|repo| repo.url().url_string == url.url_string)
+        {
+            let err = CoffeeError::new(
+                1,
+                &format!(
+                    "repository `{}` already added as `{}`",
+                    url.url_string,
+                    repo.name()
+                ),
+            );
+            return Err(err);
+        }
+        let base = url.default_name();
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while self.repos.iter().any(|repo| repo.name() == name) {
+            name = format!("{base}-{suffix}");
+            suffix += 1;
+        }
+        Ok(name)
+    }
+
+    /// Add the repository at `url` with the name derived from it.
+    pub async fn add_remote_from_url(
+        &mut self,
+        url: &str,
+        layout: Layout,
+    ) -> Result<(), CoffeeError> {
+        let name = self.remote_name(url)?;
+        self.add_remote_with_layout(&name, url, layout).await?;
+        println!("[{name}] added {url}");
+        Ok(())
+    }
+
+    /// Index again the repository `name` with the `layout`.
+    pub async fn set_remote_layout(
+        &mut self,
+        name: &str,
+        layout: Layout,
+    ) -> Result<(), CoffeeError> {
+        let repo = self
+            .repos
+            .iter_mut()
+            .find(|repo| repo.name() == name)
+            .ok_or_else(|| {
+                CoffeeError::new(1, &tr("repository-not-found", &[("repository", name)]))
+            })?;
+        repo.set_layout(layout).await?;
+        println!(
+            "[{name}] indexed with the {layout} layout, {} plugins found",
+            repo.list().await?.len()
+        );
+        self.storage.store(&self.storage_info()).await
+    }
+
+    /// upgrade the repository at `index`, saving its local
+    /// modifications when allowed, and rebuild its plugins.
+    ///
+    /// Return how many plugins were not rebuilt.
+    async fn upgrade_repository(&mut self, index: usize) -> Result<usize, CoffeeError> {
+        let name = self.repos[index].name();
+        let patched = patch::patched_files(self, &self.repos[index].url().path_string).await;
+        let changes: Vec<String> = self.repos[index]
+            .local_changes()?
+            .into_iter()
+            .filter(|change| !patched.contains(change))
+            .collect();
+        let mut stash = None;
+        if !changes.is_empty() {
+            if !self.config.settings.stash_local {
+                let err = CoffeeError::new(
+                    1,
+                    &format!(
+                        "repository `{name}` has local modifications ({}), use `--stash-local` to save them",
+                        changes.join(", ")
+                    ),
+                );
+                return Err(err);
+            }
+            let patches_dir = format!("{}/patches", self.config.root_path);
+            create_dir_all(&patches_dir).await?;
+            let patch = self.repos[index].local_diff()?;
+            let patch_path = format!("{patches_dir}/{name}-{}.diff", unix_time());
+            atomic_write(&patch_path, patch.as_bytes()).await?;
+            println!("[{name}] local modifications saved in {patch_path}");
+            stash = Some((patch, patch_path));
+        }
+
+        let from = self.repos[index].commit().ok();
+        self.repos[index].upgrade().await?;
+        println!(
+            "{}",
+            tr("repository-upgraded", &[("repository", name.as_str())])
+        );
+        if let (Some(from), Ok(to)) = (from, self.repos[index].commit()) {
+            if from != to {
+                let change = Change::Upgrade {
+                    repository: name.clone(),
+                    from,
+                    to,
+                };
+                record(self, change).await;
+            }
+        }
+        if let Some((patch, patch_path)) = stash {
+            if self.config.settings.reapply_local {
+                match self.repos[index].apply_diff(&patch) {
+                    Ok(()) => println!("[{name}] local modifications applied again"),
+                    Err(err) => println!(
+                        "[{name}] local modifications not applied ({err}), the patch is in {patch_path}"
+                    ),
+                }
+            }
+        }
+
+        self.rebuild_plugins(index).await
+    }
+
+    /// build again the installed plugins of the repository at `index`
+    /// after its checkout changed, and restart them.
+    ///
+    /// Return how many plugins were not rebuilt.
+    pub async fn rebuild_plugins(&mut self, index: usize) -> Result<usize, CoffeeError> {
+        let name = self.repos[index].name();
+        let mut failures = 0;
+        let repo_path = self.repos[index].url().path_string;
+        for position in 0..self.config.plugins.len() {
+            let installed = &self.config.plugins[position];
+            if !is_inside(&installed.root_path(), &repo_path) {
+                continue;
+            }
+            if let Some(reference) = self.config.pins.get(&installed.name()) {
+                println!("[{}] pinned at {reference}, not upgraded", installed.name());
+                continue;
+            }
+            let plugin = match self.repos[index].get_plugin_by_name(&installed.name()) {
+                Some(plugin) => plugin,
+                None => {
+                    println!(
+                        "[{}] not present anymore in `{name}`, keeping the old version",
+                        installed.name()
+                    );
+                    continue;
+                }
+            };
+            if plugin.content_hash().is_some() && plugin.content_hash() == installed.content_hash()
+            {
+                // the upgrade did not touch the plugin.
+                println!("[{}] unchanged, not rebuilt", plugin.name());
+                continue;
+            }
+            if !self.rebuild_plugin(position, plugin, "HEAD").await? {
+                failures += 1;
+            }
+        }
+        Ok(failures)
+    }
+
+    /// check out the `plugin` installed at `position` at the `reference`
+    /// of its repository inside a new worktree, build it and restart it.
+    ///
+    /// Return false when the plugin is not rebuilt and the old version
+    /// is kept.
+    pub async fn rebuild_plugin(
+        &mut self,
+        position: usize,
+        mut plugin: Plugin,
+        reference: &str,
+    ) -> Result<bool, CoffeeError> {
+        let old_path = self.config.plugins[position].exec_path();
+        if let Err(err) = trust::check_install_script(self, &plugin).await {
+            println!("[{}] not rebuilt: {err}", plugin.name());
+            return Ok(false);
+        }
+        // the new sources are checked out inside a new worktree,
+        // the node keeps running the old one until the restart.
+        let previous = self.config.worktrees.get(&plugin.name()).cloned();
+        plugin = match worktree::checkout(self, &plugin, reference).await {
+            Ok(plugin) => plugin,
+            Err(err) => {
+                println!("[{}] not rebuilt: {err}", plugin.name());
+                return Ok(false);
+            }
+        };
+        if let Err(err) = patch::apply_patches(self, &plugin).await {
+            println!("[{}] not rebuilt: {err}", plugin.name());
+            worktree::discard(self, &plugin.name(), previous).await?;
+            return Ok(false);
+        }
+        let exec_path = match build_plugin(
+            &mut plugin,
+            false,
+            &self.sandbox(),
+            &self.run_as(),
+            &self.plugin_env(),
+        )
+        .await
+        {
+            Ok(exec_path) => exec_path,
+            Err(err) => {
+                // the node keeps running the old worktree.
+                worktree::discard(self, &plugin.name(), previous).await?;
+                return Err(err);
+            }
+        };
+        if let (Some(old_path), Some(paths)) = (
+            old_path.clone(),
+            self.coffe_cln_config.fields.get_mut("plugin"),
+        ) {
+            paths
+                .iter_mut()
+                .filter(|path| **path == old_path)
+                .for_each(|path| *path = exec_path.clone());
+        }
+        if let Some(reason) = maintenance::restart_blocked(self, &plugin).await {
+            // the node runs the old code until the next restart, so
+            // its worktree is kept.
+            println!("[{}] restart deferred, {reason}", plugin.name());
+        } else {
+            if self.config.systemd_units.contains_key(&plugin.name()) {
+                systemd::update(self, &plugin).await;
+            } else if self.is_node_running() && !self.config.disabled.contains_key(&plugin.name()) {
+                // a running plugin keeps the old code until it is restarted.
+                let running = old_path.unwrap_or_else(|| exec_path.clone());
+                if self.stop_plugin(&running).await.is_ok() {
+                    if let Err(err) = self.start_pluing(&exec_path).await {
+                        warn!("plugin {exec_path} not restarted: {err}");
+                    }
+                }
+            }
+            if let Err(err) = worktree::prune(self, &plugin.name()).await {
+                warn!("old worktrees of {} not removed: {err}", plugin.name());
+            }
+        }
+        println!("[{}] rebuilt", plugin.name());
+        checksums::record(self, &plugin).await;
+        let event = Event::Upgraded {
+            plugin: plugin.name(),
+        };
+        notify(&self.config.settings, &self.config.network, event).await;
+        self.config.plugins[position] = plugin;
+        Ok(true)
+    }
+
     pub async fn setup_with_cln(&mut self, cln_dir: &str) -> Result<(), CoffeeError> {
         if !self.cln_config.is_none() {
             warn!("you are ovveriding the previous set up");
@@ -189,52 +1041,8 @@ impl CoffeeManager {
 }
 
 #[async_trait]
-impl PluginManager for CoffeeManager {
-    async fn configure(&mut self) -> Result<(), CoffeeError> {
-        debug!("plugin configured");
-        Ok(())
-    }
-
-    async fn install(
-        &mut self,
-        plugin: &str,
-        verbose: bool,
-        try_dynamic: bool,
-    ) -> Result<(), CoffeeError> {
-        debug!("installing plugin: {plugin}");
-        // keep track if the plugin that are installed with success
-        for repo in &self.repos {
-            if let Some(mut plugin) = repo.get_plugin_by_name(plugin) {
-                trace!("{:#?}", plugin);
-                let result = plugin.configure(verbose).await;
-                match result {
-                    Ok(path) => {
-                        debug!("runnable plugin path {path}");
-                        if !try_dynamic {
-                            self.config.plugins.push(plugin);
-                            self.coffe_cln_config
-                                .add_conf("plugin", &path.to_owned())
-                                .map_err(|err| CoffeeError::new(1, &err.cause))?;
-
-                            self.storage.store(&self.storage_info()).await?;
-                            self.update_conf().await?;
-                        } else {
-                            self.start_pluing(&path).await?;
-                        }
-                        return Ok(());
-                    }
-                    Err(err) => return Err(err),
-                }
-            }
-        }
-        let err = CoffeeError::new(
-            1,
-            &format!("plugin `{plugin}` are not present inside the repositories"),
-        );
-        Err(err)
-    }
-
-    async fn list(&mut self, remotes: bool) -> Result<Value, CoffeeError> {
+impl PluginInspector for CoffeeManager {
+    async fn list(&self, remotes: bool) -> Result<Value, CoffeeError> {
         let installed_plugins_vec: Vec<Plugin> = self.config.plugins.clone();
         let plugin_json;
         if remotes {
@@ -244,25 +1052,513 @@ impl PluginManager for CoffeeManager {
                 {
                     "local_name": repo.name(),
                     "link": repo.url().url_string,
+                    "layout": repo.layout().to_string(),
                     "plugins": repo.list().await.unwrap(),
                  });
                 remote_list.push(remote_repo_json);
             }
             plugin_json = json!({
                "plugins": serde_json::to_value(&installed_plugins_vec).unwrap(),
+               "overrides": serde_json::to_value(&self.config.overrides).unwrap(),
+               "aliases": serde_json::to_value(&self.config.aliases).unwrap(),
                "remotes": remote_list,
             });
         } else {
             plugin_json = json!({
                "plugins": serde_json::to_value(&installed_plugins_vec).unwrap(),
+               "overrides": serde_json::to_value(&self.config.overrides).unwrap(),
+               "aliases": serde_json::to_value(&self.config.aliases).unwrap(),
             });
         }
         Ok(plugin_json)
     }
 
-    async fn upgrade(&mut self, _: &[&str]) -> Result<(), CoffeeError> {
-        // FIXME: Fix debug message with the list of plugins to be upgraded
-        debug!("upgrading plugins");
+    async fn show(&self, plugin: &str) -> Result<Value, CoffeeError> {
+        let name = self.resolve_alias(plugin);
+        let installed = self
+            .config
+            .plugins
+            .iter()
+            .find(|installed| installed.name() == name);
+        let available = self.search_plugin(&name);
+        if installed.is_none() && available.is_none() {
+            return Err(self.not_found(&name));
+        }
+        let repository = available
+            .as_ref()
+            .and_then(|plugin| self.repo_of(plugin))
+            .map(|repo| repo.name());
+        Ok(json!({
+            "name": name,
+            "installed": serde_json::to_value(installed).unwrap(),
+            "repository": repository,
+            "available": serde_json::to_value(&available).unwrap(),
+            "outdated": installed.map(|installed| self.is_outdated(installed)),
+            "override": serde_json::to_value(self.config.overrides.get(&name)).unwrap(),
+            "options": serde_json::to_value(self.config.options.get(&name)).unwrap(),
+        }))
+    }
+}
+
+#[async_trait]
+impl PluginManager for CoffeeManager {
+    async fn configure(&mut self) -> Result<(), CoffeeError> {
+        debug!("plugin configured");
+        Ok(())
+    }
+
+    async fn install(
+        &mut self,
+        plugins: &[&str],
+        verbose: bool,
+        try_dynamic: bool,
+    ) -> Result<(), CoffeeError> {
+        debug!("installing plugins: {:?}", plugins);
+        let plugins: Vec<String> = plugins
+            .iter()
+            .map(|name| self.resolve_alias(name))
+            .collect();
+        let mut to_install = vec![];
+        for name in &plugins {
+            match self.search_plugin(name) {
+                Some(plugin) => to_install.push(plugin),
+                None => return Err(self.not_found(name)),
+            }
+        }
+
+        // the plugins needed by the requested ones are installed too,
+        // and they are marked as installed as a dependency.
+        let mut pulled = BTreeSet::new();
+        let mut index = 0;
+        while index < to_install.len() {
+            let needed_by = to_install[index].name();
+            let pending: Vec<String> = to_install[index]
+                .dependencies()
+                .iter()
+                .map(|dependency| self.resolve_alias(dependency))
+                .collect();
+            index += 1;
+            for dependency in pending {
+                let known = self
+                    .config
+                    .plugins
+                    .iter()
+                    .chain(&to_install)
+                    .any(|plugin| plugin.name() == dependency);
+                if known {
+                    continue;
+                }
+                let plugin = match self.search_plugin(&dependency) {
+                    Some(plugin) => plugin,
+                    None => {
+                        let err = CoffeeError::new(
+                            1,
+                            &format!("plugin `{dependency}` needed by `{needed_by}` are not present inside the repositories"),
+                        );
+                        return Err(err);
+                    }
+                };
+                println!("[{dependency}] needed by {needed_by}");
+                pulled.insert(dependency);
+                to_install.push(plugin);
+            }
+        }
+        // every plugin is installed from its own worktree, so the
+        // upgrades of the repository do not touch its sources.
+        let worktrees = self.config.worktrees.clone();
+        for plugin in to_install.iter_mut() {
+            *plugin = worktree::isolate(self, plugin).await?;
+        }
+        for plugin in &to_install {
+            reserved::check(self, &plugin.name())?;
+        }
+        features::check(self, &to_install).await?;
+        conflicts::check(self, &to_install).await?;
+
+        // the install script of a manifest can run any command, so
+        // we ask the user before running it.
+        for plugin in &to_install {
+            trust::check_install_script(self, plugin).await?;
+        }
+
+        // the local patches are applied before the build, a plugin
+        // that can not be patched is not built at all.
+        let mut results = vec![];
+        let mut patched = vec![];
+        // with `--fail-fast` the plugins are not built after the first
+        // failure, the builds already running are completed.
+        let fail_fast = self.config.settings.on_failure == Some(FailurePolicy::FailFast);
+        let failed = Arc::new(AtomicBool::new(false));
+        for plugin in to_install {
+            match patch::apply_patches(self, &plugin).await {
+                Ok(()) => patched.push(plugin),
+                Err(err) => {
+                    println!("[{}] failed: {err}", plugin.name());
+                    failed.store(true, Ordering::SeqCst);
+                    results.push((plugin, Err(err)));
+                }
+            }
+        }
+
+        // the plugins that live inside the same directory share the
+        // build steps, so they are installed one after the other, all
+        // the others are built concurrently.
+        let mut groups: Vec<Vec<Plugin>> = vec![];
+        for plugin in patched {
+            match groups
+                .iter_mut()
+                .find(|group| group[0].root_path() == plugin.root_path())
+            {
+                Some(group) => group.push(plugin),
+                None => groups.push(vec![plugin]),
+            }
+        }
+        let mut tasks = JoinSet::new();
+        for group in groups {
+            let sandbox = self.sandbox();
+            let run_as = self.run_as();
+            let env = self.plugin_env();
+            let failed = failed.clone();
+            tasks.spawn(async move {
+                let mut results = vec![];
+                for mut plugin in group {
+                    if fail_fast && failed.load(Ordering::SeqCst) {
+                        println!("[{}] skipped", plugin.name());
+                        let err = CoffeeError::new(1, "not built, a plugin failed before it");
+                        results.push((plugin, Err(err)));
+                        continue;
+                    }
+                    println!(
+                        "{}",
+                        tr("plugin-installing", &[("plugin", plugin.name().as_str())])
+                    );
+                    trace!("{:#?}", plugin);
+                    let result = build_plugin(&mut plugin, verbose, &sandbox, &run_as, &env).await;
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    match &result {
+                        Ok(_) => println!(
+                            "{}",
+                            tr("plugin-built", &[("plugin", plugin.name().as_str())])
+                        ),
+                        Err(err) => println!(
+                            "{}",
+                            tr(
+                                "plugin-failed",
+                                &[
+                                    ("plugin", plugin.name().as_str()),
+                                    ("error", err.to_string().as_str())
+                                ]
+                            )
+                        ),
+                    }
+                    results.push((plugin, result));
+                }
+                results
+            });
+        }
+        while let Some(group_results) = tasks.join_next().await {
+            let group_results = group_results
+                .map_err(|err| CoffeeError::new(1, &format!("install task failed: {err}")))?;
+            results.extend(group_results);
+        }
+        // the plugins not requested by name (e.g. the dependencies) go last.
+        results.sort_by_key(|(plugin, _)| {
+            plugins
+                .iter()
+                .position(|name| *name == plugin.name())
+                .unwrap_or(usize::MAX)
+        });
+
+        // the configuration is shared between all the plugins, so it
+        // is updated only once at the end.
+        let mut summary = vec![];
+        for (plugin, result) in results {
+            let status = match result {
+                Ok(path) => {
+                    debug!("runnable plugin path {path}");
+                    let health = if !try_dynamic {
+                        // the state changes only when the node configuration accepts the plugin.
+                        if let Err(err) = self.coffe_cln_config.add_conf("plugin", &path) {
+                            summary.push((plugin.name(), Err(CoffeeError::new(1, &err.cause))));
+                            continue;
+                        }
+                        self.config.plugins.push(plugin.clone());
+                        let repo = self.repo_of(&plugin);
+                        let change = Change::Install {
+                            plugin: plugin.name(),
+                            repository: repo.map(|repo| repo.name()),
+                            commit: repo.and_then(|repo| repo.commit().ok()),
+                        };
+                        record(self, change).await;
+                        checksums::record(self, &plugin).await;
+                        health::check_health(self, &path, false).await
+                    } else {
+                        match self.start_pluing(&path).await {
+                            Ok(()) => health::check_health(self, &path, true).await,
+                            Err(_) => PluginHealth::Crashed,
+                        }
+                    };
+                    if health.is_healthy() {
+                        Ok(health.to_string())
+                    } else {
+                        for line in health::plugin_logs(self, &path).await {
+                            println!("[{}] log: {line}", plugin.name());
+                        }
+                        if self.config.settings.rollback_on_failure {
+                            println!(
+                                "{}",
+                                tr("plugin-rolling-back", &[("plugin", plugin.name().as_str())])
+                            );
+                            if let Err(err) = self.stop_plugin(&path).await {
+                                warn!("plugin {path} not stopped: {err}");
+                            }
+                            if !try_dynamic {
+                                self.rollback_install(&plugin, &path).await;
+                            }
+                        }
+                        Err(CoffeeError::new(1, &health.to_string()))
+                    }
+                }
+                Err(err) => Err(err),
+            };
+            summary.push((plugin.name(), status));
+        }
+        let failures = summary.iter().filter(|(_, status)| status.is_err()).count();
+        self.config.install_failures += failures as u64;
+        for (plugin, status) in &summary {
+            let result = match status {
+                Ok(_) => worktree::prune(self, plugin).await,
+                Err(_) => worktree::discard(self, plugin, worktrees.get(plugin).cloned()).await,
+            };
+            if let Err(err) = result {
+                warn!("worktrees of {plugin} not cleaned up: {err}");
+            }
+        }
+        if !try_dynamic {
+            for (plugin, _) in summary.iter().filter(|(_, status)| status.is_ok()) {
+                if pulled.contains(plugin) {
+                    self.config.auto_installed.insert(plugin.clone());
+                } else {
+                    // a plugin requested by the user is not a dependency anymore.
+                    self.config.auto_installed.remove(plugin);
+                }
+            }
+        }
+        if !try_dynamic || failures > 0 {
+            self.storage.store(&self.storage_info()).await?;
+        }
+        if !try_dynamic {
+            self.update_conf().await?;
+            ssh::sync(self).await?;
+        }
+
+        print_install_summary(&summary);
+        for (plugin, status) in &summary {
+            let event = match status {
+                Ok(status) => Event::Installed {
+                    plugin: plugin.clone(),
+                    status: status.clone(),
+                },
+                Err(err) => Event::InstallFailed {
+                    plugin: plugin.clone(),
+                    error: err.to_string(),
+                },
+            };
+            notify(&self.config.settings, &self.config.network, event).await;
+        }
+        if failures > 0 {
+            let err = CoffeeError::new(
+                1,
+                &tr(
+                    "plugins-install-failed",
+                    &[
+                        ("failures", failures.to_string().as_str()),
+                        ("total", summary.len().to_string().as_str()),
+                    ],
+                ),
+            );
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn remove(&mut self, plugin: &str) -> Result<(), CoffeeError> {
+        debug!("removing plugin: {plugin}");
+        let plugin = self.resolve_alias(plugin);
+        let plugin = plugin.as_str();
+        let index = match self
+            .config
+            .plugins
+            .iter()
+            .position(|installed| installed.name() == plugin)
+        {
+            Some(index) => index,
+            None => return Err(self.not_installed(plugin)),
+        };
+        let question = tr("plugin-remove-question", &[("plugin", plugin)]);
+        if !self.config.settings.confirm.confirm(&question)? {
+            let err = CoffeeError::new(1, &tr("remove-aborted", &[("name", plugin)]));
+            return Err(err);
+        }
+        let plugin = self.config.plugins.remove(index);
+        if let Some(exec_path) = plugin.exec_path() {
+            if let Some(paths) = self.coffe_cln_config.fields.get_mut("plugin") {
+                paths.retain(|path| *path != exec_path);
+            }
+        }
+        for key in self
+            .config
+            .options
+            .remove(&plugin.name())
+            .unwrap_or_default()
+            .keys()
+        {
+            self.coffe_cln_config.fields.remove(key);
+        }
+        self.config.secrets.remove(&plugin.name());
+        self.config.disabled.remove(&plugin.name());
+        self.config.auto_installed.remove(&plugin.name());
+        self.config.pins.remove(&plugin.name());
+        worktree::remove(self, &plugin.name()).await?;
+        self.storage.store(&self.storage_info()).await?;
+        self.update_conf().await?;
+        ssh::sync(self).await?;
+        if plugin.lang() == PluginLang::Python {
+            python::forget_shared(&self.sandbox().python, &plugin.name()).await?;
+        }
+        let repo = self.repo_of(&plugin);
+        let change = Change::Remove {
+            plugin: plugin.name(),
+            repository: repo.map(|repo| repo.name()),
+            commit: repo.and_then(|repo| repo.commit().ok()),
+        };
+        record(self, change).await;
+        debug!("plugin removed: {}", plugin.name());
+        if self.config.systemd_units.contains_key(&plugin.name()) {
+            if let Err(err) = systemd::uninstall(self, &plugin.name()).await {
+                println!("[{}] systemd unit not removed: {err}", plugin.name());
+            }
+        }
+        Ok(())
+    }
+
+    async fn upgrade(&mut self, plugins: &[&str]) -> Result<(), CoffeeError> {
+        debug!("upgrading plugins: {:?}", plugins);
+        if self.config.settings.offline {
+            return Err(CoffeeError::new(
+                1,
+                "coffee is offline, upgrade not available",
+            ));
+        }
+        let mut names: Vec<String> = plugins
+            .iter()
+            .map(|name| self.resolve_alias(name))
+            .collect();
+        if names.is_empty() {
+            names = self
+                .config
+                .plugins
+                .iter()
+                .filter(|plugin| !plugin.is_dev_linked())
+                .map(|plugin| plugin.name())
+                .collect();
+        }
+
+        // the plugins are upgraded by upgrading the repositories
+        // where they come from.
+        let mut to_upgrade = vec![];
+        for name in &names {
+            let plugin = match self
+                .config
+                .plugins
+                .iter()
+                .find(|installed| installed.name() == *name)
+            {
+                Some(plugin) if plugin.is_dev_linked() => {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!(
+                            "plugin `{name}` is dev-linked, use `coffee dev reload` to rebuild it"
+                        ),
+                    );
+                    return Err(err);
+                }
+                Some(plugin) => plugin,
+                None => return Err(self.not_installed(name)),
+            };
+            match self
+                .repos
+                .iter()
+                .position(|repo| is_inside(&plugin.root_path(), &repo.url().path_string))
+            {
+                Some(index) if !to_upgrade.contains(&index) => to_upgrade.push(index),
+                Some(_) => {}
+                None => warn!("plugin `{name}` do not come from a repository, skipping"),
+            }
+        }
+
+        // the upstreams are fetched concurrently, within the download
+        // limits, the upgrades then go one after the other.
+        let mut fetches = JoinSet::new();
+        for index in &to_upgrade {
+            let (name, path) = (
+                self.repos[*index].name(),
+                self.repos[*index].url().path_string,
+            );
+            fetches.spawn(spawn_blocking(move || (name, git::backend().fetch(&path))));
+        }
+        while let Some(fetch) = fetches.join_next().await {
+            // the upgrade fetches again, and it reports the error.
+            if let Ok(Ok((name, Err(err)))) = fetch {
+                debug!("upstream of {name} not fetched: {err}");
+            }
+        }
+
+        let policy = self
+            .config
+            .settings
+            .on_failure
+            .unwrap_or(FailurePolicy::KeepGoing);
+        let (mut failures, mut not_upgraded) = (0, vec![]);
+        for index in to_upgrade {
+            let name = self.repos[index].name();
+            match self.upgrade_repository(index).await {
+                Ok(not_rebuilt) => failures += not_rebuilt,
+                Err(err) if interrupt::is_interrupted() => return Err(err),
+                Err(err) => {
+                    println!("[{name}] failed: {err}");
+                    not_upgraded.push((name, err));
+                }
+            }
+            if policy == FailurePolicy::FailFast && (failures > 0 || !not_upgraded.is_empty()) {
+                break;
+            }
+        }
+        self.storage.store(&self.storage_info()).await?;
+        self.update_conf().await?;
+        ssh::sync(self).await?;
+        if let Some((name, err)) = not_upgraded.first() {
+            if not_upgraded.len() == 1 {
+                return Err(err.clone());
+            }
+            let err = CoffeeError::new(
+                1,
+                &format!(
+                    "{} repositories not upgraded, the first is `{name}`: {err}",
+                    not_upgraded.len()
+                ),
+            );
+            return Err(err);
+        }
+        if failures > 0 {
+            let err = CoffeeError::new(
+                1,
+                &format!("{failures} plugins not rebuilt after the upgrade"),
+            );
+            return Err(err);
+        }
         Ok(())
     }
 
@@ -273,17 +1569,123 @@ impl PluginManager for CoffeeManager {
     }
 
     async fn add_remote(&mut self, name: &str, url: &str) -> Result<(), CoffeeError> {
-        let url = URL::new(&self.config.root_path, url, name);
-        debug!("remote adding: {} {}", name, &url.url_string);
-        let mut repo = Github::new(name, &url);
-        repo.init().await?;
-        self.repos.push(Box::new(repo));
-        debug!("remote added: {} {}", name, &url.url_string);
+        self.add_remote_with_layout(name, url, Layout::default())
+            .await
+    }
+    async fn rm_remote(&mut self, name: &str) -> Result<(), CoffeeError> {
+        debug!("remote removing: {name}");
+        let index = match self.repos.iter().position(|repo| repo.name() == name) {
+            Some(index) => index,
+            None => {
+                let err = CoffeeError::new(1, &tr("repository-not-found", &[("repository", name)]));
+                return Err(err);
+            }
+        };
+        let repo_path = self.repos[index].url().path_string;
+        let installed = self
+            .config
+            .plugins
+            .iter()
+            .filter(|plugin| is_inside(&plugin.root_path(), &repo_path))
+            .count();
+        let mut question = tr("repository-remove-question", &[("repository", name)]);
+        if installed > 0 {
+            question = tr(
+                "repository-remove-installed",
+                &[
+                    ("installed", installed.to_string().as_str()),
+                    ("repository", name),
+                    ("question", question.as_str()),
+                ],
+            );
+        }
+        if !self.config.settings.confirm.confirm(&question)? {
+            let err = CoffeeError::new(1, &tr("remove-aborted", &[("name", name)]));
+            return Err(err);
+        }
+        let repo = self.repos.remove(index);
+        // another profile can use the shared checkout.
+        if !self.is_install_root_shared() && Path::new(&repo_path).exists() {
+            remove_dir_all(&repo_path).await?;
+        }
         self.storage.store(&self.storage_info()).await?;
+        let change = Change::RemoveRemote {
+            name: name.to_owned(),
+            url: repo.url().url_string,
+        };
+        record(self, change).await;
+        debug!("remote removed: {name}");
         Ok(())
     }
 }
 
+/// build the `plugin` inside the `sandbox`, and give it to the
+/// dedicated user of `run_as` when coffee is configured with one.
+///
+/// In case of success return the path of the executable.
+async fn build_plugin(
+    plugin: &mut Plugin,
+    verbose: bool,
+    sandbox: &Sandbox,
+    run_as: &Option<(RunAs, String)>,
+    env: &PluginEnv,
+) -> Result<String, CoffeeError> {
+    let sandbox = buildlog::start(sandbox, &plugin.name()).await?;
+    let result = plugin.configure(verbose, &sandbox).await;
+    if let Err(err) = &result {
+        diagnostics::report(&plugin.name(), &sandbox, err).await;
+    }
+    buildlog::finish(&sandbox, &result).await;
+    let exec_path = result?;
+    let exec_path = match run_as {
+        Some((run_as, wrapper_dir)) => plugin.run_as(run_as, wrapper_dir).await?,
+        None => exec_path,
+    };
+    let wrapper = env.wrap(&plugin.name(), &exec_path).await?;
+    plugin.set_exec_path(&wrapper);
+    Ok(wrapper)
+}
+
+/// the `message` with the `candidates` similar to the `name`, if any.
+fn did_you_mean<'a>(
+    message: String,
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> String {
+    let suggestions = names::suggestions(name, candidates);
+    if suggestions.is_empty() {
+        return message;
+    }
+    let suggestions: Vec<String> = suggestions
+        .iter()
+        .map(|suggestion| format!("`{suggestion}`"))
+        .collect();
+    tr(
+        "did-you-mean",
+        &[
+            ("message", message.as_str()),
+            ("names", suggestions.join(", ").as_str()),
+        ],
+    )
+}
+
+/// print the final result of an install of one or more plugins.
+fn print_install_summary(summary: &[(String, Result<String, CoffeeError>)]) {
+    let width = summary
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default()
+        .max("plugin".len());
+    println!("{:width$}  status", "plugin");
+    for (name, status) in summary {
+        match status {
+            Ok(status) => println!("{name:width$}  {status}"),
+            Err(err) => println!("{name:width$}  failed ({err})"),
+        }
+    }
+}
+
 // FIXME: we need to move on but this is not safe and with the coffee
 // implementation is not true!
 unsafe impl Send for CoffeeManager {}
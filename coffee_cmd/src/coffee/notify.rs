@@ -0,0 +1,175 @@
+//! Notifications of the lifecycle of the plugins, sent to the
+//! targets configured inside the `config.yml` (a generic webhook,
+//! a Telegram chat or a Matrix room).
+//!
+//! A notification that can not be delivered is only logged, it
+//! never fails the operation that emitted it.
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::config::Settings;
+
+/// Target of the notifications.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NotificationTarget {
+    /// the event is posted as a JSON object to the url.
+    Webhook { url: String },
+    /// the event is sent as a message by the bot.
+    Telegram { bot_token: String, chat_id: String },
+    /// the event is sent as a message inside the room.
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+    },
+}
+
+/// Event of the lifecycle of the plugins.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// the install of a plugin completed, with its result.
+    Installed { plugin: String, status: String },
+    /// the install of a plugin failed.
+    InstallFailed { plugin: String, error: String },
+    /// a plugin was rebuilt after the upgrade of its repository.
+    Upgraded { plugin: String },
+    /// new versions of the plugins are available upstream.
+    UpgradesAvailable { plugins: Vec<String> },
+    /// a plugin is not running anymore.
+    Unhealthy { plugin: String, health: String },
+    /// a plugin crashed too many times, and it was disabled when
+    /// the policy asks it.
+    CrashLoop {
+        plugin: String,
+        crashes: usize,
+        disabled: bool,
+    },
+}
+
+impl Event {
+    /// stable name of the event, used by the webhooks.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Installed { .. } => "installed",
+            Event::InstallFailed { .. } => "install_failed",
+            Event::Upgraded { .. } => "upgraded",
+            Event::UpgradesAvailable { .. } => "upgrades_available",
+            Event::Unhealthy { .. } => "unhealthy",
+            Event::CrashLoop { .. } => "crash_loop",
+        }
+    }
+
+    /// human readable message of the event.
+    pub fn message(&self) -> String {
+        match self {
+            Event::Installed { plugin, status } => format!("plugin `{plugin}` installed: {status}"),
+            Event::InstallFailed { plugin, error } => {
+                format!("plugin `{plugin}` failed to install: {error}")
+            }
+            Event::Upgraded { plugin } => format!("plugin `{plugin}` upgraded"),
+            Event::UpgradesAvailable { plugins } => {
+                format!("upgrades available for {}", plugins.join(", "))
+            }
+            Event::Unhealthy { plugin, health } => format!("plugin `{plugin}` is {health}"),
+            Event::CrashLoop {
+                plugin,
+                crashes,
+                disabled,
+            } => format!(
+                "plugin `{plugin}` crashed {crashes} times{}",
+                if *disabled { ", it is disabled" } else { "" }
+            ),
+        }
+    }
+
+    /// structured content of the event.
+    pub fn to_json(&self, network: &str) -> Value {
+        let mut event = json!({
+            "event": self.name(),
+            "network": network,
+            "message": self.message(),
+        });
+        match self {
+            Event::Installed { plugin, .. }
+            | Event::InstallFailed { plugin, .. }
+            | Event::Upgraded { plugin }
+            | Event::Unhealthy { plugin, .. }
+            | Event::CrashLoop { plugin, .. } => event["plugin"] = json!(plugin),
+            Event::UpgradesAvailable { plugins } => event["plugins"] = json!(plugins),
+        }
+        event
+    }
+}
+
+/// deliver the `event` to the `target`.
+async fn send(
+    client: &reqwest::Client,
+    target: &NotificationTarget,
+    event: &Event,
+    network: &str,
+) -> Result<(), reqwest::Error> {
+    let text = format!("[coffee {network}] {}", event.message());
+    let request = match target {
+        NotificationTarget::Webhook { url } => client.post(url).json(&event.to_json(network)),
+        NotificationTarget::Telegram { bot_token, chat_id } => client
+            .post(format!(
+                "https://api.telegram.org/bot{bot_token}/sendMessage"
+            ))
+            .json(&json!({ "chat_id": chat_id, "text": text })),
+        NotificationTarget::Matrix {
+            homeserver,
+            room_id,
+            access_token,
+        } => client
+            .post(format!(
+                "{}/_matrix/client/v3/rooms/{room_id}/send/m.room.message",
+                homeserver.trim_end_matches('/')
+            ))
+            .bearer_auth(access_token)
+            .json(&json!({ "msgtype": "m.text", "body": text })),
+    };
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Send the `event` to all the targets of the `settings`.
+pub async fn notify(settings: &Settings, network: &str, event: Event) {
+    if settings.notifications.is_empty() || settings.offline {
+        return;
+    }
+    let client = reqwest::Client::new();
+    for target in &settings.notifications {
+        debug!("notifying {} to {:?}", event.name(), target);
+        if let Err(err) = send(&client, target, &event, network).await {
+            warn!("notification {} not delivered: {err}", event.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, NotificationTarget};
+
+    #[test]
+    fn test_notification_event() {
+        let event = Event::UpgradesAvailable {
+            plugins: vec!["summary".to_owned(), "clearnet".to_owned()],
+        };
+        let json = event.to_json("bitcoin");
+        assert_eq!(json["event"], "upgrades_available");
+        assert_eq!(json["plugins"][1], "clearnet");
+        assert_eq!(json["message"], "upgrades available for summary, clearnet");
+
+        let target: NotificationTarget =
+            serde_yaml::from_str("kind: telegram\nbot_token: abc\nchat_id: \"42\"\n").unwrap();
+        assert_eq!(
+            target,
+            NotificationTarget::Telegram {
+                bot_token: "abc".to_owned(),
+                chat_id: "42".to_owned()
+            }
+        );
+    }
+}
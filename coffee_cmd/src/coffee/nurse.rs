@@ -0,0 +1,119 @@
+//! Checks of the coffee state: `coffee nurse` fetches the upstreams
+//! of the repositories, looks for the problems of the installed
+//! plugins and stores the report, so `coffee status` shows it
+//! without reaching the network.
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::{atomic_write, unix_time};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::fs::read_to_string;
+
+use super::CoffeeManager;
+
+/// File of the coffee root with the report of the last run.
+const REPORT_FILE: &str = "nurse.json";
+
+/// Report of a run of the nurse.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NurseReport {
+    /// unix time of the run.
+    pub time: u64,
+    /// plugins changed by the upstream of their repository.
+    pub outdated: Vec<String>,
+    /// problems found by the run.
+    pub problems: Vec<String>,
+}
+
+fn report_path(coffee: &CoffeeManager) -> String {
+    format!("{}/{REPORT_FILE}", coffee.config.root_path)
+}
+
+/// Problems of the installed plugins that can be seen without
+/// reaching the network.
+pub fn problems(coffee: &CoffeeManager) -> Vec<String> {
+    let registered = coffee
+        .coffe_cln_config
+        .fields
+        .get("plugin")
+        .cloned()
+        .unwrap_or_default();
+    let mut problems = vec![];
+    for repo in &coffee.repos {
+        let path = repo.url().path_string;
+        if !Path::new(&path).exists() {
+            problems.push(format!("repository `{}` is missing at {path}", repo.name()));
+        }
+    }
+    for plugin in &coffee.config.plugins {
+        if let Some(reason) = coffee.config.disabled.get(&plugin.name()) {
+            problems.push(format!("`{}` is disabled: {reason}", plugin.name()));
+            continue;
+        }
+        match plugin.exec_path() {
+            Some(exec_path) if registered.contains(&exec_path) => {
+                if !Path::new(&exec_path).exists() {
+                    problems.push(format!(
+                        "`{}` executable {exec_path} is missing",
+                        plugin.name()
+                    ));
+                }
+            }
+            _ => problems.push(format!(
+                "`{}` is not registered inside {}",
+                plugin.name(),
+                coffee.config.config_path
+            )),
+        }
+    }
+    problems
+}
+
+/// The report of the last run, if any.
+pub async fn last_report(coffee: &CoffeeManager) -> Option<NurseReport> {
+    let content = read_to_string(report_path(coffee)).await.ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Fetch the upstreams of the repositories, print the problems and
+/// the outdated plugins, and store them for `coffee status`.
+pub async fn nurse(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let mut problems = vec![];
+    if coffee.config.settings.offline {
+        println!("coffee is running in offline mode, the upstreams are not fetched");
+    } else {
+        for repo in &coffee.repos {
+            if let Err(err) = repo.fetch().await {
+                problems.push(format!("repository `{}` not fetched: {err}", repo.name()));
+            }
+        }
+    }
+    problems.extend(self::problems(coffee));
+    let outdated: Vec<String> = coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| coffee.is_outdated(plugin))
+        .map(|plugin| plugin.name())
+        .collect();
+    for plugin in &outdated {
+        println!("[{plugin}] outdated, run `coffee upgrade {plugin}`");
+    }
+    for problem in &problems {
+        println!("problem: {problem}");
+    }
+    if outdated.is_empty() && problems.is_empty() {
+        println!("no problems found");
+    }
+    let report = NurseReport {
+        time: unix_time(),
+        outdated,
+        problems,
+    };
+    let content = serde_json::to_string_pretty(&report)
+        .map_err(|err| CoffeeError::new(1, &format!("nurse report not serialized: {err}")))?;
+    atomic_write(&report_path(coffee), content.as_bytes()).await?;
+    debug!("nurse report stored inside {}", report_path(coffee));
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Homepage and issue tracker of a plugin, so the user can
+//! read more about it or report a bug upstream.
+use std::io::{self, IsTerminal};
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::relative_path;
+use log::debug;
+use tokio::process::Command;
+
+use super::github_api::github_slug;
+use super::CoffeeManager;
+
+/// Print the homepage (or the issue tracker with `issues`) of the
+/// `plugin`, and open it in the browser when coffee runs in a terminal.
+pub async fn open(coffee: &CoffeeManager, plugin: &str, issues: bool) -> Result<(), CoffeeError> {
+    let name = coffee.resolve_alias(plugin);
+    let plugin = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == name)
+        .cloned()
+        .or_else(|| coffee.search_plugin(&name))
+        .ok_or_else(|| coffee.not_found(&name))?;
+    let manifest = plugin.conf().map(|conf| conf.plugin);
+    let declared = manifest.and_then(|manifest| {
+        if issues {
+            manifest.issues
+        } else {
+            manifest.homepage
+        }
+    });
+    // without a declared url, the repository where the plugin
+    // comes from is the best guess.
+    let url = declared.or_else(|| {
+        let repo = coffee.repo_of(&plugin)?;
+        let repo_url = repo.url().url_string;
+        if issues {
+            github_slug(&repo_url)
+                .map(|(owner, name)| format!("https://github.com/{owner}/{name}/issues"))
+        } else {
+            let dir = relative_path(&plugin.root_path(), &repo.url().path_string);
+            match github_slug(&repo_url) {
+                Some((owner, name)) if !dir.is_empty() => {
+                    Some(format!("https://github.com/{owner}/{name}/tree/HEAD/{dir}"))
+                }
+                _ => Some(repo_url),
+            }
+        }
+    });
+    let Some(url) = url else {
+        let what = if issues { "issue tracker" } else { "homepage" };
+        return Err(CoffeeError::new(
+            1,
+            &format!("plugin `{name}` do not declare an {what}"),
+        ));
+    };
+    println!("{url}");
+    if io::stdout().is_terminal() && url.starts_with("http") {
+        if let Err(err) = Command::new("xdg-open").arg(&url).output().await {
+            debug!("xdg-open not available: {err}");
+        }
+    }
+    Ok(())
+}
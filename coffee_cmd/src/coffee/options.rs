@@ -0,0 +1,263 @@
+//! Runtime options of the plugins, written inside the configuration
+//! include of coffee.
+//!
+//! Before an option is written, the plugin is started alone to ask
+//! its manifest (the `getmanifest` handshake of core lightning), so
+//! an unknown option or a value of the wrong type is rejected before
+//! the node restarts with it.
+use std::process::Stdio;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::secrets;
+use log::debug;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use super::audit::{record, Change};
+use super::CoffeeManager;
+
+/// How long the plugin can take to answer the manifest request.
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ask the manifest to the plugin at `exec_path`, the plugin is
+/// killed after the answer.
+pub async fn manifest(exec_path: &str) -> Result<Value, CoffeeError> {
+    let mut child = Command::new(exec_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| CoffeeError::new(1, &format!("plugin {exec_path} not started: {err}")))?;
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "getmanifest",
+        "params": { "allow-deprecated-apis": false },
+    });
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(format!("{request}\n\n").as_bytes()).await?;
+    }
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| CoffeeError::new(1, "plugin output not available"))?;
+    let answer = timeout(MANIFEST_TIMEOUT, async {
+        let mut lines = BufReader::new(stdout).lines();
+        let mut answer = String::new();
+        while let Some(line) = lines.next_line().await? {
+            answer.push_str(&line);
+            if let Ok(answer) = serde_json::from_str::<Value>(&answer) {
+                return Ok(answer);
+            }
+        }
+        Err(CoffeeError::new(1, "plugin exited without a manifest"))
+    })
+    .await
+    .map_err(|_| CoffeeError::new(1, &format!("plugin {exec_path} do not answer")))??;
+    child.kill().await?;
+    debug!("manifest of {exec_path}: {answer}");
+    answer.get("result").cloned().ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("plugin {exec_path} answered without a manifest: {answer}"),
+        )
+    })
+}
+
+/// Check that the option `key` is declared by the `manifest` and
+/// that the `value` has the declared type.
+pub fn validate(manifest: &Value, key: &str, value: &str) -> Result<(), String> {
+    let options = manifest["options"].as_array().cloned().unwrap_or_default();
+    let Some(option) = options.iter().find(|option| option["name"] == key) else {
+        let known: Vec<&str> = options
+            .iter()
+            .filter_map(|option| option["name"].as_str())
+            .collect();
+        return Err(format!(
+            "unknown option `{key}`, the plugin accepts: {}",
+            if known.is_empty() {
+                "no options".to_owned()
+            } else {
+                known.join(", ")
+            }
+        ));
+    };
+    let valid = match option["type"].as_str().unwrap_or("string") {
+        "int" | "integer" => value.parse::<i64>().is_ok(),
+        "bool" => matches!(value, "true" | "false"),
+        "flag" => value.is_empty() || value == "true",
+        _ => true,
+    };
+    if !valid {
+        return Err(format!(
+            "option `{key}` wants a value of type {}, found `{value}`",
+            option["type"]
+        ));
+    }
+    Ok(())
+}
+
+/// executable of the installed `plugin`.
+fn exec_path(coffee: &CoffeeManager, plugin: &str) -> Result<String, CoffeeError> {
+    coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .ok_or_else(|| coffee.not_installed(plugin))?
+        .exec_path()
+        .ok_or_else(|| {
+            CoffeeError::new(
+                1,
+                &format!("plugin `{plugin}` has no executable, install it again"),
+            )
+        })
+}
+
+/// Set the option `key` of the `plugin` to `value`, when `secret` is
+/// true the value is redacted from the output.
+pub async fn set(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    key: &str,
+    value: &str,
+    secret: bool,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let exec_path = exec_path(coffee, &plugin)?;
+    let manifest = manifest(&exec_path).await?;
+    validate(&manifest, key, value)
+        .map_err(|err| CoffeeError::new(1, &format!("[{plugin}] {err}")))?;
+    coffee.coffe_cln_config.fields.remove(key);
+    coffee
+        .coffe_cln_config
+        .add_conf(key, value)
+        .map_err(|err| CoffeeError::new(1, &err.cause))?;
+    let previous = coffee
+        .config
+        .options
+        .entry(plugin.clone())
+        .or_default()
+        .insert(key.to_owned(), value.to_owned());
+    let secret = secret
+        || coffee
+            .config
+            .secrets
+            .get(&plugin)
+            .is_some_and(|keys| keys.contains(key));
+    if secret {
+        coffee
+            .config
+            .secrets
+            .entry(plugin.clone())
+            .or_default()
+            .insert(key.to_owned());
+        secrets::register(value);
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    println!(
+        "{}",
+        secrets::redact(&format!(
+            "[{plugin}] {key}={value}, active after the plugin restart"
+        ))
+    );
+    let change = Change::SetOption {
+        plugin,
+        key: key.to_owned(),
+        value: value.to_owned(),
+        previous,
+        secret,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Remove the option `key` of the `plugin`.
+pub async fn remove(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    key: &str,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let removed = coffee
+        .config
+        .options
+        .get_mut(&plugin)
+        .and_then(|options| options.remove(key));
+    let Some(value) = removed else {
+        return Err(CoffeeError::new(
+            1,
+            &format!("option `{key}` of `{plugin}` is not set"),
+        ));
+    };
+    coffee
+        .config
+        .options
+        .retain(|_, options| !options.is_empty());
+    let secret = coffee
+        .config
+        .secrets
+        .get_mut(&plugin)
+        .is_some_and(|keys| keys.remove(key));
+    coffee.config.secrets.retain(|_, keys| !keys.is_empty());
+    coffee.coffe_cln_config.fields.remove(key);
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    let change = Change::RemoveOption {
+        plugin,
+        key: key.to_owned(),
+        value,
+        secret,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Print the options set for the `plugin`, or the options that the
+/// plugin declares when `declared` is true.
+pub async fn list(coffee: &CoffeeManager, plugin: &str, declared: bool) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    if declared {
+        let manifest = manifest(&exec_path(coffee, &plugin)?).await?;
+        for option in manifest["options"].as_array().cloned().unwrap_or_default() {
+            println!(
+                "{} ({}): {}",
+                option["name"].as_str().unwrap_or_default(),
+                option["type"].as_str().unwrap_or("string"),
+                option["description"].as_str().unwrap_or_default()
+            );
+        }
+        return Ok(());
+    }
+    for (key, value) in coffee.config.options.get(&plugin).into_iter().flatten() {
+        println!("{}", secrets::redact(&format!("{key}={value}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::validate;
+
+    #[test]
+    fn test_options_validate() {
+        let manifest = json!({"options": [
+            {"name": "summary-currency", "type": "string"},
+            {"name": "summary-interval", "type": "int"},
+            {"name": "summary-enabled", "type": "bool"},
+        ]});
+        assert!(validate(&manifest, "summary-currency", "EUR").is_ok());
+        assert!(validate(&manifest, "summary-interval", "10").is_ok());
+        assert!(validate(&manifest, "summary-interval", "ten").is_err());
+        assert!(validate(&manifest, "summary-enabled", "yes").is_err());
+        let err = validate(&manifest, "summary-curency", "EUR").unwrap_err();
+        assert!(err.contains("summary-currency, summary-interval"));
+    }
+}
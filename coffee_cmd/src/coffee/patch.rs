@@ -0,0 +1,173 @@
+//! Local patches of a plugin, applied on top of the upstream
+//! sources after every install and upgrade of the plugin.
+//!
+//! A patch is a diff relative to the root of the repository where
+//! the plugin comes from (e.g. the output of `git diff`).
+use std::path::Path;
+
+use coffee_github::git;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::utils::is_inside;
+use log::debug;
+use tokio::fs::{copy, create_dir_all, read_to_string, remove_file};
+
+use super::audit::{record, Change};
+use super::CoffeeManager;
+
+/// directory where the patches of the `plugin` are stored.
+fn patches_dir(coffee: &CoffeeManager, plugin: &str) -> String {
+    format!("{}/overlays/{plugin}", coffee.config.root_path)
+}
+
+/// Register the `patch` file for the `plugin`.
+pub async fn add(coffee: &mut CoffeeManager, plugin: &str, patch: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let plugin = plugin.as_str();
+    if !coffee
+        .config
+        .plugins
+        .iter()
+        .any(|installed| installed.name() == plugin)
+    {
+        return Err(coffee.not_installed(plugin));
+    }
+    let file_name = Path::new(patch)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| CoffeeError::new(1, &format!("patch `{patch}` is not a file")))?;
+    if read_to_string(patch).await?.trim().is_empty() {
+        return Err(CoffeeError::new(1, &format!("patch `{patch}` is empty")));
+    }
+    let patches = coffee.config.patches.entry(plugin.to_owned()).or_default();
+    if patches.contains(&file_name) {
+        return Err(CoffeeError::new(
+            1,
+            &format!("patch `{file_name}` is already registered for `{plugin}`"),
+        ));
+    }
+    patches.push(file_name.clone());
+    let dir = patches_dir(coffee, plugin);
+    create_dir_all(&dir).await?;
+    copy(patch, format!("{dir}/{file_name}")).await?;
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("patch `{file_name}` registered, it is applied at the next install or upgrade of `{plugin}`");
+    let change = Change::AddPatch {
+        plugin: plugin.to_owned(),
+        patch: file_name,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Remove the patch with name `patch` from the `plugin`.
+pub async fn remove(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    patch: &str,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let plugin = plugin.as_str();
+    let patches = coffee.config.patches.get_mut(plugin);
+    let index = patches
+        .as_ref()
+        .and_then(|patches| patches.iter().position(|name| name == patch));
+    let (Some(patches), Some(index)) = (patches, index) else {
+        return Err(CoffeeError::new(
+            1,
+            &format!("patch `{patch}` is not registered for `{plugin}`"),
+        ));
+    };
+    patches.remove(index);
+    if patches.is_empty() {
+        coffee.config.patches.remove(plugin);
+    }
+    let path = format!("{}/{patch}", patches_dir(coffee, plugin));
+    if Path::new(&path).exists() {
+        remove_file(&path).await?;
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("patch `{patch}` removed, the sources are restored at the next upgrade of `{plugin}`");
+    let change = Change::RemovePatch {
+        plugin: plugin.to_owned(),
+        patch: patch.to_owned(),
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Print the patches registered for the `plugin`, or for all the plugins.
+pub fn list(coffee: &CoffeeManager, plugin: Option<String>) {
+    for (name, patches) in &coffee.config.patches {
+        if plugin.as_ref().is_some_and(|plugin| plugin != name) {
+            continue;
+        }
+        for patch in patches {
+            println!("{name}: {patch}");
+        }
+    }
+}
+
+/// Apply all the patches registered for the `plugin` on its sources.
+pub async fn apply_patches(coffee: &CoffeeManager, plugin: &Plugin) -> Result<(), CoffeeError> {
+    let Some(patches) = coffee.config.patches.get(&plugin.name()) else {
+        return Ok(());
+    };
+    let repo = coffee.repo_of(plugin).ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!(
+                "plugin `{}` do not come from a repository, patches not applied",
+                plugin.name()
+            ),
+        )
+    })?;
+    // the sources of a plugin inside a worktree are patched there.
+    let dir = match coffee.config.worktrees.get(&plugin.name()) {
+        Some(worktree) => worktree.path.clone(),
+        None => repo.url().path_string,
+    };
+    for patch in patches {
+        let path = format!("{}/{patch}", patches_dir(coffee, &plugin.name()));
+        let content = read_to_string(&path).await?;
+        // the sources are not reset between two installs of the plugin.
+        if git::backend().is_applied(&dir, &content)? {
+            debug!("patch {path} already applied to {}", plugin.name());
+            continue;
+        }
+        debug!("applying patch {path} to {}", plugin.name());
+        git::backend()
+            .apply(&dir, &content)
+            .map_err(|err| CoffeeError::new(1, &format!("patch `{patch}` do not apply: {err}")))?;
+        println!("[{}] patch `{patch}` applied", plugin.name());
+    }
+    Ok(())
+}
+
+/// Files of the repository at `repo_path` that are modified by
+/// the patches of its plugins, the changes to these files are
+/// not local modifications of the user.
+pub async fn patched_files(coffee: &CoffeeManager, repo_path: &str) -> Vec<String> {
+    let mut files = vec![];
+    for plugin in &coffee.config.plugins {
+        if !is_inside(&plugin.root_path(), repo_path) {
+            continue;
+        }
+        let Some(patches) = coffee.config.patches.get(&plugin.name()) else {
+            continue;
+        };
+        for patch in patches {
+            let path = format!("{}/{patch}", patches_dir(coffee, &plugin.name()));
+            let Ok(content) = read_to_string(&path).await else {
+                continue;
+            };
+            files.extend(
+                content
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("+++ b/"))
+                    .map(|file| file.trim().to_owned()),
+            );
+        }
+    }
+    files
+}
@@ -0,0 +1,312 @@
+//! Environment variables of the plugins (e.g. the API key of an
+//! exchange rate service), encrypted with age inside the coffee root
+//! and given to the plugin by the wrapper that starts it.
+//!
+//! The wrapper decrypts the variables at every start, so they are
+//! never written in clear on the disk.
+use std::collections::BTreeMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Stdio;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::secrets;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::audit::{record, Change};
+use super::CoffeeManager;
+
+/// quote the `value` for a shell script.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// the value quoted by `quote`.
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .unwrap_or(value)
+        .replace("'\\''", "'")
+}
+
+/// true when `key` can be the name of an environment variable.
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// the script that exports the `vars`, it is the content encrypted.
+fn to_script(vars: &BTreeMap<String, String>) -> String {
+    vars.iter()
+        .map(|(key, value)| format!("export {key}={}\n", quote(value)))
+        .collect()
+}
+
+/// the variables exported by the `script`.
+fn from_script(script: &str) -> BTreeMap<String, String> {
+    script
+        .lines()
+        .filter_map(|line| line.strip_prefix("export "))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_owned(), unquote(value)))
+        .collect()
+}
+
+/// run the age `program` with the `args`, the `input` is written
+/// to its stdin, return its output.
+async fn age(program: &str, args: &[&str], input: &[u8]) -> Result<Vec<u8>, CoffeeError> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            CoffeeError::new(
+                1,
+                &format!("`{program}` not found, install age to store the environment of the plugins: {err}"),
+            )
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let err = CoffeeError::new(
+            1,
+            &format!(
+                "{program} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        );
+        return Err(err);
+    }
+    Ok(output.stdout)
+}
+
+/// Encrypted environment variables of the plugins inside `dir`.
+#[derive(Clone, Debug)]
+pub struct PluginEnv {
+    dir: String,
+}
+
+impl PluginEnv {
+    pub fn new(dir: &str) -> Self {
+        PluginEnv {
+            dir: dir.to_owned(),
+        }
+    }
+
+    /// the age identity that decrypts the variables.
+    fn identity(&self) -> String {
+        format!("{}/identity.txt", self.dir)
+    }
+
+    /// the encrypted variables of the `plugin`.
+    fn file(&self, plugin: &str) -> String {
+        format!("{}/{plugin}.age", self.dir)
+    }
+
+    /// the wrapper that starts the `plugin` with its variables.
+    fn wrapper(&self, plugin: &str) -> String {
+        format!("{}/{plugin}.sh", self.dir)
+    }
+
+    /// Read the variables of the `plugin`.
+    pub async fn read(&self, plugin: &str) -> Result<BTreeMap<String, String>, CoffeeError> {
+        let file = self.file(plugin);
+        if !Path::new(&file).exists() {
+            return Ok(BTreeMap::new());
+        }
+        let script = age("age", &["-d", "-i", &self.identity(), &file], &[]).await?;
+        Ok(from_script(&String::from_utf8_lossy(&script)))
+    }
+
+    /// Write the variables of the `plugin`, encrypted with the identity
+    /// of coffee, that is generated the first time.
+    pub async fn write(
+        &self,
+        plugin: &str,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<(), CoffeeError> {
+        let file = self.file(plugin);
+        if vars.is_empty() {
+            if Path::new(&file).exists() {
+                fs::remove_file(&file).await?;
+            }
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).await?;
+        fs::set_permissions(&self.dir, std::fs::Permissions::from_mode(0o700)).await?;
+        let identity = self.identity();
+        if !Path::new(&identity).exists() {
+            age("age-keygen", &["-o", &identity], &[]).await?;
+            fs::set_permissions(&identity, std::fs::Permissions::from_mode(0o600)).await?;
+        }
+        let recipient = age("age-keygen", &["-y", &identity], &[]).await?;
+        let recipient = String::from_utf8_lossy(&recipient).trim().to_owned();
+        let tmp = format!("{file}.tmp");
+        age(
+            "age",
+            &["-e", "-r", &recipient, "-o", &tmp],
+            to_script(vars).as_bytes(),
+        )
+        .await?;
+        fs::rename(&tmp, &file).await?;
+        Ok(())
+    }
+
+    /// Return the executable that starts the `plugin` at `exec_path`
+    /// with its variables, the wrapper when it has any.
+    pub async fn wrap(&self, plugin: &str, exec_path: &str) -> Result<String, CoffeeError> {
+        let wrapper_path = self.wrapper(plugin);
+        if exec_path == wrapper_path || !Path::new(&self.file(plugin)).exists() {
+            return Ok(exec_path.to_owned());
+        }
+        let file = quote(&self.file(plugin));
+        let wrapper = format!(
+            "#!/bin/sh\n# generated by coffee to run the plugin `{plugin}` with its environment, do not edit.\nif [ -f {file} ]; then\n  eval \"$(age -d -i {} {file})\" || exit 1\nfi\nexec {} \"$@\"\n",
+            quote(&self.identity()),
+            quote(exec_path)
+        );
+        fs::write(&wrapper_path, wrapper).await?;
+        fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+        Ok(wrapper_path)
+    }
+}
+
+/// start the installed `plugin` with the wrapper of its variables,
+/// the configuration include is updated when it changes.
+async fn use_wrapper(coffee: &mut CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let env = coffee.plugin_env();
+    let Some(installed) = coffee
+        .config
+        .plugins
+        .iter_mut()
+        .find(|installed| installed.name() == plugin)
+    else {
+        return Ok(());
+    };
+    let Some(exec_path) = installed.exec_path() else {
+        return Ok(());
+    };
+    let wrapper = env.wrap(plugin, &exec_path).await?;
+    if wrapper == exec_path {
+        return Ok(());
+    }
+    installed.set_exec_path(&wrapper);
+    if let Some(paths) = coffee.coffe_cln_config.fields.get_mut("plugin") {
+        paths
+            .iter_mut()
+            .filter(|path| **path == exec_path)
+            .for_each(|path| *path = wrapper.clone());
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await
+}
+
+/// Set the variable of the `assignment` (`KEY=VALUE`) for the `plugin`,
+/// when `secret` is true the value is redacted from the output.
+pub async fn set(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    assignment: &str,
+    secret: bool,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let Some((key, value)) = assignment.split_once('=') else {
+        return Err(CoffeeError::new(1, "the variable must be `KEY=VALUE`"));
+    };
+    if !is_valid_key(key) {
+        let err = CoffeeError::new(
+            1,
+            &format!("`{key}` is not the name of an environment variable"),
+        );
+        return Err(err);
+    }
+    let env = coffee.plugin_env();
+    let mut vars = env.read(&plugin).await?;
+    let created = vars.insert(key.to_owned(), value.to_owned()).is_none();
+    env.write(&plugin, &vars).await?;
+    if secret {
+        coffee
+            .config
+            .secrets
+            .entry(plugin.clone())
+            .or_default()
+            .insert(key.to_owned());
+        coffee.storage.store(&coffee.storage_info()).await?;
+    }
+    use_wrapper(coffee, &plugin).await?;
+    println!("[{plugin}] {key} set, active after the plugin restart");
+    let change = Change::SetEnv {
+        plugin,
+        key: key.to_owned(),
+        created,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Remove the variable `key` of the `plugin`.
+pub async fn unset(coffee: &mut CoffeeManager, plugin: &str, key: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let env = coffee.plugin_env();
+    let mut vars = env.read(&plugin).await?;
+    if vars.remove(key).is_none() {
+        let err = CoffeeError::new(1, &format!("variable `{key}` of `{plugin}` is not set"));
+        return Err(err);
+    }
+    env.write(&plugin, &vars).await?;
+    if let Some(keys) = coffee.config.secrets.get_mut(&plugin) {
+        if keys.remove(key) {
+            coffee.config.secrets.retain(|_, keys| !keys.is_empty());
+            coffee.storage.store(&coffee.storage_info()).await?;
+        }
+    }
+    println!("[{plugin}] {key} removed, active after the plugin restart");
+    let change = Change::UnsetEnv {
+        plugin,
+        key: key.to_owned(),
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Print the variables of the `plugin`.
+pub async fn list(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let secret = coffee.config.secrets.get(&plugin);
+    for (key, value) in coffee.plugin_env().read(&plugin).await? {
+        if secret.map(|keys| keys.contains(&key)).unwrap_or_default() {
+            secrets::register(&value);
+        }
+        println!("{}", secrets::redact(&format!("{key}={value}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{from_script, is_valid_key, to_script};
+
+    #[test]
+    fn test_plugin_env_script() {
+        let vars = BTreeMap::from([
+            ("API_KEY".to_owned(), "a'b c".to_owned()),
+            ("RATE".to_owned(), "1=2".to_owned()),
+        ]);
+        let script = to_script(&vars);
+        assert_eq!(script, "export API_KEY='a'\\''b c'\nexport RATE='1=2'\n");
+        assert_eq!(from_script(&script), vars);
+        assert!(is_valid_key("API_KEY"));
+        assert!(!is_valid_key("1KEY"));
+        assert!(!is_valid_key("API-KEY"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Override of the repository where a plugin comes from, so a
+//! single plugin can be installed from a fork while all the
+//! others keep coming from the upstream repositories.
+use std::path::Path;
+
+use coffee_github::repository::Github;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::repository::Repository;
+use coffee_lib::url::URL;
+use coffee_lib::utils::is_inside;
+use log::debug;
+use tokio::fs::remove_dir_all;
+
+use super::audit::{record, Change};
+use super::config::PluginOverride;
+use super::trust;
+use super::CoffeeManager;
+
+/// name of the repository that contains the fork of the `plugin`.
+fn override_repository(plugin: &str) -> String {
+    format!("{plugin}-override")
+}
+
+/// remove the repository of the override of the `plugin`, if any.
+async fn remove_override_repository(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+) -> Result<(), CoffeeError> {
+    let Some(plugin_override) = coffee.config.overrides.remove(plugin) else {
+        return Ok(());
+    };
+    let Some(index) = coffee
+        .repos
+        .iter()
+        .position(|repo| repo.name() == plugin_override.repository)
+    else {
+        return Ok(());
+    };
+    let repo_path = coffee.repos[index].url().path_string;
+    if coffee
+        .config
+        .plugins
+        .iter()
+        .any(|installed| is_inside(&installed.root_path(), &repo_path))
+    {
+        // the override is still needed by the installed plugin.
+        coffee
+            .config
+            .overrides
+            .insert(plugin.to_owned(), plugin_override);
+        return Err(CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` is installed from the fork, remove it first"),
+        ));
+    }
+    coffee.repos.remove(index);
+    if Path::new(&repo_path).exists() {
+        remove_dir_all(&repo_path).await?;
+    }
+    Ok(())
+}
+
+/// Install the `plugin` from the repository at `url` (and `branch`)
+/// from now on.
+pub async fn set(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    url: &str,
+    branch: Option<String>,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let plugin = plugin.as_str();
+    if coffee.config.settings.offline {
+        return Err(CoffeeError::new(
+            1,
+            "coffee is offline, the fork can not be cloned",
+        ));
+    }
+    let previous = coffee
+        .config
+        .overrides
+        .get(plugin)
+        .map(|previous| (previous.url.clone(), previous.branch.clone()));
+    remove_override_repository(coffee, plugin).await?;
+
+    let name = override_repository(plugin);
+    let url = URL::parse(&coffee.install_root(), url, &name)?;
+    debug!("override of {plugin} with {}", url.url_string);
+    let mut repo = Github::new(&name, &url);
+    repo.init().await?;
+    if let Some(branch) = &branch {
+        repo.checkout_branch(branch).await?;
+    }
+    if repo.get_plugin_by_name(plugin).is_none() {
+        remove_dir_all(&url.path_string).await?;
+        return Err(CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` not found inside {}", url.url_string),
+        ));
+    }
+    if let Err(err) = trust::trust_repository(coffee, &repo).await {
+        remove_dir_all(&url.path_string).await?;
+        return Err(err);
+    }
+    coffee.repos.push(Box::new(repo));
+    coffee.config.overrides.insert(
+        plugin.to_owned(),
+        PluginOverride {
+            repository: name,
+            url: url.url_string.clone(),
+            branch: branch.clone(),
+        },
+    );
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!(
+        "`{plugin}` is installed from {} from now on",
+        url.url_string
+    );
+    if coffee
+        .config
+        .plugins
+        .iter()
+        .any(|installed| installed.name() == plugin)
+    {
+        println!("`{plugin}` is already installed, remove it and install it again to use the fork");
+    }
+    let change = Change::SetOverride {
+        plugin: plugin.to_owned(),
+        url: url.url_string,
+        branch,
+        previous,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
+
+/// Install the `plugin` again from the upstream repositories.
+pub async fn reset(coffee: &mut CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let plugin = plugin.as_str();
+    let Some(previous) = coffee.config.overrides.get(plugin).cloned() else {
+        return Err(CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` has no override"),
+        ));
+    };
+    remove_override_repository(coffee, plugin).await?;
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("`{plugin}` is installed from the upstream repositories from now on");
+    let change = Change::ResetOverride {
+        plugin: plugin.to_owned(),
+        url: previous.url,
+        branch: previous.branch,
+    };
+    record(coffee, change).await;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+//! Profiles of the nodes managed by coffee, each profile lives in
+//! its own directory of the coffee home, with its own storage and
+//! core lightning configuration, so one host can run more nodes
+//! with different plugins.
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use serde_json::Value;
+use tokio::fs::{read_dir, read_to_string};
+
+use super::config::{DEFAULT_PROFILE, PROFILES_DIR};
+use super::CoffeeManager;
+
+/// Describe the profile with its root at `root_path`, the
+/// profile is not configured until it has a storage.
+async fn describe(root_path: &str) -> String {
+    let storage_path = format!("{root_path}/storage.json");
+    let storage: Option<Value> = match read_to_string(&storage_path).await {
+        Ok(content) => serde_json::from_str(&content).ok(),
+        Err(_) => None,
+    };
+    let Some(storage) = storage else {
+        return "not configured".to_owned();
+    };
+    let config = &storage["config"];
+    let plugins = config["plugins"]
+        .as_array()
+        .map(|plugins| plugins.len())
+        .unwrap_or_default();
+    format!(
+        "{}, {}, {plugins} plugins",
+        config["network"].as_str().unwrap_or("bitcoin"),
+        config["cln_root"]
+            .as_str()
+            .unwrap_or("no core lightning directory")
+    )
+}
+
+/// Print the profiles, the one in use is marked with `*`.
+pub async fn list(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let home_path = &coffee.config.home_path;
+    let mut profiles = vec![(DEFAULT_PROFILE.to_owned(), home_path.clone())];
+    let profiles_dir = format!("{home_path}/{PROFILES_DIR}");
+    if Path::new(&profiles_dir).exists() {
+        let mut entries = read_dir(&profiles_dir).await?;
+        let mut names = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            // a `default` directory can not be chosen, it is the coffee home.
+            if entry.file_type().await?.is_dir() && entry.file_name() != DEFAULT_PROFILE {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        names.sort();
+        for name in names {
+            let root_path = format!("{profiles_dir}/{name}");
+            profiles.push((name, root_path));
+        }
+    }
+    let current = coffee.config.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+    for (name, root_path) in profiles {
+        let marker = if name == current { "*" } else { " " };
+        println!("{marker} {name}: {}", describe(&root_path).await);
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Render the README of a plugin inside the terminal.
+use std::io::{self, IsTerminal};
+
+use coffee_lib::errors::CoffeeError;
+use tokio::fs::read_to_string;
+
+use super::CoffeeManager;
+
+const BOLD: &str = "\x1b[1m";
+const UNDERLINE: &str = "\x1b[4m";
+const DIM: &str = "\x1b[2m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Replace the inline markdown (`code` and **bold**) with
+/// the ANSI escape codes.
+fn render_inline(line: &str) -> String {
+    let mut rendered = String::new();
+    let mut code = false;
+    let mut bold = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => {
+                code = !code;
+                rendered += if code { CYAN } else { RESET };
+                if !code && bold {
+                    rendered += BOLD;
+                }
+            }
+            '*' if !code && chars.peek() == Some(&'*') => {
+                chars.next();
+                bold = !bold;
+                rendered += if bold { BOLD } else { RESET };
+            }
+            _ => rendered.push(c),
+        }
+    }
+    if code || bold {
+        rendered += RESET;
+    }
+    rendered
+}
+
+/// Render the `markdown` with the ANSI escape codes.
+fn render(markdown: &str) -> String {
+    let mut rendered = String::new();
+    let mut code_block = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            code_block = !code_block;
+            continue;
+        }
+        if code_block {
+            rendered += &format!("    {DIM}{line}{RESET}\n");
+        } else if let Some(title) = trimmed.strip_prefix('#') {
+            let title = title.trim_start_matches('#').trim();
+            rendered += &format!("{BOLD}{UNDERLINE}{title}{RESET}\n");
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            let indent = &line[..line.len() - trimmed.len()];
+            rendered += &format!("{indent}  • {}\n", render_inline(item));
+        } else {
+            rendered += &format!("{}\n", render_inline(line));
+        }
+    }
+    rendered
+}
+
+/// Print the README of the `plugin`, installed or available
+/// inside the repositories.
+pub async fn readme(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let installed = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .cloned();
+    let root_path = installed
+        .or_else(|| coffee.search_plugin(&plugin))
+        .map(|plugin| plugin.root_path())
+        .ok_or_else(|| coffee.not_found(&plugin))?;
+    let mut entries = std::fs::read_dir(&root_path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase().starts_with("readme"))
+                    .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    // prefer the markdown version when there are more of them.
+    entries.sort_by_key(|path| path.extension().map(|extension| extension != "md"));
+    let path = entries.first().ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` has no README inside {root_path}"),
+        )
+    })?;
+    let content = read_to_string(path).await?;
+    let is_markdown = path
+        .extension()
+        .map(|extension| extension == "md")
+        .unwrap_or_default();
+    if is_markdown && !coffee.config.settings.plain && io::stdout().is_terminal() {
+        print!("{}", render(&content));
+    } else {
+        print!("{content}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, BOLD, CYAN, RESET};
+
+    #[test]
+    fn test_render() {
+        let rendered =
+            render("# Summary\nrun `summary` **now**\n```\nlightning-cli summary\n```\n");
+        let mut lines = rendered.lines();
+        assert!(lines.next().unwrap().contains("Summary"));
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("run {CYAN}summary{RESET} {BOLD}now{RESET}")
+        );
+        assert!(lines.next().unwrap().contains("lightning-cli summary"));
+        assert!(lines.next().is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! Regtest node used to run the plugins away from the node of the
+//! user, coffee starts a bitcoind and a core lightning inside a
+//! throwaway directory, or attaches to a regtest node already running.
+use std::env;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use clightningrpc_common::client::Client;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::unix_time;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::fs::{create_dir_all, remove_dir_all};
+use tokio::net::TcpListener;
+use tokio::process::{Child, Command};
+use tokio::time::sleep;
+
+/// How long the node can take to open the rpc socket.
+const STARTUP_POLLS: u64 = 60;
+
+/// How many times the node is asked about a plugin
+/// before considering it not responding.
+const PLUGIN_POLLS: u64 = 5;
+
+/// Regtest node, the processes are killed when it is dropped.
+pub struct Regtest {
+    /// lightning directory of the node.
+    pub lightning_dir: String,
+    /// directory removed by `stop`, only for the nodes started by coffee.
+    owned_dir: Option<String>,
+    bitcoind: Option<Child>,
+    lightningd: Option<Child>,
+    rpc: Client,
+}
+
+/// a tcp port that is free at the moment.
+async fn free_port() -> Result<u16, CoffeeError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// spawn the `program` with the `args`, the output goes to the `log` file.
+fn spawn(program: &str, args: &[String], log: &str) -> Result<Child, CoffeeError> {
+    let log = std::fs::File::create(log)?;
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| CoffeeError::new(1, &format!("{program} not started: {err}")))
+}
+
+impl Regtest {
+    /// Start a bitcoind and a core lightning on regtest inside a
+    /// new directory.
+    pub async fn start() -> Result<Self, CoffeeError> {
+        let dir = format!(
+            "{}/coffee-regtest-{}-{}",
+            env::temp_dir().to_string_lossy(),
+            std::process::id(),
+            unix_time()
+        );
+        let bitcoin_dir = format!("{dir}/bitcoin");
+        let lightning_dir = format!("{dir}/lightning");
+        create_dir_all(&bitcoin_dir).await?;
+        create_dir_all(&lightning_dir).await?;
+        let rpc_port = free_port().await?;
+        let bitcoind = spawn(
+            "bitcoind",
+            &[
+                "-regtest".to_owned(),
+                format!("-datadir={bitcoin_dir}"),
+                "-server".to_owned(),
+                "-listen=0".to_owned(),
+                "-rpcuser=coffee".to_owned(),
+                "-rpcpassword=coffee".to_owned(),
+                format!("-rpcport={rpc_port}"),
+                "-fallbackfee=0.00001".to_owned(),
+            ],
+            &format!("{dir}/bitcoind.log"),
+        )?;
+        let lightningd = spawn(
+            "lightningd",
+            &[
+                "--network=regtest".to_owned(),
+                format!("--lightning-dir={lightning_dir}"),
+                "--bitcoin-rpcuser=coffee".to_owned(),
+                "--bitcoin-rpcpassword=coffee".to_owned(),
+                format!("--bitcoin-rpcport={rpc_port}"),
+                format!("--bitcoin-datadir={bitcoin_dir}"),
+                format!("--log-file={lightning_dir}/log"),
+            ],
+            &format!("{dir}/lightningd.log"),
+        )?;
+        println!("regtest node started inside {dir}");
+        let mut node = Regtest {
+            rpc: Client::new(format!("{lightning_dir}/regtest/lightning-rpc")),
+            lightning_dir,
+            owned_dir: Some(dir),
+            bitcoind: Some(bitcoind),
+            lightningd: Some(lightningd),
+        };
+        if let Err(err) = node.wait_ready().await {
+            node.stop().await;
+            return Err(err);
+        }
+        Ok(node)
+    }
+
+    /// Attach to the regtest node running inside the `lightning_dir`.
+    pub async fn attach(lightning_dir: &str) -> Result<Self, CoffeeError> {
+        let rpc_path = format!("{lightning_dir}/regtest/lightning-rpc");
+        if !Path::new(&rpc_path).exists() {
+            let err = CoffeeError::new(
+                1,
+                &format!("no regtest node running inside {lightning_dir}"),
+            );
+            return Err(err);
+        }
+        Ok(Regtest {
+            rpc: Client::new(rpc_path),
+            lightning_dir: lightning_dir.to_owned(),
+            owned_dir: None,
+            bitcoind: None,
+            lightningd: None,
+        })
+    }
+
+    /// path of the rpc socket of the node.
+    pub fn rpc_path(&self) -> String {
+        format!("{}/regtest/lightning-rpc", self.lightning_dir)
+    }
+
+    /// wait until the node answers to `getinfo`.
+    async fn wait_ready(&mut self) -> Result<(), CoffeeError> {
+        for _ in 0..STARTUP_POLLS {
+            if let Some(lightningd) = self.lightningd.as_mut() {
+                if let Some(status) = lightningd.try_wait()? {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("regtest node exited with {status}, see its log"),
+                    );
+                    return Err(err);
+                }
+            }
+            if Path::new(&self.rpc_path()).exists() && self.call("getinfo", json!({})).is_ok() {
+                return Ok(());
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+        Err(CoffeeError::new(1, "regtest node not ready in time"))
+    }
+
+    /// Call the `method` of the node.
+    pub fn call(&self, method: &str, payload: Value) -> Result<Value, CoffeeError> {
+        let response = self
+            .rpc
+            .send_request::<Value, Value>(method, payload)
+            .map_err(|err| CoffeeError::new(1, &format!("{err}")))?;
+        if let Some(err) = response.error {
+            return Err(CoffeeError::new(1, &format!("cln error: {}", err.message)));
+        }
+        Ok(response.result.unwrap_or_default())
+    }
+
+    /// Start the plugin at `exec_path` and wait until the node
+    /// reports it as active.
+    pub async fn start_plugin(&self, exec_path: &str) -> Result<(), CoffeeError> {
+        self.call(
+            "plugin",
+            json!({"subcommand": "start", "plugin": exec_path}),
+        )?;
+        for poll in 1..=PLUGIN_POLLS {
+            let active = self.plugin_state(exec_path)?;
+            debug!("regtest poll {poll} of {exec_path}: {:?}", active);
+            match active {
+                Some(true) => return Ok(()),
+                None => return Err(CoffeeError::new(1, "plugin crashed at startup")),
+                Some(false) => sleep(Duration::from_secs(1)).await,
+            }
+        }
+        Err(CoffeeError::new(1, "plugin not responding"))
+    }
+
+    /// Ask the node if the plugin at `exec_path` is active, none
+    /// when the plugin is not registered (e.g. it crashed).
+    pub fn plugin_state(&self, exec_path: &str) -> Result<Option<bool>, CoffeeError> {
+        let plugins = self.call("plugin", json!({"subcommand": "list"}))?;
+        Ok(plugins["plugins"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|plugin| plugin["name"].as_str() == Some(exec_path))
+            .map(|plugin| plugin["active"].as_bool().unwrap_or_default()))
+    }
+
+    /// Stop the node when it was started by coffee, and remove
+    /// its directory, an attached node is left running.
+    pub async fn stop(mut self) {
+        if self.lightningd.is_some() {
+            if let Err(err) = self.call("stop", json!({})) {
+                debug!("regtest node not stopped: {err}");
+            }
+        }
+        for mut child in [self.lightningd.take(), self.bitcoind.take()]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(err) = child.kill().await {
+                debug!("regtest process not killed: {err}");
+            }
+        }
+        if let Some(dir) = self.owned_dir.take() {
+            if let Err(err) = remove_dir_all(&dir).await {
+                warn!("regtest directory {dir} not removed: {err}");
+            }
+        }
+    }
+}
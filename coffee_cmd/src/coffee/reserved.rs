@@ -0,0 +1,65 @@
+//! Names reserved to the plugins shipped with core lightning, a third
+//! party plugin (or an alias) with one of them is confused with the
+//! built-in plugin, so it needs an explicit `--allow-reserved`.
+use coffee_lib::errors::CoffeeError;
+
+use super::CoffeeManager;
+
+/// Plugins shipped with core lightning.
+pub const RESERVED_NAMES: &[&str] = &[
+    "askrene",
+    "autoclean",
+    "bcli",
+    "bookkeeper",
+    "chanbackup",
+    "clnrest",
+    "cln-askrene",
+    "cln-bip353",
+    "cln-grpc",
+    "cln-renepay",
+    "cln-xpay",
+    "commando",
+    "exposesecret",
+    "funder",
+    "keysend",
+    "offers",
+    "pay",
+    "recklessrpc",
+    "recover",
+    "renepay",
+    "spenderp",
+    "sql",
+    "topology",
+    "txprepare",
+    "wss-proxy",
+    "xpay",
+];
+
+/// true when the `name` is the one of a plugin of core lightning.
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_NAMES.contains(&name.to_lowercase().as_str())
+}
+
+/// Fail when the `name` is reserved and the user did not allow it.
+pub fn check(coffee: &CoffeeManager, name: &str) -> Result<(), CoffeeError> {
+    if is_reserved(name) && !coffee.config.settings.allow_reserved {
+        let err = CoffeeError::new(
+            1,
+            &format!("`{name}` is the name of a plugin of core lightning, use `--allow-reserved` to use it anyway"),
+        );
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_reserved;
+
+    #[test]
+    fn test_reserved_names() {
+        assert!(is_reserved("pay"));
+        assert!(is_reserved("Autoclean"));
+        assert!(!is_reserved("summary"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Script of operations, the operations inside a YAML (or JSON) file
+//! run one after the other inside one invocation of coffee, and when
+//! one of them fails the operations already done are rolled back.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt::is_interrupted;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::repository::Layout;
+use coffee_lib::secrets::REDACTED;
+use serde::Deserialize;
+use tokio::fs::read_to_string;
+
+use super::config::FailurePolicy;
+use super::transaction::{self, Transaction};
+use super::{alias, options, CoffeeManager};
+
+/// Operation inside the script, e.g. `install: [summary]`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Operation {
+    /// add a repository.
+    Add {
+        name: String,
+        url: String,
+        #[serde(default)]
+        layout: Layout,
+    },
+    /// remove a repository.
+    RemoveRepository(String),
+    Install(Vec<String>),
+    Remove(Vec<String>),
+    /// upgrade the plugins, all of them when the list is empty.
+    Upgrade(Vec<String>),
+    /// set an option of a plugin.
+    Set {
+        plugin: String,
+        key: String,
+        value: String,
+        /// the value is redacted from the output.
+        #[serde(default)]
+        secret: bool,
+    },
+    /// remove an option of a plugin.
+    Unset {
+        plugin: String,
+        key: String,
+    },
+    Alias {
+        alias: String,
+        plugin: String,
+    },
+}
+
+impl Operation {
+    /// short description of the operation for the output.
+    fn describe(&self) -> String {
+        match self {
+            Operation::Add { name, url, .. } => format!("add {name} {url}"),
+            Operation::RemoveRepository(name) => format!("remove repository {name}"),
+            Operation::Install(plugins) => format!("install {}", plugins.join(" ")),
+            Operation::Remove(plugins) => format!("remove {}", plugins.join(" ")),
+            Operation::Upgrade(plugins) => format!("upgrade {}", plugins.join(" ")),
+            Operation::Set {
+                plugin,
+                key,
+                value,
+                secret,
+            } => format!(
+                "set {plugin} {key}={}",
+                if *secret { REDACTED } else { value.as_str() }
+            ),
+            Operation::Unset { plugin, key } => format!("unset {plugin} {key}"),
+            Operation::Alias { alias, plugin } => format!("alias {alias} {plugin}"),
+        }
+    }
+
+    async fn run(&self, coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+        match self {
+            Operation::Add { name, url, layout } => {
+                coffee.add_remote_with_layout(name, url, *layout).await
+            }
+            Operation::RemoveRepository(name) => coffee.rm_remote(name).await,
+            Operation::Install(plugins) => {
+                let plugins: Vec<&str> = plugins.iter().map(String::as_str).collect();
+                coffee.install(&plugins, false, false).await
+            }
+            Operation::Remove(plugins) => {
+                for plugin in plugins {
+                    coffee.remove(plugin).await?;
+                }
+                Ok(())
+            }
+            Operation::Upgrade(plugins) => {
+                let plugins: Vec<&str> = plugins.iter().map(String::as_str).collect();
+                coffee.upgrade(&plugins).await
+            }
+            Operation::Set {
+                plugin,
+                key,
+                value,
+                secret,
+            } => options::set(coffee, plugin, key, value, *secret).await,
+            Operation::Unset { plugin, key } => options::remove(coffee, plugin, key).await,
+            Operation::Alias { alias, plugin } => alias::set(coffee, alias, plugin).await,
+        }
+    }
+}
+
+/// parse the content of a script, a list of operations, every
+/// operation is a map with one key, e.g. `- install: [summary]`.
+pub fn parse(content: &str) -> Result<Vec<Operation>, CoffeeError> {
+    let deserializer = serde_yaml::Deserializer::from_str(content);
+    serde_yaml::with::singleton_map_recursive::deserialize(deserializer)
+        .map_err(|err| CoffeeError::new(1, &format!("script malformed: {err}")))
+}
+
+/// Run the operations of the script at `path`, all of them are
+/// rolled back when one fails, with `--keep-going` the operations
+/// that fail are skipped instead.
+pub async fn run(coffee: &mut CoffeeManager, path: &str) -> Result<(), CoffeeError> {
+    let content = read_to_string(path)
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("script {path}: {err}")))?;
+    let operations = parse(&content)?;
+    let keep_going = coffee.config.settings.on_failure == Some(FailurePolicy::KeepGoing);
+    let transaction = (!keep_going).then(|| Transaction::begin(coffee));
+    let mut failed = vec![];
+    for (index, operation) in operations.iter().enumerate() {
+        println!(
+            "[{}/{}] {}",
+            index + 1,
+            operations.len(),
+            operation.describe()
+        );
+        if let Err(err) = operation.run(coffee).await {
+            println!("[{}/{}] failed: {err}", index + 1, operations.len());
+            failed.push(index + 1);
+            if !keep_going || is_interrupted() {
+                break;
+            }
+        }
+    }
+    let result = match failed.as_slice() {
+        [] => Ok(()),
+        [index] => Err(CoffeeError::new(1, &format!("operation {index} failed"))),
+        failed => {
+            let failed: Vec<String> = failed.iter().map(|index| index.to_string()).collect();
+            let err = CoffeeError::new(1, &format!("operations {} failed", failed.join(", ")));
+            Err(err)
+        }
+    };
+    transaction::finish(coffee, transaction, result).await
+}
+
+#[cfg(test)]
+mod tests {
+    use coffee_lib::repository::Layout;
+
+    use super::{parse, Operation};
+
+    #[test]
+    fn test_run_parse() {
+        let operations = parse(
+            "- add:\n    name: lightningd\n    url: https://github.com/lightningd/plugins\n- install: [summary, clearnet]\n- set: {plugin: summary, key: summary-currency, value: EUR}\n- remove-repository: old\n",
+        )
+        .unwrap();
+        assert_eq!(
+            operations[0],
+            Operation::Add {
+                name: "lightningd".to_owned(),
+                url: "https://github.com/lightningd/plugins".to_owned(),
+                layout: Layout::Flat,
+            }
+        );
+        assert_eq!(
+            operations[1],
+            Operation::Install(vec!["summary".to_owned(), "clearnet".to_owned()])
+        );
+        assert_eq!(operations[3], Operation::RemoveRepository("old".to_owned()));
+        // the JSON form is accepted too.
+        assert_eq!(
+            parse("[{\"upgrade\": []}]").unwrap(),
+            vec![Operation::Upgrade(vec![])]
+        );
+        assert!(parse("- disable: summary").is_err());
+    }
+}
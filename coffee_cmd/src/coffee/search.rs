@@ -0,0 +1,133 @@
+//! Search of the plugins available inside the repositories, by name,
+//! by the tags and by what the plugins provide to the node (RPC methods,
+//! hooks and notifications) declared inside the manifests.
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::relative_path;
+use log::debug;
+
+use super::cmd::SortBy;
+use super::github_api::GithubApi;
+use super::CoffeeManager;
+
+/// Print the plugins that contain `query` in the name, that declare
+/// the `tag` and that provide the RPC method, hook or notification
+/// `provides`, all the plugins when they are missing.
+///
+/// Sorting by the forge signals asks them to the GitHub API.
+pub async fn search(
+    coffee: &CoffeeManager,
+    query: Option<String>,
+    tag: Option<String>,
+    provides: Option<String>,
+    sort: SortBy,
+) -> Result<(), CoffeeError> {
+    let query = query.map(|query| query.to_lowercase());
+    let tag = tag.map(|tag| tag.to_lowercase());
+    let mut found = vec![];
+    for repo in &coffee.repos {
+        for plugin in repo.list().await? {
+            let name = plugin.name();
+            let tags = plugin.tags();
+            if let Some(query) = &query {
+                if !name.to_lowercase().contains(query) {
+                    continue;
+                }
+            }
+            if let Some(tag) = &tag {
+                if !tags
+                    .iter()
+                    .any(|plugin_tag| plugin_tag.to_lowercase() == *tag)
+                {
+                    continue;
+                }
+            }
+            if let Some(provides) = &provides {
+                if !plugin.provides().contains(provides) {
+                    continue;
+                }
+            }
+            let dir = relative_path(&plugin.root_path(), &repo.url().path_string);
+            found.push((plugin, repo.name(), repo.url().url_string, dir));
+        }
+    }
+    if found.is_empty() {
+        println!("no plugins found");
+        return Ok(());
+    }
+
+    let mut rows = vec![];
+    if sort == SortBy::Name {
+        rows = found
+            .into_iter()
+            .map(|(plugin, repo, _, _)| (plugin, repo, None))
+            .collect();
+    } else {
+        if coffee.config.settings.offline {
+            return Err(CoffeeError::new(
+                1,
+                "coffee is offline, the forge signals are not available",
+            ));
+        }
+        let mut api = GithubApi::new(&coffee.config.settings, &coffee.config.root_path)?;
+        for (plugin, repo, url, dir) in found {
+            let stats = match api.plugin_stats(&url, &dir, &plugin.name()).await {
+                Ok(stats) => Some(stats),
+                Err(err) => {
+                    debug!("no forge signals for {}: {err}", plugin.name());
+                    None
+                }
+            };
+            rows.push((plugin, repo, stats));
+        }
+    }
+    match sort {
+        SortBy::Name => rows.sort_by_key(|(plugin, _, _)| plugin.name()),
+        SortBy::Stars => rows.sort_by_key(|(_, _, stats)| {
+            Reverse(stats.as_ref().map(|stats| stats.stars).unwrap_or_default())
+        }),
+        SortBy::Maintained => rows.sort_by_key(|(_, _, stats)| {
+            Reverse(stats.as_ref().and_then(|stats| stats.last_commit.clone()))
+        }),
+    }
+
+    for (plugin, repo, stats) in rows {
+        let tags = plugin.tags();
+        let mut line = format!("{} ({repo})", plugin.name());
+        if !tags.is_empty() {
+            line += &format!(" [{}]", tags.join(", "));
+        }
+        if let Some(stats) = stats {
+            let last_commit = stats
+                .last_commit
+                .as_deref()
+                .and_then(|date| date.split('T').next())
+                .unwrap_or("unknown");
+            line += &format!(
+                " stars: {}, last commit: {last_commit}, open issues: {}",
+                stats.stars, stats.open_issues
+            );
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Print all the tags declared by the plugins, with the
+/// number of plugins that declare them.
+pub async fn list_tags(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let mut tags: BTreeMap<String, usize> = BTreeMap::new();
+    for repo in &coffee.repos {
+        for plugin in repo.list().await? {
+            for tag in plugin.tags() {
+                *tags.entry(tag.to_lowercase()).or_default() += 1;
+            }
+        }
+    }
+    for (tag, count) in tags {
+        println!("{tag} ({count})");
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Selectors of the installed plugins inside the arguments of the
+//! commands: glob patterns on the names (`cln-*`), and the filters on
+//! the language, the repository and the outdated plugins.
+use std::collections::BTreeSet;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::names;
+use coffee_lib::plugin::{Plugin, PluginLang};
+
+use super::CoffeeManager;
+
+/// Filters of the installed plugins, shared by the commands.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct Selector {
+    /// Only the plugins written in the language, e.g. python.
+    #[arg(long)]
+    pub lang: Option<String>,
+    /// Only the plugins that come from the repository.
+    #[arg(long)]
+    pub repo: Option<String>,
+    /// Only the plugins changed by the upstream of their repository.
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub outdated: bool,
+}
+
+impl Selector {
+    /// true when the selector has no filters.
+    pub fn is_empty(&self) -> bool {
+        self.lang.is_none() && self.repo.is_none() && !self.outdated
+    }
+
+    /// true when the installed `plugin` passes all the filters.
+    fn matches(&self, coffee: &CoffeeManager, plugin: &Plugin) -> bool {
+        if let Some(lang) = &self.lang {
+            if lang_name(&plugin.lang()) != lang.to_lowercase() {
+                return false;
+            }
+        }
+        if let Some(repo) = &self.repo {
+            let from = coffee.repo_of(plugin).map(|from| from.name());
+            if from.as_deref() != Some(repo.as_str()) {
+                return false;
+            }
+        }
+        !self.outdated || coffee.is_outdated(plugin)
+    }
+}
+
+/// name of the language `lang` used by the selectors.
+fn lang_name(lang: &PluginLang) -> String {
+    match lang {
+        PluginLang::Other(name) => name.to_lowercase(),
+        lang => format!("{lang:?}").to_lowercase(),
+    }
+}
+
+/// true when the argument is a glob pattern and not a plugin name.
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?'])
+}
+
+/// true when the `name` matches the glob `pattern`, where `*` is any
+/// sequence of characters and `?` a single one. The names are
+/// compared like the plugin names, see `names::matches`.
+fn glob(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = names::normalize(pattern).chars().collect();
+    let name: Vec<char> = names::normalize(name).chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and of the name when it was found.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // the `*` takes one more character.
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// The installed plugins that pass the filters of the `selector`.
+pub fn installed(coffee: &CoffeeManager, selector: &Selector) -> Vec<Plugin> {
+    coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| selector.matches(coffee, plugin))
+        .cloned()
+        .collect()
+}
+
+/// Names of the plugins selected by the `args` (names and glob
+/// patterns) and by the `selector`, all the installed plugins that
+/// pass the selector without `args`.
+///
+/// The names that are not installed are kept, so the command reports
+/// them, but the patterns and the selector must select something.
+/// Nothing is selected without `args` and filters.
+pub fn select(
+    coffee: &CoffeeManager,
+    args: &[String],
+    selector: &Selector,
+) -> Result<Vec<String>, CoffeeError> {
+    if args.is_empty() && selector.is_empty() {
+        return Ok(vec![]);
+    }
+    let candidates = installed(coffee, selector);
+    let mut selected: Vec<String> = vec![];
+    if args.is_empty() {
+        selected = candidates.iter().map(|plugin| plugin.name()).collect();
+    }
+    for arg in args {
+        if is_pattern(arg) {
+            let matched: Vec<String> = candidates
+                .iter()
+                .map(|plugin| plugin.name())
+                .filter(|name| glob(arg, name))
+                .collect();
+            if matched.is_empty() {
+                let err = CoffeeError::new(1, &format!("no installed plugin matches `{arg}`"));
+                return Err(err);
+            }
+            selected.extend(matched);
+            continue;
+        }
+        let name = coffee.resolve_alias(arg);
+        let installed = coffee
+            .config
+            .plugins
+            .iter()
+            .any(|plugin| plugin.name() == name);
+        if !installed || candidates.iter().any(|plugin| plugin.name() == name) {
+            selected.push(name);
+        }
+    }
+    let mut seen = BTreeSet::new();
+    selected.retain(|name| seen.insert(name.clone()));
+    if selected.is_empty() {
+        let err = CoffeeError::new(1, "no installed plugin matches the selector");
+        return Err(err);
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use coffee_lib::plugin::PluginLang;
+
+    use super::{glob, is_pattern, lang_name};
+
+    #[test]
+    fn test_selector_glob() {
+        assert!(is_pattern("cln-*"));
+        assert!(!is_pattern("summary"));
+        assert!(glob("cln-*", "cln-rest"));
+        assert!(glob("CLN_*", "cln-rest"));
+        assert!(glob("*rest*", "cln-rest-python"));
+        assert!(glob("summar?", "summars"));
+        assert!(glob("*", "summary"));
+        assert!(!glob("cln-*", "clnrest"));
+        assert!(!glob("summar?", "summary-v2"));
+        assert_eq!(lang_name(&PluginLang::Python), "python");
+        assert_eq!(lang_name(&PluginLang::Other("Zig".to_owned())), "zig");
+    }
+}
@@ -0,0 +1,146 @@
+//! Self update of coffee, it reads the release feed of the
+//! project to know if a new version of coffee is available,
+//! no information about the user is sent.
+use std::env;
+use std::os::unix::fs::PermissionsExt;
+
+use coffee_lib::errors::CoffeeError;
+use log::debug;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use super::config::Settings;
+use super::github_api::GithubApi;
+use super::http_cache::{self, HttpCache};
+
+/// Release feed of the project, inside the GitHub API.
+const RELEASE_FEED: &str = "/repos/coffee-tools/coffee/releases/latest";
+
+/// Name of the release asset of the binary for the running platform,
+/// a static (musl) build is updated with the static binary.
+fn binary_asset() -> String {
+    let mut name = format!("coffee-{}-{}", env::consts::ARCH, env::consts::OS);
+    if cfg!(target_env = "musl") {
+        name += "-musl";
+    }
+    name
+}
+
+/// Name of the release asset that contains the checksums of the binaries,
+/// it comes from the same release of the binary: it catches a corrupted
+/// download, not a release replaced by someone else.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset, CoffeeError> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| {
+                CoffeeError::new(
+                    1,
+                    &format!("release {} do not contain `{name}`", self.tag_name),
+                )
+            })
+    }
+}
+
+fn http_err(err: reqwest::Error) -> CoffeeError {
+    CoffeeError::new(1, &format!("release feed not reachable: {err}"))
+}
+
+/// Check if there is a new release of coffee, and when `check`
+/// is false download it and replace the current binary after the
+/// checksum is verified.
+pub async fn self_update(
+    settings: &Settings,
+    root_path: &str,
+    check: bool,
+) -> Result<(), CoffeeError> {
+    if settings.offline {
+        return Err(CoffeeError::new(
+            1,
+            "coffee is running in offline mode, self update is not available",
+        ));
+    }
+    let current = env!("CARGO_PKG_VERSION");
+    let client = reqwest::Client::builder()
+        .user_agent(format!("coffee/{current}"))
+        .build()
+        .map_err(http_err)?;
+    let release: Release = GithubApi::new(settings, root_path)?
+        .get(RELEASE_FEED)
+        .await?;
+    debug!("latest release: {:?}", release);
+
+    let parse_version = |version: &str| {
+        Version::parse(version.trim_start_matches('v'))
+            .map_err(|err| CoffeeError::new(1, &format!("version `{version}` is not valid: {err}")))
+    };
+    let latest = parse_version(&release.tag_name)?;
+    if latest <= parse_version(current)? {
+        println!("coffee v{current} is up to date");
+        return Ok(());
+    }
+    println!(
+        "coffee v{latest} is available (current v{current}): {}",
+        release.html_url
+    );
+    if check {
+        return Ok(());
+    }
+
+    let binary_name = binary_asset();
+    let binary_url = &release.asset(&binary_name)?.browser_download_url;
+    debug!("downloading {binary_url}");
+    // the binary is used once, it does not go inside the cache.
+    let binary = http_cache::download(&client, binary_url).await?;
+    let checksums = HttpCache::new(settings, root_path)
+        .get(
+            &client,
+            &release.asset(CHECKSUMS_ASSET)?.browser_download_url,
+        )
+        .await?;
+    let checksums = String::from_utf8_lossy(&checksums);
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == binary_name)
+        .map(|(hash, _)| hash.to_lowercase())
+        .ok_or_else(|| CoffeeError::new(1, &format!("checksum of `{binary_name}` not found")))?;
+    let digest = Sha256::digest(&binary)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    if digest != expected {
+        return Err(CoffeeError::new(
+            1,
+            &format!("checksum mismatch for `{binary_name}`, expected {expected} found {digest}"),
+        ));
+    }
+
+    // write the new binary near the current one and swap them, so
+    // an error in the middle never leaves a broken coffee.
+    let current_exe = env::current_exe()?;
+    let new_exe = current_exe.with_extension("new");
+    fs::write(&new_exe, binary).await?;
+    fs::set_permissions(&new_exe, std::fs::Permissions::from_mode(0o755)).await?;
+    fs::rename(&new_exe, &current_exe).await?;
+    println!("coffee updated to v{latest}");
+    Ok(())
+}
@@ -0,0 +1,423 @@
+//! Local HTTP API of coffee, so a dashboard or an app that manages
+//! the node can drive coffee without the command line.
+//!
+//! Every request needs the token of the API in the header
+//! `Authorization: Bearer <token>`, and the answers are JSON
+//! objects with a `result` or an `error`.
+use std::path::Path;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin_manager::{PluginInspector, PluginManager};
+use coffee_lib::secrets;
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::fs::{read_to_string, remove_file, rename, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+
+use super::config::ConfirmPolicy;
+use super::CoffeeManager;
+
+/// Address where the API listens when none is given.
+pub const DEFAULT_BIND: &str = "127.0.0.1:8075";
+
+/// Biggest body accepted by the API.
+const MAX_BODY: usize = 64 * 1024;
+
+/// How long a client can take to send its request or to read
+/// the answer.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests waiting for the coffee manager, the operations run one
+/// after the other.
+const QUEUE_SIZE: usize = 16;
+
+/// Body of the requests that change the plugins.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PluginsBody {
+    plugins: Vec<String>,
+    /// run the install scripts not trusted yet, they are refused
+    /// otherwise.
+    allow_scripts: bool,
+}
+
+/// Request received by the API.
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+/// decode a component of the query string.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'+' => decoded.push(b' '),
+            b'%' if pos + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[pos + 1..pos + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        pos += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        pos += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// parse the request line and the headers, the body is read later.
+fn parse_head(head: &str) -> Result<(Request, usize), String> {
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(format!("malformed request line `{request_line}`"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect();
+    let mut token = None;
+    let mut length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_lowercase().as_str() {
+            "authorization" => token = value.strip_prefix("Bearer ").map(str::to_owned),
+            "content-length" => {
+                length = value
+                    .parse()
+                    .map_err(|_| format!("malformed content length `{value}`"))?
+            }
+            _ => {}
+        }
+    }
+    let request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        query,
+        token,
+        body: vec![],
+    };
+    Ok((request, length))
+}
+
+/// read a request from the `stream`.
+async fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|err| err.to_string())?;
+        if read == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push_str(&line);
+        if head.len() > MAX_BODY {
+            return Err("headers too big".to_owned());
+        }
+    }
+    let (mut request, length) = parse_head(&head)?;
+    if length > MAX_BODY {
+        return Err("body too big".to_owned());
+    }
+    request.body = vec![0; length];
+    reader
+        .read_exact(&mut request.body)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(request)
+}
+
+/// write the answer with the `status` code and the JSON `body`.
+async fn write_response(stream: &mut TcpStream, status: u16, body: &Value) {
+    if timeout(IO_TIMEOUT, send_response(stream, status, body))
+        .await
+        .is_err()
+    {
+        warn!("answer not sent: the client did not read it in time");
+    }
+}
+
+async fn send_response(stream: &mut TcpStream, status: u16, body: &Value) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    };
+    let body = secrets::redact_json(body).to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(err) = stream.write_all(response.as_bytes()).await {
+        warn!("answer not sent: {err}");
+    }
+}
+
+/// Token of the API, from the settings or from the token file inside
+/// the coffee root, that is generated the first time.
+async fn api_token(coffee: &CoffeeManager) -> Result<String, CoffeeError> {
+    if let Some(token) = &coffee.config.settings.api_token {
+        return Ok(token.clone());
+    }
+    let path = format!("{}/api.token", coffee.config.root_path);
+    if Path::new(&path).exists() {
+        return Ok(read_to_string(&path).await?.trim().to_owned());
+    }
+    let mut random = [0u8; 32];
+    File::open("/dev/urandom")
+        .await?
+        .read_exact(&mut random)
+        .await?;
+    let token: String = random.iter().map(|byte| format!("{byte:02x}")).collect();
+    // the file is readable only by the user from its creation, and
+    // moved over `path` when it is complete.
+    let tmp_path = format!("{path}.tmp");
+    if Path::new(&tmp_path).exists() {
+        remove_file(&tmp_path).await?;
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp_path)
+        .await?;
+    file.write_all(token.as_bytes()).await?;
+    file.sync_all().await?;
+    rename(&tmp_path, &path).await?;
+    println!("generated the token of the API inside {path}");
+    Ok(token)
+}
+
+/// true when the `token` of the request is the token of the API,
+/// compared in constant time so the time of the answer does not
+/// tell how much of the token is right.
+fn same_token(token: Option<&str>, expected: &str) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// plugins of the repositories that contain `query` in the name.
+async fn search(coffee: &CoffeeManager, query: &str) -> Result<Value, CoffeeError> {
+    let query = query.to_lowercase();
+    let mut found = vec![];
+    for repo in &coffee.repos {
+        for plugin in repo.list().await? {
+            if plugin.name().to_lowercase().contains(&query) {
+                found.push(json!({
+                    "name": plugin.name(),
+                    "repository": repo.name(),
+                    "lang": plugin.lang(),
+                    "tags": plugin.tags(),
+                }));
+            }
+        }
+    }
+    Ok(json!(found))
+}
+
+/// overview of coffee and of the node.
+fn status(coffee: &CoffeeManager) -> Value {
+    let outdated = coffee
+        .config
+        .plugins
+        .iter()
+        .filter(|plugin| coffee.is_outdated(plugin))
+        .count();
+    json!({
+        "network": coffee.config.network,
+        "node_running": coffee.is_node_running(),
+        "repositories": coffee.repos.len(),
+        "plugins": coffee.config.plugins.len(),
+        "outdated": outdated,
+    })
+}
+
+/// run the operation asked by the `request`, the operations that
+/// change the state hold its lock while they run.
+async fn handle(coffee: &mut CoffeeManager, request: &Request) -> (u16, Value) {
+    if request.method != "POST" {
+        return run(coffee, request).await;
+    }
+    if let Err(err) = coffee.lock_state().await {
+        coffee.release_state_lock();
+        return (409, json!({"error": err.to_string()}));
+    }
+    let answer = run(coffee, request).await;
+    coffee.release_state_lock();
+    answer
+}
+
+/// run the operation asked by the `request`.
+async fn run(coffee: &mut CoffeeManager, request: &Request) -> (u16, Value) {
+    let mut plugins = || {
+        let body = serde_json::from_slice::<PluginsBody>(&request.body)
+            .map_err(|err| format!("malformed body: {err}"))?;
+        // nobody can answer on the terminal of the server.
+        coffee.config.settings.scripts = Some(if body.allow_scripts {
+            ConfirmPolicy::Yes
+        } else {
+            ConfirmPolicy::No
+        });
+        Ok::<_, String>(body.plugins)
+    };
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/list") => coffee.list(false).await,
+        ("GET", "/status") => Ok(status(coffee)),
+        ("GET", "/search") => {
+            let query = request
+                .query
+                .iter()
+                .find(|(key, _)| key == "query")
+                .map(|(_, value)| value.as_str())
+                .unwrap_or_default();
+            search(coffee, query).await
+        }
+        ("POST", "/install") => match plugins() {
+            Ok(plugins) if !plugins.is_empty() => {
+                let plugins: Vec<&str> = plugins.iter().map(String::as_str).collect();
+                coffee
+                    .install(&plugins, false, false)
+                    .await
+                    .map(|_| json!({}))
+            }
+            Ok(_) => return (400, json!({"error": "no plugins to install"})),
+            Err(err) => return (400, json!({ "error": err })),
+        },
+        ("POST", "/upgrade") => match plugins() {
+            Ok(plugins) => {
+                let plugins: Vec<&str> = plugins.iter().map(String::as_str).collect();
+                coffee.upgrade(&plugins).await.map(|_| json!({}))
+            }
+            Err(err) => return (400, json!({ "error": err })),
+        },
+        _ => return (404, json!({"error": "unknown operation"})),
+    };
+    match result {
+        Ok(result) => (200, json!({ "result": result })),
+        Err(err) => (500, json!({"error": err.to_string()})),
+    }
+}
+
+/// Request of an authenticated client, with the channel of its answer.
+type Job = (Request, oneshot::Sender<(u16, Value)>);
+
+/// Read the request of the client on the `stream`, check its token
+/// and send it to the coffee manager through the `jobs` queue.
+async fn connection(mut stream: TcpStream, token: String, jobs: mpsc::Sender<Job>) {
+    let request = match timeout(IO_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(err)) => {
+            write_response(&mut stream, 400, &json!({ "error": err })).await;
+            return;
+        }
+        Err(_) => {
+            write_response(&mut stream, 408, &json!({"error": "request too slow"})).await;
+            return;
+        }
+    };
+    if !same_token(request.token.as_deref(), &token) {
+        write_response(&mut stream, 401, &json!({"error": "wrong token"})).await;
+        return;
+    }
+    let (answer, answered) = oneshot::channel();
+    if jobs.send((request, answer)).await.is_err() {
+        return;
+    }
+    if let Ok((status, body)) = answered.await {
+        write_response(&mut stream, status, &body).await;
+    }
+}
+
+/// Serve the API on the `bind` address, every client is read in a
+/// task of its own, and the operations run one after the other.
+pub async fn serve(coffee: &mut CoffeeManager, bind: &str) -> Result<(), CoffeeError> {
+    let token = api_token(coffee).await?;
+    // the handlers never read the terminal, the questions that
+    // would be asked to the user are answered no.
+    if coffee.config.settings.confirm == ConfirmPolicy::Ask {
+        coffee.config.settings.confirm = ConfirmPolicy::No;
+    }
+    let listener = TcpListener::bind(bind)
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("{bind} not available: {err}")))?;
+    println!("coffee API listening on http://{bind}");
+    let (jobs, mut queue) = mpsc::channel::<Job>(QUEUE_SIZE);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted?;
+                debug!("connection from {peer}");
+                tokio::spawn(connection(stream, token.clone(), jobs.clone()));
+            }
+            Some((request, answer)) = queue.recv() => {
+                debug!("running {} {}", request.method, request.path);
+                let _ = answer.send(handle(coffee, &request).await);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, parse_head, same_token};
+
+    #[test]
+    fn test_serve_parse_request() {
+        assert_eq!(decode("clear+net%2Dv2"), "clear net-v2");
+        assert_eq!(decode("100%"), "100%");
+        let (request, length) = parse_head(
+            "GET /search?query=sum%20mary HTTP/1.1\r\nAuthorization: Bearer abc\r\nContent-Length: 12\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.query, [("query".to_owned(), "sum mary".to_owned())]);
+        assert_eq!(request.token.as_deref(), Some("abc"));
+        assert_eq!(length, 12);
+        assert!(parse_head("").is_err());
+        assert!(same_token(Some("abc"), "abc"));
+        assert!(!same_token(Some("abd"), "abc"));
+        assert!(!same_token(Some("ab"), "abc"));
+        assert!(!same_token(None, "abc"));
+    }
+}
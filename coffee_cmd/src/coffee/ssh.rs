@@ -0,0 +1,213 @@
+//! Management of a node that runs on another host, coffee builds
+//! the plugins locally and copies them on the host over ssh, with
+//! the configuration include of coffee rewritten for the host.
+//!
+//! The wrappers, the virtualenvs and the worktrees contain the local
+//! paths of coffee, they are relocated on the host after the copy:
+//! the text files and the symbolic links that point inside the local
+//! root are rewritten to point inside the root of the host.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::shell_quote as quote;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::fs::read_to_string;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::CoffeeManager;
+
+/// Directories of the coffee root that are copied on the host.
+const SYNCED_DIRS: [&str; 2] = ["repositories", "wrappers"];
+
+/// Host where the node managed by coffee runs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SshHost {
+    /// ssh destination, e.g. `bitcoin@node.lan`.
+    pub host: String,
+    /// root path of coffee on the host.
+    pub root_path: String,
+    /// core lightning directory on the host, the configuration of
+    /// the node includes the one of coffee when it is given.
+    pub cln_dir: Option<String>,
+    /// additional options of ssh, e.g. `-p 2222`.
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+impl SshHost {
+    /// command line of ssh used also by rsync, the options are
+    /// quoted because rsync splits the command on the spaces.
+    fn ssh_command(&self) -> String {
+        let mut ssh = vec!["ssh".to_owned()];
+        ssh.extend(self.options.iter().map(|option| quote(option)));
+        ssh.join(" ")
+    }
+
+    /// run the shell `script` on the host, with `stdin` as input.
+    async fn run(&self, script: &str, stdin: Option<&str>) -> Result<(), CoffeeError> {
+        debug!("running on {}: {script}", self.host);
+        let mut child = Command::new("ssh")
+            .args(&self.options)
+            .arg(&self.host)
+            .arg(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| CoffeeError::new(1, &format!("ssh not available: {err}")))?;
+        if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+            pipe.write_all(input.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(CoffeeError::new(
+                1,
+                &format!(
+                    "ssh {}: {}",
+                    self.host,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// copy the directory `local` inside the `remote` directory of
+    /// the host, the files that are not present locally are removed.
+    async fn rsync(&self, local: &str, remote: &str) -> Result<(), CoffeeError> {
+        debug!("copying {local} on {}:{remote}", self.host);
+        let output = Command::new("rsync")
+            .args(["-az", "--delete", "-e", &self.ssh_command()])
+            .arg(format!("{local}/"))
+            .arg(format!("{}:{remote}/", self.host))
+            .output()
+            .await
+            .map_err(|err| CoffeeError::new(1, &format!("rsync not available: {err}")))?;
+        if !output.status.success() {
+            return Err(CoffeeError::new(
+                1,
+                &format!(
+                    "rsync on {}: {}",
+                    self.host,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// the `value` with a `\\` before the `special` characters of sed.
+fn sed_escape(value: &str, special: &[char]) -> String {
+    value.chars().fold(String::new(), |mut escaped, c| {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// shell script that rewrites the local root `from` of coffee with the
+/// root `to` of the host, inside the text files and the symbolic links
+/// of the `dirs` of the host.
+fn relocate_script(from: &str, to: &str, dirs: &[String]) -> String {
+    // `|` is the separator of the substitution.
+    let pattern = sed_escape(from, &['\\', '|', '.', '*', '[', ']', '^', '$']);
+    let replacement = sed_escape(to, &['\\', '|', '&']);
+    let sed = quote(&format!("s|{pattern}|{replacement}|g"));
+    let dirs: Vec<String> = dirs.iter().map(|dir| quote(dir)).collect();
+    let dirs = dirs.join(" ");
+    let (from, to) = (quote(from), quote(to));
+    format!(
+        "grep -rlIZF -e {from} -- {dirs} | xargs -0r sed -i {sed}; \
+         find {dirs} -type l | while IFS= read -r link; do \
+         target=$(readlink \"$link\"); \
+         case \"$target\" in {from}*) ln -sfn {to}\"${{target#{from}}}\" \"$link\";; esac; \
+         done"
+    )
+}
+
+/// Copy the repositories, the wrappers and the configuration include
+/// of coffee on the host, nothing happens when coffee manages a local node.
+pub async fn sync(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let Some(target) = coffee.config.settings.ssh.clone() else {
+        return Ok(());
+    };
+    let root = &coffee.config.root_path;
+    let network = &coffee.config.network;
+    let mut dirs = vec![format!("{}/{network}", target.root_path)];
+    dirs.extend(
+        SYNCED_DIRS
+            .iter()
+            .map(|dir| format!("{}/{dir}", target.root_path)),
+    );
+    let dirs: Vec<String> = dirs.iter().map(|dir| quote(dir)).collect();
+    target
+        .run(&format!("mkdir -p {}", dirs.join(" ")), None)
+        .await?;
+    let mut synced = vec![];
+    for dir in SYNCED_DIRS {
+        let local = format!("{root}/{dir}");
+        if Path::new(&local).exists() {
+            let remote = format!("{}/{dir}", target.root_path);
+            target.rsync(&local, &remote).await?;
+            synced.push(remote);
+        }
+    }
+    if !synced.is_empty() && root != &target.root_path {
+        target
+            .run(&relocate_script(root, &target.root_path, &synced), None)
+            .await?;
+    }
+
+    // the paths of the include point to the coffee root of the host.
+    let include = read_to_string(&coffee.config.config_path)
+        .await
+        .unwrap_or_default()
+        .replace(root.as_str(), &target.root_path);
+    let include_path = format!("{}/{network}/coffee.conf", target.root_path);
+    target
+        .run(&format!("cat > {}", quote(&include_path)), Some(&include))
+        .await?;
+    if let Some(cln_dir) = &target.cln_dir {
+        let cln_conf = quote(&format!("{cln_dir}/{network}/config"));
+        let line = quote(&format!("include {include_path}"));
+        target
+            .run(
+                &format!("grep -qxF {line} {cln_conf} 2>/dev/null || echo {line} >> {cln_conf}"),
+                None,
+            )
+            .await?;
+    }
+    println!("[{}] plugins synced", target.host);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relocate_script, SshHost};
+
+    #[test]
+    fn test_ssh_quote() {
+        let host = SshHost {
+            host: "bitcoin@node.lan".to_owned(),
+            root_path: "/home/bitcoin/.coffee".to_owned(),
+            cln_dir: None,
+            options: vec!["-o".to_owned(), "ProxyCommand=nc -x proxy %h %p".to_owned()],
+        };
+        assert_eq!(
+            host.ssh_command(),
+            "ssh '-o' 'ProxyCommand=nc -x proxy %h %p'"
+        );
+        let script = relocate_script(
+            "/root/.coffee",
+            "/srv/c&c",
+            &["/srv/c&c/wrappers".to_owned()],
+        );
+        let relocate = r"grep -rlIZF -e '/root/.coffee' -- '/srv/c&c/wrappers' | xargs -0r sed -i 's|/root/\.coffee|/srv/c\&c|g';";
+        assert!(script.starts_with(relocate));
+    }
+}
@@ -0,0 +1,65 @@
+//! Lock of the state of coffee, the commands that change the state
+//! hold it until they end, so two of them do not overwrite the
+//! storage and the configuration of each other.
+//!
+//! The lock is a `flock` on a file of the coffee root, it is
+//! released when the file is closed: when the command ends, also
+//! when it is interrupted or killed.
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+
+use coffee_lib::errors::CoffeeError;
+use log::debug;
+
+/// Lock file inside the coffee root.
+const LOCK_FILE: &str = "coffee.lock";
+
+/// Lock of the state inside a coffee root, released when dropped.
+pub struct StateLock {
+    _file: File,
+}
+
+impl StateLock {
+    /// Take the lock of the state inside `root_path`, it fails when
+    /// another command holds it.
+    pub fn acquire(root_path: &str) -> Result<Self, CoffeeError> {
+        std::fs::create_dir_all(root_path)?;
+        let path = format!("{root_path}/{LOCK_FILE}");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        // SAFETY: the descriptor is open for the whole call.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = CoffeeError::new(
+                1,
+                &format!("another coffee command is changing the state inside `{root_path}`, retry when it ends"),
+            );
+            return Err(err);
+        }
+        debug!("state lock {path} taken");
+        Ok(StateLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::StateLock;
+
+    #[test]
+    fn test_state_lock() {
+        let root = format!(
+            "{}/coffee-state-lock-{}",
+            env::temp_dir().display(),
+            std::process::id()
+        );
+        let lock = StateLock::acquire(&root).unwrap();
+        assert!(StateLock::acquire(&root).is_err());
+        drop(lock);
+        assert!(StateLock::acquire(&root).is_ok());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
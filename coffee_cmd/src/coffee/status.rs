@@ -0,0 +1,97 @@
+//! Overview of the coffee state, designed to fit in one
+//! screen (e.g. inside a MOTD script), it never reaches the network
+//! and it reports the problems of the last `coffee nurse`.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::unix_time;
+use serde_json::{json, Value};
+
+use super::{nurse, CoffeeManager};
+
+/// Format how much time ago the unix time `time` was.
+pub fn format_age(time: u64) -> String {
+    let age = unix_time().saturating_sub(time);
+    match age {
+        0..=59 => format!("{age}s ago"),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+/// Print the overview of coffee and of the node.
+pub async fn status(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let node = if coffee.is_node_running() {
+        match coffee.cln::<Value, Value>("getinfo", json!({})).await {
+            Ok(info) => format!("reachable ({})", info["id"].as_str().unwrap_or_default()),
+            Err(_) => "not responding".to_owned(),
+        }
+    } else if coffee.config.cln_root.is_none() {
+        "not configured, run `coffee setup`".to_owned()
+    } else {
+        "not running".to_owned()
+    };
+    println!("node:         {node}");
+    println!("network:      {}", coffee.config.network);
+
+    let last_update = coffee
+        .repos
+        .iter()
+        .filter_map(|repo| repo.last_update())
+        .max();
+    let last_update = last_update
+        .map(|time| format!(", last update {}", format_age(time)))
+        .unwrap_or_default();
+    println!("repositories: {}{last_update}", coffee.repos.len());
+
+    let registered = coffee
+        .coffe_cln_config
+        .fields
+        .get("plugin")
+        .cloned()
+        .unwrap_or_default();
+    let mut enabled = 0;
+    let mut outdated = 0;
+    let mut disabled = 0;
+    for plugin in &coffee.config.plugins {
+        // as seen by the last fetch of the repository.
+        if coffee.is_outdated(plugin) {
+            outdated += 1;
+        }
+        if coffee.config.disabled.contains_key(&plugin.name()) {
+            disabled += 1;
+        } else if plugin
+            .exec_path()
+            .is_some_and(|exec_path| registered.contains(&exec_path))
+        {
+            enabled += 1;
+        }
+    }
+    println!(
+        "plugins:      {} installed, {enabled} enabled, {disabled} disabled, {outdated} outdated",
+        coffee.config.plugins.len()
+    );
+
+    // the problems found now, and the ones of the last nurse run
+    // that need the network to be seen again.
+    let mut problems = nurse::problems(coffee);
+    match nurse::last_report(coffee).await {
+        Some(report) => {
+            println!("nurse:        last run {}", format_age(report.time));
+            for problem in report.problems {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+        None => println!("nurse:        never run, run `coffee nurse` to fetch the upstreams"),
+    }
+    if problems.is_empty() {
+        println!("problems:     none");
+    } else {
+        println!("problems:");
+        problems
+            .iter()
+            .for_each(|problem| println!("  - {problem}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Systemd units for the plugins that are companion daemons of
+//! the node, and not plugins started by core lightning.
+use std::env;
+use std::path::Path;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use log::{debug, warn};
+use tokio::fs::{create_dir_all, remove_file, write};
+use tokio::process::Command;
+
+use super::config::SystemdUnit;
+use super::CoffeeManager;
+
+/// name of the unit of the `plugin`.
+fn unit_name(plugin: &str) -> String {
+    format!("coffee-{plugin}.service")
+}
+
+/// directory of the units, of the system or of the `user`.
+fn units_dir(user: bool) -> Result<String, CoffeeError> {
+    if !user {
+        return Ok("/etc/systemd/system".to_owned());
+    }
+    if let Ok(config) = env::var("XDG_CONFIG_HOME") {
+        return Ok(format!("{config}/systemd/user"));
+    }
+    let home = env::var("HOME")
+        .map_err(|_| CoffeeError::new(1, "HOME not set, the user units can not be found"))?;
+    Ok(format!("{home}/.config/systemd/user"))
+}
+
+/// run `systemctl` with the `args`.
+async fn systemctl(user: bool, args: &[&str]) -> Result<(), CoffeeError> {
+    let mut cmd = Command::new("systemctl");
+    if user {
+        cmd.arg("--user");
+    }
+    debug!("running systemctl {}", args.join(" "));
+    let output = cmd
+        .args(args)
+        .output()
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("systemctl not available: {err}")))?;
+    if !output.status.success() {
+        return Err(CoffeeError::new(
+            1,
+            &format!(
+                "systemctl {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// the installed `plugin`.
+fn installed<'a>(coffee: &'a CoffeeManager, plugin: &str) -> Result<&'a Plugin, CoffeeError> {
+    coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .ok_or_else(|| coffee.not_installed(plugin))
+}
+
+/// Generate the unit that runs the `installed` plugin as a daemon.
+fn unit(coffee: &CoffeeManager, installed: &Plugin, user: bool) -> Result<String, CoffeeError> {
+    let plugin = installed.name();
+    let exec_path = installed.exec_path().ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` has no executable, install it again"),
+        )
+    })?;
+    let mut unit = format!(
+        "# generated by coffee for the plugin `{plugin}`, do not edit.\n\
+         [Unit]\n\
+         Description=coffee plugin {plugin}\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={exec_path}\n\
+         WorkingDirectory={}\n\
+         Environment=LIGHTNING_NETWORK={}\n",
+        installed.root_path(),
+        coffee.config.network,
+    );
+    if let Some(rpc_path) = coffee.rpc_path() {
+        unit += &format!("Environment=LIGHTNING_RPC={rpc_path}\n");
+    }
+    if let Some(cln_root) = &coffee.config.cln_root {
+        unit += &format!("Environment=LIGHTNING_DIR={cln_root}\n");
+    }
+    let wanted_by = if user {
+        "default.target"
+    } else {
+        "multi-user.target"
+    };
+    unit += &format!("Restart=on-failure\n\n[Install]\nWantedBy={wanted_by}\n");
+    Ok(unit)
+}
+
+/// Print the unit of the `plugin`, or install and enable it when
+/// `install`, in the systemd instance of the user when `user`.
+pub async fn systemd(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    install: bool,
+    user: bool,
+) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let unit = unit(coffee, installed(coffee, &plugin)?, user)?;
+    if !install {
+        print!("{unit}");
+        return Ok(());
+    }
+    let exec_path = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .and_then(|installed| installed.exec_path())
+        .unwrap_or_default();
+    let dir = units_dir(user)?;
+    create_dir_all(&dir).await?;
+    let name = unit_name(&plugin);
+    let path = format!("{dir}/{name}");
+    write(&path, unit).await?;
+    let enabled = match systemctl(user, &["daemon-reload"]).await {
+        Ok(()) => systemctl(user, &["enable", "--now", &name]).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = enabled {
+        remove_file(&path).await?;
+        return Err(err);
+    }
+    coffee.config.systemd_units.insert(
+        plugin.clone(),
+        SystemdUnit {
+            path: path.clone(),
+            user,
+        },
+    );
+    // the daemon is started by systemd, and not by core lightning.
+    if let Some(paths) = coffee.coffe_cln_config.fields.get_mut("plugin") {
+        paths.retain(|path| *path != exec_path);
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    println!("[{plugin}] unit {path} enabled");
+    Ok(())
+}
+
+/// Disable and remove the unit of the `plugin`.
+pub async fn uninstall(coffee: &mut CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let Some(unit) = coffee.config.systemd_units.remove(&plugin) else {
+        return Err(CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` has no systemd unit"),
+        ));
+    };
+    let name = unit_name(&plugin);
+    if let Err(err) = systemctl(unit.user, &["disable", "--now", &name]).await {
+        warn!("unit {name} not disabled: {err}");
+    }
+    if Path::new(&unit.path).exists() {
+        remove_file(&unit.path).await?;
+    }
+    systemctl(unit.user, &["daemon-reload"]).await?;
+    // core lightning starts the plugin again, if it is still installed.
+    let exec_path = coffee
+        .config
+        .plugins
+        .iter()
+        .find(|installed| installed.name() == plugin)
+        .and_then(|installed| installed.exec_path());
+    if let Some(exec_path) = exec_path {
+        coffee
+            .coffe_cln_config
+            .add_conf("plugin", &exec_path)
+            .map_err(|err| CoffeeError::new(1, &err.cause))?;
+        coffee.update_conf().await?;
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    println!("[{plugin}] unit {} removed", unit.path);
+    Ok(())
+}
+
+/// Write again the unit of the rebuilt `plugin`, if any, because its
+/// executable changes with the worktree, and restart it so the daemon
+/// runs the new build. A disabled unit is not started.
+pub async fn update(coffee: &CoffeeManager, plugin: &Plugin) {
+    let name = plugin.name();
+    let Some(unit) = coffee.config.systemd_units.get(&name) else {
+        return;
+    };
+    let disabled = coffee.config.disabled.contains_key(&name);
+    let result = async {
+        write(&unit.path, self::unit(coffee, plugin, unit.user)?).await?;
+        systemctl(unit.user, &["daemon-reload"]).await?;
+        if !disabled {
+            systemctl(unit.user, &["restart", &unit_name(&name)]).await?;
+        }
+        Ok::<(), CoffeeError>(())
+    }
+    .await;
+    if let Err(err) = result {
+        println!("[{name}] unit not updated: {err}");
+    }
+}
+
+/// Stop the unit of the `plugin` and do not start it at boot.
+pub async fn stop(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    match coffee.config.systemd_units.get(plugin) {
+        Some(unit) => systemctl(unit.user, &["disable", "--now", &unit_name(plugin)]).await,
+        None => Ok(()),
+    }
+}
+
+/// Start the unit of the `plugin` and start it at boot.
+pub async fn start(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    match coffee.config.systemd_units.get(plugin) {
+        Some(unit) => systemctl(unit.user, &["enable", "--now", &unit_name(plugin)]).await,
+        None => Ok(()),
+    }
+}
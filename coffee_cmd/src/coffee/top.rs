@@ -0,0 +1,224 @@
+//! Resource usage of the plugins running on the node, the processes
+//! of a plugin are found inside `/proc` by the files of the plugin on
+//! their command line, so also the plugins started by a wrapper count.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use log::debug;
+use tokio::fs::{read_dir, read_to_string};
+use tokio::time::sleep;
+
+use super::CoffeeManager;
+
+/// Ticks per second of the cpu times inside `/proc`, fixed by the
+/// ABI of the kernel.
+const TICKS_PER_SECOND: f64 = 100.0;
+
+/// Interval between the two samples of the cpu times.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resource usage of a plugin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub plugin: String,
+    pub pids: Vec<u32>,
+    /// cpu used during the sample, 100 is one core.
+    pub cpu_percent: f64,
+    /// resident memory, in bytes.
+    pub memory: u64,
+}
+
+/// processes running, with their command line.
+async fn processes() -> Result<Vec<(u32, Vec<String>)>, CoffeeError> {
+    let mut processes = vec![];
+    let mut entries = read_dir("/proc").await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        // the process can exit in the meanwhile.
+        let Ok(cmdline) = read_to_string(entry.path().join("cmdline")).await else {
+            continue;
+        };
+        let args = cmdline
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .map(str::to_owned)
+            .collect();
+        processes.push((pid, args));
+    }
+    Ok(processes)
+}
+
+/// the plugin of the process with the `args`, the one with the
+/// deepest root that contains a file of the command line.
+fn owner<'a>(args: &[String], roots: &'a [(String, String)]) -> Option<&'a str> {
+    roots
+        .iter()
+        .filter(|(_, root)| {
+            args.iter().any(|arg| {
+                arg.strip_prefix(root.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+        })
+        .max_by_key(|(_, root)| root.len())
+        .map(|(plugin, _)| plugin.as_str())
+}
+
+/// cpu time used by the process, in ticks, from its `/proc/<pid>/stat`.
+fn cpu_ticks(stat: &str) -> Option<u64> {
+    // the name of the process can contain spaces and parentheses.
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    // utime and stime, fields 14 and 15 of the stat.
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// resident memory of the process, in bytes, from its `/proc/<pid>/status`.
+fn resident_memory(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+async fn sample_ticks(pids: &[u32]) -> BTreeMap<u32, u64> {
+    let mut ticks = BTreeMap::new();
+    for pid in pids {
+        if let Ok(stat) = read_to_string(format!("/proc/{pid}/stat")).await {
+            if let Some(used) = cpu_ticks(&stat) {
+                ticks.insert(*pid, used);
+            }
+        }
+    }
+    ticks
+}
+
+/// Sample the resource usage of the installed plugins that are
+/// running, the cpu is measured during the `interval`.
+pub async fn sample(coffee: &CoffeeManager, interval: Duration) -> Result<Vec<Usage>, CoffeeError> {
+    let roots: Vec<(String, String)> = coffee
+        .config
+        .plugins
+        .iter()
+        .map(|plugin| (plugin.name(), plugin.root_path()))
+        .collect();
+    let mut owned: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    for (pid, args) in processes().await? {
+        if pid == std::process::id() {
+            continue;
+        }
+        if let Some(plugin) = owner(&args, &roots) {
+            owned.entry(plugin.to_owned()).or_default().push(pid);
+        }
+    }
+    debug!("processes of the plugins: {:?}", owned);
+    let pids: Vec<u32> = owned.values().flatten().copied().collect();
+    let before = sample_ticks(&pids).await;
+    sleep(interval).await;
+    let after = sample_ticks(&pids).await;
+
+    let mut usage = vec![];
+    for (plugin, pids) in owned {
+        let ticks: u64 = pids
+            .iter()
+            .filter_map(|pid| Some(after.get(pid)?.saturating_sub(*before.get(pid)?)))
+            .sum();
+        let mut memory = 0;
+        for pid in &pids {
+            if let Ok(status) = read_to_string(format!("/proc/{pid}/status")).await {
+                memory += resident_memory(&status).unwrap_or_default();
+            }
+        }
+        usage.push(Usage {
+            plugin,
+            pids,
+            cpu_percent: ticks as f64 / TICKS_PER_SECOND / interval.as_secs_f64() * 100.0,
+            memory,
+        });
+    }
+    usage.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+    Ok(usage)
+}
+
+/// Format the `bytes` with a binary unit.
+fn format_memory(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes}B"),
+        1024..=1048575 => format!("{:.1}KiB", bytes as f64 / 1024.0),
+        1048576..=1073741823 => format!("{:.1}MiB", bytes as f64 / 1048576.0),
+        _ => format!("{:.1}GiB", bytes as f64 / 1073741824.0),
+    }
+}
+
+/// Print the resource usage of the plugins running on the node, the
+/// ones that use more cpu first.
+pub async fn top(coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+    let usage = sample(coffee, SAMPLE_INTERVAL).await?;
+    if usage.is_empty() {
+        println!("no plugin installed by coffee is running");
+        return Ok(());
+    }
+    let width = usage
+        .iter()
+        .map(|usage| usage.plugin.len())
+        .max()
+        .unwrap_or_default()
+        .max("plugin".len());
+    println!("{:width$}  {:>6}  {:>10}  pids", "plugin", "cpu", "memory");
+    for usage in usage {
+        let pids: Vec<String> = usage.pids.iter().map(|pid| pid.to_string()).collect();
+        println!(
+            "{:width$}  {:>5.1}%  {:>10}  {}",
+            usage.plugin,
+            usage.cpu_percent,
+            format_memory(usage.memory),
+            pids.join(",")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cpu_ticks, format_memory, owner, resident_memory};
+
+    #[test]
+    fn test_top_proc() {
+        let stat =
+            "4242 (python3 (x)) S 1 4242 4242 0 -1 4194560 1234 0 0 0 150 50 0 0 20 0 1 0 100 0 0";
+        assert_eq!(cpu_ticks(stat), Some(200));
+        assert_eq!(
+            resident_memory("Name:\tpython3\nVmRSS:\t   2048 kB\n"),
+            Some(2048 * 1024)
+        );
+        assert_eq!(format_memory(3 * 1048576), "3.0MiB");
+
+        let roots = vec![
+            (
+                "summary".to_owned(),
+                "/coffee/repositories/plugins".to_owned(),
+            ),
+            (
+                "clearnet".to_owned(),
+                "/coffee/repositories/plugins/clearnet".to_owned(),
+            ),
+        ];
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            owner(
+                &args(&[
+                    "python3",
+                    "/coffee/repositories/plugins/clearnet/clearnet.py"
+                ]),
+                &roots
+            ),
+            Some("clearnet")
+        );
+        assert_eq!(
+            owner(&args(&["/coffee/repositories/plugins-old/x"]), &roots),
+            None
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Transactions of the operations made of more steps (e.g. the
+//! install of more plugins), when a step fails the steps already
+//! completed are rolled back, so the plugins and the configuration
+//! include of the node are never left in a mixed state.
+use std::path::Path;
+
+use clightningrpc_conf::CLNConf;
+use coffee_github::repository::Github;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::repository::{Layout, Repository};
+use coffee_lib::url::URL;
+use log::{debug, warn};
+use tokio::fs::remove_dir_all;
+
+use super::config::CoffeeConf;
+use super::CoffeeManager;
+
+/// State of coffee before the transaction.
+pub struct Transaction {
+    config: CoffeeConf,
+    cln_config: CLNConf,
+    /// repositories with the url, the commit checked out and the layout.
+    repos: Vec<(String, String, Option<String>, Layout)>,
+}
+
+impl Transaction {
+    /// Take the snapshot of the state of coffee.
+    pub fn begin(coffee: &CoffeeManager) -> Self {
+        let repos = coffee
+            .repos
+            .iter()
+            .map(|repo| {
+                (
+                    repo.name(),
+                    repo.url().url_string,
+                    repo.commit().ok(),
+                    repo.layout(),
+                )
+            })
+            .collect();
+        Transaction {
+            config: coffee.config.clone(),
+            cln_config: coffee.coffe_cln_config.clone(),
+            repos,
+        }
+    }
+
+    /// Bring coffee back to the state of the snapshot.
+    pub async fn rollback(self, coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+        // the plugins installed by the transaction can be running.
+        let installed: Vec<String> = self
+            .config
+            .plugins
+            .iter()
+            .filter_map(|plugin| plugin.exec_path())
+            .collect();
+        let added: Vec<String> = coffee
+            .config
+            .plugins
+            .iter()
+            .filter_map(|plugin| plugin.exec_path())
+            .filter(|exec_path| !installed.contains(exec_path))
+            .collect();
+        if coffee.is_node_running() {
+            for exec_path in added {
+                if let Err(err) = coffee.stop_plugin(&exec_path).await {
+                    debug!("plugin {exec_path} not stopped: {err}");
+                }
+            }
+        }
+
+        // the repositories added by the transaction are removed.
+        let mut index = 0;
+        while index < coffee.repos.len() {
+            let name = coffee.repos[index].name();
+            if self.repos.iter().any(|(known, ..)| *known == name) {
+                index += 1;
+                continue;
+            }
+            let repo = coffee.repos.remove(index);
+            let path = repo.url().path_string;
+            if !coffee.is_install_root_shared() && Path::new(&path).exists() {
+                remove_dir_all(&path).await?;
+            }
+            println!("[{name}] removed");
+        }
+
+        let settings = coffee.config.settings.clone();
+        coffee.config = self.config;
+        coffee.config.settings = settings;
+        coffee.coffe_cln_config = self.cln_config;
+
+        let mut failures = 0;
+        for (name, url, commit, layout) in self.repos {
+            let index = match coffee.repos.iter().position(|repo| repo.name() == name) {
+                Some(index) => index,
+                None => {
+                    // the repositories removed by the transaction are cloned again.
+                    let url = URL::new(&coffee.install_root(), &url, &name);
+                    let mut repo = Github::new(&name, &url).with_layout(layout);
+                    match repo.init().await {
+                        Ok(()) => println!("[{name}] cloned again"),
+                        Err(err) => {
+                            warn!("repository {name} not cloned: {err}");
+                            failures += 1;
+                            continue;
+                        }
+                    }
+                    coffee.repos.push(Box::new(repo));
+                    coffee.repos.len() - 1
+                }
+            };
+            let Some(commit) = commit else {
+                continue;
+            };
+            if coffee.repos[index].commit().ok().as_ref() == Some(&commit) {
+                continue;
+            }
+            coffee.repos[index].reset(&commit).await?;
+            println!("[{name}] back to {commit}");
+            failures += coffee.rebuild_plugins(index).await?;
+        }
+        coffee.storage.store(&coffee.storage_info()).await?;
+        coffee.update_conf().await?;
+        if failures > 0 {
+            let err = CoffeeError::new(
+                1,
+                &format!("{failures} repositories or plugins not restored by the rollback"),
+            );
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+/// Roll back the `transaction`, if any, when the operation failed
+/// with the `result`, that is returned as it is.
+pub async fn finish(
+    coffee: &mut CoffeeManager,
+    transaction: Option<Transaction>,
+    result: Result<(), CoffeeError>,
+) -> Result<(), CoffeeError> {
+    if let (Some(transaction), Err(err)) = (transaction, &result) {
+        println!("rolling back: {err}");
+        if let Err(rollback_err) = transaction.rollback(coffee).await {
+            println!("rollback incomplete: {rollback_err}");
+        }
+    }
+    result
+}
@@ -0,0 +1,90 @@
+//! Try of a plugin before the install, the plugin runs on a regtest
+//! node started by coffee, so the node of the user is never touched.
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use coffee_lib::errors::CoffeeError;
+use serde_json::json;
+use tokio::time::sleep;
+
+use super::regtest::Regtest;
+use super::{build_plugin, CoffeeManager};
+
+/// How long the plugin must stay active after the startup.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// rpc commands known by the `node`.
+fn commands(node: &Regtest) -> Result<BTreeSet<String>, CoffeeError> {
+    let help = node.call("help", json!({}))?;
+    Ok(help["help"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|command| command["command"].as_str())
+        // the command comes with its arguments, e.g. `summary [currency]`.
+        .filter_map(|command| command.split_whitespace().next())
+        .map(|command| command.to_owned())
+        .collect())
+}
+
+/// start the plugin at `exec_path` on the `node` and check that it
+/// stays active, return the rpc commands added by the plugin.
+async fn health_checks(node: &Regtest, exec_path: &str) -> Result<Vec<String>, CoffeeError> {
+    let before = commands(node)?;
+    node.start_plugin(exec_path).await?;
+    sleep(SETTLE_TIME).await;
+    if node.plugin_state(exec_path)? != Some(true) {
+        return Err(CoffeeError::new(1, "plugin stopped after the startup"));
+    }
+    Ok(commands(node)?.difference(&before).cloned().collect())
+}
+
+/// Build the `plugin` and run it on a throwaway regtest node, the
+/// node is removed at the end.
+pub async fn try_plugin(
+    coffee: &CoffeeManager,
+    plugin: &str,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    let name = coffee.resolve_alias(plugin);
+    let mut plugin = coffee
+        .search_plugin(&name)
+        .ok_or_else(|| coffee.not_found(&name))?;
+    // the install script runs on the machine of the user anyway.
+    if let Some(script) = plugin.conf().and_then(|conf| conf.plugin.install) {
+        let question = format!(
+            "plugin `{name}` run the following install script:\n{}\ncontinue?",
+            script.trim()
+        );
+        if !coffee.config.settings.confirm.confirm(&question)? {
+            let err = CoffeeError::new(1, &format!("try of `{name}` aborted by the user"));
+            return Err(err);
+        }
+    }
+    println!("[{name}] building");
+    let exec_path = build_plugin(
+        &mut plugin,
+        verbose,
+        &coffee.sandbox(),
+        &coffee.run_as(),
+        &coffee.plugin_env(),
+    )
+    .await?;
+    let node = Regtest::start().await?;
+    let result = health_checks(&node, &exec_path).await;
+    node.stop().await;
+    match result {
+        Ok(commands) if commands.is_empty() => {
+            println!("[{name}] active on regtest, no rpc commands added")
+        }
+        Ok(commands) => println!(
+            "[{name}] active on regtest, rpc commands added: {}",
+            commands.join(", ")
+        ),
+        Err(err) => {
+            let err = CoffeeError::new(1, &format!("plugin `{name}` failed on regtest: {err}"));
+            return Err(err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Trust on first use of the repositories and of the install scripts,
+//! coffee shows what it is about to trust (the commit of a new
+//! repository, the install script of a plugin) and asks before
+//! running anything, the decisions are kept for each repository.
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::repository::Repository;
+use coffee_lib::url::URL;
+use coffee_lib::utils::unix_time;
+use sha2::{Digest, Sha256};
+
+use super::config::{default_repositories, TrustedRepository};
+use super::CoffeeManager;
+
+/// Fingerprint of an install `script`.
+pub fn script_fingerprint(script: &str) -> String {
+    Sha256::digest(script.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// true when the `url` is one of the repositories shipped with coffee.
+fn is_default(url: &str) -> bool {
+    default_repositories()
+        .unwrap_or_default()
+        .iter()
+        .any(|default| URL::new("", &default.url, "").url_string == url)
+}
+
+/// Ask the user to trust the `repo` added for the first time, the
+/// repositories shipped with coffee and the ones already trusted
+/// are not asked again.
+pub async fn trust_repository(
+    coffee: &mut CoffeeManager,
+    repo: &(dyn Repository + Send + Sync),
+) -> Result<(), CoffeeError> {
+    let url = repo.url().url_string;
+    if coffee.config.trusted.contains_key(&url) {
+        return Ok(());
+    }
+    let commit = repo.commit().ok();
+    if !is_default(&url) {
+        let plugins = repo.list().await?;
+        let scripts: Vec<String> = plugins
+            .iter()
+            .filter(|plugin| plugin.conf().and_then(|conf| conf.plugin.install).is_some())
+            .map(|plugin| plugin.name())
+            .collect();
+        let question = format!(
+            "repository `{}` ({url}) is new\n  commit: {}\n  plugins: {}\n  plugins with an install script: {}\ntrust the repository?",
+            repo.name(),
+            commit.as_deref().unwrap_or("unknown"),
+            plugins.len(),
+            if scripts.is_empty() {
+                "none".to_owned()
+            } else {
+                scripts.join(", ")
+            }
+        );
+        if !coffee.config.settings.confirm.confirm(&question)? {
+            let err = CoffeeError::new(1, &format!("repository `{}` not trusted", repo.name()));
+            return Err(err);
+        }
+    }
+    let trusted = TrustedRepository {
+        commit,
+        time: unix_time(),
+        scripts: Default::default(),
+    };
+    coffee.config.trusted.insert(url, trusted);
+    Ok(())
+}
+
+/// Check the install script of the `plugin` before running it, the
+/// user is asked the first time and every time the script changes.
+pub async fn check_install_script(
+    coffee: &mut CoffeeManager,
+    plugin: &Plugin,
+) -> Result<(), CoffeeError> {
+    let Some(script) = plugin.conf().and_then(|conf| conf.plugin.install) else {
+        return Ok(());
+    };
+    let name = plugin.name();
+    let url = coffee
+        .repo_of(plugin)
+        .map(|repo| repo.url().url_string)
+        .unwrap_or_default();
+    let fingerprint = script_fingerprint(&script);
+    let trusted = coffee
+        .config
+        .trusted
+        .get(&url)
+        .and_then(|trusted| trusted.scripts.get(&name));
+    let question = match trusted {
+        Some(trusted) if *trusted == fingerprint => return Ok(()),
+        Some(_) => format!(
+            "the install script of plugin `{name}` changed since it was trusted, now it runs:\n{}\nfingerprint: {fingerprint}\ncontinue?",
+            script.trim()
+        ),
+        None => format!(
+            "plugin `{name}` run the following install script:\n{}\nfingerprint: {fingerprint}\ncontinue?",
+            script.trim()
+        ),
+    };
+    let settings = &coffee.config.settings;
+    let policy = settings.scripts.as_ref().unwrap_or(&settings.confirm);
+    if !policy.confirm(&question)? {
+        let err = CoffeeError::new(1, &format!("install script of `{name}` not trusted"));
+        return Err(err);
+    }
+    coffee
+        .config
+        .trusted
+        .entry(url)
+        .or_insert_with(|| TrustedRepository {
+            commit: None,
+            time: unix_time(),
+            scripts: Default::default(),
+        })
+        .scripts
+        .insert(name, fingerprint);
+    coffee.storage.store(&coffee.storage_info()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::script_fingerprint;
+
+    #[test]
+    fn test_trust_script_fingerprint() {
+        assert_eq!(script_fingerprint("make\n"), script_fingerprint("  make  "));
+        assert_ne!(script_fingerprint("make"), script_fingerprint("make all"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Interactive terminal interface of coffee, it allows
+//! to browse the repositories and the plugins with the
+//! keyboard and to trigger the plugin manager operations.
+use std::io;
+
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_manager::PluginManager;
+use log::debug;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use super::CoffeeManager;
+
+/// The panel that is receiving the keyboard input.
+#[derive(PartialEq)]
+enum Focus {
+    Repositories,
+    Plugins,
+}
+
+/// Operation requested by the user on the selected plugin.
+enum Action {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+/// State of the interface.
+struct App {
+    /// repositories name with the list of plugins.
+    repos: Vec<(String, Vec<Plugin>)>,
+    /// name of the plugins installed.
+    installed: Vec<String>,
+    focus: Focus,
+    repos_state: ListState,
+    plugins_state: ListState,
+    /// last message that we want to show to the user.
+    message: String,
+}
+
+impl App {
+    async fn new(coffee: &CoffeeManager) -> Result<Self, CoffeeError> {
+        let mut app = App {
+            repos: vec![],
+            installed: vec![],
+            focus: Focus::Repositories,
+            repos_state: ListState::default(),
+            plugins_state: ListState::default(),
+            message: "q: quit, tab: switch panel, i: install, u: upgrade, r: remove".to_owned(),
+        };
+        app.refresh(coffee).await?;
+        Ok(app)
+    }
+
+    /// reload the information from the plugin manager.
+    async fn refresh(&mut self, coffee: &CoffeeManager) -> Result<(), CoffeeError> {
+        self.repos.clear();
+        for repo in &coffee.repos {
+            self.repos.push((repo.name(), repo.list().await?));
+        }
+        self.installed = coffee
+            .config
+            .plugins
+            .iter()
+            .map(|plugin| plugin.name())
+            .collect();
+        if self.repos_state.selected().is_none() && !self.repos.is_empty() {
+            self.repos_state.select(Some(0));
+        }
+        if self.plugins_state.selected().is_none() && !self.plugins().is_empty() {
+            self.plugins_state.select(Some(0));
+        }
+        Ok(())
+    }
+
+    /// plugins of the selected repository.
+    fn plugins(&self) -> &[Plugin] {
+        self.repos_state
+            .selected()
+            .and_then(|index| self.repos.get(index))
+            .map(|(_, plugins)| plugins.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn selected_plugin(&self) -> Option<&Plugin> {
+        self.plugins_state
+            .selected()
+            .and_then(|index| self.plugins().get(index))
+    }
+
+    /// text that describe the selected plugin, this is the
+    /// coffee manifest when the plugin has one.
+    fn plugin_details(&self) -> String {
+        let Some(plugin) = self.selected_plugin() else {
+            return "no plugin selected".to_owned();
+        };
+        let mut details = format!("{plugin}\nlanguage: {:?}\n", plugin.lang());
+        if self.installed.contains(&plugin.name()) {
+            details += "status: installed\n";
+        }
+        if plugin.conf().is_some() {
+            for file in ["coffee.yaml", "coffee.yml"] {
+                let manifest_path = format!("{}/{file}", plugin.root_path());
+                if let Ok(manifest) = std::fs::read_to_string(manifest_path) {
+                    details += &format!("\n{file}\n\n{manifest}");
+                    break;
+                }
+            }
+        } else {
+            details += "\nthe plugin do not have a coffee manifest";
+        }
+        details
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let (state, len) = match self.focus {
+            Focus::Repositories => (&mut self.repos_state, self.repos.len()),
+            Focus::Plugins => {
+                let len = self.plugins().len();
+                (&mut self.plugins_state, len)
+            }
+        };
+        if len == 0 {
+            return;
+        }
+        let index = match state.selected() {
+            Some(index) if down => (index + 1) % len,
+            Some(0) | None => len - 1,
+            Some(index) => index - 1,
+        };
+        state.select(Some(index));
+        if self.focus == Focus::Repositories {
+            let has_plugins = !self.plugins().is_empty();
+            self.plugins_state.select(has_plugins.then_some(0));
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(3)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Percentage(50),
+        ])
+        .split(rows[0]);
+
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+    let panel = |title: &'static str, focused: bool| {
+        let block = Block::default().title(title).borders(Borders::ALL);
+        if focused {
+            block.border_style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            block
+        }
+    };
+
+    let repos: Vec<ListItem> = app
+        .repos
+        .iter()
+        .map(|(name, _)| ListItem::new(name.to_owned()))
+        .collect();
+    let repos = List::new(repos)
+        .block(panel("Repositories", app.focus == Focus::Repositories))
+        .highlight_style(highlight);
+    frame.render_stateful_widget(repos, columns[0], &mut app.repos_state);
+
+    let plugins: Vec<ListItem> = app
+        .plugins()
+        .iter()
+        .map(|plugin| {
+            let name = plugin.name();
+            if app.installed.contains(&name) {
+                ListItem::new(format!("{name} [installed]"))
+            } else {
+                ListItem::new(name)
+            }
+        })
+        .collect();
+    let plugins = List::new(plugins)
+        .block(panel("Plugins", app.focus == Focus::Plugins))
+        .highlight_style(highlight);
+    frame.render_stateful_widget(plugins, columns[1], &mut app.plugins_state);
+
+    let details = Paragraph::new(app.plugin_details())
+        .block(panel("Manifest", false))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(details, columns[2]);
+
+    let message = Paragraph::new(app.message.as_str()).block(panel("Coffee", false));
+    frame.render_widget(message, rows[1]);
+}
+
+/// run the operation outside the interface, so the output of the
+/// install commands is readable, and wait the user before coming back.
+async fn run_action(
+    coffee: &mut CoffeeManager,
+    terminal: DefaultTerminal,
+    action: Action,
+    plugin: &str,
+) -> Result<(DefaultTerminal, String), CoffeeError> {
+    drop(terminal);
+    ratatui::restore();
+    let result = match action {
+        Action::Install => coffee.install(&[plugin], false, false).await,
+        Action::Upgrade => coffee.upgrade(&[plugin]).await,
+        Action::Remove => coffee.remove(plugin).await,
+    };
+    let message = match result {
+        Ok(()) => format!("`{plugin}` done"),
+        Err(err) => format!("`{plugin}` failed: {err}"),
+    };
+    println!("{message}, press enter to come back to coffee");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok((ratatui::init(), message))
+}
+
+/// run the interactive interface until the user quit.
+pub async fn run(coffee: &mut CoffeeManager) -> Result<(), CoffeeError> {
+    if coffee.config.settings.plain {
+        let err = CoffeeError::new(
+            1,
+            "the interactive interface is not available with `--plain`, use the commands",
+        );
+        return Err(err);
+    }
+    let mut app = App::new(coffee).await?;
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(err) = terminal.draw(|frame| draw(frame, &mut app)) {
+            break Err(err.into());
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(err) => break Err(err.into()),
+        };
+        let action = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                app.focus = match app.focus {
+                    Focus::Repositories => Focus::Plugins,
+                    Focus::Plugins => Focus::Repositories,
+                };
+                continue;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.move_selection(true);
+                continue;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.move_selection(false);
+                continue;
+            }
+            KeyCode::Char('i') => Action::Install,
+            KeyCode::Char('u') => Action::Upgrade,
+            KeyCode::Char('r') => Action::Remove,
+            _ => continue,
+        };
+        let Some(plugin) = app.selected_plugin().map(|plugin| plugin.name()) else {
+            app.message = "select a plugin first".to_owned();
+            continue;
+        };
+        debug!("running tui action on {plugin}");
+        match run_action(coffee, terminal, action, &plugin).await {
+            Ok((new_terminal, message)) => {
+                terminal = new_terminal;
+                app.message = message;
+            }
+            Err(err) => return Err(err),
+        }
+        if let Err(err) = app.refresh(coffee).await {
+            break Err(err);
+        }
+    };
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,138 @@
+//! Why a plugin is installed, requested by the user, pulled in as a
+//! dependency of another plugin or part of a bundle, and which of the
+//! requests of the user keep it installed.
+use std::collections::{BTreeMap, BTreeSet};
+
+use coffee_lib::errors::CoffeeError;
+
+use super::graph::{self, Node};
+use super::CoffeeManager;
+
+/// Reasons why a plugin is installed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Why {
+    /// the user requested the plugin.
+    pub requested: bool,
+    /// installed bundles that contain the plugin.
+    pub bundles: Vec<String>,
+    /// installed plugins that need the plugin.
+    pub needed_by: Vec<String>,
+    /// requests of the user that keep the plugin installed, the
+    /// requested plugins and the bundles (as `bundle <name>`).
+    pub kept_by: BTreeSet<String>,
+}
+
+/// installed bundles that contain the `plugin`.
+fn bundles_of(bundles: &BTreeMap<String, Vec<String>>, plugin: &str) -> Vec<String> {
+    bundles
+        .iter()
+        .filter(|(_, plugins)| plugins.iter().any(|member| member == plugin))
+        .map(|(bundle, _)| bundle.clone())
+        .collect()
+}
+
+/// Reasons why the `plugin` of the `graph` is installed, the
+/// `auto_installed` plugins were pulled in as dependencies.
+pub fn explain(
+    graph: &BTreeMap<String, Node>,
+    auto_installed: &BTreeSet<String>,
+    bundles: &BTreeMap<String, Vec<String>>,
+    plugin: &str,
+) -> Why {
+    let mut why = Why {
+        requested: !auto_installed.contains(plugin),
+        bundles: bundles_of(bundles, plugin),
+        needed_by: graph::dependents(graph, plugin),
+        ..Why::default()
+    };
+    // walk up the dependents until the requests of the user.
+    let mut visited = BTreeSet::new();
+    let mut queue = vec![plugin.to_owned()];
+    while let Some(current) = queue.pop() {
+        if !visited.insert(current.clone()) {
+            continue;
+        }
+        if !auto_installed.contains(&current) {
+            why.kept_by.insert(current.clone());
+        }
+        for bundle in bundles_of(bundles, &current) {
+            why.kept_by.insert(format!("bundle {bundle}"));
+        }
+        queue.extend(graph::dependents(graph, &current));
+    }
+    why
+}
+
+/// Print why the installed `plugin` is installed.
+pub fn why(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let plugin = coffee.resolve_alias(plugin);
+    let graph = graph::build(coffee);
+    if !graph.contains_key(&plugin) {
+        return Err(coffee.not_installed(&plugin));
+    }
+    let why = explain(
+        &graph,
+        &coffee.config.auto_installed,
+        &coffee.config.bundles,
+        &plugin,
+    );
+    if why.requested {
+        println!("[{plugin}] requested by the user");
+    } else {
+        println!("[{plugin}] installed as a dependency");
+    }
+    for bundle in &why.bundles {
+        println!("[{plugin}] part of the bundle {bundle}");
+    }
+    for dependent in &why.needed_by {
+        println!("[{plugin}] needed by {dependent}");
+    }
+    if why.kept_by.is_empty() {
+        println!("[{plugin}] nothing keeps it installed, `coffee autoremove` removes it");
+    } else {
+        let kept_by: Vec<&str> = why.kept_by.iter().map(String::as_str).collect();
+        println!("[{plugin}] kept installed by {}", kept_by.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::explain;
+    use crate::coffee::graph::Node;
+
+    #[test]
+    fn test_why_explain() {
+        let node = |dependencies: &[&str]| Node {
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+            ..Node::default()
+        };
+        let mut graph = BTreeMap::new();
+        graph.insert("summary".to_owned(), node(&["rebalance"]));
+        graph.insert("rebalance".to_owned(), node(&["sauron"]));
+        graph.insert("sauron".to_owned(), node(&[]));
+        graph.insert("orphan".to_owned(), node(&[]));
+        let auto: BTreeSet<String> = ["rebalance", "sauron", "orphan"]
+            .iter()
+            .map(|plugin| plugin.to_string())
+            .collect();
+        let mut bundles = BTreeMap::new();
+        bundles.insert("routing-ops".to_owned(), vec!["rebalance".to_owned()]);
+
+        let why = explain(&graph, &auto, &bundles, "sauron");
+        assert!(!why.requested);
+        assert_eq!(why.needed_by, vec!["rebalance"]);
+        let kept_by: Vec<&str> = why.kept_by.iter().map(String::as_str).collect();
+        assert_eq!(kept_by, vec!["bundle routing-ops", "summary"]);
+
+        let why = explain(&graph, &auto, &bundles, "summary");
+        assert!(why.requested);
+        assert!(why.kept_by.contains("summary"));
+
+        assert!(explain(&graph, &auto, &bundles, "orphan")
+            .kept_by
+            .is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Worktrees of the installed plugins, the sources of every installed
+//! plugin are checked out inside a worktree of its own, so updating the
+//! checkout of the repository never changes what a running plugin
+//! executes. A plugin can also be pinned at a tag, a branch or a commit
+//! of its repository, while the others follow its default branch.
+//!
+//! The worktrees live inside the hidden `.worktrees` directory of the
+//! repository checkout, so the indexer skips them.
+use std::path::Path;
+
+use coffee_github::git::{self, WORKTREES_DIR};
+use coffee_github::repository::{plugin_from_dir, plugin_from_script};
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::plugin::Plugin;
+use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::utils::relative_path;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{create_dir_all, read_dir, remove_dir, remove_dir_all};
+
+use super::CoffeeManager;
+
+/// Worktree with the sources of an installed plugin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Worktree {
+    /// directory of the worktree.
+    pub path: String,
+    /// commit checked out inside the worktree.
+    pub commit: String,
+}
+
+impl Worktree {
+    /// path of the repository checkout that owns the worktree.
+    fn repo_path(&self) -> String {
+        self.path
+            .split(&format!("/{WORKTREES_DIR}/"))
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    }
+}
+
+/// directory of the worktrees of the `plugin` inside the repository
+/// at `repo_path`.
+fn plugin_dir(repo_path: &str, plugin: &str) -> String {
+    format!("{repo_path}/{WORKTREES_DIR}/{plugin}")
+}
+
+/// remove the worktree of the repository at `repo_path` in `dest`.
+async fn remove_worktree(repo_path: &str, dest: &str) -> Result<(), CoffeeError> {
+    if let Err(err) = git::backend().remove_worktree(repo_path, dest) {
+        debug!("worktree {dest} not removed: {err}");
+    }
+    if Path::new(dest).exists() {
+        remove_dir_all(dest).await?;
+    }
+    Ok(())
+}
+
+/// path of the `plugin` (its script for the standalone ones)
+/// relative to the repository at `repo_path`, also when the plugin
+/// lives inside a worktree.
+fn subpath(plugin: &Plugin, repo_path: &str) -> String {
+    let source = if plugin.is_standalone() {
+        plugin.path.clone()
+    } else {
+        plugin.root_path()
+    };
+    let subpath = relative_path(&source, repo_path);
+    match subpath.strip_prefix(&format!("{WORKTREES_DIR}/")) {
+        // skip the `<plugin>/<commit>` of the worktree.
+        Some(inside) => inside.splitn(3, '/').nth(2).unwrap_or_default().to_owned(),
+        None => subpath.to_owned(),
+    }
+}
+
+/// Check out the sources of the `plugin` at the `reference` of its
+/// repository inside a new worktree of the plugin, and return the plugin
+/// indexed inside the worktree.
+///
+/// The previous worktree of the plugin is kept, because the node may
+/// still run it, until it is pruned.
+pub async fn checkout(
+    coffee: &mut CoffeeManager,
+    plugin: &Plugin,
+    reference: &str,
+) -> Result<Plugin, CoffeeError> {
+    let name = plugin.name();
+    let repo = coffee.repo_of(plugin).ok_or_else(|| {
+        CoffeeError::new(1, &format!("plugin `{name}` do not come from a repository"))
+    })?;
+    let repo_path = repo.url().path_string;
+    let git = git::backend();
+    let commit = git.resolve(&repo_path, reference)?;
+    let short: String = commit.chars().take(12).collect();
+    let dest = format!("{}/{short}", plugin_dir(&repo_path, &name));
+    // a worktree of the same commit is checked out again, so the
+    // local patches are applied on the upstream sources.
+    if Path::new(&dest).exists() {
+        remove_worktree(&repo_path, &dest).await?;
+    }
+    create_dir_all(plugin_dir(&repo_path, &name)).await?;
+    git.add_worktree(&repo_path, &dest, &commit)?;
+    debug!("worktree of {name} at {commit} in {dest}");
+
+    // the plugin lives at the same path inside the worktree.
+    let subpath = subpath(plugin, &repo_path);
+    let target = Path::new(&dest).join(&subpath);
+    let checked_out = if plugin.is_standalone() {
+        plugin_from_script(&target)
+    } else if target.is_dir() {
+        Some(plugin_from_dir(&target).await?)
+    } else {
+        None
+    };
+    let Some(mut checked_out) = checked_out else {
+        remove_worktree(&repo_path, &dest).await?;
+        let err = CoffeeError::new(
+            1,
+            &format!("plugin `{name}` is not present at `{reference}`"),
+        );
+        return Err(err);
+    };
+    checked_out.set_content_hash(git.object_id(&repo_path, &commit, &subpath).ok().flatten());
+    coffee
+        .config
+        .worktrees
+        .insert(name, Worktree { path: dest, commit });
+    Ok(checked_out)
+}
+
+/// Check out the sources of the `plugin` inside its own worktree, at
+/// its pin or at the commit of the repository checkout. The plugins
+/// that do not come from a repository are returned as they are.
+pub async fn isolate(coffee: &mut CoffeeManager, plugin: &Plugin) -> Result<Plugin, CoffeeError> {
+    if coffee.repo_of(plugin).is_none() {
+        return Ok(plugin.clone());
+    }
+    let reference = coffee
+        .config
+        .pins
+        .get(&plugin.name())
+        .cloned()
+        .unwrap_or_else(|| "HEAD".to_owned());
+    checkout(coffee, plugin, &reference).await
+}
+
+/// Remove the worktrees of the `plugin` that it does not run anymore,
+/// all of them but the recorded one.
+pub async fn prune(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let Some(worktree) = coffee.config.worktrees.get(plugin) else {
+        return Ok(());
+    };
+    let repo_path = worktree.repo_path();
+    let mut entries = read_dir(plugin_dir(&repo_path, plugin)).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path().to_string_lossy().to_string();
+        if path != worktree.path {
+            debug!("pruning the worktree {path} of {plugin}");
+            remove_worktree(&repo_path, &path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep the `previous` worktree of the `plugin` when the install of a
+/// new one failed, and remove the new one.
+pub async fn discard(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    previous: Option<Worktree>,
+) -> Result<(), CoffeeError> {
+    match previous {
+        Some(previous) => {
+            coffee.config.worktrees.insert(plugin.to_owned(), previous);
+            prune(coffee, plugin).await
+        }
+        None => remove(coffee, plugin).await,
+    }
+}
+
+/// Check out again the recorded worktree of the `plugin` when it is
+/// missing, e.g. after the repository was cloned again.
+pub async fn restore(coffee: &CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let Some(worktree) = coffee.config.worktrees.get(plugin) else {
+        return Ok(());
+    };
+    if Path::new(&worktree.path).exists() {
+        return Ok(());
+    }
+    let repo_path = worktree.repo_path();
+    create_dir_all(plugin_dir(&repo_path, plugin)).await?;
+    git::backend().add_worktree(&repo_path, &worktree.path, &worktree.commit)
+}
+
+/// Remove all the worktrees of the `plugin`, if any.
+pub async fn remove(coffee: &mut CoffeeManager, plugin: &str) -> Result<(), CoffeeError> {
+    let Some(worktree) = coffee.config.worktrees.remove(plugin) else {
+        return Ok(());
+    };
+    let repo_path = worktree.repo_path();
+    let dir = plugin_dir(&repo_path, plugin);
+    let mut entries = read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        remove_worktree(&repo_path, &entry.path().to_string_lossy()).await?;
+    }
+    remove_dir(&dir).await?;
+    Ok(())
+}
+
+/// Install the `plugins` pinned at the `reference` of their
+/// repository, or following the repository when there is none.
+pub async fn install(
+    coffee: &mut CoffeeManager,
+    plugins: &[&str],
+    reference: Option<&str>,
+    verbose: bool,
+    dynamic: bool,
+) -> Result<(), CoffeeError> {
+    let pins = coffee.config.pins.clone();
+    for plugin in plugins {
+        let plugin = coffee.resolve_alias(plugin);
+        match reference {
+            Some(reference) => coffee.config.pins.insert(plugin, reference.to_owned()),
+            None => coffee.config.pins.remove(&plugin),
+        };
+    }
+    let result = coffee.install(plugins, verbose, dynamic).await;
+    if result.is_err() {
+        coffee.config.pins = pins;
+        coffee.storage.store(&coffee.storage_info()).await?;
+    }
+    result
+}
+
+/// Pin the installed `plugin` at the `reference` of its repository,
+/// or make it follow the repository again when there is none, and
+/// build it again from the new worktree.
+pub async fn repin(
+    coffee: &mut CoffeeManager,
+    plugin: &str,
+    reference: Option<&str>,
+) -> Result<(), CoffeeError> {
+    let Some(position) = coffee
+        .config
+        .plugins
+        .iter()
+        .position(|installed| installed.name() == plugin)
+    else {
+        return Err(coffee.not_installed(plugin));
+    };
+    let installed = coffee.config.plugins[position].clone();
+    let pins = coffee.config.pins.clone();
+    match reference {
+        Some(reference) => coffee
+            .config
+            .pins
+            .insert(plugin.to_owned(), reference.to_owned()),
+        None => coffee.config.pins.remove(plugin),
+    };
+    let reference = reference.unwrap_or("HEAD");
+    let result = match coffee.rebuild_plugin(position, installed, reference).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(CoffeeError::new(
+            1,
+            &format!("plugin `{plugin}` not checked out at `{reference}`"),
+        )),
+        Err(err) => Err(err),
+    };
+    if result.is_err() {
+        coffee.config.pins = pins;
+    }
+    coffee.storage.store(&coffee.storage_info()).await?;
+    coffee.update_conf().await?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Worktree;
+
+    #[test]
+    fn test_worktree_repo_path() {
+        let worktree = Worktree {
+            path: "/home/coffee/repositories/plugins/.worktrees/summary/0123456789ab".to_owned(),
+            commit: "0123456789abcdef".to_owned(),
+        };
+        assert_eq!(worktree.repo_path(), "/home/coffee/repositories/plugins");
+    }
+}
@@ -2,48 +2,426 @@ mod coffee;
 
 use crate::coffee::cmd::CoffeeArgs;
 use clap::Parser;
+use coffee::cmd::AliasAction;
+use coffee::cmd::BundleAction;
 use coffee::cmd::CoffeeCommand;
+use coffee::cmd::DevAction;
+use coffee::cmd::EnvAction;
+use coffee::cmd::HistoryAction;
+use coffee::cmd::IndexAction;
+use coffee::cmd::OptionAction;
+use coffee::cmd::PatchAction;
+use coffee::cmd::ProfileAction;
 use coffee::cmd::RemoteAction;
+use coffee::export::ExportFormat;
+use coffee::transaction;
 use coffee::CoffeeManager;
 
+use std::env;
+use std::io::Write;
+use std::process;
+
 use coffee_lib::errors::CoffeeError;
-use coffee_lib::plugin_manager::PluginManager;
+use coffee_lib::i18n::tr;
+use coffee_lib::interrupt;
+use coffee_lib::plugin_manager::{PluginInspector, PluginManager};
+use coffee_lib::secrets;
+use log::warn;
+use tokio::signal;
+
+/// run the index command, on the checkout at `check` or on the one
+/// of the `action`.
+async fn index(check: Option<String>, action: Option<IndexAction>) -> Result<(), CoffeeError> {
+    match (action, check) {
+        (
+            Some(IndexAction::Export {
+                path,
+                url,
+                output,
+                key,
+            }),
+            _,
+        ) => coffee::index::export(&path, url, output, key).await,
+        (None, Some(path)) => coffee::index::check(&path).await,
+        (None, None) => Err(CoffeeError::new(1, "nothing to do, use `--check <PATH>`")),
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), CoffeeError> {
-    env_logger::init();
-    let args = CoffeeArgs::parse();
-    let mut coffee = CoffeeManager::new(&args).await?;
+    // the secrets are redacted also from the logs.
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                secrets::redact(&record.args().to_string())
+            )
+        })
+        .init();
+    let args = coffee::command_alias::expand(env::args().collect()).await?;
+    let args = CoffeeArgs::parse_from(args);
+    // the first Ctrl-C asks to the running command to stop and
+    // to clean up, the second one exits immediately, and the lock
+    // of the state goes away with the process.
+    tokio::spawn(async {
+        while signal::ctrl_c().await.is_ok() {
+            if interrupt::is_interrupted() {
+                process::exit(interrupt::INTERRUPTED_CODE);
+            }
+            warn!("{}", tr("interrupting", &[]));
+            interrupt::interrupt();
+        }
+    });
+    // the index commands work on a checkout alone, without the coffee
+    // state and its lock, so they run also in the CI of a collection.
+    if let CoffeeCommand::Index { check, action } = args.command {
+        if let Err(err) = index(check, action).await {
+            panic!("{err}");
+        }
+        return Ok(());
+    }
+    let mut coffee = if args.command.is_read_only() {
+        CoffeeManager::read_only(&args).await?
+    } else {
+        CoffeeManager::new(&args).await?
+    };
     let result = match args.command {
         CoffeeCommand::Install {
-            plugin,
+            plugins,
             verbose,
             dynamic,
-        } => coffee.install(&plugin, verbose, dynamic).await,
-        CoffeeCommand::Remove => todo!(),
-        CoffeeCommand::List { remotes } => match coffee.list(remotes).await {
+            rollback_on_failure,
+            atomic,
+            at,
+        } => {
+            if rollback_on_failure {
+                coffee.settings_mut().rollback_on_failure = true;
+            }
+            coffee.settings_mut().atomic |= atomic;
+            let transaction = coffee.begin();
+            let plugins: Vec<&str> = plugins.iter().map(|plugin| plugin.as_str()).collect();
+            let result =
+                coffee::worktree::install(&mut coffee, &plugins, at.as_deref(), verbose, dynamic)
+                    .await;
+            transaction::finish(&mut coffee, transaction, result).await
+        }
+        CoffeeCommand::Remove { plugins, selector } => {
+            match coffee::selector::select(&coffee, &plugins, &selector) {
+                Ok(plugins) => {
+                    let mut result = Ok(());
+                    for plugin in &plugins {
+                        result = coffee.remove(plugin).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    if result.is_ok() {
+                        coffee::autoremove::hint(&coffee);
+                    }
+                    result
+                }
+                Err(err) => Err(err),
+            }
+        }
+        CoffeeCommand::Autoremove => coffee::autoremove::autoremove(&mut coffee).await,
+        CoffeeCommand::Bundle { action } => match action {
+            BundleAction::List => {
+                coffee::bundle::list(&coffee);
+                Ok(())
+            }
+            BundleAction::Install {
+                name,
+                verbose,
+                dynamic,
+                atomic,
+            } => {
+                coffee.settings_mut().atomic |= atomic;
+                let transaction = coffee.begin();
+                let result = coffee::bundle::install(&mut coffee, &name, verbose, dynamic).await;
+                transaction::finish(&mut coffee, transaction, result).await
+            }
+            BundleAction::Upgrade { name, atomic } => {
+                coffee.settings_mut().atomic |= atomic;
+                let transaction = coffee.begin();
+                let result = coffee::bundle::upgrade(&mut coffee, &name).await;
+                transaction::finish(&mut coffee, transaction, result).await
+            }
+            BundleAction::Remove { name } => coffee::bundle::remove(&mut coffee, &name).await,
+        },
+        CoffeeCommand::Disable { plugins, selector } => {
+            match coffee::selector::select(&coffee, &plugins, &selector) {
+                Ok(plugins) => {
+                    let mut result = Ok(());
+                    for plugin in &plugins {
+                        result =
+                            coffee::disable::disable(&mut coffee, plugin, "disabled by the user")
+                                .await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    result
+                }
+                Err(err) => Err(err),
+            }
+        }
+        CoffeeCommand::Enable { plugins, selector } => {
+            match coffee::selector::select(&coffee, &plugins, &selector) {
+                Ok(plugins) => {
+                    let mut result = Ok(());
+                    for plugin in &plugins {
+                        result = coffee::disable::enable(&mut coffee, plugin).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    result
+                }
+                Err(err) => Err(err),
+            }
+        }
+        CoffeeCommand::List { remotes, selector } => match coffee.list(remotes).await {
+            Ok(mut val) => {
+                if !selector.is_empty() {
+                    let selected = coffee::selector::installed(&coffee, &selector);
+                    val["plugins"] = serde_json::to_value(&selected).unwrap();
+                }
+                let val = secrets::redact_json(&val);
+                println!("{}", serde_json::to_string_pretty(&val).unwrap());
+                Ok(())
+            }
+            Err(err) => Err(err),
+        },
+        CoffeeCommand::Show { plugin } => match coffee.show(&plugin).await {
             Ok(val) => {
+                let val = secrets::redact_json(&val);
                 println!("{}", serde_json::to_string_pretty(&val).unwrap());
                 Ok(())
             }
             Err(err) => Err(err),
         },
-        CoffeeCommand::Upgrade => coffee.upgrade(&[""]).await,
-        CoffeeCommand::Remote { action } => {
-            if let RemoteAction::Add { name, url } = action {
-                coffee.add_remote(name.as_str(), url.as_str()).await
-            } else {
-                Err(CoffeeError::new(1, "unsupported command"))
+        CoffeeCommand::Upgrade {
+            plugins,
+            selector,
+            stash_local,
+            reapply,
+            atomic,
+            canary,
+        } => {
+            let settings = coffee.settings_mut();
+            settings.stash_local |= stash_local;
+            settings.reapply_local |= reapply;
+            // the canary rolls back with the transaction.
+            settings.atomic |= atomic || canary.is_some();
+            match coffee::selector::select(&coffee, &plugins, &selector) {
+                Ok(plugins) => {
+                    let transaction = coffee.begin();
+                    let names: Vec<&str> = plugins.iter().map(|plugin| plugin.as_str()).collect();
+                    let mut result = coffee.upgrade(&names).await;
+                    if let (Ok(()), Some(duration)) = (&result, canary) {
+                        result = coffee::canary::watch(&coffee, &plugins, duration).await;
+                    }
+                    transaction::finish(&mut coffee, transaction, result).await
+                }
+                Err(err) => Err(err),
             }
         }
-        CoffeeCommand::Setup { cln_conf } => {
-            // FIXME: read the core lightning confi and
-            // and the coffee script
-            coffee.setup(&cln_conf).await
+        CoffeeCommand::Remote { action } => match action {
+            RemoteAction::Add { defaults: true, .. } => coffee.add_default_remotes().await,
+            RemoteAction::Add {
+                name, url, layout, ..
+            } => {
+                // clap guarantees that the first is present without `--defaults`,
+                // alone it is the url.
+                let layout = layout.unwrap_or_default();
+                match (name.unwrap_or_default(), url) {
+                    (name, Some(url)) => coffee.add_remote_with_layout(&name, &url, layout).await,
+                    (url, None) => coffee.add_remote_from_url(&url, layout).await,
+                }
+            }
+            RemoteAction::Remove { name } => coffee.rm_remote(name.as_str()).await,
+            RemoteAction::Layout { name, layout } => coffee.set_remote_layout(&name, layout).await,
+            RemoteAction::Verify { name } => coffee.verify_remote(name.as_str()).await,
+            RemoteAction::Browse { name, at } => match coffee.browse_remote(&name, &at).await {
+                Ok(report) => {
+                    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            },
+        },
+        CoffeeCommand::Setup { cln_conf } => coffee.setup_wizard(cln_conf).await,
+        CoffeeCommand::Logs { plugin, follow } => {
+            coffee::logs::logs(&coffee, &plugin, follow).await
         }
+        CoffeeCommand::MigrateFromPython { reckless_dir } => {
+            coffee::migrate::migrate_from_python(&mut coffee, reckless_dir).await
+        }
+        CoffeeCommand::Status => coffee::status::status(&coffee).await,
+        CoffeeCommand::Nurse => coffee::nurse::nurse(&coffee).await,
+        CoffeeCommand::Top => coffee::top::top(&coffee).await,
+        CoffeeCommand::Why { plugin } => coffee::why::why(&coffee, &plugin),
+        CoffeeCommand::Graph { dot } => {
+            coffee::graph::graph(&coffee, dot);
+            Ok(())
+        }
+        CoffeeCommand::SelfUpdate { check } => coffee.self_update(check).await,
+        CoffeeCommand::Override {
+            plugin,
+            reset: true,
+            ..
+        } => coffee::plugin_override::reset(&mut coffee, &plugin).await,
+        CoffeeCommand::Override {
+            plugin,
+            url,
+            branch,
+            ..
+        } => {
+            // clap guarantees the url without `--reset`.
+            let url = url.unwrap_or_default();
+            coffee::plugin_override::set(&mut coffee, &plugin, &url, branch).await
+        }
+        CoffeeCommand::Search { tags: true, .. } => coffee::search::list_tags(&coffee).await,
+        CoffeeCommand::Search {
+            query,
+            tag,
+            provides,
+            sort,
+            ..
+        } => coffee::search::search(&coffee, query, tag, provides, sort).await,
+        CoffeeCommand::Readme { plugin } => coffee::readme::readme(&coffee, &plugin).await,
+        CoffeeCommand::Open { plugin, issues } => {
+            coffee::open::open(&coffee, &plugin, issues).await
+        }
+        CoffeeCommand::Alias { action } => match action {
+            AliasAction::Set { alias, plugin } => {
+                coffee::alias::set(&mut coffee, &alias, &plugin).await
+            }
+            AliasAction::Remove { alias } => coffee::alias::remove(&mut coffee, &alias).await,
+            AliasAction::List => {
+                coffee::alias::list(&coffee);
+                Ok(())
+            }
+        },
+        CoffeeCommand::Patch { action } => match action {
+            PatchAction::Add { plugin, patch } => {
+                coffee::patch::add(&mut coffee, &plugin, &patch).await
+            }
+            PatchAction::Remove { plugin, patch } => {
+                coffee::patch::remove(&mut coffee, &plugin, &patch).await
+            }
+            PatchAction::List { plugin } => {
+                coffee::patch::list(&coffee, plugin);
+                Ok(())
+            }
+        },
+        CoffeeCommand::Backup { tarball } => coffee::backup::backup(&coffee, &tarball).await,
+        CoffeeCommand::Restore { tarball } => coffee::backup::restore(&mut coffee, &tarball).await,
+        CoffeeCommand::Systemd {
+            plugin,
+            remove: true,
+            ..
+        } => coffee::systemd::uninstall(&mut coffee, &plugin).await,
+        CoffeeCommand::Systemd {
+            plugin,
+            install,
+            user,
+            ..
+        } => coffee::systemd::systemd(&mut coffee, &plugin, install, user).await,
+        CoffeeCommand::Tui => coffee::tui::run(&mut coffee).await,
+        CoffeeCommand::Export {
+            docker_compose,
+            nix,
+            image,
+            ..
+        } => {
+            let format = if docker_compose {
+                ExportFormat::DockerCompose
+            } else if nix {
+                ExportFormat::Nix
+            } else {
+                ExportFormat::Dockerfile
+            };
+            coffee::export::export(&coffee, format, &image).await
+        }
+        CoffeeCommand::Apply {
+            state,
+            prune,
+            atomic,
+        } => {
+            coffee.settings_mut().atomic |= atomic;
+            let transaction = coffee.begin();
+            let result = coffee::apply::apply(&mut coffee, &state, prune).await;
+            transaction::finish(&mut coffee, transaction, result).await
+        }
+        CoffeeCommand::Run { script } => coffee::run::run(&mut coffee, &script).await,
+        CoffeeCommand::Sync => coffee::ssh::sync(&coffee).await,
+        CoffeeCommand::Serve { bind } => coffee::serve::serve(&mut coffee, &bind).await,
+        CoffeeCommand::Daemon { interval, metrics } => {
+            coffee::daemon::daemon(&mut coffee, interval, metrics).await
+        }
+        CoffeeCommand::History { action: None } => coffee::audit::history(&coffee).await,
+        CoffeeCommand::History {
+            action: Some(HistoryAction::Revert { id }),
+        } => coffee::audit::revert(&mut coffee, id).await,
+        CoffeeCommand::Undo => coffee::audit::undo_last(&mut coffee).await,
+        CoffeeCommand::Checksums { plugin } => coffee::checksums::list(&coffee, plugin).await,
+        CoffeeCommand::Buildlog { plugin } => coffee::buildlog::show(&coffee, &plugin).await,
+        CoffeeCommand::Option { action } => match action {
+            OptionAction::Set {
+                plugin,
+                key,
+                value,
+                secret,
+            } => coffee::options::set(&mut coffee, &plugin, &key, &value, secret).await,
+            OptionAction::Remove { plugin, key } => {
+                coffee::options::remove(&mut coffee, &plugin, &key).await
+            }
+            OptionAction::List { plugin, declared } => {
+                coffee::options::list(&coffee, &plugin, declared).await
+            }
+        },
+        CoffeeCommand::Env { plugin, action } => match action {
+            EnvAction::Set { assignment, secret } => {
+                coffee::plugin_env::set(&mut coffee, &plugin, &assignment, secret).await
+            }
+            EnvAction::Unset { key } => coffee::plugin_env::unset(&mut coffee, &plugin, &key).await,
+            EnvAction::List => coffee::plugin_env::list(&coffee, &plugin).await,
+        },
+        CoffeeCommand::Try { plugin, verbose } => {
+            coffee::trial::try_plugin(&coffee, &plugin, verbose).await
+        }
+        CoffeeCommand::Index { .. } => unreachable!("the index commands run without coffee"),
+        CoffeeCommand::Dev { action } => match action {
+            DevAction::Reload {
+                path,
+                watch,
+                verbose,
+            } => coffee::dev::reload(&coffee, &path, watch, verbose).await,
+            DevAction::Link { path, verbose } => {
+                coffee::dev::link(&mut coffee, &path, verbose).await
+            }
+            DevAction::Test {
+                plugin,
+                attach,
+                verbose,
+            } => coffee::dev::test(&coffee, &plugin, attach, verbose).await,
+        },
+        CoffeeCommand::Profile {
+            action: ProfileAction::List,
+        } => coffee::profile::list(&coffee).await,
     };
 
     if let Err(err) = result {
+        if interrupt::is_interrupted() {
+            eprintln!("{}", tr("interrupted", &[]));
+            coffee.release_state_lock();
+            process::exit(interrupt::INTERRUPTED_CODE);
+        }
         panic!("{err}");
     }
 
@@ -0,0 +1,37 @@
+//! Helper to run the `git` command line, used by the hybrid gitoxide
+//! backend for the operations that gitoxide does not provide
+//! yet, see the gitoxide module.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use coffee_lib::errors::CoffeeError;
+use log::debug;
+
+/// Run `git` with the `args` inside the repository at `path`, the
+/// `input` is written on the standard input of the command.
+pub fn git(path: &str, args: &[&str], input: Option<&str>) -> Result<String, CoffeeError> {
+    debug!("running git {} inside {path}", args.join(" "));
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| CoffeeError::new(1, &format!("git command not available: {err}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes())?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CoffeeError::new(
+            1,
+            &format!("git {}: {}", args.join(" "), stderr.trim()),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,304 @@
+//! Hybrid git backend, based on gitoxide (a Rust implementation of
+//! git) and on the `git` command.
+//!
+//! gitoxide clones, fetches and reads the status of the repositories,
+//! the other operations (reset, diff, apply, checkout of a branch,
+//! lookup of the objects and of the references, export of a tree and
+//! the worktrees) run the `git` command, and they fail when it is not
+//! installed.
+//!
+//! The transfers of gitoxide take a download slot, but they are not
+//! slowed down to the download bandwidth.
+use std::fmt::Display;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use coffee_lib::download;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt::{interrupted_error, is_interrupted, INTERRUPTED};
+use gix::remote::Direction;
+use gix::status::UntrackedFiles;
+use gix::{ObjectId, Repository};
+use log::debug;
+
+use super::cli::git;
+use super::{worktree_name, GitBackend};
+
+pub struct Gitoxide;
+
+fn git_err(err: impl Display) -> CoffeeError {
+    if is_interrupted() {
+        return interrupted_error();
+    }
+    CoffeeError::new(1, &format!("{err}"))
+}
+
+/// fail when the `git` command, needed by the `operation`, is not installed.
+fn require_git(operation: &str) -> Result<(), CoffeeError> {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    let available = AVAILABLE.get_or_init(|| {
+        Command::new("git")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    });
+    if !available {
+        return Err(CoffeeError::new(
+            1,
+            &format!("the gitoxide backend needs the `git` command for {operation}, install git or build coffee with libgit2"),
+        ));
+    }
+    Ok(())
+}
+
+fn open(path: &str) -> Result<Repository, CoffeeError> {
+    gix::open(path).map_err(git_err)
+}
+
+fn oid(commit: &str) -> Result<ObjectId, CoffeeError> {
+    ObjectId::from_hex(commit.as_bytes()).map_err(git_err)
+}
+
+fn clone(url: &str, path: &str) -> Result<Repository, CoffeeError> {
+    let interrupt = &INTERRUPTED;
+    let _slot = download::wait_slot()?;
+    // gitoxide does not create the missing parent directories.
+    std::fs::create_dir_all(path)?;
+    let mut prepare = gix::prepare_clone(url, path).map_err(git_err)?;
+    let (mut checkout, _) = prepare
+        .fetch_then_checkout(gix::progress::Discard, interrupt)
+        .map_err(git_err)?;
+    let (repo, _) = checkout
+        .main_worktree(gix::progress::Discard, interrupt)
+        .map_err(git_err)?;
+    Ok(repo)
+}
+
+impl GitBackend for Gitoxide {
+    fn clone_repo(&self, url: &str, path: &str) -> Result<(), CoffeeError> {
+        let repo = clone(url, path)?;
+        let Some(submodules) = repo.submodules().map_err(git_err)? else {
+            return Ok(());
+        };
+        for (index, sub) in submodules.enumerate() {
+            let (Ok(sub_url), Ok(sub_path)) = (sub.url(), sub.path()) else {
+                continue;
+            };
+            let sub_url = sub_url.to_bstring().to_string();
+            debug!("url {}: {sub_url}", index + 1);
+            let sub_path = format!("{path}/{sub_path}");
+            // the checkout leaves an empty directory for the submodule.
+            let _ = std::fs::remove_dir(&sub_path);
+            clone(&sub_url, &sub_path)?;
+            debug!("added {sub_url} at path {sub_path}");
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, path: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let remote = repo.find_remote("origin").map_err(git_err)?;
+        let _slot = download::wait_slot()?;
+        remote
+            .connect(Direction::Fetch)
+            .map_err(git_err)?
+            .prepare_fetch(gix::progress::Discard, Default::default())
+            .map_err(git_err)?
+            .receive(gix::progress::Discard, &INTERRUPTED)
+            .map_err(git_err)?;
+        Ok(())
+    }
+
+    fn head(&self, path: &str) -> Result<String, CoffeeError> {
+        let repo = open(path)?;
+        repo.head_id()
+            .map(|head| head.to_string())
+            .map_err(|_| CoffeeError::new(1, "repository without a HEAD"))
+    }
+
+    fn upstream(&self, path: &str) -> Result<Option<String>, CoffeeError> {
+        let repo = open(path)?;
+        let Some(branch) = repo.head_name().map_err(git_err)? else {
+            return Ok(None);
+        };
+        let Some(tracking) =
+            repo.branch_remote_tracking_ref_name(branch.as_ref(), Direction::Fetch)
+        else {
+            return Ok(None);
+        };
+        let tracking = tracking.map_err(git_err)?;
+        let upstream = repo
+            .try_find_reference(tracking.as_ref())
+            .map_err(git_err)?
+            .and_then(|mut reference| reference.peel_to_id_in_place().ok())
+            .map(|upstream| upstream.to_string());
+        Ok(upstream)
+    }
+
+    fn remote_contains(&self, path: &str, commit: &str) -> Result<bool, CoffeeError> {
+        let repo = open(path)?;
+        let commit = oid(commit)?;
+        let references = repo.references().map_err(git_err)?;
+        let contains = references
+            .remote_branches()
+            .map_err(git_err)?
+            .filter_map(|reference| reference.ok())
+            .filter_map(|mut reference| reference.peel_to_id_in_place().ok())
+            .any(|target| {
+                target == commit
+                    || repo
+                        .merge_base(commit, target)
+                        .map(|base| base == commit)
+                        .unwrap_or_default()
+            });
+        Ok(contains)
+    }
+
+    fn local_changes(&self, path: &str) -> Result<Vec<String>, CoffeeError> {
+        let repo = open(path)?;
+        let changes = repo
+            .status(gix::progress::Discard)
+            .map_err(git_err)?
+            .untracked_files(UntrackedFiles::None)
+            .into_iter(None)
+            .map_err(git_err)?
+            .filter_map(|item| item.ok())
+            .map(|item| item.location().to_string())
+            .collect();
+        Ok(changes)
+    }
+
+    fn reset_hard(&self, path: &str, commit: &str) -> Result<(), CoffeeError> {
+        require_git("resetting a repository")?;
+        git(path, &["reset", "--hard", commit], None)?;
+        Ok(())
+    }
+
+    fn diff(&self, path: &str) -> Result<String, CoffeeError> {
+        require_git("the diff of a repository")?;
+        git(path, &["diff", "HEAD"], None)
+    }
+
+    fn apply(&self, path: &str, patch: &str) -> Result<(), CoffeeError> {
+        require_git("applying the patches")?;
+        git(path, &["apply", "-"], Some(patch))?;
+        Ok(())
+    }
+
+    fn is_applied(&self, path: &str, patch: &str) -> Result<bool, CoffeeError> {
+        require_git("checking the patches")?;
+        Ok(git(path, &["apply", "--check", "--reverse", "-"], Some(patch)).is_ok())
+    }
+
+    fn checkout_branch(&self, path: &str, branch: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let current = repo
+            .head_name()
+            .map_err(git_err)?
+            .map(|name| name.shorten().to_string());
+        if current.as_deref() == Some(branch) {
+            return Ok(());
+        }
+        require_git("the checkout of a branch")?;
+        let upstream = format!("origin/{branch}");
+        if git(path, &["rev-parse", "--verify", "--quiet", &upstream], None).is_err() {
+            return Err(CoffeeError::new(1, &format!("branch `{branch}` not found")));
+        }
+        git(
+            path,
+            &["checkout", "--force", "-B", branch, "--track", &upstream],
+            None,
+        )?;
+        Ok(())
+    }
+
+    fn object_id(
+        &self,
+        path: &str,
+        commit: &str,
+        subpath: &str,
+    ) -> Result<Option<String>, CoffeeError> {
+        require_git("the lookup of an object")?;
+        let object = if subpath.is_empty() {
+            format!("{commit}^{{tree}}")
+        } else {
+            format!("{commit}:{subpath}")
+        };
+        // a missing object is not an error.
+        Ok(
+            git(path, &["rev-parse", "--verify", "--quiet", &object], None)
+                .ok()
+                .map(|id| id.trim().to_owned()),
+        )
+    }
+
+    fn changed_files(&self, path: &str, from: &str, to: &str) -> Result<Vec<String>, CoffeeError> {
+        require_git("the changed files")?;
+        let files = git(
+            path,
+            &["diff", "--name-only", "--no-renames", from, to],
+            None,
+        )?;
+        Ok(files.lines().map(str::to_owned).collect())
+    }
+
+    fn resolve(&self, path: &str, reference: &str) -> Result<String, CoffeeError> {
+        require_git("the lookup of a reference")?;
+        let commit = |reference: &str| {
+            git(
+                path,
+                &["rev-parse", "--verify", &format!("{reference}^{{commit}}")],
+                None,
+            )
+        };
+        commit(reference)
+            .or_else(|_| commit(&format!("origin/{reference}")))
+            .map(|commit| commit.trim().to_owned())
+            .map_err(|_| CoffeeError::new(1, &format!("reference `{reference}` not found")))
+    }
+
+    fn export_tree(&self, path: &str, commit: &str, dest: &str) -> Result<(), CoffeeError> {
+        require_git("the export of a tree")?;
+        std::fs::create_dir_all(dest)?;
+        let archive = format!("{dest}.tar");
+        git(
+            path,
+            &["archive", "--format=tar", "-o", &archive, commit],
+            None,
+        )?;
+        let extracted = Command::new("tar")
+            .args(["-xf", &archive, "-C", dest])
+            .status();
+        let _ = std::fs::remove_file(&archive);
+        match extracted {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(CoffeeError::new(1, &format!("tar exited with {status}"))),
+            Err(err) => Err(CoffeeError::new(1, &format!("tar not started: {err}"))),
+        }
+    }
+
+    fn add_worktree(&self, path: &str, dest: &str, commit: &str) -> Result<(), CoffeeError> {
+        require_git("the worktrees")?;
+        let branch = format!("coffee-{}", worktree_name(dest));
+        git(
+            path,
+            &["worktree", "add", "-B", &branch, dest, commit],
+            None,
+        )?;
+        Ok(())
+    }
+
+    fn remove_worktree(&self, path: &str, dest: &str) -> Result<(), CoffeeError> {
+        require_git("the worktrees")?;
+        let branch = format!("coffee-{}", worktree_name(dest));
+        if std::path::Path::new(dest).exists() {
+            git(path, &["worktree", "remove", "--force", dest], None)?;
+        }
+        git(path, &["worktree", "prune"], None)?;
+        // the branch is missing when the worktree was never added.
+        let _ = git(path, &["branch", "-D", &branch], None);
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+//! Git backend based on libgit2.
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+use coffee_lib::download;
+use coffee_lib::errors::CoffeeError;
+use coffee_lib::interrupt::{interrupted_error, is_interrupted};
+use git2::build::RepoBuilder;
+use git2::{
+    ApplyLocation, ApplyOptions, BranchType, Diff, DiffFormat, FetchOptions, ObjectType, Oid,
+    RemoteCallbacks, Repository, ResetType, StatusOptions, TreeEntry, TreeWalkMode, TreeWalkResult,
+    WorktreeAddOptions, WorktreePruneOptions,
+};
+use log::debug;
+
+use super::{reverse_patch, worktree_name, GitBackend};
+
+pub struct LibGit2;
+
+fn git_err(err: git2::Error) -> CoffeeError {
+    if is_interrupted() {
+        return interrupted_error();
+    }
+    CoffeeError::new(1, err.message())
+}
+
+/// options of the transfers, that stop when the user interrupts coffee
+/// and that stay within the download bandwidth.
+fn fetch_options<'a>() -> FetchOptions<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut received = 0;
+    callbacks.transfer_progress(move |progress| {
+        download::throttle_blocking(progress.received_bytes().saturating_sub(received) as u64);
+        received = progress.received_bytes();
+        !is_interrupted()
+    });
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
+
+fn clone(url: &str, path: &str) -> Result<Repository, CoffeeError> {
+    let _slot = download::wait_slot()?;
+    RepoBuilder::new()
+        .fetch_options(fetch_options())
+        .clone(url, Path::new(path))
+        .map_err(git_err)
+}
+
+fn open(path: &str) -> Result<Repository, CoffeeError> {
+    Repository::open(path).map_err(git_err)
+}
+
+fn oid(commit: &str) -> Result<Oid, CoffeeError> {
+    Oid::from_str(commit).map_err(git_err)
+}
+
+/// write the `entry` of the tree at `root` inside the `dest` directory.
+fn write_entry(
+    repo: &Repository,
+    dest: &str,
+    root: &str,
+    entry: &TreeEntry,
+) -> Result<(), CoffeeError> {
+    let file = Path::new(dest)
+        .join(root)
+        .join(entry.name().unwrap_or_default());
+    match entry.kind() {
+        Some(ObjectType::Tree) => fs::create_dir_all(&file)?,
+        Some(ObjectType::Blob) => {
+            let blob = entry
+                .to_object(repo)
+                .and_then(|object| object.peel_to_blob())
+                .map_err(git_err)?;
+            match entry.filemode() {
+                // the content of a symbolic link is its target.
+                0o120000 => symlink(String::from_utf8_lossy(blob.content()).as_ref(), &file)?,
+                mode => {
+                    fs::write(&file, blob.content())?;
+                    if mode == 0o100755 {
+                        fs::set_permissions(&file, fs::Permissions::from_mode(0o755))?;
+                    }
+                }
+            }
+        }
+        // the submodules are not part of the tree.
+        _ => {}
+    }
+    Ok(())
+}
+
+impl GitBackend for LibGit2 {
+    fn clone_repo(&self, url: &str, path: &str) -> Result<(), CoffeeError> {
+        let repo = clone(url, path)?;
+        let submodules = repo.submodules().unwrap_or_default();
+        debug!("submodule count: {}", submodules.len());
+        for (index, sub) in submodules.iter().enumerate() {
+            let (Some(sub_url), Some(sub_path)) = (sub.url(), sub.path().to_str()) else {
+                continue;
+            };
+            debug!("url {}: {sub_url}", index + 1);
+            let sub_path = format!("{path}/{sub_path}");
+            clone(sub_url, &sub_path)?;
+            debug!("added {sub_url} at path {sub_path}");
+        }
+        Ok(())
+    }
+
+    fn fetch(&self, path: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let mut remote = repo.find_remote("origin").map_err(git_err)?;
+        let _slot = download::wait_slot()?;
+        remote
+            .fetch(&[] as &[&str], Some(&mut fetch_options()), None)
+            .map_err(git_err)
+    }
+
+    fn head(&self, path: &str) -> Result<String, CoffeeError> {
+        let repo = open(path)?;
+        repo.head()
+            .ok()
+            .and_then(|head| head.target())
+            .map(|head| head.to_string())
+            .ok_or_else(|| CoffeeError::new(1, "repository without a HEAD"))
+    }
+
+    fn upstream(&self, path: &str) -> Result<Option<String>, CoffeeError> {
+        let repo = open(path)?;
+        let upstream = repo
+            .head()
+            .ok()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand().map(str::to_owned))
+            .and_then(|branch| repo.find_branch(&branch, BranchType::Local).ok())
+            .and_then(|branch| branch.upstream().ok())
+            .and_then(|upstream| upstream.get().target())
+            .map(|upstream| upstream.to_string());
+        Ok(upstream)
+    }
+
+    fn remote_contains(&self, path: &str, commit: &str) -> Result<bool, CoffeeError> {
+        let repo = open(path)?;
+        let commit = oid(commit)?;
+        let branches = repo.branches(Some(BranchType::Remote)).map_err(git_err)?;
+        let contains = branches
+            .filter_map(|branch| branch.ok())
+            .filter_map(|(branch, _)| branch.get().target())
+            .any(|target| {
+                target == commit || repo.graph_descendant_of(target, commit).unwrap_or_default()
+            });
+        Ok(contains)
+    }
+
+    fn local_changes(&self, path: &str) -> Result<Vec<String>, CoffeeError> {
+        let repo = open(path)?;
+        let mut options = StatusOptions::new();
+        options.include_untracked(false);
+        let statuses = repo.statuses(Some(&mut options)).map_err(git_err)?;
+        let changes = statuses
+            .iter()
+            .filter(|status| !status.status().is_ignored())
+            .filter_map(|status| status.path().map(str::to_owned))
+            .collect();
+        Ok(changes)
+    }
+
+    fn reset_hard(&self, path: &str, commit: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let commit = repo.find_object(oid(commit)?, None).map_err(git_err)?;
+        repo.reset(&commit, ResetType::Hard, None).map_err(git_err)
+    }
+
+    fn diff(&self, path: &str) -> Result<String, CoffeeError> {
+        let repo = open(path)?;
+        let head = repo
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .map_err(git_err)?;
+        let diff = repo
+            .diff_tree_to_workdir_with_index(Some(&head), None)
+            .map_err(git_err)?;
+        let mut patch = String::new();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                patch.push(line.origin());
+            }
+            patch.push_str(&String::from_utf8_lossy(line.content()));
+            true
+        })
+        .map_err(git_err)?;
+        Ok(patch)
+    }
+
+    fn apply(&self, path: &str, patch: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let diff = Diff::from_buffer(patch.as_bytes()).map_err(git_err)?;
+        repo.apply(&diff, ApplyLocation::WorkDir, None)
+            .map_err(git_err)
+    }
+
+    fn is_applied(&self, path: &str, patch: &str) -> Result<bool, CoffeeError> {
+        let repo = open(path)?;
+        let diff = Diff::from_buffer(reverse_patch(patch).as_bytes()).map_err(git_err)?;
+        let mut options = ApplyOptions::new();
+        options.check(true);
+        Ok(repo
+            .apply(&diff, ApplyLocation::WorkDir, Some(&mut options))
+            .is_ok())
+    }
+
+    fn checkout_branch(&self, path: &str, branch: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let current = repo
+            .head()
+            .ok()
+            .and_then(|head| head.shorthand().map(str::to_owned));
+        if current.as_deref() == Some(branch) {
+            return Ok(());
+        }
+        let upstream_name = format!("origin/{branch}");
+        let upstream = repo
+            .find_branch(&upstream_name, BranchType::Remote)
+            .map_err(|_| CoffeeError::new(1, &format!("branch `{branch}` not found")))?;
+        let commit = upstream.get().peel_to_commit().map_err(git_err)?;
+        let mut local = repo.branch(branch, &commit, true).map_err(git_err)?;
+        local.set_upstream(Some(&upstream_name)).map_err(git_err)?;
+        repo.set_head(&format!("refs/heads/{branch}"))
+            .map_err(git_err)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .map_err(git_err)
+    }
+
+    fn object_id(
+        &self,
+        path: &str,
+        commit: &str,
+        subpath: &str,
+    ) -> Result<Option<String>, CoffeeError> {
+        let repo = open(path)?;
+        let tree = repo
+            .find_commit(oid(commit)?)
+            .and_then(|commit| commit.tree())
+            .map_err(git_err)?;
+        if subpath.is_empty() {
+            return Ok(Some(tree.id().to_string()));
+        }
+        Ok(tree
+            .get_path(Path::new(subpath))
+            .ok()
+            .map(|entry| entry.id().to_string()))
+    }
+
+    fn changed_files(&self, path: &str, from: &str, to: &str) -> Result<Vec<String>, CoffeeError> {
+        let repo = open(path)?;
+        let tree = |commit: &str| {
+            repo.find_commit(oid(commit)?)
+                .and_then(|commit| commit.tree())
+                .map_err(git_err)
+        };
+        let diff = repo
+            .diff_tree_to_tree(Some(&tree(from)?), Some(&tree(to)?), None)
+            .map_err(git_err)?;
+        // a renamed file changes both the old and the new directory.
+        let files = diff
+            .deltas()
+            .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+            .flatten()
+            .map(|file| file.to_string_lossy().to_string())
+            .collect();
+        Ok(files)
+    }
+
+    fn resolve(&self, path: &str, reference: &str) -> Result<String, CoffeeError> {
+        let repo = open(path)?;
+        let commit = repo
+            .revparse_single(reference)
+            .or_else(|_| repo.revparse_single(&format!("origin/{reference}")))
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| CoffeeError::new(1, &format!("reference `{reference}` not found")))?;
+        Ok(commit.id().to_string())
+    }
+
+    fn export_tree(&self, path: &str, commit: &str, dest: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let tree = repo
+            .find_commit(oid(commit)?)
+            .and_then(|commit| commit.tree())
+            .map_err(git_err)?;
+        fs::create_dir_all(dest)?;
+        let mut written = Ok(());
+        let walked = tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            match write_entry(&repo, dest, root, entry) {
+                Ok(()) => TreeWalkResult::Ok,
+                Err(err) => {
+                    written = Err(err);
+                    TreeWalkResult::Abort
+                }
+            }
+        });
+        written?;
+        walked.map_err(git_err)
+    }
+
+    fn add_worktree(&self, path: &str, dest: &str, commit: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let name = worktree_name(dest);
+        let commit = repo.find_commit(oid(commit)?).map_err(git_err)?;
+        let branch = repo
+            .branch(&format!("coffee-{name}"), &commit, true)
+            .map_err(git_err)?;
+        let mut options = WorktreeAddOptions::new();
+        options.reference(Some(branch.get()));
+        repo.worktree(&name, Path::new(dest), Some(&options))
+            .map_err(git_err)?;
+        Ok(())
+    }
+
+    fn remove_worktree(&self, path: &str, dest: &str) -> Result<(), CoffeeError> {
+        let repo = open(path)?;
+        let name = worktree_name(dest);
+        if let Ok(worktree) = repo.find_worktree(&name) {
+            let mut options = WorktreePruneOptions::new();
+            options.valid(true).locked(true).working_tree(true);
+            worktree.prune(Some(&mut options)).map_err(git_err)?;
+        }
+        if let Ok(mut branch) = repo.find_branch(&format!("coffee-{name}"), BranchType::Local) {
+            branch.delete().map_err(git_err)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Git backends used by the repositories, the backend is chosen
+//! at build time with the cargo features:
+//!
+//! - `libgit2` (default): the bindings to libgit2.
+//! - `gitoxide`: a hybrid backend, so coffee can be built without
+//!   libgit2 and OpenSSL. gitoxide (a Rust implementation of git) only
+//!   clones, fetches and reads the repositories, the other operations
+//!   run the `git` command and fail when it is not installed.
+//!
+//! When both are enabled, `gitoxide` is used.
+use coffee_lib::errors::CoffeeError;
+
+#[cfg(feature = "gitoxide")]
+mod cli;
+#[cfg(feature = "gitoxide")]
+mod gitoxide;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+
+#[cfg(not(any(feature = "libgit2", feature = "gitoxide")))]
+compile_error!("one of the `libgit2` or `gitoxide` features must be enabled");
+
+/// Operations of git needed by coffee, all the paths are the
+/// root of the working tree of a repository.
+pub trait GitBackend: Send + Sync {
+    /// clone the repository at `url` inside `path`, with its submodules.
+    fn clone_repo(&self, url: &str, path: &str) -> Result<(), CoffeeError>;
+
+    /// fetch the `origin` remote.
+    fn fetch(&self, path: &str) -> Result<(), CoffeeError>;
+
+    /// commit checked out.
+    fn head(&self, path: &str) -> Result<String, CoffeeError>;
+
+    /// commit of the upstream branch of the branch checked out, if any.
+    fn upstream(&self, path: &str) -> Result<Option<String>, CoffeeError>;
+
+    /// true when the `commit` is reachable from a remote branch.
+    fn remote_contains(&self, path: &str, commit: &str) -> Result<bool, CoffeeError>;
+
+    /// files of the working tree that differ from the commit checked out.
+    fn local_changes(&self, path: &str) -> Result<Vec<String>, CoffeeError>;
+
+    /// move the branch checked out, the index and the working tree
+    /// to the `commit`, the local modifications are lost.
+    fn reset_hard(&self, path: &str, commit: &str) -> Result<(), CoffeeError>;
+
+    /// local modifications as a patch.
+    fn diff(&self, path: &str) -> Result<String, CoffeeError>;
+
+    /// apply the `patch` on the working tree.
+    fn apply(&self, path: &str, patch: &str) -> Result<(), CoffeeError>;
+
+    /// true when the `patch` is already applied on the working tree,
+    /// so its reverse applies.
+    fn is_applied(&self, path: &str, patch: &str) -> Result<bool, CoffeeError>;
+
+    /// check out the `branch` of the `origin` remote, tracking it.
+    fn checkout_branch(&self, path: &str, branch: &str) -> Result<(), CoffeeError>;
+
+    /// id of the object (tree or blob) at the `subpath` of the
+    /// `commit`, the root tree when `subpath` is empty.
+    fn object_id(
+        &self,
+        path: &str,
+        commit: &str,
+        subpath: &str,
+    ) -> Result<Option<String>, CoffeeError>;
+
+    /// files changed between the commits `from` and `to`.
+    fn changed_files(&self, path: &str, from: &str, to: &str) -> Result<Vec<String>, CoffeeError>;
+
+    /// commit of the `reference`, a tag, a branch (also of the
+    /// `origin` remote) or a commit.
+    fn resolve(&self, path: &str, reference: &str) -> Result<String, CoffeeError>;
+
+    /// write the files of the `commit` inside the `dest` directory,
+    /// the working tree and the index are not touched.
+    fn export_tree(&self, path: &str, commit: &str, dest: &str) -> Result<(), CoffeeError>;
+
+    /// check out the `commit` inside a new worktree of the repository
+    /// at `dest`, on its own branch, so the branch of the checkout
+    /// does not move.
+    fn add_worktree(&self, path: &str, dest: &str, commit: &str) -> Result<(), CoffeeError>;
+
+    /// remove the worktree of the repository at `dest`, with its branch.
+    fn remove_worktree(&self, path: &str, dest: &str) -> Result<(), CoffeeError>;
+}
+
+/// Directory of the worktrees of the installed plugins inside the
+/// checkout of a repository, one `<plugin>/<commit>` for each of them.
+pub const WORKTREES_DIR: &str = ".worktrees";
+
+/// Name of the worktree at `dest` (`<plugin>-<commit>`), and of its branch.
+fn worktree_name(dest: &str) -> String {
+    let dest = std::path::Path::new(dest);
+    let name = |path: Option<&std::path::Path>| {
+        path.and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    format!("{}-{}", name(dest.parent()), name(Some(dest)))
+}
+
+/// length of the range `start,len` of a hunk header, 1 without it.
+#[cfg(any(feature = "libgit2", test))]
+fn range_len(range: &str) -> usize {
+    range
+        .split_once(',')
+        .map(|(_, len)| len.parse().unwrap_or_default())
+        .unwrap_or(1)
+}
+
+/// The `patch` that undoes the unified diff `patch`.
+#[cfg(any(feature = "libgit2", test))]
+fn reverse_patch(patch: &str) -> String {
+    let mut reversed = vec![];
+    let mut lines = patch.lines().peekable();
+    // lines of the old and of the new file left inside the hunk.
+    let (mut old, mut new) = (0, 0);
+    while let Some(line) = lines.next() {
+        if old > 0 || new > 0 {
+            let (sign, rest) = line.split_at(line.len().min(1));
+            match sign {
+                "-" => {
+                    old -= 1;
+                    reversed.push(format!("+{rest}"));
+                }
+                "+" => {
+                    new -= 1;
+                    reversed.push(format!("-{rest}"));
+                }
+                "\\" => reversed.push(line.to_owned()),
+                _ => {
+                    old -= 1;
+                    new -= 1;
+                    reversed.push(line.to_owned());
+                }
+            }
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("@@ -") {
+            let (ranges, rest) = header.split_once(" @@").unwrap_or((header, ""));
+            let (from, to) = ranges.split_once(" +").unwrap_or((ranges, ""));
+            (old, new) = (range_len(from), range_len(to));
+            reversed.push(format!("@@ -{to} +{from} @@{rest}"));
+        } else if let Some(from) = line.strip_prefix("--- ") {
+            let to = lines
+                .next_if(|line| line.starts_with("+++ "))
+                .map(|line| line[4..].to_owned())
+                .unwrap_or_default();
+            reversed.push(format!("--- {}", swap_prefix(&to, "b/", "a/")));
+            reversed.push(format!("+++ {}", swap_prefix(from, "a/", "b/")));
+        } else if let Some(files) = line.strip_prefix("diff --git ") {
+            let (from, to) = files.split_once(" b/").unwrap_or((files, ""));
+            let from = from.strip_prefix("a/").unwrap_or(from);
+            reversed.push(format!("diff --git a/{to} b/{from}"));
+        } else if let Some(mode) = line.strip_prefix("new file mode ") {
+            reversed.push(format!("deleted file mode {mode}"));
+        } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+            reversed.push(format!("new file mode {mode}"));
+        } else if let Some(index) = line.strip_prefix("index ") {
+            let (ids, mode) = index.split_once(' ').unwrap_or((index, ""));
+            let (from, to) = ids.split_once("..").unwrap_or((ids, ""));
+            let mode = if mode.is_empty() {
+                String::new()
+            } else {
+                format!(" {mode}")
+            };
+            reversed.push(format!("index {to}..{from}{mode}"));
+        } else {
+            reversed.push(line.to_owned());
+        }
+    }
+    let mut reversed = reversed.join("\n");
+    reversed.push('\n');
+    reversed
+}
+
+/// the file `path` of a `---` or `+++` line with the `from` prefix
+/// replaced by `to`, `/dev/null` is kept.
+#[cfg(any(feature = "libgit2", test))]
+fn swap_prefix(path: &str, from: &str, to: &str) -> String {
+    match path.strip_prefix(from) {
+        Some(path) => format!("{to}{path}"),
+        None => path.to_owned(),
+    }
+}
+
+/// Backend chosen at build time.
+pub fn backend() -> &'static dyn GitBackend {
+    #[cfg(feature = "gitoxide")]
+    return &gitoxide::Gitoxide;
+    #[cfg(all(feature = "libgit2", not(feature = "gitoxide")))]
+    return &libgit2::LibGit2;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reverse_patch;
+
+    #[test]
+    fn test_git_reverse_patch() {
+        let patch = "diff --git a/main.py b/main.py
+index 1111111..2222222 100644
+--- a/main.py
++++ b/main.py
+@@ -1,3 +1,3 @@ def main():
+ import os
+--- removed comment
++print(os.name)
+ exit(0)
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+";
+        let reversed = "diff --git a/main.py b/main.py
+index 2222222..1111111 100644
+--- a/main.py
++++ b/main.py
+@@ -1,3 +1,3 @@ def main():
+ import os
++-- removed comment
+-print(os.name)
+ exit(0)
+diff --git a/new.txt b/new.txt
+deleted file mode 100644
+index 3333333..0000000
+--- a/new.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-hello
+";
+        assert_eq!(reverse_patch(patch), reversed);
+        assert_eq!(reverse_patch(reversed), patch);
+    }
+}
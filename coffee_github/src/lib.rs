@@ -1,17 +1,23 @@
 //! Github repository implementation
 
+pub mod git;
 pub mod repository;
+// the submodules are cloned by the git backends now.
+#[cfg(feature = "libgit2")]
+#[allow(dead_code)]
 mod utils;
 
 #[cfg(test)]
 mod tests {
-    use std::{path::Path, sync::Once};
+    use std::{env, path::Path, sync::Once};
 
+    use coffee_lib::plugin::PluginLang;
     use coffee_lib::repository::Repository;
     use coffee_lib::url::URL;
-    use std::fs::remove_dir_all;
+    use std::fs::{create_dir_all, remove_dir_all, set_permissions, write, Permissions};
+    use std::os::unix::fs::PermissionsExt;
 
-    use crate::repository::Github;
+    use crate::repository::{plugin_from_dir, plugin_from_script, Github};
 
     static INIT: Once = Once::new();
 
@@ -37,4 +43,62 @@ mod tests {
         assert_eq!(Path::new(&url.path_string).exists(), true);
         remove_dir_all(&url.path_string).unwrap();
     }
+
+    #[tokio::test]
+    async fn plugin_language_from_sources() {
+        let path = format!("{}/coffee-lang-sources", env::temp_dir().to_string_lossy());
+        create_dir_all(format!("{path}/rusty/src")).unwrap();
+        create_dir_all(format!("{path}/mixed")).unwrap();
+        write(format!("{path}/rusty/Cargo.toml"), "").unwrap();
+        write(format!("{path}/rusty/src/main.rs"), "").unwrap();
+        write(format!("{path}/mixed/feeadjuster.py"), "").unwrap();
+        write(format!("{path}/mixed/utils.py"), "").unwrap();
+        write(format!("{path}/mixed/helper.js"), "").unwrap();
+
+        let rusty = plugin_from_dir(Path::new(&format!("{path}/rusty")))
+            .await
+            .unwrap();
+        assert_eq!(rusty.lang(), PluginLang::Rust);
+        let mixed = plugin_from_dir(Path::new(&format!("{path}/mixed")))
+            .await
+            .unwrap();
+        assert_eq!(mixed.lang(), PluginLang::Python);
+        remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn plugin_language_from_makefile() {
+        let path = format!("{}/coffee-lang-makefile", env::temp_dir().to_string_lossy());
+        create_dir_all(format!("{path}/sauron")).unwrap();
+        create_dir_all(format!("{path}/pymake")).unwrap();
+        write(format!("{path}/sauron/Makefile"), "").unwrap();
+        write(format!("{path}/sauron/sauron.c"), "").unwrap();
+        write(format!("{path}/pymake/Makefile"), "").unwrap();
+        write(format!("{path}/pymake/pymake.py"), "").unwrap();
+
+        let sauron = plugin_from_dir(Path::new(&format!("{path}/sauron")))
+            .await
+            .unwrap();
+        assert_eq!(sauron.lang(), PluginLang::C);
+        let pymake = plugin_from_dir(Path::new(&format!("{path}/pymake")))
+            .await
+            .unwrap();
+        assert_eq!(pymake.lang(), PluginLang::Python);
+        remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn plugin_from_shell_script() {
+        let path = format!("{}/coffee-shell-script", env::temp_dir().to_string_lossy());
+        create_dir_all(&path).unwrap();
+        write(format!("{path}/hello.sh"), "#!/bin/bash\necho hello\n").unwrap();
+        write(format!("{path}/ci.sh"), "#!/bin/sh\nmake check\n").unwrap();
+        set_permissions(format!("{path}/hello.sh"), Permissions::from_mode(0o755)).unwrap();
+
+        let hello = plugin_from_script(Path::new(&format!("{path}/hello.sh"))).unwrap();
+        assert_eq!(hello.lang(), PluginLang::Shell);
+        assert_eq!(hello.name(), "hello");
+        assert!(plugin_from_script(Path::new(&format!("{path}/ci.sh"))).is_none());
+        remove_dir_all(&path).unwrap();
+    }
 }
@@ -1,19 +1,22 @@
 use std::any::Any;
+use std::collections::BTreeSet;
 
-use crate::utils::clone_recursive_fix;
+use crate::git;
 use async_trait::async_trait;
 use coffee_lib::errors::CoffeeError;
+use coffee_lib::names;
 use coffee_lib::plugin::Plugin;
 use coffee_lib::plugin::PluginLang;
 use coffee_lib::plugin_conf::Conf;
-use coffee_lib::repository::Repository;
+use coffee_lib::repository::{Layout, Repository};
 use coffee_lib::url::URL;
-use coffee_lib::utils::get_plugin_info_from_path;
+use coffee_lib::utils::{get_shebang_interpreter, unix_time};
 use coffee_storage::model::repository::Kind;
 use coffee_storage::model::repository::Repository as StorageRepository;
-use git2;
 use log::debug;
-use tokio::fs::File;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::fs::{remove_dir_all, File};
 use tokio::io::AsyncReadExt;
 use walkdir::DirEntry;
 use walkdir::WalkDir;
@@ -28,10 +31,14 @@ pub struct Github {
     /// all the plugin that are listed inside the
     /// repository
     plugins: Vec<Plugin>,
+    /// unix time of the last update of the repository.
+    last_update: Option<u64>,
+    /// organization of the plugins inside the repository.
+    layout: Layout,
 }
 
 // FIXME: move this inside a utils dir craters
-fn is_hidden(entry: &DirEntry) -> bool {
+pub fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
         .to_str()
@@ -48,106 +55,69 @@ impl Github {
             name: name.to_owned(),
             url: url.clone(),
             plugins: vec![],
+            last_update: None,
+            layout: Layout::default(),
         }
     }
 
+    /// Index the repository with the `layout` instead of the flat one.
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Check out the `branch` of the upstream, and index again
+    /// the plugins of the repository.
+    pub async fn checkout_branch(&mut self, branch: &str) -> Result<(), CoffeeError> {
+        debug!("checking out {branch} in repository {}", self.name);
+        git::backend()
+            .checkout_branch(&self.url.path_string, branch)
+            .map_err(|err| {
+                CoffeeError::new(
+                    1,
+                    &format!("branch `{branch}` of `{}`: {err}", self.url.url_string),
+                )
+            })?;
+        self.plugins.clear();
+        self.index_repository().await
+    }
+
     /// Index the repository to store information
     /// related to the plugins
     pub async fn index_repository(&mut self) -> Result<(), CoffeeError> {
-        let repo_path = &self.url.path_string;
-        let target_dirs = WalkDir::new(repo_path)
-            .max_depth(1)
+        let repo_path = self.url.path_string.clone();
+        let layout = self.layout;
+        let target_dirs = WalkDir::new(&repo_path)
+            .max_depth(layout.depth())
             .into_iter()
-            .filter_entry(|dir_entry| !is_hidden(dir_entry));
+            .filter_entry(|dir_entry| {
+                let subpath = dir_entry
+                    .path()
+                    .strip_prefix(&repo_path)
+                    .unwrap_or(dir_entry.path())
+                    .to_string_lossy();
+                !is_hidden(dir_entry) && !layout.is_excluded(&subpath)
+            });
 
         for plugin_dir in target_dirs {
             match plugin_dir {
                 Ok(plugin_path) => {
-                    let root_path = plugin_path
-                        .path()
-                        .as_os_str()
-                        .to_os_string()
-                        .to_string_lossy()
-                        .to_string();
-                    let mut path_to_plugin = None;
-                    let mut plugin_name = None;
-                    let mut plugin_lang = PluginLang::Unknown;
-
-                    // check if the plugin has the custom configuration to read.
-                    let mut conf = None;
-                    for file in ["coffee.yaml", "coffee.yml"] {
-                        let conf_path = format!("{}/{}", root_path, file);
-                        if let Ok(mut conf_file) = File::open(conf_path).await {
-                            let mut conf_str = String::new();
-                            conf_file.read_to_string(&mut conf_str).await?;
-                            debug!("found plugin configuration: {}", conf_str);
-
-                            let conf_file =
-                                serde_yaml::from_str::<Conf>(&conf_str).map_err(|err| {
-                                    CoffeeError::new(1, &format!("Coffe manifest malformed: {err}"))
-                                })?;
-                            plugin_name = Some(conf_file.plugin.name.to_string());
-                            path_to_plugin = Some(root_path.to_owned());
-                            let conf_lang = (&conf_file.plugin.lang).to_owned();
-                            match conf_lang.as_str() {
-                                "py" => plugin_lang = PluginLang::Python,
-                                "go" => plugin_lang = PluginLang::Go,
-                                "rs" => plugin_lang = PluginLang::Rust,
-                                "dart" => plugin_lang = PluginLang::Dart,
-                                "js" => plugin_lang = PluginLang::JavaScript,
-                                "ts" => plugin_lang = PluginLang::TypeScript,
-                                "java" | "kotlin" | "scala" => plugin_lang = PluginLang::JVM,
-                                _ => {
-                                    return Err(CoffeeError::new(
-                                        1,
-                                        &format!("language {conf_lang} not supported"),
-                                    ))
-                                }
-                            };
-
-                            conf = Some(conf_file);
-                            break;
+                    if plugin_path.file_type().is_file() {
+                        // a single script at the root of the repository
+                        // is a plugin too, other files are ignored.
+                        if let Some(mut plugin) = plugin_from_script(plugin_path.path()) {
+                            plugin.set_content_hash(self.content_hash(plugin_path.path()));
+                            debug!("new standalone plugin: {:?}", plugin);
+                            self.plugins.push(plugin);
                         }
+                        continue;
                     }
-
-                    // check if there was a coffee configuration file
-                    if conf == None {
-                        debug!("conf file not found, so we try to guess the language");
-                        // try to understand the language from the file
-                        let files = WalkDir::new(plugin_path.path()).max_depth(1);
-                        for file in files {
-                            let file_dir = file.unwrap().clone();
-                            let (tmp_path_to_plugin, tmp_plugin_name) =
-                                get_plugin_info_from_path(file_dir.path()).unwrap();
-                            path_to_plugin = Some(tmp_path_to_plugin.to_string());
-                            plugin_name = Some(tmp_plugin_name.to_string());
-                            debug!("looking for {tmp_plugin_name} in {tmp_path_to_plugin}");
-                            let file_name = file_dir.file_name().to_str().unwrap();
-                            plugin_lang = match file_name {
-                                "requirements.txt" => PluginLang::Python,
-                                "go.mod" => PluginLang::Go,
-                                "cargo.toml" => PluginLang::Rust,
-                                "pubspec.yaml" => PluginLang::Dart,
-                                "package.json" => PluginLang::JavaScript,
-                                "tsconfig.json" => PluginLang::TypeScript,
-                                _ => PluginLang::Unknown,
-                            };
-                            if plugin_lang != PluginLang::Unknown {
-                                break;
-                            }
-                        }
-                        debug!("possible plugin language: {:?}", plugin_lang);
+                    // the directories of the languages only group the plugins.
+                    if plugin_path.depth() > 0 && plugin_path.depth() < layout.depth() {
+                        continue;
                     }
-
-                    // The language is already contained inside the configuration file.
-                    let plugin = Plugin::new(
-                        plugin_name.unwrap().as_str(),
-                        &root_path,
-                        path_to_plugin.unwrap().as_str(),
-                        plugin_lang,
-                        conf.clone(),
-                    );
-
+                    let mut plugin = plugin_from_dir(plugin_path.path()).await?;
+                    plugin.set_content_hash(self.content_hash(plugin_path.path()));
                     debug!("new plugin: {:?}", plugin);
                     self.plugins.push(plugin);
                 }
@@ -156,6 +126,248 @@ impl Github {
         }
         Ok(())
     }
+
+    /// Index again only the plugins of the directories changed
+    /// between the commits `from` and `to`, a change at the root
+    /// of the repository index again the whole repository.
+    async fn index_changes(&mut self, from: &str, to: &str) -> Result<(), CoffeeError> {
+        if from == to {
+            return Ok(());
+        }
+        let repo_path = self.url.path_string.clone();
+        let changed = match git::backend().changed_files(&repo_path, from, to) {
+            Ok(changed) => changed,
+            Err(err) => {
+                debug!("changes of {} not available: {err}", self.name);
+                self.plugins.clear();
+                return self.index_repository().await;
+            }
+        };
+        let depth = self.layout.depth();
+        let mut dirs = BTreeSet::new();
+        for file in &changed {
+            let components: Vec<&str> = file.split('/').collect();
+            if components.len() <= depth {
+                // the root can be a plugin, and the root and the
+                // directories of the languages can contain the scripts.
+                self.plugins.clear();
+                return self.index_repository().await;
+            }
+            dirs.insert(components[..depth].join("/"));
+        }
+        debug!("indexing again {:?} of {}", dirs, self.name);
+        for dir in dirs {
+            let plugin_path = Path::new(&repo_path).join(&dir);
+            let root_path = plugin_path.to_string_lossy().to_string();
+            self.plugins
+                .retain(|plugin| plugin.is_standalone() || plugin.root_path() != root_path);
+            let hidden = dir.split('/').any(|component| component.starts_with('.'));
+            if hidden || self.layout.is_excluded(&dir) || !plugin_path.is_dir() {
+                continue;
+            }
+            let mut plugin = plugin_from_dir(&plugin_path).await?;
+            plugin.set_content_hash(self.content_hash(&plugin_path));
+            debug!("new plugin: {:?}", plugin);
+            self.plugins.push(plugin);
+        }
+        Ok(())
+    }
+
+    /// path of the `plugin_path` inside the repository, also when it
+    /// lives inside the worktree of an installed plugin.
+    fn subpath(&self, plugin_path: &Path) -> String {
+        let subpath = plugin_path
+            .strip_prefix(&self.url.path_string)
+            .unwrap_or(plugin_path);
+        let subpath = match subpath.strip_prefix(git::WORKTREES_DIR) {
+            // skip the `<plugin>/<commit>` of the worktree.
+            Ok(inside) => inside.components().skip(2).collect::<PathBuf>(),
+            Err(_) => subpath.to_path_buf(),
+        };
+        subpath.to_string_lossy().to_string()
+    }
+
+    /// hash of the content at `plugin_path` in the commit checked out,
+    /// that is the id of the git object, so the files generated by the
+    /// build are not part of it.
+    fn content_hash(&self, plugin_path: &Path) -> Option<String> {
+        let git = git::backend();
+        let path = &self.url.path_string;
+        let head = git.head(path).ok()?;
+        git.object_id(path, &head, &self.subpath(plugin_path))
+            .ok()
+            .flatten()
+    }
+}
+
+/// Build a plugin from a single script file (e.g. placed at the root
+/// of the repository), the script need to declare its interpreter
+/// with a shebang line.
+pub fn plugin_from_script(script_path: &Path) -> Option<Plugin> {
+    let interpreter = get_shebang_interpreter(script_path)?;
+    let plugin_lang = PluginLang::from_shebang(&interpreter);
+    if plugin_lang == PluginLang::Unknown {
+        debug!("interpreter `{interpreter}` not supported, skipping it");
+        return None;
+    }
+    // the repositories are full of shell scripts that are not
+    // plugins (e.g. for the CI), only the executable ones are.
+    if plugin_lang == PluginLang::Shell && !is_executable(script_path) {
+        debug!("shell script {script_path:?} not executable, skipping it");
+        return None;
+    }
+    let name = script_path.file_stem()?.to_string_lossy().to_string();
+    let root_path = script_path.parent()?.to_string_lossy().to_string();
+    let path = script_path.to_string_lossy().to_string();
+    Some(Plugin::new_standalone(
+        &name,
+        &root_path,
+        &path,
+        plugin_lang,
+    ))
+}
+
+/// true when the file at `path` is marked as executable.
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|meta| meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Language of the most of the source files inside the plugin
+/// directory (and its direct subdirectories, e.g. `src`), unknown
+/// when two languages have the same number of files.
+fn lang_from_sources(plugin_path: &Path) -> PluginLang {
+    let mut counts: Vec<(PluginLang, usize)> = vec![];
+    let files = WalkDir::new(plugin_path)
+        .max_depth(2)
+        .into_iter()
+        .filter_entry(|entry| !is_hidden(entry))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file());
+    for file in files {
+        let extension = file.path().extension().unwrap_or_default();
+        let lang = PluginLang::from_extension(&extension.to_string_lossy());
+        if lang == PluginLang::Unknown {
+            continue;
+        }
+        match counts.iter_mut().find(|(counted, _)| *counted == lang) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((lang, 1)),
+        }
+    }
+    counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+    match counts.as_slice() {
+        [(_, first), (_, second), ..] if first == second => PluginLang::Unknown,
+        [(lang, _), ..] => lang.clone(),
+        [] => PluginLang::Unknown,
+    }
+}
+
+/// Build a plugin from its directory, with the coffee manifest when
+/// the directory has one, otherwise the language is guessed from
+/// the files of the directory.
+pub async fn plugin_from_dir(plugin_path: &Path) -> Result<Plugin, CoffeeError> {
+    let root_path = plugin_path
+        .as_os_str()
+        .to_os_string()
+        .to_string_lossy()
+        .to_string();
+    let mut path_to_plugin = None;
+    let mut plugin_name = None;
+    let mut plugin_lang = PluginLang::Unknown;
+
+    // check if the plugin has the custom configuration to read.
+    let mut conf = None;
+    for file in ["coffee.yaml", "coffee.yml"] {
+        let conf_path = format!("{}/{}", root_path, file);
+        if let Ok(mut conf_file) = File::open(conf_path).await {
+            let mut conf_str = String::new();
+            conf_file.read_to_string(&mut conf_str).await?;
+            debug!("found plugin configuration: {}", conf_str);
+
+            let conf_file = serde_yaml::from_str::<Conf>(&conf_str)
+                .map_err(|err| CoffeeError::new(1, &format!("Coffe manifest malformed: {err}")))?;
+            plugin_name = Some(conf_file.plugin.name.to_string());
+            path_to_plugin = Some(root_path.to_owned());
+            let conf_lang = (&conf_file.plugin.lang).to_owned();
+            match conf_lang.as_str() {
+                "py" => plugin_lang = PluginLang::Python,
+                "go" => plugin_lang = PluginLang::Go,
+                "rs" => plugin_lang = PluginLang::Rust,
+                "dart" => plugin_lang = PluginLang::Dart,
+                "js" => plugin_lang = PluginLang::JavaScript,
+                "ts" => plugin_lang = PluginLang::TypeScript,
+                "java" | "kotlin" | "scala" => plugin_lang = PluginLang::JVM,
+                "c" | "cpp" | "c++" => plugin_lang = PluginLang::C,
+                "sh" | "bash" => plugin_lang = PluginLang::Shell,
+                // built by the installer registered for it, if any.
+                _ => plugin_lang = PluginLang::Other(conf_lang.to_lowercase()),
+            };
+
+            conf = Some(conf_file);
+            break;
+        }
+    }
+
+    // check if there was a coffee configuration file
+    if conf == None {
+        debug!("conf file not found, so we try to guess the language");
+        // try to understand the language from the file
+        // the plugin is the directory itself, the files are used
+        // only to guess the language, so an helper file can not
+        // change the name or the path of the plugin.
+        path_to_plugin = Some(root_path.to_owned());
+        plugin_name = Some(
+            plugin_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        );
+        // a Makefile is common inside the plugins of any language,
+        // so it is used only when nothing else is found.
+        let mut makefile = false;
+        let files = WalkDir::new(plugin_path).min_depth(1).max_depth(1);
+        for file in files {
+            let file_dir = file.unwrap().clone();
+            let file_name = file_dir.file_name().to_str().unwrap();
+            if matches!(file_name, "Makefile" | "configure") {
+                makefile = true;
+                continue;
+            }
+            plugin_lang = match file_name {
+                "requirements.txt" => PluginLang::Python,
+                "go.mod" => PluginLang::Go,
+                "Cargo.toml" => PluginLang::Rust,
+                "pubspec.yaml" => PluginLang::Dart,
+                "package.json" => PluginLang::JavaScript,
+                "tsconfig.json" => PluginLang::TypeScript,
+                "build.gradle" | "build.gradle.kts" | "pom.xml" => PluginLang::JVM,
+                _ => PluginLang::Unknown,
+            };
+            if plugin_lang != PluginLang::Unknown {
+                break;
+            }
+        }
+        if plugin_lang == PluginLang::Unknown {
+            // many old plugins ship only the sources.
+            plugin_lang = lang_from_sources(plugin_path);
+        }
+        if plugin_lang == PluginLang::Unknown && makefile {
+            plugin_lang = PluginLang::C;
+        }
+        debug!("possible plugin language: {:?}", plugin_lang);
+    }
+
+    // The language is already contained inside the configuration file.
+    Ok(Plugin::new(
+        plugin_name.unwrap().as_str(),
+        &root_path,
+        path_to_plugin.unwrap().as_str(),
+        plugin_lang,
+        conf,
+    ))
 }
 
 #[async_trait]
@@ -170,15 +382,19 @@ impl Repository for Github {
             "initializing repository: {} {} > {}",
             self.name, &self.url.url_string, &self.url.path_string,
         );
-        let res = git2::Repository::clone(&self.url.url_string, &self.url.path_string);
-        match res {
-            Ok(repo) => {
-                let clone = clone_recursive_fix(repo, &self.url).await;
-                self.index_repository().await?;
-                clone
+        let path = &self.url.path_string;
+        let existed = Path::new(path).exists();
+        if let Err(err) = git::backend().clone_repo(&self.url.url_string, path) {
+            // do not leave a partial clone behind, e.g. when the
+            // clone is interrupted.
+            if !existed && Path::new(path).exists() {
+                remove_dir_all(path).await?;
             }
-            Err(err) => Err(CoffeeError::new(1, err.message())),
+            return Err(err);
         }
+        self.index_repository().await?;
+        self.last_update = Some(unix_time());
+        Ok(())
     }
 
     /// list of the plugin installed inside the repository.
@@ -196,6 +412,134 @@ impl Repository for Github {
         self.url.clone()
     }
 
+    /// last time that the repository was updated.
+    fn last_update(&self) -> Option<u64> {
+        self.last_update
+    }
+
+    /// compare the local checkout with the upstream branch
+    /// fetched inside the repository.
+    fn is_outdated(&self) -> bool {
+        let git = git::backend();
+        let path = &self.url.path_string;
+        match (git.head(path), git.upstream(path)) {
+            (Ok(head), Ok(Some(upstream))) => head != upstream,
+            (Err(err), _) | (_, Err(err)) => {
+                debug!("repository {} not available: {err}", self.name);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// compare the content of the plugin checked out with the content
+    /// inside the upstream branch.
+    fn is_plugin_outdated(&self, plugin: &Plugin) -> bool {
+        if !self.is_outdated() {
+            return false;
+        }
+        let Some(content_hash) = plugin.content_hash() else {
+            return true;
+        };
+        let git = git::backend();
+        let path = &self.url.path_string;
+        let subpath = if plugin.is_standalone() {
+            self.subpath(Path::new(&plugin.path))
+        } else {
+            self.subpath(Path::new(&plugin.root_path()))
+        };
+        match git.upstream(path) {
+            Ok(Some(upstream)) => match git.object_id(path, &upstream, &subpath) {
+                Ok(upstream_hash) => upstream_hash.as_ref() != Some(&content_hash),
+                Err(err) => {
+                    debug!("content of {subpath} not available: {err}");
+                    true
+                }
+            },
+            _ => true,
+        }
+    }
+
+    /// files of the working tree that differ from the checked out commit.
+    fn local_changes(&self) -> Result<Vec<String>, CoffeeError> {
+        git::backend().local_changes(&self.url.path_string)
+    }
+
+    fn commit(&self) -> Result<String, CoffeeError> {
+        git::backend().head(&self.url.path_string)
+    }
+
+    /// check that HEAD is reachable from one of the branches of
+    /// the upstream, a missing HEAD means that the upstream history
+    /// was rewritten (e.g. force push) or that the local history was
+    /// tampered.
+    async fn verify(&self, fetch: bool) -> Result<(), CoffeeError> {
+        let git = git::backend();
+        let path = &self.url.path_string;
+        if fetch {
+            debug!("fetching upstream of {}", self.name);
+            git.fetch(path)?;
+        }
+        let head = git.head(path)?;
+        if !git.remote_contains(path, &head)? {
+            let err = CoffeeError::new(
+                1,
+                &format!(
+                    "commit {head} of `{}` is not part of the upstream history",
+                    self.name
+                ),
+            );
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// fetch the upstream, the outdated check uses what is fetched.
+    async fn fetch(&self) -> Result<(), CoffeeError> {
+        debug!("fetching upstream of {}", self.name);
+        git::backend().fetch(&self.url.path_string)
+    }
+
+    /// fetch the upstream and move the checkout on the upstream
+    /// branch, also when the upstream history was rewritten.
+    async fn upgrade(&mut self) -> Result<(), CoffeeError> {
+        debug!("upgrading repository: {}", self.name);
+        let git = git::backend();
+        let path = &self.url.path_string;
+        git.fetch(path)?;
+        let upstream = git.upstream(path)?.ok_or_else(|| {
+            CoffeeError::new(
+                1,
+                &format!("repository `{}` do not track an upstream branch", self.name),
+            )
+        })?;
+        let head = git.head(path)?;
+        git.reset_hard(path, &upstream)?;
+        self.index_changes(&head, &upstream).await?;
+        self.last_update = Some(unix_time());
+        Ok(())
+    }
+
+    /// move the checkout to a commit already fetched, e.g. the
+    /// commit before an upgrade.
+    async fn reset(&mut self, commit: &str) -> Result<(), CoffeeError> {
+        debug!("resetting repository {} to {commit}", self.name);
+        let git = git::backend();
+        let head = git.head(&self.url.path_string)?;
+        git.reset_hard(&self.url.path_string, commit)?;
+        self.index_changes(&head, commit).await
+    }
+
+    /// diff between the checked out commit and the working tree.
+    fn local_diff(&self) -> Result<String, CoffeeError> {
+        git::backend().diff(&self.url.path_string)
+    }
+
+    /// apply the patch on the working tree of the repository.
+    fn apply_diff(&self, patch: &str) -> Result<(), CoffeeError> {
+        git::backend().apply(&self.url.path_string, patch)
+    }
+
     /// search inside the repository a plugin by name.
     fn get_plugin_by_name(&self, name: &str) -> Option<Plugin> {
         for plugin in &self.plugins {
@@ -203,7 +547,27 @@ impl Repository for Github {
                 return Some(plugin.to_owned());
             }
         }
-        None
+        self.plugins
+            .iter()
+            .find(|plugin| names::matches(&plugin.name(), name))
+            .cloned()
+    }
+
+    fn plugin_names(&self) -> Vec<String> {
+        self.plugins.iter().map(|plugin| plugin.name()).collect()
+    }
+
+    /// organization of the plugins inside the repository.
+    fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// index again the plugins of the repository with the `layout`.
+    async fn set_layout(&mut self, layout: Layout) -> Result<(), CoffeeError> {
+        debug!("indexing {} with the {layout} layout", self.name);
+        self.layout = layout;
+        self.plugins.clear();
+        self.index_repository().await
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -217,6 +581,8 @@ impl From<StorageRepository> for Github {
             url: value.url,
             name: value.name,
             plugins: value.plugins,
+            last_update: value.last_update,
+            layout: value.layout,
         }
     }
 }
@@ -227,6 +593,8 @@ impl From<&StorageRepository> for Github {
             url: value.url.to_owned(),
             name: value.name.to_owned(),
             plugins: value.plugins.to_owned(),
+            last_update: value.last_update,
+            layout: value.layout,
         }
     }
 }
@@ -238,6 +606,8 @@ impl From<Github> for StorageRepository {
             name: value.name,
             url: value.url,
             plugins: value.plugins,
+            last_update: value.last_update,
+            layout: value.layout,
         }
     }
 }
@@ -249,6 +619,8 @@ impl From<&Github> for StorageRepository {
             name: value.name.to_owned(),
             url: value.url.to_owned(),
             plugins: value.plugins.to_owned(),
+            last_update: value.last_update,
+            layout: value.layout,
         }
     }
 }
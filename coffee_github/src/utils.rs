@@ -8,7 +8,7 @@ pub async fn clone_recursive_fix(repo: git2::Repository, url: &URL) -> Result<()
     for (index, sub) in repository.iter().enumerate() {
         debug!("url {}: {}", index + 1, sub.url().unwrap());
         let path = format!("{}/{}", &url.path_string, sub.path().to_str().unwrap());
-        let _ = match git2::Repository::clone(sub.url().unwrap(), &path) {
+        match git2::Repository::clone(sub.url().unwrap(), &path) {
             // Fix error handling
             Ok(_) => {
                 debug!("added {}", sub.url().unwrap());
@@ -0,0 +1,135 @@
+//! Build of the rust plugins with cargo, offline from the crates
+//! vendored with the plugin or from a cargo home shared by the
+//! plugins, and with the `Cargo.lock` of the upstream when `locked`.
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+use crate::sandbox::Sandbox;
+
+/// Cargo options of the rust plugins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CargoConf {
+    /// build with the `Cargo.lock` of the plugin, and fail when
+    /// it is out of date.
+    pub locked: bool,
+    /// share one cargo home between the plugins, so the crates are
+    /// downloaded once and the next builds can run offline.
+    pub shared_home: bool,
+    /// directory of the shared cargo home, chosen by the plugin manager.
+    #[serde(skip)]
+    pub home_dir: Option<String>,
+    /// build without the network.
+    #[serde(skip)]
+    pub offline: bool,
+}
+
+impl CargoConf {
+    pub fn with_home_dir(mut self, home_dir: &str) -> Self {
+        self.home_dir = Some(home_dir.to_owned());
+        self
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// the arguments of `env` that run the cargo build of the
+    /// plugin inside `path`.
+    fn build_args(&self, path: &str, build_flags: &[String]) -> Vec<String> {
+        let mut args = vec![];
+        if let (true, Some(home_dir)) = (self.shared_home, &self.home_dir) {
+            args.push(format!("CARGO_HOME={home_dir}"));
+        }
+        args.extend(["cargo", "build", "--release"].map(str::to_owned));
+        if self.locked {
+            args.push("--locked".to_owned());
+        }
+        if self.offline {
+            args.push("--offline".to_owned());
+        }
+        // the crates vendored with `cargo vendor`, without the
+        // configuration that cargo vendor prints.
+        if Path::new(&format!("{path}/vendor")).is_dir()
+            && !Path::new(&format!("{path}/.cargo/config.toml")).exists()
+        {
+            args.extend(
+                [
+                    "--config",
+                    "source.crates-io.replace-with=\"vendored-sources\"",
+                    "--config",
+                    "source.vendored-sources.directory=\"vendor\"",
+                ]
+                .map(str::to_owned),
+            );
+        }
+        args.extend(build_flags.iter().cloned());
+        args
+    }
+}
+
+/// Build the rust plugin `name` inside the directory `path`, with the
+/// `build_flags` of the manifest, return the binary.
+pub async fn install(
+    sandbox: &Sandbox,
+    path: &str,
+    name: &str,
+    build_flags: &[String],
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let conf = &sandbox.cargo;
+    if let (true, Some(home_dir)) = (conf.shared_home, &conf.home_dir) {
+        tokio::fs::create_dir_all(home_dir).await?;
+    }
+    let args = conf.build_args(path, build_flags);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    // the variables go through `env`, the sandbox clears the environment.
+    sandbox
+        .run_build(&PluginLang::Rust, "env", &args, path, verbose)
+        .await?;
+    let binary = format!("{path}/target/release/{name}");
+    if !Path::new(&binary).is_file() {
+        let err = CoffeeError::new(
+            1,
+            &format!("cargo did not build the binary `{name}` inside `{path}`"),
+        );
+        return Err(err);
+    }
+    Ok(binary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CargoConf;
+
+    #[test]
+    fn test_cargo_build_args() {
+        let conf = CargoConf {
+            locked: true,
+            shared_home: true,
+            home_dir: Some("/coffee/cargo".to_owned()),
+            offline: true,
+        };
+        assert_eq!(
+            conf.build_args("/nonexistent", &["--features=tor".to_owned()]),
+            vec![
+                "CARGO_HOME=/coffee/cargo",
+                "cargo",
+                "build",
+                "--release",
+                "--locked",
+                "--offline",
+                "--features=tor"
+            ]
+        );
+        let conf = CargoConf::default().with_home_dir("/coffee/cargo");
+        assert_eq!(
+            conf.build_args("/nonexistent", &[]),
+            vec!["cargo", "build", "--release"]
+        );
+    }
+}
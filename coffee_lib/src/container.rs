@@ -0,0 +1,203 @@
+//! Build of the plugins inside a container, so the host does not
+//! need the toolchains of all the languages installed.
+//!
+//! The sources are copied inside the container, and the build
+//! artifacts are copied back once the command succeeds.
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+
+/// Directory of the sources inside the container.
+const WORKDIR: &str = "/plugin";
+
+/// Environment variables passed to the container.
+const PROXY_ENV: &[&str] = &["HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY"];
+
+/// Counter of the build containers, plugins are built concurrently.
+static CONTAINERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Container build configuration, from the `config.yml`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerConf {
+    /// build the plugins inside a container.
+    pub enabled: bool,
+    /// `docker` or `podman`, detected when missing.
+    pub engine: Option<String>,
+    /// image used for a language, e.g. `go: golang:1.22`.
+    pub images: BTreeMap<String, String>,
+}
+
+/// name of the `lang` inside the images configuration.
+fn lang_key(lang: &PluginLang) -> &str {
+    match lang {
+        PluginLang::Python => "python",
+        PluginLang::Go => "go",
+        PluginLang::Rust => "rust",
+        PluginLang::Dart => "dart",
+        PluginLang::JVM => "jvm",
+        PluginLang::JavaScript => "javascript",
+        PluginLang::TypeScript => "typescript",
+        PluginLang::C => "c",
+        PluginLang::Shell => "shell",
+        PluginLang::Other(lang) => lang.as_str(),
+        PluginLang::Unknown => "unknown",
+    }
+}
+
+/// image used when the configuration has none for the `lang`.
+fn default_image(lang: &PluginLang) -> Option<&'static str> {
+    match lang {
+        PluginLang::Python => Some("python:3"),
+        PluginLang::Go => Some("golang:1"),
+        PluginLang::Rust => Some("rust:1"),
+        PluginLang::Dart => Some("dart:stable"),
+        PluginLang::JVM => Some("eclipse-temurin:21"),
+        PluginLang::JavaScript => Some("node:lts"),
+        PluginLang::TypeScript => Some("denoland/deno"),
+        PluginLang::C => Some("gcc:14"),
+        PluginLang::Shell => Some("bash:5"),
+        PluginLang::Other(_) => None,
+        PluginLang::Unknown => None,
+    }
+}
+
+/// true when the `program` is inside the PATH.
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or_default()
+}
+
+/// run the container `engine` with the `args`, and return its output.
+async fn engine_cmd(engine: &str, args: &[&str]) -> Result<String, CoffeeError> {
+    debug!("running {engine} {}", args.join(" "));
+    let output = Command::new(engine).args(args).output().await?;
+    if !output.status.success() {
+        return Err(CoffeeError::new(
+            1,
+            &format!(
+                "{engine} {}: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl ContainerConf {
+    /// the container engine available on the host.
+    pub fn engine(&self) -> Result<String, CoffeeError> {
+        if let Some(engine) = &self.engine {
+            return Ok(engine.clone());
+        }
+        ["podman", "docker"]
+            .into_iter()
+            .find(|engine| in_path(engine))
+            .map(str::to_owned)
+            .ok_or_else(|| CoffeeError::new(1, "container build needs podman or docker installed"))
+    }
+
+    /// the image used to build a plugin written in `lang`.
+    pub fn image(&self, lang: &PluginLang) -> Result<String, CoffeeError> {
+        if let Some(image) = self.images.get(lang_key(lang)) {
+            return Ok(image.clone());
+        }
+        default_image(lang).map(str::to_owned).ok_or_else(|| {
+            CoffeeError::new(
+                1,
+                &format!(
+                    "no container image for a plugin of language {}, set one for `{}`",
+                    lang_key(lang),
+                    lang_key(lang)
+                ),
+            )
+        })
+    }
+
+    /// Create the container that runs `program` with the `args` on
+    /// a copy of the directory `dir`, the container is started by
+    /// the caller and it must be removed with [`remove`].
+    ///
+    /// Return the engine and the id of the container.
+    pub async fn create(
+        &self,
+        lang: &PluginLang,
+        program: &str,
+        args: &[&str],
+        dir: &str,
+        memory: Option<u64>,
+    ) -> Result<(String, String), CoffeeError> {
+        let engine = self.engine()?;
+        let image = self.image(lang)?;
+        let name = format!(
+            "coffee-build-{}-{}",
+            std::process::id(),
+            CONTAINERS.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut create = vec!["create".to_owned(), "--name".to_owned(), name];
+        create.extend(["-w".to_owned(), WORKDIR.to_owned()]);
+        if let Some(memory) = memory {
+            create.push(format!("--memory={memory}m"));
+        }
+        for var in PROXY_ENV {
+            if let Ok(value) = env::var(var) {
+                create.extend(["-e".to_owned(), format!("{var}={value}")]);
+            }
+        }
+        create.extend([image, program.to_owned()]);
+        create.extend(args.iter().map(|arg| arg.to_string()));
+        let create: Vec<&str> = create.iter().map(String::as_str).collect();
+        let id = engine_cmd(&engine, &create).await?;
+        let source = format!("{dir}/.");
+        let target = format!("{id}:{WORKDIR}");
+        if let Err(err) = engine_cmd(&engine, &["cp", &source, &target]).await {
+            remove(&engine, &id).await;
+            return Err(err);
+        }
+        Ok((engine, id))
+    }
+
+    /// Copy the content of the build directory of the container `id`
+    /// inside the directory `dir`.
+    pub async fn copy_out(&self, engine: &str, id: &str, dir: &str) -> Result<(), CoffeeError> {
+        let source = format!("{id}:{WORKDIR}/.");
+        engine_cmd(engine, &["cp", &source, dir]).await?;
+        Ok(())
+    }
+}
+
+/// Remove the container `id`, also when it is still running.
+pub async fn remove(engine: &str, id: &str) {
+    if let Err(err) = engine_cmd(engine, &["rm", "-f", id]).await {
+        warn!("build container {id} not removed: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::ContainerConf;
+    use crate::plugin::PluginLang;
+
+    #[test]
+    fn test_container_image() {
+        let conf = ContainerConf {
+            enabled: true,
+            engine: None,
+            images: BTreeMap::from([("go".to_owned(), "golang:1.22".to_owned())]),
+        };
+        assert_eq!(conf.image(&PluginLang::Go).unwrap(), "golang:1.22");
+        assert_eq!(conf.image(&PluginLang::Rust).unwrap(), "rust:1");
+        assert!(conf.image(&PluginLang::Unknown).is_err());
+    }
+}
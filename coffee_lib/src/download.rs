@@ -0,0 +1,153 @@
+//! Limits of the downloads (clones, fetches and archives), so a bulk
+//! upgrade does not saturate the link that the node uses for the
+//! gossip and the payments.
+//!
+//! The limits are global: all the downloads of coffee share the
+//! same slots and the same bandwidth.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CoffeeError;
+use crate::interrupt::{interrupted_error, is_interrupted};
+
+/// How often a download waiting for a free slot checks again.
+const SLOT_POLL: Duration = Duration::from_millis(50);
+
+/// Limits of the downloads, no limit when missing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadLimits {
+    /// downloads running at the same time.
+    pub max_concurrent: Option<usize>,
+    /// bandwidth shared by all the downloads, in KiB/s.
+    pub max_rate: Option<u64>,
+}
+
+static LIMITS: Mutex<DownloadLimits> = Mutex::new(DownloadLimits {
+    max_concurrent: None,
+    max_rate: None,
+});
+
+/// Downloads running.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Instant when the bandwidth used by the previous chunks is
+/// available again.
+static BANDWIDTH_FREE_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Set the limits used by all the following downloads.
+pub fn configure(limits: &DownloadLimits) {
+    *LIMITS.lock().unwrap() = limits.clone();
+}
+
+fn limits() -> DownloadLimits {
+    LIMITS.lock().unwrap().clone()
+}
+
+/// Slot of a running download, it is released when dropped.
+pub struct DownloadSlot;
+
+impl Drop for DownloadSlot {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// take a slot when there is one free.
+fn try_slot() -> Option<DownloadSlot> {
+    let max = limits().max_concurrent.unwrap_or(usize::MAX).max(1);
+    RUNNING
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+            (running < max).then_some(running + 1)
+        })
+        .ok()
+        .map(|_| DownloadSlot)
+}
+
+/// Wait for a free download slot, for the downloads that run
+/// outside the async runtime (e.g. the clones of git).
+pub fn wait_slot() -> Result<DownloadSlot, CoffeeError> {
+    loop {
+        if let Some(slot) = try_slot() {
+            return Ok(slot);
+        }
+        if is_interrupted() {
+            return Err(interrupted_error());
+        }
+        std::thread::sleep(SLOT_POLL);
+    }
+}
+
+/// Wait for a free download slot.
+pub async fn slot() -> Result<DownloadSlot, CoffeeError> {
+    loop {
+        if let Some(slot) = try_slot() {
+            return Ok(slot);
+        }
+        if is_interrupted() {
+            return Err(interrupted_error());
+        }
+        tokio::time::sleep(SLOT_POLL).await;
+    }
+}
+
+/// reserve the bandwidth for `bytes` just received, and return
+/// how long the download must wait to stay within the rate.
+fn reserve(bytes: u64, now: Instant) -> Duration {
+    let Some(rate) = limits().max_rate.filter(|rate| *rate > 0) else {
+        return Duration::ZERO;
+    };
+    let mut free_at = BANDWIDTH_FREE_AT.lock().unwrap();
+    // the bandwidth not used in the past is not saved for a burst.
+    let start = free_at.map_or(now, |free_at| free_at.max(now));
+    let end = start + Duration::from_secs_f64(bytes as f64 / (rate * 1024) as f64);
+    *free_at = Some(end);
+    end - now
+}
+
+/// Slow down the download that just received `bytes`, for the
+/// downloads that run outside the async runtime.
+pub fn throttle_blocking(bytes: u64) {
+    let wait = reserve(bytes, Instant::now());
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
+
+/// Slow down the download that just received `bytes`.
+pub async fn throttle(bytes: u64) {
+    let wait = reserve(bytes, Instant::now());
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{configure, reserve, try_slot, DownloadLimits};
+
+    #[test]
+    fn test_download_limits() {
+        configure(&DownloadLimits {
+            max_concurrent: Some(1),
+            max_rate: Some(100),
+        });
+        let slot = try_slot();
+        assert!(slot.is_some());
+        assert!(try_slot().is_none());
+        drop(slot);
+        assert!(try_slot().is_some());
+
+        // two chunks of one second each at 100 KiB/s.
+        let now = Instant::now();
+        assert_eq!(reserve(100 * 1024, now), Duration::from_secs(1));
+        assert_eq!(reserve(100 * 1024, now), Duration::from_secs(2));
+        configure(&DownloadLimits::default());
+        assert_eq!(reserve(100 * 1024, now), Duration::ZERO);
+    }
+}
@@ -0,0 +1,229 @@
+//! Entry point resolution of a plugin, this contains the
+//! rules used to understand what is the file that core lightning
+//! need to run, so helper files (README, tests, setup scripts)
+//! are never picked by mistake.
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+use crate::utils::get_shebang_interpreter;
+
+/// Files that are never the entry point of a plugin.
+const HELPER_FILES: [&str; 6] = [
+    "setup.py",
+    "conftest.py",
+    "__init__.py",
+    "noxfile.py",
+    "manage.py",
+    "build.rs",
+];
+
+/// Check if the file name looks like an helper of the plugin,
+/// like a test or a documentation file.
+fn is_helper_file(file_name: &str) -> bool {
+    let lower_name = file_name.to_lowercase();
+    HELPER_FILES.contains(&lower_name.as_str())
+        || lower_name.starts_with("readme")
+        || lower_name.starts_with("license")
+        || lower_name.starts_with("test_")
+        || lower_name.contains("_test.")
+        || lower_name.contains(".test.")
+}
+
+/// Check if the file can be run by core lightning, so it is
+/// marked as executable or it declares an interpreter.
+fn is_runnable(path: &Path) -> bool {
+    let executable = fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+    executable || get_shebang_interpreter(path).is_some()
+}
+
+/// Return the first file that exists inside the plugin directory.
+fn first_existing(path: &str, candidates: &[String]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|candidate| format!("{path}/{candidate}"))
+        .find(|candidate| Path::new(candidate).is_file())
+}
+
+/// Read the python scripts declared inside the `[project.scripts]`
+/// section of the `pyproject.toml` or inside the `console_scripts`
+/// of the `setup.cfg`, and map them to the module file.
+fn declared_python_scripts(path: &str) -> Vec<String> {
+    let mut scripts = vec![];
+    for (file, section) in [
+        ("pyproject.toml", "[project.scripts]"),
+        ("setup.cfg", "console_scripts"),
+    ] {
+        let Ok(content) = fs::read_to_string(format!("{path}/{file}")) else {
+            continue;
+        };
+        let mut in_section = false;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with(section) {
+                in_section = true;
+                continue;
+            }
+            if in_section && (line.is_empty() || line.starts_with('[')) {
+                break;
+            }
+            if !in_section {
+                continue;
+            }
+            // `summary = summary.main:run` is the module `summary/main.py`
+            if let Some((_, target)) = line.split_once('=') {
+                let target = target.trim().trim_matches('"');
+                let module = target.split(':').next().unwrap_or_default();
+                if !module.is_empty() {
+                    scripts.push(format!("{}.py", module.replace('.', "/")));
+                }
+            }
+        }
+    }
+    scripts
+}
+
+/// Look inside the plugin directory for runnable files with one of
+/// the extensions, ignoring the helper files.
+///
+/// Return an error if there is more than one candidate.
+fn single_runnable_file(path: &str, extensions: &[&str]) -> Result<Option<String>, CoffeeError> {
+    let Ok(entries) = fs::read_dir(path) else {
+        return Ok(None);
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            !file_name.starts_with('.')
+                && !is_helper_file(&file_name)
+                && extensions.iter().any(|ext| file_name.ends_with(ext))
+                && is_runnable(&entry.path())
+        })
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .collect();
+    candidates.sort();
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.pop()),
+        _ => Err(CoffeeError::new(
+            1,
+            &format!(
+                "ambiguous entry point inside `{path}`, candidates are: {}",
+                candidates.join(", ")
+            ),
+        )),
+    }
+}
+
+/// Resolve the entry point of the plugin with the name `name`
+/// contained inside the directory `path`.
+///
+/// The rules are language specific, and the first rule that match
+/// win, when more than one file can be the entry point an error is
+/// returned to avoid running the wrong file.
+pub fn resolve_entry_point(
+    lang: &PluginLang,
+    path: &str,
+    name: &str,
+) -> Result<String, CoffeeError> {
+    let entry_point = match lang {
+        PluginLang::Python => {
+            if let Some(main) = first_existing(path, &[format!("{name}.py")]) {
+                Some(main)
+            } else if let Some(main) = first_existing(path, &declared_python_scripts(path)) {
+                Some(main)
+            } else {
+                single_runnable_file(path, &[".py"])?
+            }
+        }
+        PluginLang::Go => {
+            first_existing(path, &[format!("cmd/{name}/main.go"), "main.go".to_owned()])
+        }
+        PluginLang::Rust => first_existing(
+            path,
+            &[format!("src/bin/{name}.rs"), "src/main.rs".to_owned()],
+        ),
+        PluginLang::Dart => first_existing(
+            path,
+            &[format!("bin/{name}.dart"), "bin/main.dart".to_owned()],
+        ),
+        PluginLang::JavaScript => {
+            match first_existing(path, &[format!("{name}.js"), "index.js".to_owned()]) {
+                Some(main) => Some(main),
+                None => single_runnable_file(path, &[".js"])?,
+            }
+        }
+        PluginLang::TypeScript => match first_existing(
+            path,
+            &[
+                format!("{name}.ts"),
+                format!("src/{name}.ts"),
+                "src/index.ts".to_owned(),
+            ],
+        ) {
+            Some(main) => Some(main),
+            None => single_runnable_file(path, &[".ts"])?,
+        },
+        // the binary does not exist before the build, make
+        // builds it with the name of the plugin.
+        PluginLang::C => Some(
+            first_existing(path, &[format!("build/{name}"), format!("bin/{name}")])
+                .unwrap_or_else(|| format!("{path}/{name}")),
+        ),
+        PluginLang::Shell => match first_existing(path, &[format!("{name}.sh"), name.to_owned()]) {
+            Some(main) => Some(main),
+            None => single_runnable_file(path, &[".sh"])?,
+        },
+        PluginLang::JVM | PluginLang::Other(_) | PluginLang::Unknown => None,
+    };
+    entry_point.ok_or_else(|| {
+        CoffeeError::new(
+            1,
+            &format!("unable to find the entry point of the plugin `{name}` inside `{path}`"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all, set_permissions, write, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::resolve_entry_point;
+    use crate::plugin::PluginLang;
+
+    #[test]
+    fn test_python_entry_point() {
+        let path = format!("{}/coffee-entry-point", env::temp_dir().to_string_lossy());
+        create_dir_all(&path).unwrap();
+        for file in [
+            "README.md",
+            "test_summary.py",
+            "setup.py",
+            "summary_impl.py",
+        ] {
+            write(format!("{path}/{file}"), "#!/usr/bin/env python3\n").unwrap();
+        }
+        // only one runnable file that is not an helper.
+        let main = resolve_entry_point(&PluginLang::Python, &path, "clnsummary").unwrap();
+        assert_eq!(main, format!("{path}/summary_impl.py"));
+
+        // two candidates are ambiguous.
+        let other = format!("{path}/other.py");
+        write(&other, "print('hello')\n").unwrap();
+        set_permissions(&other, Permissions::from_mode(0o755)).unwrap();
+        assert!(resolve_entry_point(&PluginLang::Python, &path, "clnsummary").is_err());
+
+        // the file that match the plugin name always win.
+        write(format!("{path}/clnsummary.py"), "").unwrap();
+        let main = resolve_entry_point(&PluginLang::Python, &path, "clnsummary").unwrap();
+        assert_eq!(main, format!("{path}/clnsummary.py"));
+        remove_dir_all(path).unwrap();
+    }
+}
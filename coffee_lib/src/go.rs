@@ -0,0 +1,124 @@
+//! Build of the go plugins, with the module proxy and the flags of
+//! the configuration, so the plugins can be built behind a corporate
+//! proxy, or offline from the modules vendored with the plugin.
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::entry_point::resolve_entry_point;
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+use crate::sandbox::Sandbox;
+
+/// Go options of the go plugins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GoConf {
+    /// `GOPROXY` of the builds, e.g. `https://goproxy.example.com,direct`.
+    pub proxy: Option<String>,
+    /// `GOPRIVATE` of the builds, the modules that skip the proxy.
+    pub private: Option<String>,
+    /// `GOFLAGS` of the builds.
+    pub flags: Option<String>,
+    /// build only from the vendored modules, without the network.
+    #[serde(skip)]
+    pub offline: bool,
+}
+
+impl GoConf {
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// the environment of the go commands for the plugin inside `path`.
+    fn env(&self, path: &str) -> Result<Vec<String>, CoffeeError> {
+        let vendored = Path::new(&format!("{path}/vendor/modules.txt")).exists();
+        let mut env = vec![];
+        let mut flags = self.flags.clone().unwrap_or_default();
+        if vendored {
+            flags = format!("{flags} -mod=vendor");
+        }
+        if self.offline {
+            if !vendored {
+                let err = CoffeeError::new(
+                    1,
+                    &format!("the go plugin inside `{path}` has no vendor directory, it can not be built offline"),
+                );
+                return Err(err);
+            }
+            env.push("GOPROXY=off".to_owned());
+        } else if let Some(proxy) = &self.proxy {
+            env.push(format!("GOPROXY={proxy}"));
+        }
+        if let Some(private) = &self.private {
+            env.push(format!("GOPRIVATE={private}"));
+        }
+        if !flags.trim().is_empty() {
+            env.push(format!("GOFLAGS={}", flags.trim()));
+        }
+        Ok(env)
+    }
+}
+
+/// Build the go plugin `name` inside the directory `path`, with the
+/// `build_flags` of the manifest, return the binary.
+pub async fn install(
+    sandbox: &Sandbox,
+    path: &str,
+    name: &str,
+    build_flags: &[String],
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let main_file = resolve_entry_point(&PluginLang::Go, path, name)?;
+    // e.g. `./cmd/<name>` for `cmd/<name>/main.go`.
+    let package = Path::new(&main_file)
+        .parent()
+        .and_then(|dir| dir.strip_prefix(path).ok())
+        .map(|dir| format!("./{}", dir.to_string_lossy()))
+        .unwrap_or_else(|| ".".to_owned());
+    // the variables go through `env`, the sandbox clears the environment.
+    let env = sandbox.go.env(path)?;
+    let mut args: Vec<&str> = env.iter().map(String::as_str).collect();
+    // relative to the plugin, it can be built inside a container.
+    args.extend(["go", "build", "-o", name]);
+    args.extend(build_flags.iter().map(String::as_str));
+    args.push(&package);
+    sandbox
+        .run_build(&PluginLang::Go, "env", &args, path, verbose)
+        .await?;
+    Ok(format!("{path}/{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::GoConf;
+
+    #[test]
+    fn test_go_env() {
+        let path = format!("{}/coffee-go-env", env::temp_dir().to_string_lossy());
+        create_dir_all(&path).unwrap();
+        let conf = GoConf {
+            proxy: Some("https://goproxy.example.com".to_owned()),
+            private: None,
+            flags: Some("-trimpath".to_owned()),
+            offline: false,
+        };
+        assert_eq!(
+            conf.env(&path).unwrap(),
+            vec!["GOPROXY=https://goproxy.example.com", "GOFLAGS=-trimpath"]
+        );
+        assert!(conf.clone().with_offline(true).env(&path).is_err());
+
+        create_dir_all(format!("{path}/vendor")).unwrap();
+        write(format!("{path}/vendor/modules.txt"), "").unwrap();
+        assert_eq!(
+            conf.with_offline(true).env(&path).unwrap(),
+            vec!["GOPROXY=off", "GOFLAGS=-trimpath -mod=vendor"]
+        );
+        remove_dir_all(&path).unwrap();
+    }
+}
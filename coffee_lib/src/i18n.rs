@@ -0,0 +1,210 @@
+//! Messages of coffee for the user, translated inside the locale of
+//! the user, the messages are looked up inside the catalog by a
+//! stable id, and the english message is used when one is missing.
+//!
+//! The codes of the errors do not change with the locale, so the
+//! scripts can match on them.
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Locales with a catalog of messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    It,
+}
+
+impl Locale {
+    /// Parse a locale tag (e.g. `it`, `it_IT.UTF-8` or `en-US`),
+    /// `None` when coffee has no catalog for it.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::En),
+            "it" => Some(Locale::It),
+            _ => None,
+        }
+    }
+
+    /// Select the locale of the user: the `COFFEE_LANG` variable,
+    /// then the `configured` locale, then the locale of the system.
+    pub fn detect(configured: Option<&str>) -> Self {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        env("COFFEE_LANG")
+            .into_iter()
+            .chain(configured.map(str::to_owned))
+            .chain(
+                ["LC_ALL", "LC_MESSAGES", "LANG"]
+                    .into_iter()
+                    .filter_map(env),
+            )
+            .find_map(|tag| Locale::from_tag(&tag))
+            .unwrap_or_default()
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::En => EN,
+            Locale::It => IT,
+        }
+    }
+
+    /// The answers that confirm a question.
+    pub fn yes(&self) -> &'static [&'static str] {
+        match self {
+            Locale::En => &["y", "yes"],
+            Locale::It => &["s", "si", "sì", "y", "yes"],
+        }
+    }
+}
+
+const EN: &[(&str, &str)] = &[
+    ("confirm-prompt", "{question} [y/N] "),
+    (
+        "confirm-no-terminal",
+        "{question} no terminal available, use `--yes` to confirm",
+    ),
+    ("interrupted", "interrupted"),
+    (
+        "interrupting",
+        "interrupting, press Ctrl-C again to exit immediately",
+    ),
+    ("plugin-not-installed", "plugin `{plugin}` is not installed"),
+    ("plugin-installing", "[{plugin}] installing"),
+    ("plugin-built", "[{plugin}] built"),
+    ("plugin-failed", "[{plugin}] failed: {error}"),
+    ("plugin-rolling-back", "[{plugin}] rolling back"),
+    ("plugin-remove-question", "remove the plugin `{plugin}`?"),
+    ("repository-upgraded", "[{repository}] upgraded"),
+    (
+        "plugins-install-failed",
+        "{failures} of {total} plugins failed to install",
+    ),
+    (
+        "repository-not-found",
+        "repository `{repository}` not found",
+    ),
+    (
+        "repository-remove-question",
+        "remove the repository `{repository}`?",
+    ),
+    (
+        "repository-remove-installed",
+        "{installed} installed plugins come from `{repository}`, {question}",
+    ),
+    ("remove-aborted", "remove of `{name}` aborted by the user"),
+    ("did-you-mean", "{message}, did you mean {names}?"),
+];
+
+const IT: &[(&str, &str)] = &[
+    ("confirm-prompt", "{question} [s/N] "),
+    (
+        "confirm-no-terminal",
+        "{question} nessun terminale disponibile, usa `--yes` per confermare",
+    ),
+    ("interrupted", "interrotto"),
+    (
+        "interrupting",
+        "interruzione, premi Ctrl-C di nuovo per uscire subito",
+    ),
+    (
+        "plugin-not-installed",
+        "il plugin `{plugin}` non è installato",
+    ),
+    ("plugin-installing", "[{plugin}] installazione"),
+    ("plugin-built", "[{plugin}] compilato"),
+    ("plugin-failed", "[{plugin}] fallito: {error}"),
+    ("plugin-rolling-back", "[{plugin}] ripristino"),
+    ("plugin-remove-question", "rimuovere il plugin `{plugin}`?"),
+    ("repository-upgraded", "[{repository}] aggiornato"),
+    (
+        "plugins-install-failed",
+        "{failures} di {total} plugin non installati",
+    ),
+    (
+        "repository-not-found",
+        "repository `{repository}` non trovato",
+    ),
+    (
+        "repository-remove-question",
+        "rimuovere il repository `{repository}`?",
+    ),
+    (
+        "repository-remove-installed",
+        "{installed} plugin installati vengono da `{repository}`, {question}",
+    ),
+    (
+        "remove-aborted",
+        "rimozione di `{name}` annullata dall'utente",
+    ),
+    ("did-you-mean", "{message}, forse intendevi {names}?"),
+];
+
+static LOCALE: Mutex<Locale> = Mutex::new(Locale::En);
+
+/// Set the locale used by all the following messages.
+pub fn configure(locale: Locale) {
+    *LOCALE.lock().unwrap() = locale;
+}
+
+pub fn locale() -> Locale {
+    *LOCALE.lock().unwrap()
+}
+
+/// the message `id` inside the catalog of the `locale`, with the
+/// `{name}` placeholders replaced by the `args`.
+fn translate(locale: Locale, id: &str, args: &[(&str, &str)]) -> String {
+    let lookup = |catalog: &[(&str, &'static str)]| {
+        catalog
+            .iter()
+            .find(|(key, _)| *key == id)
+            .map(|(_, message)| *message)
+    };
+    let mut message = lookup(locale.catalog())
+        .or_else(|| lookup(EN))
+        .unwrap_or(id)
+        .to_owned();
+    for (name, value) in args {
+        message = message.replace(&format!("{{{name}}}"), value);
+    }
+    message
+}
+
+/// The message `id` inside the locale of the user, with the
+/// `{name}` placeholders replaced by the `args`.
+pub fn tr(id: &str, args: &[(&str, &str)]) -> String {
+    translate(locale(), id, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{translate, Locale, EN, IT};
+
+    #[test]
+    fn test_i18n_catalog() {
+        assert_eq!(Locale::from_tag("it_IT.UTF-8"), Some(Locale::It));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("de_DE"), None);
+        let args = [("plugin", "summary")];
+        assert_eq!(
+            translate(Locale::En, "plugin-not-installed", &args),
+            "plugin `summary` is not installed"
+        );
+        assert_eq!(
+            translate(Locale::It, "plugin-not-installed", &args),
+            "il plugin `summary` non è installato"
+        );
+        assert_eq!(translate(Locale::It, "unknown-id", &[]), "unknown-id");
+        // every message has a translation.
+        for (id, _) in EN {
+            assert!(IT.iter().any(|(key, _)| key == id), "`{id}` missing");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Installers of the languages that coffee does not know, an
+//! embedder (or a crate built on top of coffee) registers the
+//! installer of a language, and the plugins that declare it inside
+//! their manifest (`PluginLang::Other`) are built with it.
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+
+use crate::errors::CoffeeError;
+use crate::sandbox::Sandbox;
+
+/// Build procedure of the plugins written in a language.
+#[async_trait]
+pub trait Installer: Send + Sync {
+    /// Build the plugin with the name `name` contained inside the
+    /// directory `path`, the build commands should run inside the
+    /// `sandbox`, in case of success return the path of the executable.
+    async fn install(
+        &self,
+        path: &str,
+        name: &str,
+        build_flags: &[String],
+        verbose: bool,
+        sandbox: &Sandbox,
+    ) -> Result<String, CoffeeError>;
+}
+
+static INSTALLERS: RwLock<BTreeMap<String, Arc<dyn Installer>>> = RwLock::new(BTreeMap::new());
+
+/// Registry of the installers, shared by all the plugin managers
+/// of the process.
+pub struct InstallerRegistry;
+
+impl InstallerRegistry {
+    /// Register the `installer` for the language `lang`, as it is
+    /// written inside the manifests (e.g. `zig`), an installer already
+    /// registered for the language is replaced.
+    pub fn register(lang: &str, installer: Arc<dyn Installer>) {
+        INSTALLERS
+            .write()
+            .unwrap()
+            .insert(lang.to_lowercase(), installer);
+    }
+
+    /// Remove the installer of the language `lang`, if any.
+    pub fn unregister(lang: &str) {
+        INSTALLERS.write().unwrap().remove(&lang.to_lowercase());
+    }
+
+    /// The installer registered for the language `lang`.
+    pub fn get(lang: &str) -> Option<Arc<dyn Installer>> {
+        INSTALLERS
+            .read()
+            .unwrap()
+            .get(&lang.to_lowercase())
+            .cloned()
+    }
+
+    /// The languages with an installer registered.
+    pub fn languages() -> Vec<String> {
+        INSTALLERS.read().unwrap().keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use super::{Installer, InstallerRegistry};
+    use crate::errors::CoffeeError;
+    use crate::plugin::PluginLang;
+    use crate::sandbox::Sandbox;
+
+    struct Zig;
+
+    #[async_trait]
+    impl Installer for Zig {
+        async fn install(
+            &self,
+            path: &str,
+            name: &str,
+            _: &[String],
+            _: bool,
+            _: &Sandbox,
+        ) -> Result<String, CoffeeError> {
+            Ok(format!("{path}/zig-out/bin/{name}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_installer_registry() {
+        let sandbox = Sandbox::default();
+        let zig = PluginLang::Other("zig".to_owned());
+        assert!(zig
+            .default_install("/tmp/hello", "hello", &[], false, &sandbox)
+            .await
+            .is_err());
+        InstallerRegistry::register("Zig", Arc::new(Zig));
+        assert_eq!(InstallerRegistry::languages(), vec!["zig".to_owned()]);
+        let exec_path = zig
+            .default_install("/tmp/hello", "hello", &[], false, &sandbox)
+            .await
+            .unwrap();
+        assert_eq!(exec_path, "/tmp/hello/zig-out/bin/hello");
+        InstallerRegistry::unregister("zig");
+        assert!(InstallerRegistry::get("zig").is_none());
+    }
+}
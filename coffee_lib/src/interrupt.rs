@@ -0,0 +1,117 @@
+//! Cancellation of the running command when the user press Ctrl-C.
+//!
+//! The interruption is cooperative: the signal only raises a flag,
+//! the clones stop at the next chunk of data and the build commands
+//! are killed, so every step can clean up what it left behind.
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::time::sleep;
+
+use crate::errors::CoffeeError;
+
+/// Exit code of coffee when it is interrupted (128 + SIGINT).
+pub const INTERRUPTED_CODE: i32 = 130;
+
+/// Raised on the first Ctrl-C.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// true when the user asked to interrupt the command.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// ask to the running command to stop.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// the error returned by the steps that were interrupted.
+pub fn interrupted_error() -> CoffeeError {
+    CoffeeError::new(INTERRUPTED_CODE as u64, "interrupted by the user")
+}
+
+/// resolve when the user asks to interrupt the command.
+pub async fn interrupted() {
+    while !is_interrupted() {
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// copy the output of a command from the `reader` to the `log`, and
+/// to the output of coffee when `verbose`.
+async fn tee<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    log: Option<&Mutex<File>>,
+    verbose: bool,
+    stderr: bool,
+) {
+    let Some(mut reader) = reader else {
+        return;
+    };
+    let mut buf = [0; 8192];
+    loop {
+        let read = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        if let Some(log) = log {
+            let _ = log.lock().unwrap().write_all(&buf[..read]);
+        }
+        if verbose && stderr {
+            let _ = std::io::stderr().write_all(&buf[..read]);
+        } else if verbose {
+            let _ = std::io::stdout().write_all(&buf[..read]);
+        }
+    }
+}
+
+/// Run the `cmd` until it exits, the command is killed when the
+/// user interrupts coffee. The output is shown only when `verbose`,
+/// and it is appended to the `log` file when given.
+pub async fn run_command(
+    cmd: &mut Command,
+    verbose: bool,
+    log: Option<&str>,
+) -> Result<ExitStatus, CoffeeError> {
+    if is_interrupted() {
+        return Err(interrupted_error());
+    }
+    let log = match log {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+    if !verbose && log.is_none() {
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::null());
+    }
+    let mut child = cmd.kill_on_drop(true).spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let output = async {
+        let (status, _, _) = tokio::join!(
+            child.wait(),
+            tee(stdout, log.as_ref(), verbose, false),
+            tee(stderr, log.as_ref(), verbose, true)
+        );
+        status
+    };
+    tokio::select! {
+        status = output => Ok(status?),
+        _ = interrupted() => {
+            child.kill().await?;
+            Err(interrupted_error())
+        }
+    }
+}
@@ -4,11 +4,25 @@
 //!
 //! author: Vincenzo Palazzo <vincenzopalazzodev@gmail.com>
 #![allow(dead_code)]
+pub mod cargo;
 pub mod cln_conf;
+pub mod container;
+pub mod download;
+pub mod entry_point;
 pub mod errors;
+pub mod go;
+pub mod i18n;
+pub mod installer;
+pub mod interrupt;
+pub mod names;
+pub mod node;
 pub mod plugin;
 pub mod plugin_conf;
 pub mod plugin_manager;
+pub mod python;
 pub mod repository;
+pub mod run_as;
+pub mod sandbox;
+pub mod secrets;
 pub mod url;
 pub mod utils;
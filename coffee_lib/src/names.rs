@@ -0,0 +1,87 @@
+//! Matching of the plugin names typed by the user, the names are
+//! compared without the case and with `-` and `_` as the same
+//! character, and the closest names are suggested on a typo.
+
+/// Most names suggested to the user.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The `name` in the form used to compare it: lowercase and with
+/// `_` replaced by `-`.
+pub fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "-")
+}
+
+/// true when `name` and `other` are the same plugin name.
+pub fn matches(name: &str, other: &str) -> bool {
+    name == other || normalize(name) == normalize(other)
+}
+
+/// edit distance between `a` and `b`, counting the insertions,
+/// the deletions and the substitutions.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The `candidates` close to the `name`, the closest first: a few
+/// typos away, or containing it.
+pub fn suggestions<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let name = normalize(name);
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, String)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let normalized = normalize(candidate);
+            let distance = distance(&name, &normalized);
+            let contains = name.len() >= 3 && normalized.contains(&name);
+            (distance <= max_distance || contains).then(|| (distance, candidate.to_owned()))
+        })
+        .collect();
+    close.sort();
+    close.dedup_by(|a, b| a.1 == b.1);
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, matches, suggestions};
+
+    #[test]
+    fn test_names_matching() {
+        assert!(matches("Summary", "summary"));
+        assert!(matches("cln_rest", "cln-rest"));
+        assert!(!matches("summary", "summars"));
+        assert_eq!(distance("summary", "sumary"), 1);
+        assert_eq!(distance("", "abc"), 3);
+
+        let names = [
+            "summary",
+            "summars",
+            "rebalance",
+            "clnrest",
+            "cln-rest-python",
+        ];
+        assert_eq!(
+            suggestions("sumary", names.iter().copied()),
+            vec!["summary", "summars"]
+        );
+        assert_eq!(
+            suggestions("CLN_REST", names.iter().copied()),
+            vec!["clnrest", "cln-rest-python"]
+        );
+        assert!(suggestions("sauron", names.iter().copied()).is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+//! Node runtime of the javascript plugins, the version required by a
+//! plugin (`engines.node` of its `package.json`) is checked against
+//! the node of the system, or against the runtime pinned inside the
+//! configuration, that coffee downloads and uses only for the plugins.
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use log::debug;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::download;
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+use crate::sandbox::Sandbox;
+
+/// Where the node runtimes are downloaded from.
+const NODE_DIST: &str = "https://nodejs.org/dist";
+
+/// Node options of the javascript plugins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeConf {
+    /// version of the node runtime of the plugins, e.g. `20.11.1`,
+    /// the node of the system is used when missing.
+    pub runtime: Option<String>,
+    /// directory of the node runtimes, chosen by the plugin manager.
+    #[serde(skip)]
+    pub runtime_dir: Option<String>,
+}
+
+impl NodeConf {
+    pub fn with_runtime_dir(mut self, runtime_dir: &str) -> Self {
+        self.runtime_dir = Some(runtime_dir.to_owned());
+        self
+    }
+}
+
+/// Parse the node `range` of `engines.node` (e.g. `>=18 <21 || 22.x`),
+/// every alternative of the range is a requirement.
+fn parse_range(range: &str) -> Result<Vec<VersionReq>, CoffeeError> {
+    range
+        .split("||")
+        .map(|alternative| {
+            // the comparators of npm are separated by spaces.
+            let alternative = alternative
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(", ")
+                .replace(">=, ", ">=")
+                .replace("<=, ", "<=")
+                .replace(">, ", ">")
+                .replace("<, ", "<")
+                .replace("=, ", "=");
+            let alternative = if alternative.is_empty() {
+                "*"
+            } else {
+                &alternative
+            };
+            VersionReq::parse(alternative).map_err(|err| {
+                CoffeeError::new(1, &format!("node version `{range}` not supported: {err}"))
+            })
+        })
+        .collect()
+}
+
+/// true when the node `version` (e.g. `v20.11.1`) is inside the `range`.
+fn satisfies(range: &[VersionReq], version: &str) -> bool {
+    let Ok(version) = Version::parse(version.trim().trim_start_matches('v')) else {
+        return false;
+    };
+    range.iter().any(|req| req.matches(&version))
+}
+
+/// The node version required by the plugin inside `path`, if any.
+async fn required_version(path: &str) -> Result<Option<String>, CoffeeError> {
+    let package = format!("{path}/package.json");
+    if !Path::new(&package).exists() {
+        return Ok(None);
+    }
+    let package: Value = serde_json::from_str(&fs::read_to_string(&package).await?)
+        .map_err(|err| CoffeeError::new(1, &format!("{package} malformed: {err}")))?;
+    Ok(package["engines"]["node"].as_str().map(str::to_owned))
+}
+
+/// the version of the node inside the PATH.
+async fn system_version() -> Result<String, CoffeeError> {
+    let output = Command::new("node")
+        .arg("--version")
+        .output()
+        .await
+        .map_err(|err| CoffeeError::new(1, &format!("node not found: {err}")))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// the name of the architecture inside the node dist.
+fn node_arch() -> Result<&'static str, CoffeeError> {
+    match std::env::consts::ARCH {
+        "x86_64" => Ok("x64"),
+        "aarch64" => Ok("arm64"),
+        "arm" => Ok("armv7l"),
+        arch => Err(CoffeeError::new(
+            1,
+            &format!("no node runtime for the architecture {arch}"),
+        )),
+    }
+}
+
+/// Download the node runtime `version` inside the `runtime_dir`, if
+/// it is not there yet, return the directory of the runtime.
+async fn download_runtime(
+    sandbox: &Sandbox,
+    runtime_dir: &str,
+    version: &str,
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let dist = format!("node-v{version}-linux-{}", node_arch()?);
+    let dir = format!("{runtime_dir}/{dist}");
+    if Path::new(&format!("{dir}/bin/node")).exists() {
+        return Ok(dir);
+    }
+    fs::create_dir_all(runtime_dir).await?;
+    let archive = format!("{dist}.tar.xz");
+    let _slot = download::slot().await?;
+    for file in [archive.as_str(), "SHASUMS256.txt"] {
+        let url = format!("{NODE_DIST}/v{version}/{file}");
+        sandbox
+            .run("curl", &["-fsSL", "-o", file, &url], runtime_dir, verbose)
+            .await?;
+    }
+    let archive_path = format!("{runtime_dir}/{archive}");
+    let sums = fs::read_to_string(format!("{runtime_dir}/SHASUMS256.txt")).await?;
+    let expected = sums
+        .lines()
+        .find(|line| line.ends_with(&format!("  {archive}")))
+        .and_then(|line| line.split_whitespace().next())
+        .unwrap_or_default()
+        .to_owned();
+    let hash: String = Sha256::digest(fs::read(&archive_path).await?)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if hash != expected {
+        let _ = fs::remove_file(&archive_path).await;
+        let err = CoffeeError::new(
+            1,
+            &format!("checksum of {archive} does not match, the download is removed"),
+        );
+        return Err(err);
+    }
+    sandbox
+        .run("tar", &["-xJf", &archive], runtime_dir, verbose)
+        .await?;
+    fs::remove_file(&archive_path).await?;
+    fs::remove_file(format!("{runtime_dir}/SHASUMS256.txt")).await?;
+    debug!("node runtime {version} downloaded inside {dir}");
+    Ok(dir)
+}
+
+/// Install the javascript plugin `name` inside the directory `path`,
+/// with the node required by the plugin, return the executable.
+pub async fn install(
+    sandbox: &Sandbox,
+    path: &str,
+    name: &str,
+    main_file: &str,
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let conf = &sandbox.node;
+    let range = match required_version(path).await? {
+        Some(range) => Some((parse_range(&range)?, range)),
+        None => None,
+    };
+    let runtime = match (&conf.runtime, &conf.runtime_dir) {
+        (Some(version), Some(runtime_dir)) => {
+            if let Some((req, range)) = &range {
+                if !satisfies(req, version) {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("plugin `{name}` needs node {range}, the runtime of coffee is {version}"),
+                    );
+                    return Err(err);
+                }
+            }
+            Some(download_runtime(sandbox, runtime_dir, version, verbose).await?)
+        }
+        _ => {
+            if let Some((req, range)) = &range {
+                let version = system_version().await?;
+                if !satisfies(req, &version) {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("plugin `{name}` needs node {range}, the system has {version}, pin a runtime with `node.runtime`"),
+                    );
+                    return Err(err);
+                }
+            }
+            None
+        }
+    };
+    let node = match &runtime {
+        Some(dir) => format!("{dir}/bin/node"),
+        None => "node".to_owned(),
+    };
+    if Path::new(&format!("{path}/package.json")).exists() {
+        match &runtime {
+            // the npm of the runtime must run with the node of the runtime.
+            Some(dir) => {
+                let npm = format!("{dir}/lib/node_modules/npm/bin/npm-cli.js");
+                sandbox
+                    .run(&node, &[&npm, "install", "--omit=dev"], path, verbose)
+                    .await?
+            }
+            None => {
+                let args = ["install", "--omit=dev"];
+                sandbox
+                    .run_build(&PluginLang::JavaScript, "npm", &args, path, verbose)
+                    .await?
+            }
+        }
+    }
+    let wrapper_path = format!("{path}/.coffee-{name}");
+    let wrapper = format!(
+        "#!/bin/sh\n# generated by coffee for the plugin `{name}`, do not edit.\nexec '{node}' '{main_file}' \"$@\"\n"
+    );
+    fs::write(&wrapper_path, wrapper).await?;
+    fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+    Ok(wrapper_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, satisfies};
+
+    #[test]
+    fn test_node_range() {
+        let range = parse_range(">=18 <21 || 22.x").unwrap();
+        assert!(satisfies(&range, "v18.19.0"));
+        assert!(satisfies(&range, "v20.11.1"));
+        assert!(!satisfies(&range, "v21.0.0"));
+        assert!(satisfies(&range, "22.1.0"));
+        assert!(!satisfies(&range, "v16.20.2"));
+        let range = parse_range("^20.0.0").unwrap();
+        assert!(satisfies(&range, "v20.11.1"));
+        assert!(!satisfies(&range, "v18.19.0"));
+    }
+}
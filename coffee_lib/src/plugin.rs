@@ -1,10 +1,19 @@
 //! Plugin module that abstract the concept of a cln plugin
 //! from a plugin manager point of view.
-use crate::{errors::CoffeeError, plugin_conf::Conf};
+use crate::entry_point::resolve_entry_point;
+use crate::errors::CoffeeError;
+use crate::installer::InstallerRegistry;
+use crate::plugin_conf::{Conf, Provides};
+use crate::run_as::RunAs;
+use crate::sandbox::Sandbox;
+use crate::utils::{get_shebang_interpreter, shell_quote};
+use crate::{cargo, go, node, python};
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use tokio::process::Command;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tokio::fs;
 
 /// Plugin language definition
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -16,43 +25,70 @@ pub enum PluginLang {
     JVM,
     JavaScript,
     TypeScript,
+    /// C and C++, built with make.
+    C,
+    /// sh and bash scripts, nothing to build.
+    Shell,
+    /// language declared inside the manifest and built by the
+    /// installer registered for it, see `InstallerRegistry`.
+    Other(String),
     Unknown,
 }
 
 impl PluginLang {
+    /// guess the language of a script from the interpreter
+    /// declared inside the shebang line.
+    pub fn from_shebang(interpreter: &str) -> Self {
+        let interpreter = interpreter.split('/').next_back().unwrap_or_default();
+        match interpreter {
+            name if name.contains("python") => PluginLang::Python,
+            name if name.contains("deno") || name.contains("ts-node") => PluginLang::TypeScript,
+            name if name.contains("node") => PluginLang::JavaScript,
+            name if name.contains("dart") => PluginLang::Dart,
+            name if name.contains("bash") || name == "sh" || name == "env sh" => PluginLang::Shell,
+            _ => PluginLang::Unknown,
+        }
+    }
+
+    /// guess the language of a source file from its `extension`.
+    pub fn from_extension(extension: &str) -> Self {
+        match extension {
+            "py" => PluginLang::Python,
+            "go" => PluginLang::Go,
+            "rs" => PluginLang::Rust,
+            "ts" => PluginLang::TypeScript,
+            "js" => PluginLang::JavaScript,
+            "c" | "cc" | "cpp" | "cxx" => PluginLang::C,
+            "sh" | "bash" => PluginLang::Shell,
+            "java" | "kt" | "scala" => PluginLang::JVM,
+            _ => PluginLang::Unknown,
+        }
+    }
+
     pub async fn default_install(
         &self,
         path: &str,
         name: &str,
+        build_flags: &[String],
         verbose: bool,
+        sandbox: &Sandbox,
     ) -> Result<String, CoffeeError> {
         match self {
             PluginLang::Python => {
                 /* 1. RUN PIP install or poetry install
                  * 2. return the path of the main file */
-                let req_file = format!("{path}/requirements.txt");
-                let main_file = format!("{path}/{name}.py");
-                let mut cmd = Command::new("pip");
-                cmd.arg("install").arg("-r").arg(&req_file.clone());
-                if verbose {
-                    let _ = cmd
-                        .spawn()
-                        .expect("Unable to run the command")
-                        .wait()
-                        .await?;
-                } else {
-                    let _ = cmd.output().await?;
-                }
-                Ok(main_file)
+                let main_file = resolve_entry_point(self, path, name)?;
+                python::install(sandbox, path, name, &main_file, verbose).await
             }
             PluginLang::Go => {
-                /* better instructions needed here */
-                todo!()
+                /* 1. run go build with the proxy and the flags of the configuration
+                 * 2. return the binary path */
+                go::install(sandbox, path, name, build_flags, verbose).await
             }
             PluginLang::Rust => {
                 /* 1. run cargo build in release mode
                  * 2. return the binary path */
-                todo!()
+                cargo::install(sandbox, path, name, build_flags, verbose).await
             }
             PluginLang::Dart => {
                 /* 1. run dart compile exe and
@@ -60,16 +96,97 @@ impl PluginLang {
                 todo!()
             }
             PluginLang::JavaScript => {
-                /* better instructions needed here */
-                todo!()
+                /* 1. check the node required by the plugin, or download the pinned one
+                 * 2. run npm install and return a wrapper that runs the main file */
+                let main_file = resolve_entry_point(self, path, name)?;
+                node::install(sandbox, path, name, &main_file, verbose).await
             }
             PluginLang::TypeScript => {
                 /* 1. From https://github.com/runcitadel/core-ln.ts
                 deno run --allow-env --allow-read --allow-write src/generate.ts
                  * 2. run the ts file */
-                todo!()
+                let err = CoffeeError::new(
+                    1,
+                    &format!("TypeScript plugins need an install script, `{name}` has none"),
+                );
+                Err(err)
+            }
+            PluginLang::JVM => {
+                /* 1. run the gradle or the maven build
+                 * 2. return a launcher that runs the jar with java */
+                let (program, args, jar_dir) = if Path::new(&format!("{path}/pom.xml")).exists() {
+                    ("mvn", vec!["-q", "-DskipTests", "package"], "target")
+                } else if Path::new(&format!("{path}/gradlew")).exists() {
+                    ("./gradlew", vec!["-q", "build", "-x", "test"], "build/libs")
+                } else {
+                    ("gradle", vec!["-q", "build", "-x", "test"], "build/libs")
+                };
+                sandbox
+                    .run_build(self, program, &args, path, verbose)
+                    .await?;
+                let jar = built_jar(&format!("{path}/{jar_dir}")).await?;
+                let launcher_path = wrapper_path(sandbox, name).await?;
+                let launcher = format!(
+                    "#!/bin/sh\n# generated by coffee for the plugin `{name}`, do not edit.\nif ! command -v java >/dev/null 2>&1; then\n  echo {} >&2\n  exit 1\nfi\nexec java -jar {} \"$@\"\n",
+                    shell_quote(&format!("java not found, the plugin `{name}` needs a java runtime")),
+                    shell_quote(&jar)
+                );
+                fs::write(&launcher_path, launcher).await?;
+                fs::set_permissions(&launcher_path, std::fs::Permissions::from_mode(0o755)).await?;
+                Ok(launcher_path)
+            }
+            PluginLang::C => {
+                /* 1. run the configure script, when there is no Makefile yet
+                 * 2. run make with the build flags of the manifest
+                 * 3. return the binary, named as the plugin */
+                if !Path::new(&format!("{path}/Makefile")).exists()
+                    && Path::new(&format!("{path}/configure")).exists()
+                {
+                    sandbox
+                        .run_build(self, "sh", &["configure"], path, verbose)
+                        .await?;
+                }
+                let flags: Vec<&str> = build_flags.iter().map(String::as_str).collect();
+                sandbox
+                    .run_build(self, "make", &flags, path, verbose)
+                    .await?;
+                let main_file = resolve_entry_point(self, path, name)?;
+                if !Path::new(&main_file).is_file() {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("make did not build `{name}` inside `{path}`"),
+                    );
+                    return Err(err);
+                }
+                Ok(main_file)
+            }
+            PluginLang::Shell => {
+                /* 1. nothing to build, check that the script is executable
+                 * 2. return a wrapper that runs it from the plugin directory */
+                let main_file = resolve_entry_point(self, path, name)?;
+                check_executable(&main_file).await?;
+                let wrapper_path = wrapper_path(sandbox, name).await?;
+                let wrapper = format!(
+                    "#!/bin/sh\n# generated by coffee for the plugin `{name}`, do not edit.\ncd {} && exec {} \"$@\"\n",
+                    shell_quote(path),
+                    shell_quote(&main_file)
+                );
+                fs::write(&wrapper_path, wrapper).await?;
+                fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+                Ok(wrapper_path)
+            }
+            PluginLang::Other(lang) => {
+                let Some(installer) = InstallerRegistry::get(lang) else {
+                    let err = CoffeeError::new(
+                        1,
+                        &format!("language {lang} not supported, no installer registered for it"),
+                    );
+                    return Err(err);
+                };
+                installer
+                    .install(path, name, build_flags, verbose, sandbox)
+                    .await
             }
-            PluginLang::JVM => todo!(),
             PluginLang::Unknown => {
                 /* 1. emit an error message  */
                 let err = CoffeeError::new(
@@ -82,6 +199,63 @@ impl PluginLang {
     }
 }
 
+/// Path of the wrapper of the plugin `name`, inside the wrapper
+/// directory of the `sandbox` and outside of the repository.
+async fn wrapper_path(sandbox: &Sandbox, name: &str) -> Result<String, CoffeeError> {
+    let Some(wrapper_dir) = &sandbox.wrapper_dir else {
+        let err = CoffeeError::new(1, &format!("no directory for the wrapper of `{name}`"));
+        return Err(err);
+    };
+    fs::create_dir_all(wrapper_dir).await?;
+    Ok(format!("{wrapper_dir}/{name}"))
+}
+
+/// The jar built inside `dir`, the jars with the sources, the docs
+/// or without the dependencies (gradle `-plain`) are not runnable.
+async fn built_jar(dir: &str) -> Result<String, CoffeeError> {
+    let mut jars = vec![];
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = file_name.strip_suffix(".jar") else {
+            continue;
+        };
+        if ["-sources", "-javadoc", "-plain"]
+            .iter()
+            .any(|suffix| stem.ends_with(suffix))
+        {
+            continue;
+        }
+        jars.push(entry.path().to_string_lossy().to_string());
+    }
+    jars.sort();
+    match jars.len() {
+        1 => Ok(jars.remove(0)),
+        0 => Err(CoffeeError::new(
+            1,
+            &format!("the build did not produce any jar inside `{dir}`"),
+        )),
+        _ => Err(CoffeeError::new(
+            1,
+            &format!("more jars built inside `{dir}`: {}", jars.join(", ")),
+        )),
+    }
+}
+
+/// Fail when the script at `path` is not executable, the shell
+/// plugins often run themselves or their helpers directly.
+async fn check_executable(path: &str) -> Result<(), CoffeeError> {
+    let mode = fs::metadata(path).await?.permissions().mode();
+    if mode & 0o111 == 0 {
+        let err = CoffeeError::new(
+            1,
+            &format!("script `{path}` is not executable, run `chmod +x` on it"),
+        );
+        return Err(err);
+    }
+    Ok(())
+}
+
 /// Plugin struct definition
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Plugin {
@@ -92,6 +266,23 @@ pub struct Plugin {
     pub path: String,
     lang: PluginLang,
     conf: Option<Conf>,
+    /// the plugin is a single script file and not
+    /// a directory with a plugin inside.
+    #[serde(default)]
+    standalone: bool,
+    /// path of the executable registered inside the
+    /// core lightning configuration, known after the
+    /// plugin is configured.
+    #[serde(default)]
+    exec_path: Option<String>,
+    /// the plugin runs from the working directory of the
+    /// developer, and it is never upgraded by coffee.
+    #[serde(default)]
+    dev_linked: bool,
+    /// hash of the content of the plugin inside the repository,
+    /// it changes only when the plugin changes.
+    #[serde(default)]
+    content_hash: Option<String>,
 }
 
 impl Plugin {
@@ -109,13 +300,96 @@ impl Plugin {
             path: path.to_owned(),
             lang: plugin_lang,
             conf: config,
+            standalone: false,
+            exec_path: None,
+            dev_linked: false,
+            content_hash: None,
+        }
+    }
+
+    /// create a new instance of a plugin that is a single
+    /// script file (e.g. `summary.py` at the root of the repository).
+    pub fn new_standalone(
+        name: &str,
+        root_path: &str,
+        script_path: &str,
+        lang: PluginLang,
+    ) -> Self {
+        Plugin {
+            name: name.to_owned(),
+            root_path: root_path.to_owned(),
+            path: script_path.to_owned(),
+            lang,
+            conf: None,
+            standalone: true,
+            exec_path: None,
+            dev_linked: false,
+            content_hash: None,
         }
     }
 
+    /// generate the wrapper that run the standalone script with
+    /// the interpreter declared in its shebang, so the script
+    /// works also when it is not marked as executable.
+    ///
+    /// The wrapper is written inside `wrapper_dir`, outside of the
+    /// repository, in case of success return the path of the wrapper.
+    async fn generate_wrapper(&self, wrapper_dir: Option<&str>) -> Result<String, CoffeeError> {
+        let interpreter = match get_shebang_interpreter(Path::new(&self.path)) {
+            Some(interpreter) => interpreter,
+            None => {
+                let err = CoffeeError::new(
+                    1,
+                    &format!("script `{}` do not declare any interpreter", self.path),
+                );
+                return Err(err);
+            }
+        };
+        let Some(wrapper_dir) = wrapper_dir else {
+            let err = CoffeeError::new(
+                1,
+                &format!("no directory for the wrapper of `{}`", self.name),
+            );
+            return Err(err);
+        };
+        fs::create_dir_all(wrapper_dir).await?;
+        let wrapper_path = format!("{wrapper_dir}/{}", self.name);
+        let wrapper = format!(
+            "#!/bin/sh\n# generated by coffee for the plugin `{}`, do not edit.\nexec {} {} \"$@\"\n",
+            self.name,
+            interpreter,
+            shell_quote(&self.path)
+        );
+        fs::write(&wrapper_path, wrapper).await?;
+        fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+        debug!("generated wrapper {wrapper_path} for {}", self.path);
+        Ok(wrapper_path)
+    }
+
     /// configure the plugin in order to work with cln.
     ///
-    /// In case of success return the path of the executable.
-    pub async fn configure(&mut self, verbose: bool) -> Result<String, CoffeeError> {
+    /// The build commands run inside the `sandbox`, in case of
+    /// success return the path of the executable.
+    pub async fn configure(
+        &mut self,
+        verbose: bool,
+        sandbox: &Sandbox,
+    ) -> Result<String, CoffeeError> {
+        if self.standalone {
+            if self.lang == PluginLang::Shell {
+                check_executable(&self.path).await?;
+            }
+            let exec_path = self
+                .generate_wrapper(sandbox.wrapper_dir.as_deref())
+                .await?;
+            self.exec_path = Some(exec_path.clone());
+            return Ok(exec_path);
+        }
+        let build_flags = self
+            .conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.build_flags.clone())
+            .unwrap_or_default();
         let exec_path = if let Some(conf) = &self.conf {
             if let Some(script) = &conf.plugin.install {
                 let script = script.trim();
@@ -125,33 +399,64 @@ impl Plugin {
                     debug!("cmd {:#?}", cmd);
                     let cmd_tok: Vec<&str> = cmd.split(" ").collect();
                     let command = cmd_tok.first().unwrap().to_string();
-                    let mut cmd = Command::new(command);
-                    cmd.args(&cmd_tok[1..cmd_tok.len()]);
-                    cmd.current_dir(self.root_path.clone());
-                    if verbose {
-                        let _ = cmd
-                            .spawn()
-                            .expect("Unable to run the command")
-                            .wait()
-                            .await?;
-                    } else {
-                        let _ = cmd.output().await?;
-                    }
+                    sandbox
+                        .run_build(
+                            &self.lang,
+                            &command,
+                            &cmd_tok[1..],
+                            &self.root_path,
+                            verbose,
+                        )
+                        .await?;
                 }
                 format!("{}/{}", self.path, conf.plugin.main)
             } else {
                 self.lang
-                    .default_install(&self.path, &self.name, verbose)
+                    .default_install(&self.path, &self.name, &build_flags, verbose, sandbox)
                     .await?
             }
         } else {
             self.lang
-                .default_install(&self.path, &self.name, verbose)
+                .default_install(&self.path, &self.name, &build_flags, verbose, sandbox)
                 .await?
         };
+        self.exec_path = Some(exec_path.clone());
         Ok(exec_path)
     }
 
+    /// give the plugin to the user of `run_as`, the plugin is then
+    /// executed by the wrapper generated inside `wrapper_dir`.
+    ///
+    /// In case of success return the path of the wrapper.
+    pub async fn run_as(
+        &mut self,
+        run_as: &RunAs,
+        wrapper_dir: &str,
+    ) -> Result<String, CoffeeError> {
+        let Some(exec_path) = self.exec_path.clone() else {
+            let err = CoffeeError::new(1, &format!("plugin `{}` is not configured", self.name));
+            return Err(err);
+        };
+        // a standalone script lives at the root of the repository,
+        // only the script is given to the user and not the others.
+        if self.standalone {
+            run_as.chown(&self.path).await?;
+        } else {
+            run_as.chown(&self.root_path).await?;
+        }
+        fs::create_dir_all(wrapper_dir).await?;
+        let wrapper_path = format!("{wrapper_dir}/{}", self.name);
+        run_as
+            .write_wrapper(&self.name, &exec_path, &wrapper_path)
+            .await?;
+        debug!(
+            "plugin {} runs as {} with {wrapper_path}",
+            self.name, run_as.user
+        );
+        self.exec_path = Some(wrapper_path.clone());
+        Ok(wrapper_path)
+    }
+
     /// upgrade the plugin to a new version.
     pub async fn upgrade(&mut self) -> Result<(), CoffeeError> {
         todo!("not implemented yet")
@@ -165,6 +470,103 @@ impl Plugin {
     pub fn name(&self) -> String {
         self.name.clone()
     }
+
+    /// root path of the plugin.
+    pub fn root_path(&self) -> String {
+        self.root_path.clone()
+    }
+
+    /// language of the plugin.
+    pub fn lang(&self) -> PluginLang {
+        self.lang.clone()
+    }
+
+    /// coffee manifest of the plugin, if any.
+    pub fn conf(&self) -> Option<Conf> {
+        self.conf.clone()
+    }
+
+    /// true when the plugin is a single script file.
+    pub fn is_standalone(&self) -> bool {
+        self.standalone
+    }
+
+    /// tags declared inside the coffee manifest of the plugin.
+    pub fn tags(&self) -> Vec<String> {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.tags.clone())
+            .unwrap_or_default()
+    }
+
+    /// RPC methods, hooks and notifications declared inside the
+    /// coffee manifest of the plugin.
+    pub fn provides(&self) -> Provides {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.provides.clone())
+            .unwrap_or_default()
+    }
+
+    /// other plugins that the plugin needs, declared inside the
+    /// coffee manifest.
+    pub fn dependencies(&self) -> Vec<String> {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.dependencies.clone())
+            .unwrap_or_default()
+    }
+
+    /// true when the coffee manifest declares the plugin payment critical.
+    pub fn is_payment_critical(&self) -> bool {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.payment_critical)
+            .unwrap_or_default()
+    }
+
+    /// node features declared inside the coffee manifest of the plugin.
+    pub fn node_features(&self) -> Vec<String> {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.plugin.node_features.clone())
+            .unwrap_or_default()
+    }
+
+    /// path of the executable registered inside the core
+    /// lightning configuration.
+    pub fn exec_path(&self) -> Option<String> {
+        self.exec_path.clone()
+    }
+
+    /// set the path of the executable registered inside the
+    /// core lightning configuration, used when the plugin is
+    /// already configured by another plugin manager.
+    pub fn set_exec_path(&mut self, exec_path: &str) {
+        self.exec_path = Some(exec_path.to_owned());
+    }
+
+    /// true when the plugin runs from the working directory
+    /// of the developer.
+    pub fn is_dev_linked(&self) -> bool {
+        self.dev_linked
+    }
+
+    /// mark the plugin as running from the working directory
+    /// of the developer.
+    pub fn set_dev_linked(&mut self) {
+        self.dev_linked = true;
+    }
+
+    /// hash of the content of the plugin inside the repository.
+    pub fn content_hash(&self) -> Option<String> {
+        self.content_hash.clone()
+    }
+
+    /// set the hash of the content of the plugin inside the repository.
+    pub fn set_content_hash(&mut self, content_hash: Option<String>) {
+        self.content_hash = content_hash;
+    }
 }
 
 impl fmt::Display for Plugin {
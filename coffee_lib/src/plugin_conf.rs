@@ -14,9 +14,60 @@ pub struct Plugin {
     pub version: String,
     pub lang: String,
     pub deprecated: Option<()>,
+    /// other plugins that the plugin needs, by name.
     pub dependencies: Option<Vec<String>>,
     pub install: Option<String>,
     pub main: String,
+    /// categories of the plugin (e.g. backup, routing, accounting).
+    pub tags: Option<Vec<String>>,
+    /// homepage of the plugin.
+    pub homepage: Option<String>,
+    /// issue tracker where the plugin bugs are reported.
+    pub issues: Option<String>,
+    /// features that the node must enable for the plugin,
+    /// e.g. `dual-fund` or `offers`.
+    pub node_features: Option<Vec<String>>,
+    /// command that tests the plugin against a running
+    /// regtest node, e.g. `pytest tests/`.
+    pub test: Option<String>,
+    /// arguments of the build tool when there is no install
+    /// script, e.g. `CFLAGS=-O2` for make.
+    pub build_flags: Option<Vec<String>>,
+    /// RPC methods, hooks and notifications of core lightning
+    /// that the plugin provides.
+    pub provides: Option<Provides>,
+    /// the plugin takes part in the payments of the node (e.g. it
+    /// holds HTLCs inside a hook), so it is not stopped while the
+    /// node has HTLCs in flight.
+    pub payment_critical: Option<bool>,
+}
+
+/// What a plugin provides to the node, declared inside the manifest.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Provides {
+    /// RPC methods registered by the plugin, e.g. `summary`.
+    pub rpc_methods: Vec<String>,
+    /// hooks registered by the plugin, e.g. `htlc_accepted`.
+    pub hooks: Vec<String>,
+    /// notifications the plugin subscribes to, e.g. `forward_event`.
+    pub notifications: Vec<String>,
+}
+
+impl Provides {
+    pub fn is_empty(&self) -> bool {
+        self.rpc_methods.is_empty() && self.hooks.is_empty() && self.notifications.is_empty()
+    }
+
+    /// true when the plugin provides the RPC method, the hook
+    /// or the notification `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.rpc_methods
+            .iter()
+            .chain(&self.hooks)
+            .chain(&self.notifications)
+            .any(|provided| provided.eq_ignore_ascii_case(name))
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -26,6 +77,23 @@ pub struct Deprecaterd {
 
 #[cfg(test)]
 mod tests {
+    use super::Conf;
+
     #[test]
     fn test_remote() {}
+
+    #[test]
+    fn test_plugin_conf_provides() {
+        let conf: Conf = serde_json::from_str(
+            r#"{"plugin": {"name": "summary", "version": "0.1.0", "lang": "python", "main": "summary.py",
+                "provides": {"rpc_methods": ["summary"], "hooks": ["htlc_accepted"]}}}"#,
+        )
+        .unwrap();
+        let provides = conf.plugin.provides.unwrap();
+        assert!(provides.contains("htlc_accepted"));
+        assert!(provides.contains("summary"));
+        assert!(!provides.contains("forward_event"));
+        assert!(provides.notifications.is_empty());
+        assert!(conf.plugin.payment_critical.is_none());
+    }
 }
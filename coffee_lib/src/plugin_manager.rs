@@ -4,23 +4,37 @@ use serde_json::Value;
 
 use crate::errors::CoffeeError;
 
+/// Read-only API of a plugin manager, it works from the local
+/// state alone, so it never changes the state and never reaches
+/// the network.
+#[async_trait]
+pub trait PluginInspector {
+    /// return the list of plugins installed by the plugin manager.
+    async fn list(&self, remotes: bool) -> Result<Value, CoffeeError>;
+
+    /// return the information about a plugin, installed or
+    /// available inside the repositories.
+    async fn show(&self, plugin: &str) -> Result<Value, CoffeeError>;
+}
+
 /// Plugin manager traits that define the API a generic
 /// plugin manager.
 #[async_trait]
-pub trait PluginManager {
+pub trait PluginManager: PluginInspector {
     /// configure the plugin manger.
     async fn configure(&mut self) -> Result<(), CoffeeError>;
 
-    /// install a plugin by name, return an error if some error happens.
+    /// install a sequence of plugins by name, return an error if some
+    /// of them fails to install.
     async fn install(
         &mut self,
-        plugins: &str,
+        plugins: &[&str],
         verbose: bool,
         try_dynamic: bool,
     ) -> Result<(), CoffeeError>;
 
-    /// return the list of plugins installed by the plugin manager.
-    async fn list(&mut self, remotes: bool) -> Result<Value, CoffeeError>;
+    /// remove a plugin installed by the plugin manager.
+    async fn remove(&mut self, plugin: &str) -> Result<(), CoffeeError>;
 
     /// upgrade a sequence of plugin managed by the plugin manager.
     async fn upgrade(&mut self, plugins: &[&str]) -> Result<(), CoffeeError>;
@@ -28,6 +42,9 @@ pub trait PluginManager {
     /// add the remote repository to the plugin manager.
     async fn add_remote(&mut self, name: &str, url: &str) -> Result<(), CoffeeError>;
 
+    /// remove the remote repository from the plugin manager.
+    async fn rm_remote(&mut self, name: &str) -> Result<(), CoffeeError>;
+
     /// set up the core lightning configuration target for the
     /// plugin manager.
     async fn setup(&mut self, cln_conf_path: &str) -> Result<(), CoffeeError>;
@@ -0,0 +1,535 @@
+//! Install of the dependencies of the python plugins.
+//!
+//! With `require_hashes` the dependencies resolved at the first install
+//! are pinned with their hashes inside the lockfile of the plugin, and
+//! the following installs accept only the same files (pip `--require-hashes`).
+//!
+//! The dependencies go inside the python environment of coffee, or
+//! inside a virtualenv of the plugin, or inside a virtualenv shared by
+//! the plugins with compatible requirements, and they are installed
+//! with pip, uv or the pip run by pipx. With the container build the
+//! dependencies are installed by the pip of the container inside the
+//! directory of the plugin, and the plugin runs with the host python.
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::errors::CoffeeError;
+use crate::plugin::PluginLang;
+use crate::sandbox::Sandbox;
+use crate::utils::shell_quote;
+
+/// Header of the lockfiles, followed by the hash of the
+/// `requirements.txt` that was resolved.
+const LOCK_HEADER: &str = "# generated by coffee, requirements:";
+
+/// Directory of the plugin where the container build installs the
+/// dependencies.
+const CONTAINER_DEPS: &str = ".coffee-deps";
+
+/// Python environment where the dependencies of a plugin go.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PythonEnv {
+    /// the environment of the `pip` inside the PATH.
+    #[default]
+    System,
+    /// a virtualenv for each plugin.
+    Isolated,
+    /// one virtualenv for all the plugins with compatible
+    /// requirements, the others get their own virtualenv.
+    Shared,
+}
+
+/// Tool that installs the python dependencies.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PythonInstaller {
+    #[default]
+    Pip,
+    Uv,
+    /// the latest pip run by pipx, for the hosts without a pip,
+    /// it needs a virtualenv.
+    Pipx,
+}
+
+/// Install options of the python plugins.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PythonConf {
+    /// pin the dependencies with their hashes at the first install.
+    pub require_hashes: bool,
+    /// constraints files (pip `-c`) applied to all the plugins.
+    pub constraints: Vec<String>,
+    pub environment: PythonEnv,
+    pub installer: PythonInstaller,
+    /// directory of the lockfiles, chosen by the plugin manager.
+    #[serde(skip)]
+    pub lock_dir: Option<String>,
+    /// directory of the shared virtualenv, chosen by the plugin manager.
+    #[serde(skip)]
+    pub shared_dir: Option<String>,
+}
+
+impl PythonConf {
+    pub fn with_lock_dir(mut self, lock_dir: &str) -> Self {
+        self.lock_dir = Some(lock_dir.to_owned());
+        self
+    }
+
+    pub fn with_shared_dir(mut self, shared_dir: &str) -> Self {
+        self.shared_dir = Some(shared_dir.to_owned());
+        self
+    }
+
+    /// pip arguments of the constraints files.
+    fn constraints_args(&self) -> Vec<&str> {
+        self.constraints
+            .iter()
+            .flat_map(|constraints| ["-c", constraints.as_str()])
+            .collect()
+    }
+}
+
+/// Install command of the installer for a python environment.
+struct PipCommand {
+    program: String,
+    /// arguments before the ones of the install.
+    before: Vec<String>,
+    /// arguments after the ones of the install.
+    after: Vec<String>,
+}
+
+impl PipCommand {
+    /// the command of the `installer` for the virtualenv `venv`, or
+    /// for the python inside the PATH when missing.
+    fn new(installer: &PythonInstaller, venv: Option<&str>) -> Result<Self, CoffeeError> {
+        let (program, before, after) = match (installer, venv) {
+            (PythonInstaller::Pip, Some(venv)) => (format!("{venv}/bin/pip"), vec![], vec![]),
+            (PythonInstaller::Pip, None) => ("pip".to_owned(), vec![], vec![]),
+            (PythonInstaller::Uv, Some(venv)) => (
+                "uv".to_owned(),
+                vec!["pip".to_owned()],
+                vec!["--python".to_owned(), format!("{venv}/bin/python")],
+            ),
+            (PythonInstaller::Uv, None) => (
+                "uv".to_owned(),
+                vec!["pip".to_owned()],
+                vec!["--system".to_owned()],
+            ),
+            (PythonInstaller::Pipx, Some(venv)) => (
+                "pipx".to_owned(),
+                vec![
+                    "run".to_owned(),
+                    "pip".to_owned(),
+                    "--python".to_owned(),
+                    format!("{venv}/bin/python"),
+                ],
+                vec![],
+            ),
+            (PythonInstaller::Pipx, None) => {
+                let err = CoffeeError::new(
+                    1,
+                    "the pipx installer needs the python environment `isolated` or `shared`",
+                );
+                return Err(err);
+            }
+        };
+        Ok(PipCommand {
+            program,
+            before,
+            after,
+        })
+    }
+
+    async fn run(
+        &self,
+        sandbox: &Sandbox,
+        args: &[&str],
+        dir: &str,
+        verbose: bool,
+    ) -> Result<(), CoffeeError> {
+        let mut full_args: Vec<&str> = self.before.iter().map(String::as_str).collect();
+        full_args.extend(args);
+        full_args.extend(self.after.iter().map(String::as_str));
+        sandbox.run(&self.program, &full_args, dir, verbose).await
+    }
+}
+
+/// hash of the content of a `requirements.txt`.
+fn requirements_hash(requirements: &str) -> String {
+    Sha256::digest(requirements.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Lockfile with the dependencies listed inside the pip installation
+/// `report`, every dependency is pinned to its version and its hash.
+fn lock_from_report(report: &Value, requirements: &str) -> Result<String, CoffeeError> {
+    let mut lock = format!("{LOCK_HEADER} {}\n", requirements_hash(requirements));
+    let mut pinned = vec![];
+    for item in report["install"].as_array().into_iter().flatten() {
+        let name = item["metadata"]["name"].as_str().unwrap_or_default();
+        let version = item["metadata"]["version"].as_str().unwrap_or_default();
+        // e.g. `sha256=0a1b...`, missing for a directory or a vcs url.
+        let Some(hash) = item["download_info"]["archive_info"]["hash"].as_str() else {
+            let err = CoffeeError::new(
+                1,
+                &format!("dependency `{name}` can not be pinned with a hash"),
+            );
+            return Err(err);
+        };
+        pinned.push(format!(
+            "{name}=={version} --hash={}",
+            hash.replacen('=', ":", 1)
+        ));
+    }
+    pinned.sort();
+    for line in pinned {
+        lock += &format!("{line}\n");
+    }
+    Ok(lock)
+}
+
+/// Pin the `requirements` of the plugin `name` with the hashes, the
+/// commands run inside the directory `dir`, return the lockfile.
+async fn resolve_lock(
+    sandbox: &Sandbox,
+    pip: &PipCommand,
+    dir: &str,
+    req_file: &str,
+    requirements: &str,
+    name: &str,
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let constraints = sandbox.python.constraints_args();
+    if sandbox.python.installer == PythonInstaller::Uv {
+        // uv has no installation report, but it writes the hashes itself.
+        let output = format!("{dir}/.coffee-uv-lock-{name}.txt");
+        let mut args = vec![
+            "pip",
+            "compile",
+            "--generate-hashes",
+            "--quiet",
+            "-o",
+            &output,
+            req_file,
+        ];
+        args.extend(&constraints);
+        sandbox.run("uv", &args, dir, verbose).await?;
+        let pinned = fs::read_to_string(&output).await?;
+        let _ = fs::remove_file(&output).await;
+        return Ok(format!(
+            "{LOCK_HEADER} {}\n{pinned}",
+            requirements_hash(requirements)
+        ));
+    }
+    let report_file = format!("{dir}/.coffee-pip-report-{name}.json");
+    let mut args = vec![
+        "install",
+        "--dry-run",
+        "--ignore-installed",
+        "--quiet",
+        "--report",
+        &report_file,
+        "-r",
+        req_file,
+    ];
+    args.extend(&constraints);
+    pip.run(sandbox, &args, dir, verbose).await?;
+    let report = fs::read_to_string(&report_file).await?;
+    let _ = fs::remove_file(&report_file).await;
+    let report: Value = serde_json::from_str(&report)
+        .map_err(|err| CoffeeError::new(1, &format!("pip report of `{name}` malformed: {err}")))?;
+    lock_from_report(&report, requirements)
+}
+
+/// Install the `requirements.txt` of the plugin `name` inside the
+/// directory `path` with the `pip` of the python environment, the
+/// commands run inside the directory `dir`.
+async fn install_requirements(
+    sandbox: &Sandbox,
+    pip: &PipCommand,
+    dir: &str,
+    path: &str,
+    name: &str,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    let conf = &sandbox.python;
+    let req_file = format!("{path}/requirements.txt");
+    if !Path::new(&req_file).exists() {
+        debug!("plugin {name} has no requirements.txt");
+        return Ok(());
+    }
+    let constraints = conf.constraints_args();
+    let (true, Some(lock_dir)) = (conf.require_hashes, &conf.lock_dir) else {
+        let mut args = vec!["install", "-r", &req_file];
+        args.extend(&constraints);
+        return pip.run(sandbox, &args, dir, verbose).await;
+    };
+    let requirements = fs::read_to_string(&req_file).await?;
+    let lock_file = format!("{lock_dir}/{name}.txt");
+    let header = format!("{LOCK_HEADER} {}", requirements_hash(&requirements));
+    let locked = fs::read_to_string(&lock_file)
+        .await
+        .map(|lock| lock.lines().next() == Some(header.as_str()))
+        .unwrap_or_default();
+    if !locked {
+        // the requirements are new or they changed, resolve them again.
+        let lock = resolve_lock(sandbox, pip, dir, &req_file, &requirements, name, verbose).await?;
+        fs::create_dir_all(lock_dir).await?;
+        fs::write(&lock_file, lock).await?;
+        debug!("dependencies of {name} pinned inside {lock_file}");
+    }
+    let args = ["install", "--require-hashes", "--no-deps", "-r", &lock_file];
+    pip.run(sandbox, &args, dir, verbose).await
+}
+
+/// Create the virtualenv `venv`, if it does not exist yet.
+async fn create_venv(
+    sandbox: &Sandbox,
+    venv: &str,
+    dir: &str,
+    verbose: bool,
+) -> Result<(), CoffeeError> {
+    if Path::new(&format!("{venv}/bin/python")).exists() {
+        return Ok(());
+    }
+    match sandbox.python.installer {
+        PythonInstaller::Pip => {
+            sandbox
+                .run("python3", &["-m", "venv", venv], dir, verbose)
+                .await
+        }
+        // the virtualenv does not need a pip of its own.
+        PythonInstaller::Pipx => {
+            sandbox
+                .run(
+                    "python3",
+                    &["-m", "venv", "--without-pip", venv],
+                    dir,
+                    verbose,
+                )
+                .await
+        }
+        PythonInstaller::Uv => sandbox.run("uv", &["venv", venv], dir, verbose).await,
+    }
+}
+
+/// Write the wrapper that runs the `main_file` of the plugin `name`
+/// with the python of the virtualenv `venv`, return its path.
+async fn write_venv_wrapper(
+    path: &str,
+    name: &str,
+    main_file: &str,
+    venv: &str,
+) -> Result<String, CoffeeError> {
+    let wrapper_path = format!("{path}/.coffee-{name}");
+    let wrapper = format!(
+        "#!/bin/sh\n# generated by coffee for the plugin `{name}`, do not edit.\nexec '{venv}/bin/python' '{main_file}' \"$@\"\n"
+    );
+    fs::write(&wrapper_path, wrapper).await?;
+    fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+    Ok(wrapper_path)
+}
+
+/// Requirements files of the plugins installed inside the shared
+/// virtualenv at `shared_dir`, but the plugin `name`.
+async fn shared_requirements(shared_dir: &str, name: &str) -> Result<Vec<String>, CoffeeError> {
+    let dir = format!("{shared_dir}/requirements");
+    if !Path::new(&dir).exists() {
+        return Ok(vec![]);
+    }
+    let mut files = vec![];
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy() != format!("{name}.txt") {
+            files.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Install the plugin `name` inside the shared virtualenv, when its
+/// requirements are compatible with the ones of the plugins already
+/// there, return false otherwise.
+async fn install_shared(
+    sandbox: &Sandbox,
+    shared_dir: &str,
+    path: &str,
+    name: &str,
+    verbose: bool,
+) -> Result<bool, CoffeeError> {
+    let venv = format!("{shared_dir}/venv");
+    let pip = PipCommand::new(&sandbox.python.installer, Some(&venv))?;
+    fs::create_dir_all(format!("{shared_dir}/requirements")).await?;
+    create_venv(sandbox, &venv, shared_dir, verbose).await?;
+    let req_file = format!("{path}/requirements.txt");
+    if Path::new(&req_file).exists() {
+        // pip resolves all the requirements together, and it fails
+        // when two plugins need versions that can not live together.
+        let others = shared_requirements(shared_dir, name).await?;
+        let mut args = vec!["install", "--dry-run", "--quiet", "-r", &req_file];
+        for other in &others {
+            args.extend(["-r", other.as_str()]);
+        }
+        if let Err(err) = pip.run(sandbox, &args, shared_dir, verbose).await {
+            debug!("requirements of {name} not compatible with the shared virtualenv: {err}");
+            return Ok(false);
+        }
+    }
+    install_requirements(sandbox, &pip, shared_dir, path, name, verbose).await?;
+    let requirements = fs::read_to_string(&req_file).await.unwrap_or_default();
+    fs::write(
+        format!("{shared_dir}/requirements/{name}.txt"),
+        requirements,
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Install the dependencies of the python plugin `name` inside the
+/// directory `path`, with the options of the sandbox, return the
+/// executable of the plugin.
+pub async fn install(
+    sandbox: &Sandbox,
+    path: &str,
+    name: &str,
+    main_file: &str,
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    let conf = &sandbox.python;
+    if sandbox.container.enabled {
+        return install_container(sandbox, path, name, main_file, verbose).await;
+    }
+    let shared_dir = match (&conf.environment, &conf.shared_dir) {
+        (PythonEnv::System, _) => {
+            let pip = PipCommand::new(&conf.installer, None)?;
+            install_requirements(sandbox, &pip, path, path, name, verbose).await?;
+            return Ok(main_file.to_owned());
+        }
+        (PythonEnv::Shared, Some(shared_dir)) => Some(shared_dir),
+        _ => None,
+    };
+    if let Some(shared_dir) = shared_dir {
+        if install_shared(sandbox, shared_dir, path, name, verbose).await? {
+            let venv = format!("{shared_dir}/venv");
+            return write_venv_wrapper(path, name, main_file, &venv).await;
+        }
+        warn!("plugin {name} conflicts with the shared virtualenv, it gets its own");
+    }
+    let venv = format!("{path}/.venv");
+    create_venv(sandbox, &venv, path, verbose).await?;
+    let pip = PipCommand::new(&conf.installer, Some(&venv))?;
+    install_requirements(sandbox, &pip, path, path, name, verbose).await?;
+    write_venv_wrapper(path, name, main_file, &venv).await
+}
+
+/// Install the dependencies of the plugin `name` with the pip of the
+/// build container inside the directory `path`, and return the wrapper
+/// that runs the `main_file` with them.
+async fn install_container(
+    sandbox: &Sandbox,
+    path: &str,
+    name: &str,
+    main_file: &str,
+    verbose: bool,
+) -> Result<String, CoffeeError> {
+    if Path::new(&format!("{path}/requirements.txt")).exists() {
+        if sandbox.python.require_hashes {
+            warn!("the dependencies of {name} are not pinned inside the build container");
+        }
+        // the container works on a copy of the directory, the paths are relative.
+        let args = [
+            "install",
+            "--target",
+            CONTAINER_DEPS,
+            "-r",
+            "requirements.txt",
+        ];
+        sandbox
+            .run_build(&PluginLang::Python, "pip", &args, path, verbose)
+            .await?;
+    }
+    let wrapper_path = format!("{path}/.coffee-{name}");
+    let deps = shell_quote(&format!("{path}/{CONTAINER_DEPS}"));
+    let wrapper = format!(
+        "#!/bin/sh\n# generated by coffee for the plugin `{name}`, do not edit.\nPYTHONPATH={deps} exec python3 {} \"$@\"\n",
+        shell_quote(main_file)
+    );
+    fs::write(&wrapper_path, wrapper).await?;
+    fs::set_permissions(&wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+    Ok(wrapper_path)
+}
+
+/// Forget the requirements of the plugin `name` removed from the
+/// shared virtualenv, so they do not constrain the next installs.
+pub async fn forget_shared(conf: &PythonConf, name: &str) -> Result<(), CoffeeError> {
+    let Some(shared_dir) = &conf.shared_dir else {
+        return Ok(());
+    };
+    let file = format!("{shared_dir}/requirements/{name}.txt");
+    if Path::new(&file).exists() {
+        fs::remove_file(file).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{lock_from_report, requirements_hash, PipCommand, PythonInstaller, LOCK_HEADER};
+
+    #[test]
+    fn test_python_pip_command() {
+        let uv = PipCommand::new(&PythonInstaller::Uv, Some("/coffee/python/venv")).unwrap();
+        assert_eq!(uv.program, "uv");
+        assert_eq!(uv.before, vec!["pip"]);
+        assert_eq!(uv.after, vec!["--python", "/coffee/python/venv/bin/python"]);
+        let pip = PipCommand::new(&PythonInstaller::Pip, None).unwrap();
+        assert_eq!(pip.program, "pip");
+        assert!(PipCommand::new(&PythonInstaller::Pipx, None).is_err());
+    }
+
+    #[test]
+    fn test_python_lock_from_report() {
+        let report = json!({
+            "install": [
+                {
+                    "metadata": { "name": "pyln-client", "version": "24.2" },
+                    "download_info": { "archive_info": { "hash": "sha256=bbbb" } }
+                },
+                {
+                    "metadata": { "name": "idna", "version": "3.6" },
+                    "download_info": { "archive_info": { "hash": "sha256=aaaa" } }
+                }
+            ]
+        });
+        let lock = lock_from_report(&report, "pyln-client\n").unwrap();
+        assert_eq!(
+            lock,
+            format!(
+                "{LOCK_HEADER} {}\nidna==3.6 --hash=sha256:aaaa\npyln-client==24.2 --hash=sha256:bbbb\n",
+                requirements_hash("pyln-client\n")
+            )
+        );
+
+        let report = json!({
+            "install": [
+                {
+                    "metadata": { "name": "fork", "version": "0.1" },
+                    "download_info": { "vcs_info": { "vcs": "git" } }
+                }
+            ]
+        });
+        assert!(lock_from_report(&report, "").is_err());
+    }
+}
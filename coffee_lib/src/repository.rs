@@ -1,12 +1,74 @@
 //! Repository module implementation that contains all the code to build a repository
 //! for a plugin manager.
 use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
 
 use crate::errors::CoffeeError;
 use crate::plugin::Plugin;
 use crate::url::URL;
 
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Directories of the archived plugins, skipped by the
+/// `ArchivedExcluded` layout.
+const ARCHIVED_DIRS: [&str; 2] = ["archived", "archive"];
+
+/// Organization of the plugins inside a repository.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// a plugin for each directory at the root.
+    #[default]
+    Flat,
+    /// the plugins are grouped inside a directory for each
+    /// language, e.g. `python/summary`.
+    ByLanguage,
+    /// like `Flat`, without the archived plugins.
+    ArchivedExcluded,
+}
+
+impl Layout {
+    /// depth of the plugin directories inside the repository.
+    pub fn depth(&self) -> usize {
+        match self {
+            Layout::ByLanguage => 2,
+            Layout::Flat | Layout::ArchivedExcluded => 1,
+        }
+    }
+
+    /// true when the `subpath` of the repository is not indexed.
+    pub fn is_excluded(&self, subpath: &str) -> bool {
+        let top = subpath.split('/').next().unwrap_or_default();
+        *self == Layout::ArchivedExcluded && ARCHIVED_DIRS.contains(&top.to_lowercase().as_str())
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Flat => write!(f, "flat"),
+            Layout::ByLanguage => write!(f, "by-language"),
+            Layout::ArchivedExcluded => write!(f, "archived-excluded"),
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(layout: &str) -> Result<Self, Self::Err> {
+        match layout {
+            "flat" => Ok(Layout::Flat),
+            "by-language" => Ok(Layout::ByLanguage),
+            "archived-excluded" => Ok(Layout::ArchivedExcluded),
+            _ => Err(format!(
+                "layout `{layout}` unknown, use flat, by-language or archived-excluded"
+            )),
+        }
+    }
+}
 
 #[async_trait]
 pub trait Repository: Any {
@@ -16,9 +78,13 @@ pub trait Repository: Any {
     /// This should work like a `git fetch`.
     async fn init(&mut self) -> Result<(), CoffeeError>;
 
-    /// search inside the repository a plugin by name.
+    /// search inside the repository a plugin by name, the name
+    /// matches without the case and with `-` and `_` as the same.
     fn get_plugin_by_name(&self, name: &str) -> Option<Plugin>;
 
+    /// return the names of the plugins inside the repository.
+    fn plugin_names(&self) -> Vec<String>;
+
     /// return the list of plugin that are register contained inside the repository.
     async fn list(&self) -> Result<Vec<Plugin>, CoffeeError>;
 
@@ -28,5 +94,69 @@ pub trait Repository: Any {
     /// return the url of the repository.
     fn url(&self) -> URL;
 
+    /// return the unix time of the last update of the repository.
+    fn last_update(&self) -> Option<u64>;
+
+    /// return true when the upstream repository contains changes
+    /// that are not checked out locally.
+    fn is_outdated(&self) -> bool;
+
+    /// return true when the upstream repository changed the content
+    /// of the `plugin`, a plugin without a content hash is outdated
+    /// together with the repository.
+    fn is_plugin_outdated(&self, plugin: &Plugin) -> bool;
+
+    /// return the files that are modified locally, and that
+    /// an update of the repository would override.
+    fn local_changes(&self) -> Result<Vec<String>, CoffeeError>;
+
+    /// return the commit checked out locally.
+    fn commit(&self) -> Result<String, CoffeeError>;
+
+    /// check that the commit checked out locally is part of the
+    /// upstream history, when `fetch` is true the upstream is
+    /// fetched before the check.
+    async fn verify(&self, fetch: bool) -> Result<(), CoffeeError>;
+
+    /// fetch the upstream without changing the local checkout.
+    async fn fetch(&self) -> Result<(), CoffeeError>;
+
+    /// bring the local checkout to the last upstream version and
+    /// index the plugins again, the local modifications are lost.
+    async fn upgrade(&mut self) -> Result<(), CoffeeError>;
+
+    /// move the local checkout to the `commit` and index the
+    /// plugins again, the local modifications are lost.
+    async fn reset(&mut self, commit: &str) -> Result<(), CoffeeError>;
+
+    /// return the local modifications as a patch.
+    fn local_diff(&self) -> Result<String, CoffeeError>;
+
+    /// apply the `patch` on top of the local checkout.
+    fn apply_diff(&self, patch: &str) -> Result<(), CoffeeError>;
+
+    /// return the organization of the plugins inside the repository.
+    fn layout(&self) -> Layout;
+
+    /// index again the plugins of the repository with the `layout`.
+    async fn set_layout(&mut self, layout: Layout) -> Result<(), CoffeeError>;
+
     fn as_any(&self) -> &dyn Any;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+
+    #[test]
+    fn test_repository_layout() {
+        let layout: Layout = "archived-excluded".parse().unwrap();
+        assert_eq!(layout.to_string(), "archived-excluded");
+        assert!(layout.is_excluded("archived/summary"));
+        assert!(layout.is_excluded("Archive"));
+        assert!(!layout.is_excluded("summary"));
+        assert!(!Layout::Flat.is_excluded("archived"));
+        assert_eq!(Layout::ByLanguage.depth(), 2);
+        assert!("nested".parse::<Layout>().is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Run the plugins under a dedicated unix user, so a compromised
+//! plugin can not read the files of the node (e.g. the hsm_secret).
+use std::os::unix::fs::PermissionsExt;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::errors::CoffeeError;
+use crate::utils::shell_quote as quote;
+
+/// Environment of core lightning that the plugin needs.
+const PLUGIN_ENV: &[&str] = &["LIGHTNINGD_PLUGIN", "LIGHTNINGD_VERSION"];
+
+/// Tool used by the wrapper to drop the privileges.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunAsTool {
+    /// `runuser`, needs core lightning running as root.
+    #[default]
+    Runuser,
+    /// `sudo`, needs a sudoers rule that allows the node user.
+    Sudo,
+    /// `systemd-run`, runs the plugin as a transient unit.
+    Systemd,
+}
+
+/// Unix user and group that run the plugins.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunAs {
+    pub user: String,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub tool: RunAsTool,
+}
+
+impl RunAs {
+    /// `user:group` as understood by chown.
+    fn owner(&self) -> String {
+        match &self.group {
+            Some(group) => format!("{}:{group}", self.user),
+            None => self.user.clone(),
+        }
+    }
+
+    /// Give the files inside `path` to the user, or the file at
+    /// `path` when it is not a directory.
+    ///
+    /// The directory itself and the git data stay to the node user,
+    /// so the user of the plugin can not change the configuration of
+    /// the repository (e.g. with a hook) that coffee uses.
+    pub async fn chown(&self, path: &str) -> Result<(), CoffeeError> {
+        let mut files = vec![];
+        if fs::metadata(path).await?.is_dir() {
+            let mut entries = fs::read_dir(path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name() != ".git" {
+                    files.push(entry.path());
+                }
+            }
+        } else {
+            files.push(path.into());
+        }
+        if files.is_empty() {
+            return Ok(());
+        }
+        let output = Command::new("chown")
+            .arg("-R")
+            .arg(self.owner())
+            .args(&files)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(CoffeeError::new(
+                1,
+                &format!(
+                    "files of {path} not given to `{}`: {}",
+                    self.owner(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Command line that runs the `exec_path` as the user.
+    fn command(&self, exec_path: &str) -> String {
+        let user = quote(&self.user);
+        let group = self.group.as_deref().map(quote);
+        let mut cmd = match self.tool {
+            RunAsTool::Runuser => {
+                let mut cmd = format!("runuser -u {user}");
+                if let Some(group) = &group {
+                    cmd += &format!(" -g {group}");
+                }
+                cmd
+            }
+            RunAsTool::Sudo => {
+                let mut cmd = format!("sudo -n --preserve-env={} -u {user}", PLUGIN_ENV.join(","));
+                if let Some(group) = &group {
+                    cmd += &format!(" -g {group}");
+                }
+                cmd
+            }
+            RunAsTool::Systemd => {
+                let mut cmd = format!("systemd-run --quiet --pipe --wait --collect -p User={user}");
+                if let Some(group) = &group {
+                    cmd += &format!(" -p Group={group}");
+                }
+                for var in PLUGIN_ENV {
+                    cmd += &format!(" -E {var}");
+                }
+                cmd
+            }
+        };
+        cmd += &format!(" -- {} \"$@\"", quote(exec_path));
+        cmd
+    }
+
+    /// Write inside `wrapper_path` the wrapper that runs the plugin
+    /// at `exec_path` as the user.
+    ///
+    /// The wrapper must live outside the files of the plugin, the
+    /// user of the plugin can not touch what the node executes.
+    pub async fn write_wrapper(
+        &self,
+        plugin: &str,
+        exec_path: &str,
+        wrapper_path: &str,
+    ) -> Result<(), CoffeeError> {
+        let wrapper = format!(
+            "#!/bin/sh\n# generated by coffee to run the plugin `{plugin}` as `{}`, do not edit.\nexec {}\n",
+            self.user,
+            self.command(exec_path)
+        );
+        fs::write(wrapper_path, wrapper).await?;
+        fs::set_permissions(wrapper_path, std::fs::Permissions::from_mode(0o755)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunAs, RunAsTool};
+
+    #[test]
+    fn test_run_as_command() {
+        let run_as = RunAs {
+            user: "plugins".to_owned(),
+            group: Some("lightning".to_owned()),
+            tool: RunAsTool::Runuser,
+        };
+        assert_eq!(
+            run_as.command("/plugins/summary.py"),
+            "runuser -u 'plugins' -g 'lightning' -- '/plugins/summary.py' \"$@\""
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Sandbox of the commands that build a plugin, the install
+//! script of a manifest can run anything, so coffee reduces what
+//! a malicious script can read and write.
+use std::env;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{create_dir_all, remove_dir_all, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::cargo::CargoConf;
+use crate::container::{self, ContainerConf};
+use crate::errors::CoffeeError;
+use crate::go::GoConf;
+use crate::interrupt::run_command;
+use crate::node::NodeConf;
+use crate::plugin::PluginLang;
+use crate::python::PythonConf;
+
+/// Environment variables that the build commands receive.
+const KEEP_ENV: &[&str] = &[
+    "PATH",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "TERM",
+    "TZ",
+    "USER",
+    "LOGNAME",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "VIRTUAL_ENV",
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "GOPATH",
+];
+
+/// Environment variables of the build commands in plain mode,
+/// the toolchains print no colors and no progress bars.
+const PLAIN_ENV: &[(&str, &str)] = &[
+    ("TERM", "dumb"),
+    ("NO_COLOR", "1"),
+    ("CARGO_TERM_COLOR", "never"),
+    ("CARGO_TERM_PROGRESS_WHEN", "never"),
+    ("PIP_PROGRESS_BAR", "off"),
+    ("npm_config_progress", "false"),
+    ("npm_config_color", "false"),
+];
+
+/// Error code of a build command that exceeded one of its limits.
+pub const LIMIT_EXCEEDED_CODE: u64 = 2;
+
+/// Resources that a build command can use, no limit when missing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// CPU time, in seconds.
+    pub cpu_time: Option<u64>,
+    /// address space, in megabytes.
+    pub memory: Option<u64>,
+    /// wall-clock time, in seconds.
+    pub timeout: Option<u64>,
+}
+
+impl Limits {
+    /// apply the CPU and memory limits to the process of the `cmd`.
+    fn apply(&self, cmd: &mut Command) {
+        let cpu_time = self.cpu_time;
+        let memory = self.memory.map(|memory| memory * 1024 * 1024);
+        if cpu_time.is_none() && memory.is_none() {
+            return;
+        }
+        let set_limit = |resource, value: u64| {
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        };
+        // SAFETY: the closure runs in the child before the exec, and
+        // it only calls setrlimit, that is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(cpu_time) = cpu_time {
+                    set_limit(libc::RLIMIT_CPU, cpu_time)?;
+                }
+                if let Some(memory) = memory {
+                    set_limit(libc::RLIMIT_AS, memory)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// the error of a `program` that exited with `status`, when
+    /// it was killed because it exceeded a limit.
+    fn exceeded(&self, program: &str, status: &ExitStatus) -> Option<CoffeeError> {
+        let signal = status.signal()?;
+        let msg = match signal {
+            libc::SIGXCPU | libc::SIGKILL if self.cpu_time.is_some() => format!(
+                "`{program}` exceeded the CPU time limit of {}s",
+                self.cpu_time.unwrap_or_default()
+            ),
+            libc::SIGSEGV | libc::SIGABRT | libc::SIGBUS if self.memory.is_some() => format!(
+                "`{program}` killed by the signal {signal}, it probably exceeded the memory limit of {}MB",
+                self.memory.unwrap_or_default()
+            ),
+            _ => return None,
+        };
+        Some(CoffeeError::new(LIMIT_EXCEEDED_CODE, &msg))
+    }
+}
+
+/// How much the build commands are isolated.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxPolicy {
+    /// run the commands with the environment of coffee, the
+    /// python dependencies installed inside the user site stay
+    /// in the HOME of the user.
+    #[default]
+    None,
+    /// clean the environment and use a temporary HOME.
+    Env,
+    /// as `env`, and run the commands inside bubblewrap or nsjail,
+    /// where only the plugin directory can be written.
+    Isolate,
+}
+
+/// Sandbox used to run the build commands.
+#[derive(Clone, Debug, Default)]
+pub struct Sandbox {
+    pub policy: SandboxPolicy,
+    /// environment variables kept in addition to the defaults.
+    pub keep_env: Vec<String>,
+    pub limits: Limits,
+    /// build the plugins inside a container, when enabled.
+    pub container: ContainerConf,
+    /// install options of the dependencies of the python plugins.
+    pub python: PythonConf,
+    /// node runtime of the javascript plugins.
+    pub node: NodeConf,
+    /// proxy and flags of the go builds.
+    pub go: GoConf,
+    /// options of the cargo builds.
+    pub cargo: CargoConf,
+    /// directory of the build logs, chosen by the plugin manager.
+    pub build_log_dir: Option<String>,
+    /// file where the output of the build commands is appended.
+    pub build_log: Option<String>,
+    /// directory of the wrappers of the standalone scripts, outside
+    /// of the repositories, chosen by the plugin manager.
+    pub wrapper_dir: Option<String>,
+    /// the output of the build commands is plain text, without
+    /// colors and progress bars.
+    pub plain: bool,
+}
+
+/// Counter of the temporary HOME, plugins are built concurrently.
+static HOMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Return the path of the `program` inside the PATH.
+fn which(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program));
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+impl Sandbox {
+    pub fn new(policy: SandboxPolicy, keep_env: Vec<String>, limits: Limits) -> Self {
+        Sandbox {
+            policy,
+            keep_env,
+            limits,
+            container: ContainerConf::default(),
+            python: PythonConf::default(),
+            node: NodeConf::default(),
+            go: GoConf::default(),
+            cargo: CargoConf::default(),
+            build_log_dir: None,
+            build_log: None,
+            wrapper_dir: None,
+            plain: false,
+        }
+    }
+
+    pub fn with_container(mut self, container: ContainerConf) -> Self {
+        self.container = container;
+        self
+    }
+
+    pub fn with_python(mut self, python: PythonConf) -> Self {
+        self.python = python;
+        self
+    }
+
+    pub fn with_node(mut self, node: NodeConf) -> Self {
+        self.node = node;
+        self
+    }
+
+    pub fn with_go(mut self, go: GoConf) -> Self {
+        self.go = go;
+        self
+    }
+
+    pub fn with_cargo(mut self, cargo: CargoConf) -> Self {
+        self.cargo = cargo;
+        self
+    }
+
+    pub fn with_build_log_dir(mut self, build_log_dir: &str) -> Self {
+        self.build_log_dir = Some(build_log_dir.to_owned());
+        self
+    }
+
+    pub fn with_build_log(mut self, build_log: &str) -> Self {
+        self.build_log = Some(build_log.to_owned());
+        self
+    }
+
+    pub fn with_wrapper_dir(mut self, wrapper_dir: &str) -> Self {
+        self.wrapper_dir = Some(wrapper_dir.to_owned());
+        self
+    }
+
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// set the environment of the plain mode to the `cmd`.
+    fn plain_env(&self, cmd: &mut Command) {
+        if self.plain {
+            cmd.envs(PLAIN_ENV.iter().copied());
+        }
+    }
+
+    /// append the command that is about to run to the build log.
+    async fn log_command(&self, program: &str, args: &[&str]) -> Result<(), CoffeeError> {
+        let Some(log) = &self.build_log else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .await?;
+        file.write_all(format!("$ {program} {}\n", args.join(" ")).as_bytes())
+            .await?;
+        Ok(())
+    }
+
+    /// Run the build command `program` of a plugin written in `lang`,
+    /// inside a container when the container build is enabled.
+    pub async fn run_build(
+        &self,
+        lang: &PluginLang,
+        program: &str,
+        args: &[&str],
+        dir: &str,
+        verbose: bool,
+    ) -> Result<(), CoffeeError> {
+        if !self.container.enabled {
+            return self.run(program, args, dir, verbose).await;
+        }
+        self.log_command(program, args).await?;
+        let (engine, id) = self
+            .container
+            .create(lang, program, args, dir, self.limits.memory)
+            .await?;
+        let mut start = Command::new(&engine);
+        start.args(["start", "-a", &id]);
+        let status = match self.limits.timeout {
+            Some(secs) => {
+                match timeout(
+                    Duration::from_secs(secs),
+                    run_command(&mut start, verbose, self.build_log.as_deref()),
+                )
+                .await
+                {
+                    Ok(status) => status,
+                    Err(_) => Err(CoffeeError::new(
+                        LIMIT_EXCEEDED_CODE,
+                        &format!("`{program}` exceeded the time limit of {secs}s"),
+                    )),
+                }
+            }
+            None => run_command(&mut start, verbose, self.build_log.as_deref()).await,
+        };
+        let result = match status {
+            Ok(status) if status.success() => self.container.copy_out(&engine, &id, dir).await,
+            Ok(status) => Err(CoffeeError::new(
+                1,
+                &format!("`{program}` failed inside the container with {status}"),
+            )),
+            Err(err) => Err(err),
+        };
+        container::remove(&engine, &id).await;
+        result
+    }
+
+    /// Run the `cmd` of the `program` within the limits.
+    async fn run_limited(
+        &self,
+        program: &str,
+        cmd: &mut Command,
+        verbose: bool,
+    ) -> Result<(), CoffeeError> {
+        self.limits.apply(cmd);
+        let status = match self.limits.timeout {
+            // the command is killed when its future is dropped.
+            Some(secs) => timeout(
+                Duration::from_secs(secs),
+                run_command(cmd, verbose, self.build_log.as_deref()),
+            )
+            .await
+            .map_err(|_| {
+                CoffeeError::new(
+                    LIMIT_EXCEEDED_CODE,
+                    &format!("`{program}` exceeded the time limit of {secs}s"),
+                )
+            })??,
+            None => run_command(cmd, verbose, self.build_log.as_deref()).await?,
+        };
+        if let Some(err) = self.limits.exceeded(program, &status) {
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Command that runs the `program` inside the isolation tool.
+    fn isolated(
+        &self,
+        program: &str,
+        args: &[&str],
+        dir: &str,
+        home: &str,
+    ) -> Result<Command, CoffeeError> {
+        let program = which(program)
+            .ok_or_else(|| CoffeeError::new(1, &format!("command `{program}` not found")))?;
+        let program = program.to_string_lossy().to_string();
+        let mut writable = vec![dir.to_owned(), home.to_owned()];
+        if let Ok(venv) = env::var("VIRTUAL_ENV") {
+            writable.push(venv);
+        }
+        if let Some(bwrap) = which("bwrap") {
+            let mut cmd = Command::new(bwrap);
+            cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+            cmd.args(["--tmpfs", "/tmp"]);
+            for path in &writable {
+                cmd.args(["--bind", path, path]);
+            }
+            cmd.args(["--unshare-all", "--share-net", "--die-with-parent"]);
+            cmd.args(["--chdir", dir, "--", &program]).args(args);
+            return Ok(cmd);
+        }
+        if let Some(nsjail) = which("nsjail") {
+            let mut cmd = Command::new(nsjail);
+            cmd.args(["-Mo", "--quiet", "--disable_clone_newnet", "-t", "0"]);
+            for limit in ["as", "fsize", "nofile", "nproc", "stack"] {
+                cmd.arg(format!("--rlimit_{limit}")).arg("soft");
+            }
+            cmd.args(["-R", "/"]);
+            for path in &writable {
+                cmd.args(["-B", path]);
+            }
+            cmd.args(["--cwd", dir, "--", &program]).args(args);
+            return Ok(cmd);
+        }
+        Err(CoffeeError::new(
+            1,
+            "sandbox `isolate` needs bubblewrap (bwrap) or nsjail installed",
+        ))
+    }
+
+    /// Run the `program` with the `args` inside the directory `dir`,
+    /// the output is shown only when `verbose`.
+    pub async fn run(
+        &self,
+        program: &str,
+        args: &[&str],
+        dir: &str,
+        verbose: bool,
+    ) -> Result<(), CoffeeError> {
+        self.log_command(program, args).await?;
+        if self.policy == SandboxPolicy::None {
+            let mut cmd = Command::new(program);
+            cmd.args(args).current_dir(dir);
+            self.plain_env(&mut cmd);
+            return self.run_limited(program, &mut cmd, verbose).await;
+        }
+
+        let home = env::temp_dir().join(format!(
+            "coffee-home-{}-{}",
+            std::process::id(),
+            HOMES.fetch_add(1, Ordering::Relaxed)
+        ));
+        create_dir_all(&home).await?;
+        let home = home.to_string_lossy().to_string();
+        let mut cmd = match self.policy {
+            SandboxPolicy::Isolate => match self.isolated(program, args, dir, &home) {
+                Ok(cmd) => cmd,
+                Err(err) => {
+                    remove_dir_all(&home).await?;
+                    return Err(err);
+                }
+            },
+            _ => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                cmd
+            }
+        };
+        cmd.current_dir(dir).env_clear().env("HOME", &home);
+        // the toolchains installed inside the real HOME keep working.
+        if let Ok(real_home) = env::var("HOME") {
+            for (var, dir) in [("CARGO_HOME", ".cargo"), ("RUSTUP_HOME", ".rustup")] {
+                let path = format!("{real_home}/{dir}");
+                if env::var_os(var).is_none() && Path::new(&path).exists() {
+                    cmd.env(var, path);
+                }
+            }
+        }
+        let keep = KEEP_ENV
+            .iter()
+            .copied()
+            .chain(self.keep_env.iter().map(String::as_str));
+        for var in keep {
+            if let Some(value) = env::var_os(var) {
+                cmd.env(var, value);
+            }
+        }
+        self.plain_env(&mut cmd);
+        debug!("running {program} {args:?} inside the sandbox with HOME {home}");
+        let result = self.run_limited(program, &mut cmd, verbose).await;
+        if let Err(err) = remove_dir_all(&home).await {
+            warn!("temporary HOME {home} not removed: {err}");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{Limits, Sandbox, SandboxPolicy};
+
+    #[tokio::test]
+    async fn test_sandbox_env() {
+        let dir = env::temp_dir().join(format!("coffee-sandbox-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+        // cargo gives the variable to the tests, the sandbox drops it.
+        let sandbox = Sandbox::new(SandboxPolicy::Env, vec![], Limits::default());
+        sandbox
+            .run(
+                "sh",
+                &["-c", "echo \"$HOME:$CARGO_MANIFEST_DIR\" > out"],
+                &dir,
+                false,
+            )
+            .await
+            .unwrap();
+        let out = std::fs::read_to_string(format!("{dir}/out")).unwrap();
+        let (home, secret) = out.trim().split_once(':').unwrap();
+        assert!(home.contains("coffee-home-"));
+        assert_eq!(secret, "");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_limit() {
+        let limits = Limits {
+            timeout: Some(1),
+            ..Limits::default()
+        };
+        let sandbox = Sandbox::new(SandboxPolicy::None, vec![], limits);
+        let err = sandbox.run("sleep", &["10"], "/", false).await.unwrap_err();
+        assert!(format!("{err}").contains("time limit"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Redaction of the secret values (e.g. the API key inside an option of
+//! a plugin), the values are registered when coffee loads them and
+//! replaced everywhere they are printed or logged.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+/// What is printed in place of a secret.
+pub const REDACTED: &str = "<redacted>";
+
+/// Secrets shorter than this are not redacted, they would hide
+/// any occurrence of common words or numbers.
+const MIN_SECRET_LEN: usize = 4;
+
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+static REVEAL: AtomicBool = AtomicBool::new(false);
+
+/// Print the secrets in clear when `reveal` is true.
+pub fn configure(reveal: bool) {
+    REVEAL.store(reveal, Ordering::SeqCst);
+}
+
+/// Register the `value` as a secret.
+pub fn register(value: &str) {
+    if value.len() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.lock().unwrap();
+    if !secrets.iter().any(|secret| secret == value) {
+        secrets.push(value.to_owned());
+        // the longest first, so a secret that contains another
+        // one is redacted as a whole.
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    }
+}
+
+/// The `text` with the secrets replaced, even when they are revealed,
+/// for what is stored (e.g. the audit log).
+pub fn conceal(text: &str) -> String {
+    SECRETS
+        .lock()
+        .unwrap()
+        .iter()
+        .fold(text.to_owned(), |text, secret| {
+            text.replace(secret.as_str(), REDACTED)
+        })
+}
+
+/// The `text` with the secrets replaced, unless they are revealed.
+pub fn redact(text: &str) -> String {
+    if REVEAL.load(Ordering::SeqCst) {
+        return text.to_owned();
+    }
+    conceal(text)
+}
+
+/// The strings inside the `value` with the secrets replaced, unless
+/// they are revealed.
+pub fn redact_json(value: &Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(redact(text)),
+        Value::Array(items) => Value::Array(items.iter().map(redact_json).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), redact_json(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{conceal, register, REDACTED};
+
+    #[test]
+    fn test_secrets_conceal() {
+        register("s3cr3t-key");
+        register("s3cr3t");
+        register("abc");
+        assert_eq!(
+            conceal("api-key=s3cr3t-key other=s3cr3t"),
+            format!("api-key={REDACTED} other={REDACTED}")
+        );
+        assert_eq!(conceal("abc"), "abc");
+        assert_eq!(
+            super::redact_json(&json!({"options": {"api-key": "s3cr3t-key"}, "n": 1})),
+            json!({"options": {"api-key": REDACTED}, "n": 1})
+        );
+    }
+}
@@ -2,6 +2,11 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use crate::errors::CoffeeError;
+
+/// Schemes of the urls that git can clone.
+const SCHEMES: [&str; 5] = ["https", "http", "ssh", "git", "file"];
+
 /// This struct will make sure our URL's are of the
 /// correct format and will also check correctness
 /// of associated fields
@@ -17,6 +22,153 @@ pub struct URL {
     pub repo_name: String,
 }
 
+/// How the url is written.
+#[derive(Debug, PartialEq)]
+enum Style {
+    /// `scheme://host/path`.
+    Url,
+    /// `user@host:path`, like ssh.
+    Scp,
+    /// `/path` on the local file system.
+    Local,
+}
+
+/// Pieces of a git url.
+#[derive(Debug, PartialEq)]
+struct Parts {
+    style: Style,
+    scheme: String,
+    /// user, host and port, with the host in lowercase.
+    authority: String,
+    host: String,
+    /// segments of the path, without the `.git` suffix of the last one.
+    segments: Vec<String>,
+}
+
+impl Parts {
+    /// The url in its normalized form.
+    fn normalized(&self) -> String {
+        let path = self.segments.join("/");
+        match self.style {
+            Style::Url => format!("{}://{}/{path}", self.scheme, self.authority),
+            Style::Scp => format!("{}:{path}", self.authority),
+            Style::Local => format!("/{path}"),
+        }
+    }
+}
+
+/// split the `authority` into user, host and port, and check the host.
+fn host_of(authority: &str) -> Result<(String, String), String> {
+    let (user, host_port) = match authority.rsplit_once('@') {
+        Some((user, host_port)) => (format!("{user}@"), host_port),
+        None => (String::new(), authority),
+    };
+    let (host, port) = match host_port.split_once(':') {
+        Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => {
+            (host, format!(":{port}"))
+        }
+        Some(_) => return Err(format!("invalid port in `{host_port}`")),
+        None => (host_port, String::new()),
+    };
+    if host.is_empty() {
+        return Err("the host is missing".to_owned());
+    }
+    let valid = host
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_');
+    if !valid {
+        return Err(format!("invalid host `{host}`"));
+    }
+    let host = host.to_lowercase();
+    Ok((format!("{user}{host}{port}"), host))
+}
+
+/// Parse the `url`, a `scheme://host/path` url, a scp-like
+/// `user@host:path` or a local path.
+fn parse(url: &str) -> Result<Parts, String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("the url is empty".to_owned());
+    }
+    if url.chars().any(char::is_whitespace) {
+        return Err("the url contains spaces".to_owned());
+    }
+    let (style, scheme, authority, path) = match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let scheme = scheme.to_lowercase();
+            if !SCHEMES.contains(&scheme.as_str()) {
+                return Err(format!("unsupported scheme `{scheme}`"));
+            }
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            (Style::Url, scheme, authority, path)
+        }
+        None if url.starts_with('/') => (Style::Local, "file".to_owned(), "", url),
+        None => match url.split_once(':') {
+            Some((authority, path)) if !authority.contains('/') => {
+                (Style::Scp, "ssh".to_owned(), authority, path)
+            }
+            _ => return Err("not a url nor an absolute path".to_owned()),
+        },
+    };
+    let (authority, host) = if scheme == "file" {
+        if !authority.is_empty() {
+            return Err("a file url has no host".to_owned());
+        }
+        (String::new(), String::new())
+    } else {
+        host_of(authority)?
+    };
+    let mut segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if segments
+        .iter()
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err("the path contains `.` or `..`".to_owned());
+    }
+    if let Some(last) = segments.last_mut() {
+        if let Some(repo) = last.strip_suffix(".git") {
+            *last = repo.to_owned();
+        }
+    }
+    if segments.last().map(String::is_empty).unwrap_or(true) {
+        return Err("the repository is missing".to_owned());
+    }
+    Ok(Parts {
+        style,
+        scheme,
+        authority,
+        host,
+        segments,
+    })
+}
+
+/// Longest name of a repository.
+const MAX_NAME_LEN: usize = 64;
+
+/// true for the characters allowed inside the name of a repository.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+}
+
+/// Check that the `name` of a repository can be used as a directory,
+/// the empty name is derived from the url.
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Ok(());
+    }
+    if name.starts_with('.') || name.len() > MAX_NAME_LEN || !name.chars().all(is_name_char) {
+        let err = format!(
+            "invalid repository name `{name}`, use at most {MAX_NAME_LEN} letters, digits, `-`, `_` and `.`, not at the start"
+        );
+        return Err(err);
+    }
+    Ok(())
+}
+
 /// Handle GitHub HTTP links
 fn remove_dot_git_from_url(url: &str) -> &str {
     match url.strip_suffix(".git") {
@@ -46,16 +198,85 @@ fn get_repo_name_from_url(url: &str) -> String {
     repo_name
 }
 
+/// Directory of the repository `name` inside `local_path`.
+fn repository_path(local_path: &str, name: &str) -> String {
+    format!("{}/repositories/{name}", local_path.trim_end_matches('/'))
+}
+
 impl URL {
-    /// Build a new URL and initialize its fields
+    /// Build a new URL and initialize its fields, an url that can
+    /// not be parsed is kept as it is.
     pub fn new(local_path: &str, url: &str, remote_name: &str) -> Self {
-        URL {
-            name: remote_name.to_owned(),
-            url_string: handle_incorrect_url(&url),
-            path_string: format!("{local_path}/repositories/{remote_name}"),
-            repo_name: get_repo_name_from_url(&url),
+        match Self::build(local_path, url, remote_name) {
+            Ok(url) => url,
+            Err(_) => {
+                let url_string = handle_incorrect_url(url);
+                URL {
+                    name: remote_name.to_owned(),
+                    repo_name: get_repo_name_from_url(&url_string),
+                    url_string,
+                    path_string: repository_path(local_path, remote_name),
+                }
+            }
         }
     }
+
+    /// Parse and normalize the `url` of the repository `remote_name`,
+    /// the local path of the repository is derived from its name, or
+    /// from the name of the repository inside the url when it is empty.
+    pub fn parse(local_path: &str, url: &str, remote_name: &str) -> Result<Self, CoffeeError> {
+        Self::build(local_path, url, remote_name).map_err(|err| CoffeeError::new(1, &err))
+    }
+
+    /// the url, or the reason why it is not valid.
+    fn build(local_path: &str, url: &str, remote_name: &str) -> Result<Self, String> {
+        let parts = parse(url).map_err(|reason| format!("invalid url `{url}`: {reason}"))?;
+        check_name(remote_name)?;
+        let repo_name = parts.segments.last().cloned().unwrap_or_default();
+        let dir = if remote_name.is_empty() {
+            &repo_name
+        } else {
+            remote_name
+        };
+        Ok(URL {
+            name: remote_name.to_owned(),
+            url_string: parts.normalized(),
+            path_string: repository_path(local_path, dir),
+            repo_name,
+        })
+    }
+
+    /// Name of the repository when the user does not give one,
+    /// `<owner>-<repo>` in lowercase with only the characters
+    /// allowed inside a name.
+    pub fn default_name(&self) -> String {
+        let name = match self.owner() {
+            Some(owner) => format!("{owner}-{}", self.repo_name),
+            None => self.repo_name.clone(),
+        };
+        let name: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| if is_name_char(c) { c } else { '-' })
+            .collect();
+        let name = name.trim_start_matches('.');
+        name.chars().take(MAX_NAME_LEN).collect()
+    }
+
+    /// Host of the url, empty for the local repositories.
+    pub fn host(&self) -> String {
+        parse(&self.url_string)
+            .map(|parts| parts.host)
+            .unwrap_or_default()
+    }
+
+    /// Owner of the repository, the segment of the path before the
+    /// name of the repository.
+    pub fn owner(&self) -> Option<String> {
+        let parts = parse(&self.url_string).ok()?;
+        let len = parts.segments.len();
+        (len >= 2).then(|| parts.segments[len - 2].clone())
+    }
 }
 
 impl fmt::Display for URL {
@@ -78,6 +299,66 @@ mod tests {
         let url = URL::new("/tmp/", u, "lightningd_plugins");
         assert_eq!(url.repo_name, "plugins");
         assert_eq!(url.url_string, u);
+        assert_eq!(url.path_string, "/tmp/repositories/lightningd_plugins");
         println!("{}", &url);
     }
+
+    #[test]
+    fn test_url_normalized() {
+        for u in [
+            "https://github.com/lightningd/plugins",
+            "https://GitHub.com/lightningd/plugins.git",
+            "https://github.com/lightningd/plugins/",
+            " https://github.com//lightningd/plugins.git/ ",
+        ] {
+            let url = URL::parse("/tmp", u, "").unwrap();
+            assert_eq!(url.url_string, "https://github.com/lightningd/plugins");
+            assert_eq!(url.repo_name, "plugins");
+            assert_eq!(url.path_string, "/tmp/repositories/plugins");
+            assert_eq!(url.host(), "github.com");
+            assert_eq!(url.owner().as_deref(), Some("lightningd"));
+        }
+        let url = URL::parse("/tmp", "git@github.com:lightningd/plugins.git", "cln").unwrap();
+        assert_eq!(url.url_string, "git@github.com:lightningd/plugins");
+        assert_eq!(url.host(), "github.com");
+        let url = URL::parse("/tmp", "/srv/git/plugins.git", "local").unwrap();
+        assert_eq!(url.url_string, "/srv/git/plugins");
+        assert_eq!(url.host(), "");
+        let url = URL::parse("/tmp", "ssh://git@Example.org:2222/plugins", "ssh").unwrap();
+        assert_eq!(url.url_string, "ssh://git@example.org:2222/plugins");
+        assert_eq!(url.owner(), None);
+    }
+
+    #[test]
+    fn test_url_invalid() {
+        for u in [
+            "",
+            "github.com",
+            "https://github.com",
+            "https://github.com/",
+            "ftp://github.com/lightningd/plugins",
+            "https://git hub.com/lightningd/plugins",
+            "https://github.com:port/lightningd/plugins",
+            "https://github.com/lightningd/../plugins",
+            "file://host/srv/plugins",
+        ] {
+            assert!(URL::parse("/tmp", u, "").is_err(), "{u} is valid");
+        }
+        let u = "https://github.com/lightningd/plugins";
+        assert!(URL::parse("/tmp", u, "../plugins").is_err());
+        assert!(URL::parse("/tmp", u, "lightningd/plugins").is_err());
+        assert!(URL::parse("/tmp", u, ".hidden").is_err());
+        assert!(URL::parse("/tmp", u, "cln plugins").is_err());
+        assert!(URL::parse("/tmp", u, "lightningd_plugins-2.0").is_ok());
+    }
+
+    #[test]
+    fn test_url_default_name() {
+        let url = URL::new("/tmp", "https://github.com/ZmnSCPxj/clboss.git", "");
+        assert_eq!(url.default_name(), "zmnscpxj-clboss");
+        let url = URL::new("/tmp", "/srv/plugins", "");
+        assert_eq!(url.default_name(), "srv-plugins");
+        let url = URL::new("/tmp", "ssh://example.org/.plugins", "");
+        assert_eq!(url.default_name(), "plugins");
+    }
 }
@@ -1,5 +1,9 @@
 use crate::errors::CoffeeError;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
 
 pub fn get_plugin_info_from_path(path: &Path) -> Result<(String, String), CoffeeError> {
     match path.parent() {
@@ -16,15 +20,96 @@ pub fn get_plugin_info_from_path(path: &Path) -> Result<(String, String), Coffee
     }
 }
 
+/// true when `path` is `dir` or lives inside it, the paths are
+/// compared by components, so `/plugins-fork` is not inside `/plugins`.
+pub fn is_inside(path: &str, dir: &str) -> bool {
+    Path::new(path).starts_with(dir)
+}
+
+/// `path` relative to `dir`, empty when it is not inside `dir`.
+pub fn relative_path(path: &str, dir: &str) -> String {
+    Path::new(path)
+        .strip_prefix(dir)
+        .map(|relative| relative.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Return the interpreter declared inside the shebang line
+/// of the file (e.g. `/usr/bin/env python3`), if any.
+pub fn get_shebang_interpreter(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut first_line = String::new();
+    BufReader::new(file).read_line(&mut first_line).ok()?;
+    let interpreter = first_line.strip_prefix("#!")?.trim();
+    if interpreter.is_empty() {
+        return None;
+    }
+    Some(interpreter.to_owned())
+}
+
+/// home directory of the user, from the `HOME` variable.
+pub fn home_dir() -> Option<String> {
+    std::env::var("HOME").ok().filter(|home| !home.is_empty())
+}
+
+/// quote the `value` for a shell script.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// current unix time in seconds.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// current minute of the day in the local time zone, from 0 to 1439.
+pub fn local_minute_of_day() -> u32 {
+    let now = unix_time() as libc::time_t;
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    // localtime_r is the thread safe version, it reads the time zone
+    // from the TZ variable or from the system.
+    if unsafe { libc::localtime_r(&now, &mut local) }.is_null() {
+        return ((now / 60) % 1440) as u32;
+    }
+    (local.tm_hour * 60 + local.tm_min) as u32
+}
+
+/// Write the content inside the file at `path` without leaving
+/// a partial file around when coffee crashes in the middle of the write.
+///
+/// The content is written and synced on a temporary file in the same
+/// directory, and then moved over `path` with an atomic rename.
+pub async fn atomic_write(path: &str, content: &[u8]) -> Result<(), CoffeeError> {
+    let tmp_path = format!("{path}.tmp");
+    let mut file = tokio::fs::File::create(&tmp_path).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    // sync the directory too, otherwise the rename can be lost
+    // on power loss.
+    if let Some(parent) = Path::new(path).parent() {
+        if let Ok(dir) = tokio::fs::File::open(parent).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::create_dir_all;
 
     use std::env;
     use std::fs::remove_dir_all;
+    use std::fs::write;
     use std::path::Path;
     use std::sync::Once;
 
+    use super::{atomic_write, get_shebang_interpreter, is_inside, relative_path, shell_quote};
+
     static INIT: Once = Once::new();
 
     fn init() {
@@ -55,4 +140,52 @@ mod tests {
         assert_eq!(Path::new(&path).exists(), true);
         remove_dir_all(path).unwrap();
     }
+
+    #[test]
+    fn test_shebang_interpreter() {
+        init();
+        let path = create_dir_in_home(".coffee-shebang");
+        let script = format!("{path}/summary.py");
+        write(&script, "#!/usr/bin/env python3\nprint('hello')\n").unwrap();
+        let interpreter = get_shebang_interpreter(Path::new(&script));
+        assert_eq!(interpreter, Some("/usr/bin/env python3".to_owned()));
+
+        let readme = format!("{path}/README.md");
+        write(&readme, "# summary\n").unwrap();
+        assert!(get_shebang_interpreter(Path::new(&readme)).is_none());
+        remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_atomic_write() {
+        init();
+        let path = create_dir_in_home(".coffee-atomic");
+        let file = format!("{path}/storage.json");
+        atomic_write(&file, b"{}").await.unwrap();
+        atomic_write(&file, b"{\"version\":1}").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "{\"version\":1}");
+        assert!(!Path::new(&format!("{file}.tmp")).exists());
+        remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/plugins/summary.py"), "'/plugins/summary.py'");
+        assert_eq!(shell_quote("/it's/a.py"), "'/it'\\''s/a.py'");
+    }
+
+    #[test]
+    fn test_path_inside() {
+        assert!(is_inside("/repos/plugins/summary", "/repos/plugins"));
+        assert!(is_inside("/repos/plugins", "/repos/plugins"));
+        assert!(!is_inside("/repos/plugins-fork/summary", "/repos/plugins"));
+        assert_eq!(
+            relative_path("/repos/plugins/summary", "/repos/plugins"),
+            "summary"
+        );
+        assert_eq!(
+            relative_path("/repos/plugins-fork/summary", "/repos/plugins"),
+            ""
+        );
+    }
 }
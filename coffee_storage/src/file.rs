@@ -5,19 +5,27 @@
 //! This will work for the initial version
 //! of it, but maybe in the future it is needed
 //! a more smart version of storage manager
+use crate::migration::{migrate, Migration, VERSION_KEY};
 use crate::storage::StorageManager;
 use async_trait::async_trait;
 use coffee_lib::errors::CoffeeError;
+use coffee_lib::utils::atomic_write;
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
+    fs::{copy, File},
+    io::AsyncReadExt,
 };
 
 pub struct FileStorage {
     /// path of the storage file
     pub path: String,
     name_file: String,
+    /// migrations of the schema, the version of the
+    /// schema is the number of migrations.
+    migrations: Vec<Migration>,
+    /// the storage file is never written.
+    read_only: bool,
 }
 
 impl FileStorage {
@@ -25,12 +33,37 @@ impl FileStorage {
         FileStorage {
             path: path.to_owned(),
             name_file: "storage.json".to_owned(),
+            migrations: vec![],
+            read_only: false,
         }
     }
 
+    /// never write the storage file, the migrations are
+    /// applied only in memory.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// set the migrations of the schema applied when the
+    /// storage file is loaded.
+    pub fn with_migrations(mut self, migrations: &[Migration]) -> Self {
+        self.migrations = migrations.to_vec();
+        self
+    }
+
     pub fn get_path(&self) -> String {
         format!("{}/{}", self.path, self.name_file)
     }
+
+    /// version of the schema used to store the information.
+    pub fn version(&self) -> u64 {
+        self.migrations.len() as u64
+    }
+}
+
+fn malformed(err: serde_json::Error) -> CoffeeError {
+    CoffeeError::new(1, &format!("storage malformed: {err}"))
 }
 
 #[async_trait]
@@ -46,7 +79,17 @@ impl<T> StorageManager<T> for FileStorage {
             .await?
             .read_to_string(&mut content)
             .await?;
-        let val = serde_json::from_str::<T>(&content).unwrap();
+        let mut value: Value = serde_json::from_str(&content).map_err(malformed)?;
+        let from = migrate(&mut value, &self.migrations)?;
+        if from < self.version() && !self.read_only {
+            // keep the old version around, in case something goes wrong
+            // with the migration the user can still go back.
+            let backup = format!("{}.v{from}", self.get_path());
+            copy(self.get_path(), &backup).await?;
+            let content = serde_json::to_string(&value).map_err(malformed)?;
+            atomic_write(&self.get_path(), content.as_bytes()).await?;
+        }
+        let val = serde_json::from_value::<T>(value).map_err(malformed)?;
         Ok(val)
     }
 
@@ -54,11 +97,15 @@ impl<T> StorageManager<T> for FileStorage {
     where
         T: Serialize + Send + Sync,
     {
-        let content = serde_json::to_string(to_store).unwrap();
-        File::create(self.get_path())
-            .await?
-            .write_all(content.as_bytes())
-            .await?;
+        if self.read_only {
+            return Err(CoffeeError::new(1, "the storage is opened read-only"));
+        }
+        let mut value = serde_json::to_value(to_store).map_err(malformed)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert(VERSION_KEY.to_owned(), Value::from(self.version()));
+        }
+        let content = serde_json::to_string(&value).map_err(malformed)?;
+        atomic_write(&self.get_path(), content.as_bytes()).await?;
         Ok(())
     }
 }
@@ -3,5 +3,6 @@
 //! order to define the interface to manage the
 //! information to store on disk.
 pub mod file;
+pub mod migration;
 pub mod model;
 pub mod storage;
@@ -0,0 +1,74 @@
+//! migration contains the logic to upgrade the
+//! information stored on disk by an older version
+//! of coffee to the current version of the schema.
+use coffee_lib::errors::CoffeeError;
+use serde_json::Value;
+
+/// Key that contains the version of the schema
+/// inside the stored json.
+pub const VERSION_KEY: &str = "version";
+
+/// A migration upgrades the stored json from the version `n`
+/// to the version `n + 1`, where `n` is the position of the
+/// migration inside the list of migrations.
+pub type Migration = fn(&mut Value) -> Result<(), CoffeeError>;
+
+/// Version of the stored json, the json stored before the
+/// versioning of the schema is at version 0.
+pub fn version_of(value: &Value) -> u64 {
+    value
+        .get(VERSION_KEY)
+        .and_then(|version| version.as_u64())
+        .unwrap_or_default()
+}
+
+/// Apply all the migrations needed to bring the stored json to
+/// the last version, and return the version where it started.
+pub fn migrate(value: &mut Value, migrations: &[Migration]) -> Result<u64, CoffeeError> {
+    let from = version_of(value);
+    let last = migrations.len() as u64;
+    if from > last {
+        return Err(CoffeeError::new(
+            1,
+            &format!(
+                "storage version {from} is created by a newer coffee, the last known is {last}"
+            ),
+        ));
+    }
+    for (version, migration) in migrations.iter().enumerate().skip(from as usize) {
+        migration(value)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert(VERSION_KEY.to_owned(), Value::from(version as u64 + 1));
+        }
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{migrate, version_of, Migration};
+
+    #[test]
+    fn test_migrate() {
+        let migrations: [Migration; 2] = [
+            |value| {
+                value["repositories"] = json!([]);
+                Ok(())
+            },
+            |value| {
+                value["renamed"] = value["old"].take();
+                Ok(())
+            },
+        ];
+        let mut value = json!({"old": 1});
+        assert_eq!(migrate(&mut value, &migrations).unwrap(), 0);
+        assert_eq!(version_of(&value), 2);
+        assert_eq!(value["renamed"], 1);
+
+        // a migrated json is not touched anymore
+        assert_eq!(migrate(&mut value, &migrations).unwrap(), 2);
+        assert!(migrate(&mut value, &migrations[..1]).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 //! Minimal information to make persistent
 //! a repository.
-use coffee_lib::{plugin::Plugin, url::URL};
+use coffee_lib::{plugin::Plugin, repository::Layout, url::URL};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -14,4 +14,10 @@ pub struct Repository {
     pub name: String,
     pub url: URL,
     pub plugins: Vec<Plugin>,
+    /// unix time of the last update of the repository.
+    #[serde(default)]
+    pub last_update: Option<u64>,
+    /// organization of the plugins inside the repository.
+    #[serde(default)]
+    pub layout: Layout,
 }